@@ -0,0 +1,166 @@
+//! Benchmarks of the hot path of the claimer: deserializing blocks, matching their
+//! outputs against the pending covenants and constructing claims
+//!
+//! Run them with `cargo bench`
+
+use std::fs;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use elements::{AddressParams, Block, Transaction};
+
+use covclaim::claimer::constructor::construct_claim;
+use covclaim::db;
+use covclaim::db::helpers::{
+    get_pending_covenant_for_output, get_pending_covenants_for_outputs, import_covenants,
+};
+
+mod common;
+
+fn block_deserialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("block_deserialization");
+
+    for tx_count in [100, 1_000, 5_000] {
+        let raw = elements::encode::serialize(&common::block(tx_count, 2, &[]));
+
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(tx_count), &raw, |b, raw| {
+            b.iter(|| elements::encode::deserialize::<Block>(raw).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+// Database with the given number of covenants of which 10 are paid by the returned lockups
+fn covenant_db(
+    runtime: &tokio::runtime::Runtime,
+    covenant_count: usize,
+) -> (db::Pool, std::path::PathBuf, Vec<Transaction>) {
+    let db_path =
+        std::env::temp_dir().join(format!("covclaim-bench-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&db_path);
+    let pool = db::establish_connection(&format!("sqlite://{}", db_path.display())).unwrap();
+
+    let lockups: Vec<_> = (0..10).map(|_| common::covenant(false)).collect();
+    let mut covenants = common::random_covenants(covenant_count - lockups.len());
+    covenants.extend(lockups.iter().map(|(covenant, _)| covenant.clone()));
+    runtime
+        .block_on(import_covenants(pool.clone(), covenants))
+        .unwrap();
+
+    (
+        pool,
+        db_path,
+        lockups.into_iter().map(|(_, tx)| tx).collect(),
+    )
+}
+
+// Every output is looked up with its own query
+async fn match_per_output(pool: &db::Pool, block: &Block) -> usize {
+    let mut found = 0;
+    for tx in &block.txdata {
+        for out in &tx.output {
+            if get_pending_covenant_for_output(pool.clone(), out.script_pubkey.as_bytes())
+                .await
+                .unwrap()
+                .is_some()
+            {
+                found += 1;
+            }
+        }
+    }
+    found
+}
+
+// Like Claimer::handle_tx, all outputs of a transaction are looked up with one query
+async fn match_per_transaction(pool: &db::Pool, block: &Block) -> usize {
+    let mut found = 0;
+    for tx in &block.txdata {
+        let scripts = tx
+            .output
+            .iter()
+            .map(|out| out.script_pubkey.to_bytes())
+            .collect();
+        found += get_pending_covenants_for_outputs(pool.clone(), scripts)
+            .await
+            .unwrap()
+            .len();
+    }
+    found
+}
+
+fn tx_matching(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("tx_matching");
+
+    for covenant_count in [100, 1_000, 10_000] {
+        let (pool, db_path, lockups) = covenant_db(&runtime, covenant_count);
+
+        let block = common::block(100, 2, &lockups);
+        let outputs = block.txdata.iter().map(|tx| tx.output.len()).sum::<usize>();
+
+        group.throughput(Throughput::Elements(outputs as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(covenant_count),
+            &block,
+            |b, block| {
+                b.to_async(&runtime)
+                    .iter(|| async { assert_eq!(match_per_transaction(&pool, block).await, 10) })
+            },
+        );
+
+        let _ = fs::remove_file(&db_path);
+    }
+
+    group.finish();
+}
+
+// Peg-ins and batched payouts have hundreds of outputs, which makes a query per output
+// the bottleneck of processing their blocks
+fn pegin_matching(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group("pegin_matching");
+
+    let (pool, db_path, lockups) = covenant_db(&runtime, 10_000);
+    let block = common::block(20, 500, &lockups);
+    let outputs = block.txdata.iter().map(|tx| tx.output.len()).sum::<usize>();
+
+    group.throughput(Throughput::Elements(outputs as u64));
+    group.bench_function("per_output", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { assert_eq!(match_per_output(&pool, &block).await, 10) })
+    });
+    group.bench_function("per_transaction", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { assert_eq!(match_per_transaction(&pool, &block).await, 10) })
+    });
+
+    let _ = fs::remove_file(&db_path);
+    group.finish();
+}
+
+fn claim_construction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("claim_construction");
+
+    for (name, blinded) in [("unblinded", false), ("blinded", true)] {
+        let (covenant, lockup_tx) = common::covenant(blinded);
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                construct_claim(&covenant, &lockup_tx, &AddressParams::ELEMENTS, None, None)
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    block_deserialization,
+    tx_matching,
+    pegin_matching,
+    claim_construction
+);
+criterion_main!(benches);
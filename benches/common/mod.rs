@@ -0,0 +1,245 @@
+//! Synthetic blocks and covenants for the benchmarks
+
+use elements::confidential::{Asset, AssetBlindingFactor, Nonce, Value, ValueBlindingFactor};
+use elements::hashes::{sha256, Hash};
+use elements::opcodes::all::{
+    OP_CHECKSIG, OP_DROP, OP_EQUAL, OP_EQUALVERIFY, OP_INSPECTOUTPUTASSET,
+    OP_INSPECTOUTPUTSCRIPTPUBKEY, OP_INSPECTOUTPUTVALUE, OP_PUSHNUM_1, OP_SHA256, OP_SIZE,
+};
+use elements::script::Builder;
+use elements::secp256k1_zkp::rand::rngs::OsRng;
+use elements::secp256k1_zkp::rand::RngCore;
+use elements::secp256k1_zkp::{MusigKeyAggCache, PublicKey, SecretKey};
+use elements::{
+    AddressParams, AssetId, Block, BlockExtData, BlockHash, BlockHeader, LockTime, OutPoint,
+    Script, Sequence, Transaction, TxIn, TxInWitness, TxMerkleNode, TxOut, TxOutSecrets,
+    TxOutWitness, Txid,
+};
+
+use covclaim::claimer::tree::{SwapTree, TreeScript};
+use covclaim::db::models::{PendingCovenant, PendingCovenantStatus};
+
+pub const LOCKUP_AMOUNT: u64 = 100_000;
+pub const CLAIM_AMOUNT: u64 = 99_000;
+
+pub fn asset() -> AssetId {
+    AssetId::from_slice(&[1; 32]).unwrap()
+}
+
+/// Block with the lockup transactions between `tx_count` transactions with
+/// `outputs_per_tx` random P2WPKH outputs
+pub fn block(tx_count: usize, outputs_per_tx: usize, lockups: &[Transaction]) -> Block {
+    let mut txdata: Vec<Transaction> = (0..tx_count)
+        .map(|_| transaction((0..outputs_per_tx).map(|_| random_output()).collect()))
+        .collect();
+
+    let spacing = tx_count / (lockups.len() + 1) + 1;
+    for (i, lockup) in lockups.iter().enumerate() {
+        txdata.insert(((i + 1) * spacing).min(txdata.len()), lockup.clone());
+    }
+
+    Block {
+        header: BlockHeader {
+            version: 0x2000_0000,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_700_000_000,
+            height: 1,
+            ext: BlockExtData::Proof {
+                challenge: Script::new(),
+                solution: Script::new(),
+            },
+        },
+        txdata,
+    }
+}
+
+/// Random output scripts of covenants that are not in any block
+pub fn random_covenants(count: usize) -> Vec<PendingCovenant> {
+    (0..count)
+        .map(|_| {
+            let mut output_script = vec![0x51, 0x20];
+            output_script.extend(random_bytes());
+
+            PendingCovenant {
+                output_script,
+                status: PendingCovenantStatus::Pending.to_int(),
+                internal_key: random_bytes().to_vec(),
+                preimage: random_bytes().to_vec().into(),
+                swap_tree: "{}".to_string(),
+                address: vec![],
+                blinding_key: None,
+                tx_id: None,
+                tx_time: None,
+                swap_id: None,
+                lease_until: None,
+                fee_rate: None,
+                max_fee: None,
+                op_return_data: None,
+                expected_lockup_amount: None,
+            }
+        })
+        .collect()
+}
+
+/// Covenant that can be claimed and its lockup transaction
+pub fn covenant(blinded: bool) -> (PendingCovenant, Transaction) {
+    let secp = SwapTree::secp();
+    let claim_public_key = PublicKey::from_secret_key(secp, &SecretKey::new(&mut OsRng));
+    let refund_public_key = PublicKey::from_secret_key(secp, &SecretKey::new(&mut OsRng));
+    let preimage = random_bytes();
+
+    let mut claim_script = vec![0x00, 0x14];
+    claim_script.extend(&random_bytes()[..20]);
+    let claim_script = Script::from(claim_script);
+
+    let tree = SwapTree {
+        claim_leaf: TreeScript {
+            output: checksig_leaf(&claim_public_key),
+        },
+        refund_leaf: TreeScript {
+            output: checksig_leaf(&refund_public_key),
+        },
+        covenant_claim_leaf: TreeScript {
+            output: covenant_leaf(&preimage, &claim_script),
+        },
+        leaves: None,
+    };
+
+    let internal_key = MusigKeyAggCache::new(secp, &[refund_public_key, claim_public_key])
+        .agg_pk()
+        .serialize()
+        .to_vec();
+    let lockup_address = tree
+        .clone()
+        .address(internal_key.clone(), &AddressParams::ELEMENTS)
+        .unwrap();
+
+    let (lockup_output, blinding_key) = match blinded {
+        true => {
+            let blinding_key = SecretKey::new(&mut OsRng);
+            let (output, _, _, _) = TxOut::new_not_last_confidential(
+                &mut OsRng,
+                secp,
+                LOCKUP_AMOUNT,
+                lockup_address.to_confidential(PublicKey::from_secret_key(secp, &blinding_key)),
+                asset(),
+                &[TxOutSecrets::new(
+                    asset(),
+                    AssetBlindingFactor::zero(),
+                    LOCKUP_AMOUNT,
+                    ValueBlindingFactor::zero(),
+                )],
+            )
+            .unwrap();
+
+            (output, Some(blinding_key.secret_bytes().to_vec().into()))
+        }
+        false => (
+            explicit_output(lockup_address.script_pubkey(), LOCKUP_AMOUNT),
+            None,
+        ),
+    };
+
+    let covenant = PendingCovenant {
+        output_script: lockup_address.script_pubkey().into_bytes(),
+        status: PendingCovenantStatus::Pending.to_int(),
+        internal_key,
+        preimage: preimage.to_vec().into(),
+        swap_tree: serde_json::to_string(&tree).unwrap(),
+        address: claim_script.into_bytes(),
+        blinding_key,
+        tx_id: None,
+        tx_time: None,
+        swap_id: None,
+        lease_until: None,
+        fee_rate: None,
+        max_fee: None,
+        op_return_data: None,
+        expected_lockup_amount: None,
+    };
+
+    (covenant, transaction(vec![random_output(), lockup_output]))
+}
+
+fn transaction(output: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_byte_array(random_bytes()), 0),
+            is_pegin: false,
+            script_sig: Script::new(),
+            sequence: Sequence::MAX,
+            asset_issuance: Default::default(),
+            witness: TxInWitness {
+                // Signature and public key like a P2WPKH spend
+                script_witness: vec![vec![1; 72], vec![2; 33]],
+                ..Default::default()
+            },
+        }],
+        output,
+    }
+}
+
+fn random_output() -> TxOut {
+    let mut script = vec![0x00, 0x14];
+    script.extend(&random_bytes()[..20]);
+
+    explicit_output(Script::from(script), OsRng.next_u64() % 100_000_000)
+}
+
+fn explicit_output(script_pubkey: Script, value: u64) -> TxOut {
+    TxOut {
+        asset: Asset::Explicit(asset()),
+        value: Value::Explicit(value),
+        nonce: Nonce::Null,
+        script_pubkey,
+        witness: TxOutWitness::default(),
+    }
+}
+
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn checksig_leaf(public_key: &PublicKey) -> Vec<u8> {
+    Builder::new()
+        .push_slice(&public_key.x_only_public_key().0.serialize())
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+        .into_bytes()
+}
+
+fn covenant_leaf(preimage: &[u8; 32], output: &Script) -> Vec<u8> {
+    let preimage_hash: sha256::Hash = Hash::hash(preimage);
+
+    Builder::new()
+        .push_opcode(OP_SIZE)
+        .push_int(32)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_SHA256)
+        .push_slice(preimage_hash.as_byte_array())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTSCRIPTPUBKEY)
+        .push_int(0)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_slice(&output.as_bytes()[2..])
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTASSET)
+        .push_opcode(OP_PUSHNUM_1)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_slice(&elements::encode::serialize(&asset()))
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTVALUE)
+        .push_opcode(OP_DROP)
+        .push_slice(&CLAIM_AMOUNT.to_le_bytes())
+        .push_opcode(OP_EQUAL)
+        .into_script()
+        .into_bytes()
+}
@@ -0,0 +1,12 @@
+#![no_main]
+
+use covclaim::claimer::leaf::CovenantDetails;
+use elements::Script;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(details) = CovenantDetails::parse(&Script::from(data.to_vec())) {
+        let _ = details.is_preimage_valid(data);
+        let _ = details.is_expected_output(&Script::from(data.to_vec()));
+    }
+});
@@ -0,0 +1,26 @@
+#![no_main]
+
+use covclaim::claimer::tree::SwapTree;
+use elements::AddressParams;
+use libfuzzer_sys::fuzz_target;
+
+// Swap trees are sent to the API as JSON and must never crash the daemon
+fuzz_target!(|data: &[u8]| {
+    let tree = match serde_json::from_slice::<SwapTree>(data) {
+        Ok(res) => res,
+        Err(_) => return,
+    };
+
+    let _ = tree.clone().covenant_details();
+
+    // Internal key of the tests of the swap tree
+    let internal_key = vec![
+        0x81, 0x69, 0x63, 0xaf, 0x90, 0xd4, 0xb8, 0x82, 0xcc, 0xbc, 0xaa, 0xcc, 0x92, 0x0b, 0xa8,
+        0xe4, 0xfd, 0xd3, 0x5c, 0x08, 0x3a, 0x05, 0x2a, 0x08, 0xd5, 0xc1, 0x73, 0x22, 0x72, 0xff,
+        0xcc, 0xd8,
+    ];
+    let _ = tree
+        .clone()
+        .address(internal_key.clone(), &AddressParams::ELEMENTS);
+    let _ = tree.control_block(internal_key);
+});
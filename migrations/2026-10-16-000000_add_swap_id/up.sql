@@ -0,0 +1,3 @@
+ALTER TABLE pending_covenants ADD COLUMN swap_id VARCHAR;
+
+CREATE INDEX pending_covenants_swap_id_idx ON pending_covenants (swap_id);
@@ -0,0 +1,10 @@
+CREATE TABLE covenant_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    output_script BLOB NOT NULL,
+    status INT NOT NULL,
+    reason VARCHAR NOT NULL,
+    tx_id BLOB,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX covenant_events_output_script_idx ON covenant_events (output_script);
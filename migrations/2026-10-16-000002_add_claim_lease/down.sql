@@ -0,0 +1 @@
+ALTER TABLE pending_covenants DROP COLUMN lease_until;
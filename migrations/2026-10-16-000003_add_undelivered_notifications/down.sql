@@ -0,0 +1 @@
+DROP TABLE undelivered_notifications;
@@ -0,0 +1,5 @@
+CREATE TABLE undelivered_notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    payload VARCHAR NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+ALTER TABLE pending_covenants DROP COLUMN max_fee;
+ALTER TABLE pending_covenants DROP COLUMN fee_rate;
@@ -0,0 +1,2 @@
+ALTER TABLE pending_covenants ADD COLUMN fee_rate DOUBLE;
+ALTER TABLE pending_covenants ADD COLUMN max_fee BIGINT;
@@ -0,0 +1,5 @@
+DROP INDEX pending_covenants_claim_tx_id_idx;
+
+ALTER TABLE pending_covenants DROP COLUMN confirmed_at;
+ALTER TABLE pending_covenants DROP COLUMN claimed_at;
+ALTER TABLE pending_covenants DROP COLUMN claim_tx_id;
@@ -0,0 +1 @@
+ALTER TABLE covenant_events DROP COLUMN request_id;
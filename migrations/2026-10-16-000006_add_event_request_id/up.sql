@@ -0,0 +1 @@
+ALTER TABLE covenant_events ADD COLUMN request_id TEXT;
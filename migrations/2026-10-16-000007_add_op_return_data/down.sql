@@ -0,0 +1 @@
+ALTER TABLE pending_covenants DROP COLUMN op_return_data;
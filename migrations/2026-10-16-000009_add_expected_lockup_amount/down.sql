@@ -0,0 +1 @@
+ALTER TABLE pending_covenants DROP COLUMN expected_lockup_amount;
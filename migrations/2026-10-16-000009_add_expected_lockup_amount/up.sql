@@ -0,0 +1 @@
+ALTER TABLE pending_covenants ADD COLUMN expected_lockup_amount BIGINT;
@@ -0,0 +1,3 @@
+-- Covenants that were claimed before claims were tracked until their confirmation
+UPDATE pending_covenants SET confirmed_at = COALESCE(claimed_at, created_at)
+    WHERE status = 2 AND confirmed_at IS NULL;
@@ -0,0 +1,3 @@
+DROP INDEX pending_covenants_swap_id_idx;
+
+ALTER TABLE pending_covenants DROP COLUMN swap_id;
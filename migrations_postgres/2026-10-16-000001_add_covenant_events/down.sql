@@ -0,0 +1,3 @@
+DROP INDEX covenant_events_output_script_idx;
+
+DROP TABLE covenant_events;
@@ -0,0 +1,10 @@
+CREATE TABLE covenant_events (
+    id SERIAL PRIMARY KEY NOT NULL,
+    output_script BYTEA NOT NULL,
+    status INT NOT NULL,
+    reason VARCHAR NOT NULL,
+    tx_id BYTEA,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX covenant_events_output_script_idx ON covenant_events (output_script);
@@ -0,0 +1 @@
+ALTER TABLE pending_covenants ADD COLUMN lease_until TIMESTAMP;
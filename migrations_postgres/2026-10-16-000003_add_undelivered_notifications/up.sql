@@ -0,0 +1,5 @@
+CREATE TABLE undelivered_notifications (
+    id SERIAL PRIMARY KEY NOT NULL,
+    payload VARCHAR NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,5 @@
+ALTER TABLE pending_covenants ADD COLUMN claim_tx_id BYTEA;
+ALTER TABLE pending_covenants ADD COLUMN claimed_at TIMESTAMP;
+ALTER TABLE pending_covenants ADD COLUMN confirmed_at TIMESTAMP;
+
+CREATE INDEX pending_covenants_claim_tx_id_idx ON pending_covenants (claim_tx_id);
@@ -0,0 +1 @@
+ALTER TABLE pending_covenants ADD COLUMN op_return_data BYTEA;
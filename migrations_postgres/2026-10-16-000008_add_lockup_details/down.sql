@@ -0,0 +1,3 @@
+ALTER TABLE pending_covenants DROP COLUMN lockup_height;
+ALTER TABLE pending_covenants DROP COLUMN lockup_amount;
+ALTER TABLE pending_covenants DROP COLUMN lockup_vout;
@@ -0,0 +1,3 @@
+ALTER TABLE pending_covenants ADD COLUMN lockup_vout INTEGER;
+ALTER TABLE pending_covenants ADD COLUMN lockup_amount BIGINT;
+ALTER TABLE pending_covenants ADD COLUMN lockup_height BIGINT;
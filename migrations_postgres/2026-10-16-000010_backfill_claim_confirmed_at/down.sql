@@ -0,0 +1,2 @@
+-- The backfilled times cannot be told apart from recorded ones
+SELECT 1;
@@ -0,0 +1,129 @@
+use std::str::FromStr;
+
+use elements::{Address, AddressParams, Script};
+
+/// Output scripts covenants may pay to; every address is allowed when none are configured.
+/// Confidential and unconfidential versions of an address have the same output script
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AllowedDestinations {
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Pattern {
+    Script(Vec<u8>),
+    ScriptPrefix(Vec<u8>),
+}
+
+impl AllowedDestinations {
+    /// Parses a comma separated list of addresses and hex output scripts prefixed with
+    /// `script:`; a trailing `*` makes a script match every script that starts with it
+    pub fn parse(params: &'static AddressParams, value: &str) -> Result<Self, String> {
+        let patterns = value
+            .split(',')
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.strip_prefix("script:") {
+                Some(script) => {
+                    let (script, is_prefix) = match script.strip_suffix('*') {
+                        Some(prefix) => (prefix, true),
+                        None => (script, false),
+                    };
+                    let script = hex::decode(script)
+                        .map_err(|err| format!("invalid script {}: {}", entry, err))?;
+
+                    Ok(match is_prefix {
+                        true => Pattern::ScriptPrefix(script),
+                        false => Pattern::Script(script),
+                    })
+                }
+                None => {
+                    let address = Address::from_str(entry)
+                        .map_err(|err| format!("invalid address {}: {}", entry, err))?;
+                    if address.params != params {
+                        return Err(format!("address {} has invalid network", entry));
+                    }
+
+                    Ok(Pattern::Script(address.script_pubkey().to_bytes()))
+                }
+            })
+            .collect::<Result<Vec<Pattern>, String>>()?;
+
+        Ok(AllowedDestinations { patterns })
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn is_allowed(&self, script: &Script) -> bool {
+        self.is_empty()
+            || self.patterns.iter().any(|pattern| match pattern {
+                Pattern::Script(allowed) => allowed.as_slice() == script.as_bytes(),
+                Pattern::ScriptPrefix(prefix) => script.as_bytes().starts_with(prefix),
+            })
+    }
+}
+
+#[cfg(test)]
+mod destinations_test {
+    use std::str::FromStr;
+
+    use elements::{Address, AddressParams, Script};
+
+    use crate::api::destinations::AllowedDestinations;
+
+    const ADDRESS: &str = "el1qq2kqp5kej5gjfh24scawfxpl3uju5fr9tqv6f78sjauxlakgq8musy544qwhad34768q5t0ppmzr0z9wyn70cx5fkee9lzv4j";
+
+    fn script(address: &str) -> Script {
+        Address::from_str(address).unwrap().script_pubkey()
+    }
+
+    #[test]
+    fn test_unrestricted() {
+        let destinations = AllowedDestinations::parse(&AddressParams::ELEMENTS, " ").unwrap();
+        assert!(destinations.is_empty());
+        assert!(destinations.is_allowed(&script(ADDRESS)));
+    }
+
+    #[test]
+    fn test_address() {
+        let destinations = AllowedDestinations::parse(&AddressParams::ELEMENTS, ADDRESS).unwrap();
+        assert!(!destinations.is_empty());
+        assert!(destinations.is_allowed(&script(ADDRESS)));
+        assert!(!destinations.is_allowed(&script("ert1qpf0c8tqm70908xalp9jh4275etnq5lgnet663j")));
+
+        // The unconfidential version of the address pays to the same script
+        let unconfidential = Address::from_str(ADDRESS).unwrap().to_unconfidential();
+        assert!(destinations.is_allowed(&unconfidential.script_pubkey()));
+    }
+
+    #[test]
+    fn test_scripts() {
+        let destinations =
+            AllowedDestinations::parse(&AddressParams::ELEMENTS, "script:0014aaaa, script:5120*")
+                .unwrap();
+        assert_eq!(destinations.len(), 2);
+
+        assert!(destinations.is_allowed(&Script::from(vec![0x00, 0x14, 0xaa, 0xaa])));
+        assert!(!destinations.is_allowed(&Script::from(vec![0x00, 0x14, 0xaa])));
+        assert!(destinations.is_allowed(&Script::from(vec![0x51, 0x20, 0x01, 0x02])));
+        assert!(!destinations.is_allowed(&Script::from(vec![0x00, 0x20, 0x01, 0x02])));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(
+            AllowedDestinations::parse(&AddressParams::ELEMENTS, "script:zz").err(),
+            Some("invalid script script:zz: Invalid character 'z' at position 0".to_string())
+        );
+        assert_eq!(
+            AllowedDestinations::parse(&AddressParams::LIQUID, ADDRESS).err(),
+            Some(format!("address {} has invalid network", ADDRESS))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+pub mod destinations;
 mod routes;
 pub mod server;
-mod types;
+pub mod types;
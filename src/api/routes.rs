@@ -1,21 +1,38 @@
-use std::error::Error;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::{Extension, Json};
-use elements::hashes::Hash;
+use diesel::internal::derives::multiconnection::chrono::{TimeDelta, Utc};
 use elements::secp256k1_zkp::{MusigKeyAggCache, PublicKey, SecretKey};
-use elements::{hashes, Address, AddressParams};
-use log::debug;
+use elements::{Address, AddressParams, Script, Transaction};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::debug;
+use zeroize::Zeroizing;
 
 use crate::api::types::RouterState;
+use crate::claimer::constructor::{construct_claim, dust_threshold, MAX_OP_RETURN_DATA_LENGTH};
 use crate::claimer::tree::SwapTree;
-use crate::db::helpers::insert_covenant;
-use crate::db::models::{PendingCovenant, PendingCovenantStatus};
+use crate::db::helpers::{
+    count_claims_since, get_block_height, get_claim_timing_by_swap_id,
+    get_covenant_by_output_script, get_covenant_by_swap_id, get_covenant_events,
+    get_covenant_status_by_swap_id, get_covenant_status_counts, get_oldest_pending_covenant_time,
+    insert_covenant, update_covenant_address,
+};
+use crate::db::models::{CovenantEvent, PendingCovenant, PendingCovenantStatus, Secret};
+use crate::db::transfer::{export_covenants, import, ExportedCovenant, ImportError};
+use crate::notifier;
+use crate::notifier::{Notification, NotificationKind};
+
+// Registrations are a few hundred bytes; the covenant claim leaf checks the preimage length
+pub const MAX_COVENANT_REQUEST_SIZE: usize = 16 * 1024;
+const MAX_PREIMAGE_LENGTH: usize = 32;
+const MAX_LEAF_SCRIPT_LENGTH: usize = 1024;
+const MAX_TREE_LEAVES: usize = 16;
 
 #[derive(Clone, Serialize, Deserialize)]
 struct EmptyResponse {}
@@ -39,15 +56,43 @@ pub struct CovenantClaimRequest {
     pub preimage: Vec<u8>,
 
     #[serde(rename = "blindingKey")]
-    pub blinding_key: Option<String>,
+    pub blinding_key: Option<Zeroizing<String>>,
 
     pub address: String,
     pub tree: SwapTree,
+
+    #[serde(rename = "swapId")]
+    pub swap_id: Option<String>,
+
+    // Fee rate in sat/vbyte; the estimation of the chain backend is used when not set
+    #[serde(rename = "feeRate")]
+    pub fee_rate: Option<f64>,
+
+    // Maximal fee in sats the claim may pay
+    #[serde(rename = "maxFee")]
+    pub max_fee: Option<u64>,
+
+    // Hex of the data embedded in an OP_RETURN output of the claim
+    #[serde(rename = "opReturnData")]
+    pub op_return_data: Option<String>,
+
+    // Amount in sats the lockup has to have; required when lockups need confirmations
+    #[serde(rename = "expectedLockupAmount")]
+    pub expected_lockup_amount: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+struct ConflictResponse {
+    pub error: String,
+    pub status: &'static str,
 }
 
 #[derive(Serialize)]
 enum CovenantClaimResponse {
     Error(ErrorResponse),
+    Conflict(ConflictResponse),
+    DestinationNotAllowed(ErrorResponse),
+    Unchanged(EmptyResponse),
     Success(EmptyResponse),
 }
 
@@ -57,6 +102,13 @@ impl IntoResponse for CovenantClaimResponse {
             CovenantClaimResponse::Success(resp) => {
                 (StatusCode::CREATED, Json(resp)).into_response()
             }
+            CovenantClaimResponse::Unchanged(resp) => (StatusCode::OK, Json(resp)).into_response(),
+            CovenantClaimResponse::Conflict(err) => {
+                (StatusCode::CONFLICT, Json(err)).into_response()
+            }
+            CovenantClaimResponse::DestinationNotAllowed(err) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, Json(err)).into_response()
+            }
             CovenantClaimResponse::Error(err) => {
                 (StatusCode::BAD_REQUEST, Json(err)).into_response()
             }
@@ -64,51 +116,681 @@ impl IntoResponse for CovenantClaimResponse {
     }
 }
 
-pub async fn post_covenant_claim(
+#[derive(Serialize)]
+struct CovenantEventResponse {
+    pub status: &'static str,
+    pub reason: String,
+    #[serde(rename = "txId")]
+    pub tx_id: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+}
+
+impl From<CovenantEvent> for CovenantEventResponse {
+    fn from(event: CovenantEvent) -> Self {
+        CovenantEventResponse {
+            reason: event.reason,
+            status: PendingCovenantStatus::name(event.status),
+            tx_id: event.tx_id.map(|tx_id| tx_id.to_string()),
+            created_at: event.created_at.to_string(),
+            request_id: event.request_id,
+        }
+    }
+}
+
+enum CovenantEventsResponse {
+    Error(ErrorResponse),
+    NotFound(ErrorResponse),
+    Success(Vec<CovenantEventResponse>),
+}
+
+impl IntoResponse for CovenantEventsResponse {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            CovenantEventsResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+            CovenantEventsResponse::NotFound(err) => {
+                (StatusCode::NOT_FOUND, Json(err)).into_response()
+            }
+            CovenantEventsResponse::Error(err) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(err)).into_response()
+            }
+        }
+    }
+}
+
+pub async fn get_covenant_claim_events(
     Extension(state): Extension<Arc<RouterState>>,
-    Json(body): Json<CovenantClaimRequest>,
+    Path(swap_id): Path<String>,
 ) -> impl IntoResponse {
-    let address = match parse_address(state.address_params, body.address) {
-        Ok(addr) => addr,
-        Err(err) => return CovenantClaimResponse::Error(err),
+    let covenant = match get_covenant_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            return CovenantEventsResponse::NotFound(ErrorResponse {
+                error: format!("no covenant for swap {}", swap_id),
+            })
+        }
+        Err(err) => {
+            return CovenantEventsResponse::Error(ErrorResponse {
+                error: err.to_string(),
+            })
+        }
     };
 
-    let blinding_key: Result<Option<Vec<u8>>, Box<dyn Error>> = match body.blinding_key {
-        Some(res) => match hex::decode(res) {
-            Ok(res) => match SecretKey::from_slice(res.as_slice()) {
-                Ok(_) => Ok(Some(res)),
-                Err(err) => Err(err.into()),
+    match get_covenant_events(state.db.clone(), covenant.output_script).await {
+        Ok(events) => CovenantEventsResponse::Success(
+            events
+                .into_iter()
+                .map(CovenantEventResponse::from)
+                .collect(),
+        ),
+        Err(err) => CovenantEventsResponse::Error(ErrorResponse {
+            error: err.to_string(),
+        }),
+    }
+}
+
+#[derive(Serialize)]
+struct ClaimTimingResponse {
+    #[serde(rename = "registeredAt")]
+    pub registered_at: String,
+    #[serde(rename = "lockupFoundAt")]
+    pub lockup_found_at: Option<String>,
+    #[serde(rename = "claimBroadcastAt")]
+    pub claim_broadcast_at: Option<String>,
+    #[serde(rename = "claimConfirmedAt")]
+    pub claim_confirmed_at: Option<String>,
+    // Seconds each completed stage took
+    pub stages: BTreeMap<&'static str, f64>,
+}
+
+pub async fn get_covenant_claim_timing(
+    Extension(state): Extension<Arc<RouterState>>,
+    Path(swap_id): Path<String>,
+) -> axum::response::Response {
+    match get_claim_timing_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(timing)) => (
+            StatusCode::OK,
+            Json(ClaimTimingResponse {
+                stages: timing.stages().into_iter().collect(),
+                registered_at: timing.created_at.to_string(),
+                lockup_found_at: timing.tx_time.map(|time| time.to_string()),
+                claim_broadcast_at: timing.claimed_at.map(|time| time.to_string()),
+                claim_confirmed_at: timing.confirmed_at.map(|time| time.to_string()),
+            }),
+        )
+            .into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("no covenant for swap {}", swap_id),
+            }),
+        )
+            .into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Serialize)]
+struct LockupResponse {
+    #[serde(rename = "txId")]
+    pub tx_id: String,
+    pub vout: Option<i32>,
+    // Missing when the output could not be unblinded
+    pub amount: Option<i64>,
+    // Missing while the lockup transaction is unconfirmed
+    #[serde(rename = "blockHeight")]
+    pub block_height: Option<i64>,
+    #[serde(rename = "foundAt")]
+    pub found_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CovenantStatusResponse {
+    pub status: &'static str,
+    pub lockup: Option<LockupResponse>,
+    #[serde(rename = "claimTxId")]
+    pub claim_tx_id: Option<String>,
+}
+
+pub async fn get_covenant_status(
+    Extension(state): Extension<Arc<RouterState>>,
+    Path(swap_id): Path<String>,
+) -> axum::response::Response {
+    match get_covenant_status_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(covenant)) => (
+            StatusCode::OK,
+            Json(CovenantStatusResponse {
+                status: PendingCovenantStatus::name(covenant.status),
+                lockup: covenant.tx_id.map(|tx_id| LockupResponse {
+                    tx_id: tx_id.to_string(),
+                    vout: covenant.lockup_vout,
+                    amount: covenant.lockup_amount,
+                    block_height: covenant.lockup_height,
+                    found_at: covenant.tx_time.map(|time| time.to_string()),
+                }),
+                claim_tx_id: covenant.claim_tx_id.map(|tx_id| tx_id.to_string()),
+            }),
+        )
+            .into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("no covenant for swap {}", swap_id),
+            }),
+        )
+            .into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Serialize)]
+struct ClaimOutputPreview {
+    // "claim", "opReturn" or "fee"
+    pub kind: &'static str,
+    pub amount: u64,
+}
+
+#[derive(Serialize)]
+struct FeePreviewResponse {
+    #[serde(rename = "lockupTxId")]
+    pub lockup_tx_id: String,
+    pub vsize: u64,
+    pub fee: u64,
+    // In sat/vbyte
+    #[serde(rename = "feeRate")]
+    pub fee_rate: f64,
+    // Fee rate in sat/vbyte the claim is compared to; the fee is fixed by the covenant
+    #[serde(rename = "feeTarget")]
+    pub fee_target: f64,
+    pub outputs: Vec<ClaimOutputPreview>,
+}
+
+/// Constructs the claim of a covenant whose lockup transaction was found, without broadcasting it
+pub async fn get_covenant_fee_preview(
+    Extension(state): Extension<Arc<RouterState>>,
+    Path(swap_id): Path<String>,
+) -> axum::response::Response {
+    let covenant = match get_covenant_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: format!("no covenant for swap {}", swap_id),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: err.to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let lockup_tx_id = match &covenant.tx_id {
+        Some(res) => res.txid().to_string(),
+        None => {
+            return (
+                StatusCode::CONFLICT,
+                Json(ConflictResponse {
+                    error: "lockup transaction of covenant was not found yet".to_string(),
+                    status: PendingCovenantStatus::name(covenant.status),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let lockup_tx = match state
+        .chain_backend
+        .get_transaction(lockup_tx_id.clone())
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("could not fetch lockup transaction: {}", err),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let (tx, fee) = match construct_claim(
+        &covenant,
+        &lockup_tx,
+        state.address_params,
+        state.policy_asset,
+        state.op_return_data.as_deref(),
+    ) {
+        Ok(res) => res,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: err.to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let vsize = tx.weight().div_ceil(4) as u64;
+    let fee_target = match covenant.fee_rate {
+        Some(res) => res,
+        None => state.fee_estimator.estimate().await,
+    };
+
+    (
+        StatusCode::OK,
+        Json(FeePreviewResponse {
+            lockup_tx_id,
+            vsize,
+            fee,
+            fee_rate: fee as f64 / vsize as f64,
+            fee_target,
+            outputs: preview_outputs(&covenant, &tx),
+        }),
+    )
+        .into_response()
+}
+
+fn preview_outputs(covenant: &PendingCovenant, tx: &Transaction) -> Vec<ClaimOutputPreview> {
+    tx.output
+        .iter()
+        .map(|out| ClaimOutputPreview {
+            kind: if out.is_fee() {
+                "fee"
+            } else if out.script_pubkey.as_bytes() == covenant.address.as_slice() {
+                "claim"
+            } else {
+                "opReturn"
             },
-            Err(err) => Err(err.into()),
+            // The value of the OP_RETURN of blinded claims is blinded, but always 1 sat
+            amount: out.value.explicit().unwrap_or(1),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    // Number of covenants per status
+    pub covenants: BTreeMap<&'static str, i64>,
+    // Seconds since the oldest covenant whose lockup was not found yet was registered
+    #[serde(rename = "oldestPendingAge")]
+    pub oldest_pending_age: Option<i64>,
+    // Of the last block that was processed
+    #[serde(rename = "blockHeight")]
+    pub block_height: Option<u64>,
+    #[serde(rename = "blockHash")]
+    pub block_hash: Option<String>,
+    #[serde(rename = "claimsLast24h")]
+    pub claims_last_24h: i64,
+}
+
+pub async fn get_stats(Extension(state): Extension<Arc<RouterState>>) -> axum::response::Response {
+    let now = Utc::now().naive_utc();
+
+    let stats = async {
+        let counts = get_covenant_status_counts(state.db.clone()).await?;
+        let oldest_pending = get_oldest_pending_covenant_time(state.db.clone()).await?;
+        let claims = count_claims_since(state.db.clone(), now - TimeDelta::hours(24)).await?;
+        let block_height = get_block_height(state.db.clone()).await?;
+
+        Ok::<_, crate::db::Error>((counts, oldest_pending, claims, block_height))
+    }
+    .await;
+
+    let (counts, oldest_pending, claims, block_height) = match stats {
+        Ok(res) => res,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: err.to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    // Statuses without covenants are included with a count of 0
+    let mut covenants: BTreeMap<&'static str, i64> = [
+        PendingCovenantStatus::Pending,
+        PendingCovenantStatus::TransactionFound,
+        PendingCovenantStatus::Claimed,
+        PendingCovenantStatus::Unclaimable,
+    ]
+    .into_iter()
+    .map(|status| (PendingCovenantStatus::name(status.to_int()), 0))
+    .collect();
+    for (status, count) in counts {
+        *covenants
+            .entry(PendingCovenantStatus::name(status))
+            .or_default() += count;
+    }
+
+    let block_hash = match block_height {
+        Some(height) => match state.chain_backend.get_block_hash(height).await {
+            Ok(hash) => Some(hash),
+            Err(err) => {
+                debug!("Could not get hash of block {}: {}", height, err);
+                None
+            }
         },
-        None => Ok(None),
+        None => None,
     };
 
-    if blinding_key.is_err() {
+    (
+        StatusCode::OK,
+        Json(StatsResponse {
+            covenants,
+            oldest_pending_age: oldest_pending.map(|time| (now - time).num_seconds().max(0)),
+            block_height,
+            block_hash,
+            claims_last_24h: claims,
+        }),
+    )
+        .into_response()
+}
+
+#[derive(Serialize)]
+struct ReadyResponse {
+    pub database: bool,
+}
+
+pub async fn get_version(Extension(state): Extension<Arc<RouterState>>) -> impl IntoResponse {
+    (StatusCode::OK, Json(state.version.clone()))
+}
+
+pub async fn get_ready(Extension(state): Extension<Arc<RouterState>>) -> impl IntoResponse {
+    let database = state.health.is_healthy();
+
+    (
+        if database {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(ReadyResponse { database }),
+    )
+}
+
+pub async fn get_metrics(Extension(state): Extension<Arc<RouterState>>) -> impl IntoResponse {
+    state.metrics.render()
+}
+
+#[derive(Serialize)]
+struct CovenantImportResponse {
+    pub imported: usize,
+}
+
+pub async fn get_covenants_export(
+    Extension(state): Extension<Arc<RouterState>>,
+) -> axum::response::Response {
+    match export_covenants(state.db.clone()).await {
+        Ok(covenants) => (StatusCode::OK, Json(covenants)).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn post_covenants_import(
+    Extension(state): Extension<Arc<RouterState>>,
+    Json(body): Json<Vec<ExportedCovenant>>,
+) -> axum::response::Response {
+    match import(
+        state.db.clone(),
+        state.address_params,
+        &state.allowed_destinations,
+        state.lockup_confirmations,
+        body,
+    )
+    .await
+    {
+        Ok(imported) => (StatusCode::OK, Json(CovenantImportResponse { imported })).into_response(),
+        Err(err) => (
+            match err {
+                ImportError::DestinationNotAllowed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                ImportError::InvalidCovenant(_, _) => StatusCode::BAD_REQUEST,
+                ImportError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn post_covenant_claim(
+    Extension(state): Extension<Arc<RouterState>>,
+    Json(body): Json<CovenantClaimRequest>,
+) -> impl IntoResponse {
+    let covenant = match parse_covenant(state.address_params, body) {
+        Ok(res) => res,
+        Err(err) => return CovenantClaimResponse::Error(err),
+    };
+    if !state
+        .allowed_destinations
+        .is_allowed(&Script::from(covenant.address.clone()))
+    {
+        return CovenantClaimResponse::DestinationNotAllowed(destination_not_allowed());
+    }
+    // Without it, any lockup with enough confirmations would reveal the preimage
+    if state.lockup_confirmations > 0 && covenant.expected_lockup_amount.is_none() {
         return CovenantClaimResponse::Error(ErrorResponse {
-            error: format!(
-                "could not parse blinding key: {}",
-                blinding_key.err().unwrap()
-            ),
+            error: "expectedLockupAmount is required when lockups need confirmations".to_string(),
+        });
+    }
+
+    // Clients retry registrations, so resubmitting the same covenant is not an error
+    match get_covenant_by_output_script(state.db.clone(), covenant.output_script.clone()).await {
+        Ok(None) => {}
+        Ok(Some(existing)) => {
+            return if is_same_submission(&existing, &covenant) {
+                CovenantClaimResponse::Unchanged(EmptyResponse {})
+            } else {
+                CovenantClaimResponse::Conflict(ConflictResponse {
+                    error: "covenant exists already".to_string(),
+                    status: PendingCovenantStatus::name(existing.status),
+                })
+            };
+        }
+        Err(err) => {
+            return CovenantClaimResponse::Error(ErrorResponse {
+                error: err.to_string(),
+            })
+        }
+    }
+
+    if let Some(swap_id) = &covenant.swap_id {
+        match get_covenant_by_swap_id(state.db.clone(), swap_id).await {
+            Ok(None) => {}
+            Ok(Some(existing)) => {
+                return CovenantClaimResponse::Conflict(ConflictResponse {
+                    error: format!("covenant for swap {} exists already", swap_id),
+                    status: PendingCovenantStatus::name(existing.status),
+                })
+            }
+            Err(err) => {
+                return CovenantClaimResponse::Error(ErrorResponse {
+                    error: err.to_string(),
+                })
+            }
+        }
+    }
+
+    match insert_covenant(state.db.clone(), covenant.clone()).await {
+        Ok(_) => {
+            debug!(
+                "Inserted new covenant to claim for swap: {}",
+                covenant.swap_id.clone().unwrap_or_default()
+            );
+            notifier::send(
+                state.notifier.clone(),
+                Notification::new(
+                    &covenant,
+                    NotificationKind::CovenantRegistered,
+                    PendingCovenantStatus::Pending,
+                    "covenant registered".to_string(),
+                    None,
+                ),
+            );
+            if let Some(verifier) = &state.verifier {
+                verifier.verify_registered(covenant);
+            }
+            CovenantClaimResponse::Success(EmptyResponse {})
+        }
+        Err(e) => CovenantClaimResponse::Error(ErrorResponse {
+            error: e.to_string(),
+        }),
+    }
+}
+
+fn destination_not_allowed() -> ErrorResponse {
+    ErrorResponse {
+        error: "address is not an allowed claim destination".to_string(),
+    }
+}
+
+// The output script commits to the tree and keys already
+fn is_same_submission(existing: &PendingCovenant, covenant: &PendingCovenant) -> bool {
+    existing.preimage == covenant.preimage
+        && existing.address == covenant.address
+        && existing.blinding_key == covenant.blinding_key
+        && existing.swap_id == covenant.swap_id
+        && existing.fee_rate == covenant.fee_rate
+        && existing.max_fee == covenant.max_fee
+        && existing.op_return_data == covenant.op_return_data
+        && existing.expected_lockup_amount == covenant.expected_lockup_amount
+}
+
+#[derive(Deserialize)]
+pub struct ClaimTransactionRequest {
+    #[serde(flatten)]
+    pub covenant: CovenantClaimRequest,
+
+    #[serde(rename = "lockupTransaction")]
+    pub lockup_transaction: String,
+}
+
+#[derive(Serialize)]
+struct ClaimTransactionResponse {
+    pub transaction: String,
+    pub fee: u64,
+}
+
+/// Constructs the claim without storing the covenant or broadcasting the transaction
+pub async fn post_claim_transaction(
+    Extension(state): Extension<Arc<RouterState>>,
+    Json(body): Json<ClaimTransactionRequest>,
+) -> axum::response::Response {
+    let covenant = match parse_covenant(state.address_params, body.covenant) {
+        Ok(res) => res,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(err)).into_response(),
+    };
+
+    let lockup_tx = match hex::decode(body.lockup_transaction)
+        .map_err(|err| err.to_string())
+        .and_then(|raw| {
+            elements::encode::deserialize::<Transaction>(&raw).map_err(|err| err.to_string())
+        }) {
+        Ok(res) => res,
+        Err(err) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: format!("could not parse lockupTransaction: {}", err),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    match construct_claim(
+        &covenant,
+        &lockup_tx,
+        state.address_params,
+        state.policy_asset,
+        state.op_return_data.as_deref(),
+    ) {
+        Ok((tx, fee)) => (
+            StatusCode::OK,
+            Json(ClaimTransactionResponse {
+                transaction: hex::encode(elements::encode::serialize(&tx)),
+                fee,
+            }),
+        )
+            .into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+// Validates the request and derives the output script of the covenant
+fn parse_covenant(
+    address_params: &'static AddressParams,
+    body: CovenantClaimRequest,
+) -> Result<PendingCovenant, ErrorResponse> {
+    check_request_limits(&body)?;
+
+    let address = parse_address(address_params, body.address)?;
+    let blinding_key = parse_blinding_key(&body.blinding_key)?;
+    let op_return_data = parse_op_return_data(&body.op_return_data)?;
+    if address.is_blinded() && blinding_key.is_none() {
+        return Err(ErrorResponse {
+            error: "blinding key is required for confidential addresses".to_string(),
         });
     }
 
     let covenant_details = match body.tree.clone().covenant_details() {
         Ok(res) => res,
         Err(err) => {
-            return CovenantClaimResponse::Error(ErrorResponse {
+            return Err(ErrorResponse {
                 error: format!("could not parse swap tree: {}", err),
             })
         }
     };
 
     let aggregate = MusigKeyAggCache::new(
-        &SwapTree::secp(),
+        SwapTree::secp(),
         &[
             match PublicKey::from_slice(body.refund_public_key.as_ref()) {
                 Ok(res) => res,
                 Err(err) => {
-                    return CovenantClaimResponse::Error(ErrorResponse {
+                    return Err(ErrorResponse {
                         error: format!("could not parse refundPublicKey: {}", err),
                     })
                 }
@@ -116,7 +798,7 @@ pub async fn post_covenant_claim(
             match PublicKey::from_slice(body.claim_public_key.as_ref()) {
                 Ok(res) => res,
                 Err(err) => {
-                    return CovenantClaimResponse::Error(ErrorResponse {
+                    return Err(ErrorResponse {
                         error: format!("could not parse claimPublicKey: {}", err),
                     })
                 }
@@ -124,44 +806,432 @@ pub async fn post_covenant_claim(
         ],
     );
     let internal_key = Vec::from(aggregate.agg_pk().serialize());
+    let output_script = match body
+        .tree
+        .clone()
+        .address(internal_key.clone(), address_params)
+    {
+        Ok(res) => res.script_pubkey(),
+        Err(err) => {
+            return Err(ErrorResponse {
+                error: format!("could not parse swap tree: {}", err),
+            })
+        }
+    };
 
-    let preimage_hash: hashes::hash160::Hash = Hash::hash(body.preimage.clone().as_ref());
-    if covenant_details.preimage_hash != *preimage_hash.as_byte_array() {
-        return CovenantClaimResponse::Error(ErrorResponse {
+    if !covenant_details.is_preimage_valid(body.preimage.as_ref()) {
+        return Err(ErrorResponse {
             error: "invalid preimage".to_string(),
         });
     }
 
-    match insert_covenant(
-        state.db.clone(),
-        PendingCovenant {
-            preimage: body.preimage,
-            blinding_key: blinding_key.unwrap(),
-            swap_tree: json!(body.tree).to_string(),
-            internal_key: internal_key.clone(),
-            status: PendingCovenantStatus::Pending.to_int(),
-            address: elements::pset::serialize::Serialize::serialize(&address.script_pubkey()),
-            output_script: elements::pset::serialize::Serialize::serialize(
-                &body
-                    .tree
-                    .clone()
-                    .address(internal_key, state.address_params)
-                    .script_pubkey(),
+    // Claims to any other address would be rejected by the covenant
+    if !covenant_details.is_expected_output(&address.script_pubkey()) {
+        return Err(ErrorResponse {
+            error: "address does not match the output of the covenant".to_string(),
+        });
+    }
+
+    // Nodes would never relay the claim
+    let dust_threshold = dust_threshold(&address.script_pubkey());
+    if covenant_details.expected_amount < dust_threshold {
+        return Err(ErrorResponse {
+            error: format!(
+                "amount of the covenant is below the dust threshold of {} sats",
+                dust_threshold
             ),
-            tx_id: None,
-            tx_time: None,
-        },
-    ) {
+        });
+    }
+
+    Ok(PendingCovenant {
+        preimage: body.preimage.into(),
+        blinding_key,
+        swap_tree: json!(body.tree).to_string(),
+        internal_key,
+        status: PendingCovenantStatus::Pending.to_int(),
+        address: elements::pset::serialize::Serialize::serialize(&address.script_pubkey()),
+        output_script: elements::pset::serialize::Serialize::serialize(&output_script),
+        tx_id: None,
+        tx_time: None,
+        swap_id: body.swap_id,
+        lease_until: None,
+        fee_rate: body.fee_rate,
+        max_fee: body.max_fee.map(|fee| fee as i64),
+        op_return_data,
+        expected_lockup_amount: body.expected_lockup_amount.map(|amount| amount as i64),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct CovenantAddressUpdateRequest {
+    pub address: String,
+
+    // The blinding key of the covenant is kept when not set
+    #[serde(rename = "blindingKey")]
+    pub blinding_key: Option<Zeroizing<String>>,
+}
+
+pub async fn patch_covenant_address(
+    Extension(state): Extension<Arc<RouterState>>,
+    Path(swap_id): Path<String>,
+    Json(body): Json<CovenantAddressUpdateRequest>,
+) -> axum::response::Response {
+    let covenant = match get_covenant_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: format!("no covenant for swap {}", swap_id),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: err.to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let lockup_found = (
+        StatusCode::CONFLICT,
+        Json(ConflictResponse {
+            error: "lockup transaction of covenant was found already".to_string(),
+            status: PendingCovenantStatus::name(covenant.status),
+        }),
+    );
+    if covenant.status != PendingCovenantStatus::Pending.to_int() {
+        return lockup_found.into_response();
+    }
+
+    let address = match parse_address(state.address_params, body.address) {
+        Ok(addr) => addr,
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(err)).into_response(),
+    };
+    let blinding_key = match parse_blinding_key(&body.blinding_key) {
+        Ok(Some(res)) => Some(res),
+        Ok(None) => covenant.blinding_key.clone(),
+        Err(err) => return (StatusCode::BAD_REQUEST, Json(err)).into_response(),
+    };
+    if address.is_blinded() && blinding_key.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "blinding key is required for confidential addresses".to_string(),
+            }),
+        )
+            .into_response();
+    }
+
+    let covenant_details = match serde_json::from_str::<SwapTree>(&covenant.swap_tree)
+        .map_err(|err| err.to_string())
+        .and_then(|tree| tree.covenant_details().map_err(|err| err.to_string()))
+    {
+        Ok(res) => res,
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("could not parse swap tree: {}", err),
+                }),
+            )
+                .into_response()
+        }
+    };
+    if !covenant_details.is_expected_output(&address.script_pubkey()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "address does not match the output of the covenant".to_string(),
+            }),
+        )
+            .into_response();
+    }
+    if !state
+        .allowed_destinations
+        .is_allowed(&address.script_pubkey())
+    {
+        return (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(destination_not_allowed()),
+        )
+            .into_response();
+    }
+
+    match update_covenant_address(
+        state.db.clone(),
+        covenant.output_script,
+        elements::pset::serialize::Serialize::serialize(&address.script_pubkey()),
+        blinding_key,
+    )
+    .await
+    {
+        Ok(0) => lockup_found.into_response(),
         Ok(_) => {
-            debug!("Inserted new covenant to claim");
-            CovenantClaimResponse::Success(EmptyResponse {})
+            debug!("Updated address of covenant for swap: {}", swap_id);
+            (StatusCode::OK, Json(EmptyResponse {})).into_response()
         }
-        Err(e) => CovenantClaimResponse::Error(ErrorResponse {
-            error: e.to_string(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: err.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CovenantLockupRequest {
+    // Either the raw lockup transaction or its id to fetch it from the chain backend
+    pub transaction: Option<String>,
+    #[serde(rename = "txId")]
+    pub tx_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CovenantLockupResponse {
+    #[serde(rename = "txId")]
+    pub tx_id: String,
+    pub vout: usize,
+}
+
+// Transactions the chain backend does not know are broadcast, so that only lockups
+// that are in the mempool or a block are claimed
+async fn get_pushed_lockup(state: &RouterState, tx_hex: String) -> Result<Transaction, String> {
+    let lockup_tx = hex::decode(&tx_hex)
+        .map_err(|err| err.to_string())
+        .and_then(|raw| {
+            elements::encode::deserialize::<Transaction>(&raw).map_err(|err| err.to_string())
+        })
+        .map_err(|err| format!("could not parse transaction: {}", err))?;
+
+    if let Ok(tx) = state
+        .chain_backend
+        .get_transaction(lockup_tx.txid().to_string())
+        .await
+    {
+        return Ok(tx);
+    }
+
+    match state.chain_backend.broadcast_transaction(tx_hex).await {
+        Ok(_) => Ok(lockup_tx),
+        Err(err) => Err(format!("could not broadcast transaction: {}", err)),
+    }
+}
+
+/// Schedules the claim of a lockup transaction that was not found by the chain backend yet
+pub async fn post_covenant_lockup(
+    Extension(state): Extension<Arc<RouterState>>,
+    Path(swap_id): Path<String>,
+    Json(body): Json<CovenantLockupRequest>,
+) -> axum::response::Response {
+    let covenant = match get_covenant_by_swap_id(state.db.clone(), &swap_id).await {
+        Ok(Some(res)) => res,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: format!("no covenant for swap {}", swap_id),
+                }),
+            )
+                .into_response()
+        }
+        Err(err) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: err.to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    if covenant.status != PendingCovenantStatus::Pending.to_int() {
+        return (
+            StatusCode::CONFLICT,
+            Json(ConflictResponse {
+                error: "lockup transaction of covenant was found already".to_string(),
+                status: PendingCovenantStatus::name(covenant.status),
+            }),
+        )
+            .into_response();
+    }
+
+    let lockup_tx = match (body.transaction, body.tx_id) {
+        (Some(tx_hex), None) => get_pushed_lockup(&state, tx_hex).await,
+        (None, Some(tx_id)) => state
+            .chain_backend
+            .get_transaction(tx_id)
+            .await
+            .map_err(|err| format!("could not fetch transaction: {}", err)),
+        _ => Err("either transaction or txId has to be set".to_string()),
+    };
+    let lockup_tx = match lockup_tx {
+        Ok(res) => res,
+        Err(err) => {
+            return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: err })).into_response()
+        }
+    };
+
+    let vout = match lockup_tx
+        .output
+        .iter()
+        .position(|out| out.script_pubkey.as_bytes() == covenant.output_script.as_slice())
+    {
+        Some(res) => res,
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "transaction does not pay to covenant".to_string(),
+                }),
+            )
+                .into_response()
+        }
+    };
+
+    let tx_id = lockup_tx.txid().to_string();
+    if let Err(err) = state.lockup_sender.send(lockup_tx).await {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: err }),
+        )
+            .into_response();
+    }
+
+    debug!(
+        "Received lockup transaction {} for swap: {}",
+        tx_id, swap_id
+    );
+    (
+        StatusCode::ACCEPTED,
+        Json(CovenantLockupResponse { tx_id, vout }),
+    )
+        .into_response()
+}
+
+fn parse_op_return_data(data: &Option<String>) -> Result<Option<Vec<u8>>, ErrorResponse> {
+    let data = match data {
+        Some(res) => match hex::decode(res) {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(ErrorResponse {
+                    error: format!("could not parse opReturnData: {}", err),
+                })
+            }
+        },
+        None => return Ok(None),
+    };
+
+    if data.len() > MAX_OP_RETURN_DATA_LENGTH {
+        return Err(ErrorResponse {
+            error: format!(
+                "opReturnData is longer than {} bytes",
+                MAX_OP_RETURN_DATA_LENGTH
+            ),
+        });
+    }
+
+    Ok(Some(data))
+}
+
+fn parse_blinding_key(
+    blinding_key: &Option<Zeroizing<String>>,
+) -> Result<Option<Secret>, ErrorResponse> {
+    let blinding_key = match blinding_key {
+        Some(res) => res,
+        None => return Ok(None),
+    };
+
+    let res: Result<Secret, String> = match hex::decode(blinding_key.as_bytes()) {
+        Ok(res) => {
+            let res = Secret::from(res);
+            match SecretKey::from_slice(&res) {
+                Ok(_) => Ok(res),
+                Err(err) => Err(err.to_string()),
+            }
+        }
+        Err(err) => Err(err.to_string()),
+    };
+
+    match res {
+        Ok(res) => Ok(Some(res)),
+        Err(err) => Err(ErrorResponse {
+            error: format!("could not parse blinding key: {}", err),
         }),
     }
 }
 
+fn check_request_limits(body: &CovenantClaimRequest) -> Result<(), ErrorResponse> {
+    if body.preimage.len() > MAX_PREIMAGE_LENGTH {
+        return Err(ErrorResponse {
+            error: format!("preimage is longer than {} bytes", MAX_PREIMAGE_LENGTH),
+        });
+    }
+
+    if let Some(fee_rate) = body.fee_rate {
+        if !fee_rate.is_finite() || fee_rate <= 0.0 {
+            return Err(ErrorResponse {
+                error: "feeRate has to be a positive number".to_string(),
+            });
+        }
+    }
+    if let Some(max_fee) = body.max_fee {
+        if max_fee > i64::MAX as u64 {
+            return Err(ErrorResponse {
+                error: "maxFee is too big".to_string(),
+            });
+        }
+    }
+    if let Some(amount) = body.expected_lockup_amount {
+        if amount > i64::MAX as u64 {
+            return Err(ErrorResponse {
+                error: "expectedLockupAmount is too big".to_string(),
+            });
+        }
+    }
+
+    for (name, leaf) in [
+        ("claimLeaf", &body.tree.claim_leaf),
+        ("refundLeaf", &body.tree.refund_leaf),
+        ("covenantClaimLeaf", &body.tree.covenant_claim_leaf),
+    ] {
+        if leaf.output.len() > MAX_LEAF_SCRIPT_LENGTH {
+            return Err(ErrorResponse {
+                error: format!("{} is longer than {} bytes", name, MAX_LEAF_SCRIPT_LENGTH),
+            });
+        }
+    }
+
+    if let Some(leaves) = &body.tree.leaves {
+        if leaves.len() > MAX_TREE_LEAVES {
+            return Err(ErrorResponse {
+                error: format!("tree has more than {} leaves", MAX_TREE_LEAVES),
+            });
+        }
+        if let Some(index) = leaves
+            .iter()
+            .position(|leaf| leaf.output.len() > MAX_LEAF_SCRIPT_LENGTH)
+        {
+            return Err(ErrorResponse {
+                error: format!(
+                    "leaf {} is longer than {} bytes",
+                    index, MAX_LEAF_SCRIPT_LENGTH
+                ),
+            });
+        }
+    }
+
+    Ok(())
+}
+
 fn parse_address(
     params: &'static AddressParams,
     address: String,
@@ -228,3 +1298,134 @@ mod parse_address_test {
         assert_eq!(res.err().unwrap().error, "address has invalid network");
     }
 }
+
+#[cfg(test)]
+mod fee_preview_test {
+    use elements::confidential::{Asset, Nonce, Value};
+    use elements::{AssetId, Script, Transaction, TxOut, TxOutWitness};
+
+    use crate::api::routes::preview_outputs;
+    use crate::db::models::{PendingCovenant, PendingCovenantStatus};
+
+    #[test]
+    fn test_preview_outputs() {
+        let address = hex::decode("0014aff4f5af812e3db39024f2000db7e23091dc0603").unwrap();
+        let asset = AssetId::from_slice(&[1; 32]).unwrap();
+
+        let covenant = PendingCovenant {
+            output_script: vec![],
+            status: PendingCovenantStatus::TransactionFound.to_int(),
+            internal_key: vec![],
+            preimage: vec![].into(),
+            swap_tree: "".to_string(),
+            address: address.clone(),
+            blinding_key: None,
+            tx_id: None,
+            tx_time: None,
+            swap_id: None,
+            lease_until: None,
+            fee_rate: None,
+            max_fee: None,
+            op_return_data: None,
+            expected_lockup_amount: None,
+        };
+        let tx = Transaction {
+            version: 2,
+            lock_time: elements::LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    asset: Asset::Explicit(asset),
+                    value: Value::Explicit(99_000),
+                    nonce: Nonce::Null,
+                    script_pubkey: Script::from(address),
+                    witness: TxOutWitness::default(),
+                },
+                TxOut::new_fee(1_000, asset),
+            ],
+        };
+
+        let outputs = preview_outputs(&covenant, &tx);
+        assert_eq!(
+            outputs
+                .iter()
+                .map(|out| (out.kind, out.amount))
+                .collect::<Vec<_>>(),
+            vec![("claim", 99_000), ("fee", 1_000)]
+        );
+    }
+}
+
+#[cfg(test)]
+mod request_limits_test {
+    use crate::api::routes::{check_request_limits, CovenantClaimRequest};
+    use crate::claimer::tree::{SwapTree, TreeLeaf, TreeScript};
+
+    fn request(preimage_length: usize, leaf_length: usize) -> CovenantClaimRequest {
+        let leaf = TreeScript {
+            output: vec![0; leaf_length],
+        };
+
+        CovenantClaimRequest {
+            claim_public_key: vec![],
+            refund_public_key: vec![],
+            preimage: vec![0; preimage_length],
+            blinding_key: None,
+            address: "".to_string(),
+            tree: SwapTree {
+                claim_leaf: leaf.clone(),
+                refund_leaf: leaf.clone(),
+                covenant_claim_leaf: leaf,
+                leaves: None,
+            },
+            swap_id: None,
+            fee_rate: None,
+            max_fee: None,
+            op_return_data: None,
+            expected_lockup_amount: None,
+        }
+    }
+
+    #[test]
+    fn test_check_request_limits() {
+        assert!(check_request_limits(&request(32, 1024)).is_ok());
+        assert_eq!(
+            check_request_limits(&request(33, 100)).err().unwrap().error,
+            "preimage is longer than 32 bytes"
+        );
+        assert_eq!(
+            check_request_limits(&request(32, 1025))
+                .err()
+                .unwrap()
+                .error,
+            "claimLeaf is longer than 1024 bytes"
+        );
+
+        let mut invalid_fee_rate = request(32, 100);
+        invalid_fee_rate.fee_rate = Some(0.0);
+        assert_eq!(
+            check_request_limits(&invalid_fee_rate).err().unwrap().error,
+            "feeRate has to be a positive number"
+        );
+
+        let leaf = |length: usize| TreeLeaf {
+            depth: 4,
+            output: vec![0; length],
+        };
+        let mut leaves = request(32, 100);
+        leaves.tree.leaves = Some((0..16).map(|_| leaf(1024)).collect());
+        assert!(check_request_limits(&leaves).is_ok());
+
+        leaves.tree.leaves = Some(vec![leaf(100), leaf(1025)]);
+        assert_eq!(
+            check_request_limits(&leaves).err().unwrap().error,
+            "leaf 1 is longer than 1024 bytes"
+        );
+
+        leaves.tree.leaves = Some((0..17).map(|_| leaf(100)).collect());
+        assert_eq!(
+            check_request_limits(&leaves).err().unwrap().error,
+            "tree has more than 16 leaves"
+        );
+    }
+}
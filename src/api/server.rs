@@ -1,26 +1,103 @@
 use std::io::Error;
 use std::sync::Arc;
 
-use axum::routing::post;
-use axum::{Extension, Router};
-use elements::AddressParams;
+use axum::extract::{DefaultBodyLimit, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, patch, post};
+use axum::{Extension, Json, Router};
+use elements::hashes::{sha256, Hash};
+use serde_json::json;
 use tower_http::cors::CorsLayer;
+use tower_http::trace::TraceLayer;
+use tracing::{info, info_span, Instrument};
 
 use crate::api;
 use crate::api::types::RouterState;
-use crate::db::Pool;
+use crate::utils::request_id;
+use crate::utils::request_id::REQUEST_ID_HEADER;
+use crate::utils::systemd;
 
+/// In read-only mode, routes that change covenants or expose their secrets are rejected,
+/// so that a public status instance can share the database of the claiming one. Exporting
+/// and importing covenants requires the admin token and is not available without one
 pub async fn start_server(
-    db: Pool,
-    address_params: &'static AddressParams,
+    state: RouterState,
     host: &str,
     port: u32,
+    read_only: bool,
+    admin_token: Option<String>,
 ) -> Result<Result<(), Error>, Error> {
-    let shared_state = Arc::new(RouterState { db, address_params });
+    let shared_state = Arc::new(state);
+
+    let mut mutating = Router::new()
+        .route(
+            "/covenant",
+            post(api::routes::post_covenant_claim).layer(DefaultBodyLimit::max(
+                api::routes::MAX_COVENANT_REQUEST_SIZE,
+            )),
+        )
+        .route(
+            "/covenant/:swap_id",
+            patch(api::routes::patch_covenant_address),
+        )
+        .route(
+            "/covenant/:swap_id/lockup",
+            post(api::routes::post_covenant_lockup),
+        );
+    match admin_token {
+        Some(token) => {
+            mutating = mutating.merge(
+                Router::new()
+                    .route("/covenants/export", get(api::routes::get_covenants_export))
+                    .route(
+                        "/covenants/import",
+                        post(api::routes::post_covenants_import),
+                    )
+                    .route_layer(middleware::from_fn_with_state(
+                        Arc::new(sha256::Hash::hash(token.as_bytes())),
+                        require_admin_token,
+                    )),
+            );
+        }
+        None => info!("Not exposing export and import of covenants without an admin token"),
+    };
+    if read_only {
+        info!("API is read-only");
+        mutating = mutating.route_layer(middleware::from_fn(reject_read_only));
+    }
 
     let app = Router::new()
-        .route("/covenant", post(api::routes::post_covenant_claim))
+        .route("/ready", get(api::routes::get_ready))
+        .route("/version", get(api::routes::get_version))
+        .route("/metrics", get(api::routes::get_metrics))
+        .route("/stats", get(api::routes::get_stats))
+        .route(
+            "/covenant/claim-tx",
+            post(api::routes::post_claim_transaction),
+        )
+        .route(
+            "/covenant/:swap_id/status",
+            get(api::routes::get_covenant_status),
+        )
+        .route(
+            "/covenant/:swap_id/timing",
+            get(api::routes::get_covenant_claim_timing),
+        )
+        .route(
+            "/covenant/:swap_id/events",
+            get(api::routes::get_covenant_claim_events),
+        )
+        .route(
+            "/covenant/:swap_id/fee-preview",
+            get(api::routes::get_covenant_fee_preview),
+        )
+        .merge(mutating)
         .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(request_id))
         .layer(Extension(shared_state));
 
     let listener = tokio::net::TcpListener::bind(format!("{}:{}", host, port)).await;
@@ -28,5 +105,67 @@ pub async fn start_server(
         return Err(listener.err().unwrap());
     }
 
+    // The database and chain backend are connected before the API is started
+    systemd::notify_ready();
+
     Ok(axum::serve(listener.unwrap(), app.clone()).await)
 }
+
+async fn reject_read_only(_req: Request, _next: Next) -> Response {
+    (
+        StatusCode::FORBIDDEN,
+        Json(json!({
+            "error": "API is read-only",
+        })),
+    )
+        .into_response()
+}
+
+// Hashes are compared so that the time of the comparison does not reveal the token
+async fn require_admin_token(
+    State(token_hash): State<Arc<sha256::Hash>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let authorized = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| sha256::Hash::hash(token.as_bytes()) == *token_hash);
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({
+                "error": "admin token required",
+            })),
+        )
+            .into_response();
+    }
+
+    next.run(req).await
+}
+
+// Correlates the logs, covenant events and Boltz requests caused by an API call
+async fn request_id(req: Request, next: Next) -> Response {
+    let id = request_id::parse_or_generate(
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let span = info_span!(
+        "api_request",
+        request_id = %id,
+        method = %req.method(),
+        path = %req.uri().path(),
+    );
+
+    let mut res = request_id::scope(Some(id.clone()), next.run(req))
+        .instrument(span)
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    res
+}
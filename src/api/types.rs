@@ -1,7 +1,49 @@
+use crate::chain::fee::FeeEstimator;
+use crate::chain::types::ChainBackend;
+use crate::db::health::HealthCheck;
 use crate::db::Pool;
-use elements::AddressParams;
+use crate::utils::channel::Sender;
+use elements::{AddressParams, AssetId, Transaction};
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::api::destinations::AllowedDestinations;
+use crate::boltz::verifier::Verifier;
+use crate::notifier::Notifier;
+
+/// Build and configuration of the running instance
+#[derive(Serialize, Clone)]
+pub struct VersionInfo {
+    pub version: String,
+    #[serde(rename = "gitCommit")]
+    pub git_commit: Option<String>,
+    // Whether the working tree had uncommitted changes when building
+    pub dirty: Option<bool>,
+    pub rustc: String,
+    pub profile: String,
+    pub network: String,
+    #[serde(rename = "chainBackend")]
+    pub chain_backend: String,
+}
 
 pub struct RouterState {
     pub db: Pool,
     pub address_params: &'static AddressParams,
+    pub chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    pub fee_estimator: FeeEstimator,
+    pub policy_asset: Option<AssetId>,
+    // Covenants paying to other addresses are rejected at registration
+    pub allowed_destinations: AllowedDestinations,
+    // Claims wait for this many confirmations of the lockup when not 0
+    pub lockup_confirmations: u64,
+    // Embedded in claims of covenants that do not set their own OP_RETURN data
+    pub op_return_data: Option<Vec<u8>>,
+    // Lockup transactions that were pushed via the API
+    pub lockup_sender: Sender<Transaction>,
+    pub health: HealthCheck,
+    pub metrics: PrometheusHandle,
+    pub notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+    pub verifier: Option<Verifier>,
+    pub version: VersionInfo,
 }
@@ -0,0 +1,52 @@
+use thiserror::Error;
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+
+#[derive(Debug, Error)]
+pub enum BoltzError {
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    // Boxed, because it is much larger than all other variants
+    #[error("{0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    /// Error message in a response of the API
+    #[error("{0}")]
+    Api(String),
+    /// Error message in a response of the API for something that does not exist
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for BoltzError {
+    fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+        BoltzError::WebSocket(Box::new(value))
+    }
+}
+
+impl From<String> for BoltzError {
+    fn from(value: String) -> Self {
+        BoltzError::Other(value)
+    }
+}
+
+impl From<&str> for BoltzError {
+    fn from(value: &str) -> Self {
+        BoltzError::Other(value.to_string())
+    }
+}
+
+// Boltz relays the errors of its node when it cannot broadcast a transaction
+impl From<BoltzError> for TransactionBroadcastError {
+    fn from(value: BoltzError) -> Self {
+        match value {
+            BoltzError::Api(message) => ChainError::rpc(None, message).into(),
+            BoltzError::Http(err) => ChainError::Http(err).into(),
+            BoltzError::Json(err) => ChainError::Json(err).into(),
+            err => ChainError::Other(err.to_string()).into(),
+        }
+    }
+}
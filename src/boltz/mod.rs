@@ -1,2 +1,5 @@
 pub mod api;
+pub mod error;
 mod types;
+pub mod verifier;
+pub mod ws;
@@ -9,3 +9,32 @@ pub struct ErrorResponse {
 pub struct TransactionPostResponse {
     pub id: String,
 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwapStatusResponse {
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReverseSwapTransactionResponse {
+    pub hex: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WsMessage {
+    pub event: String,
+    pub channel: Option<String>,
+    pub args: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwapUpdate {
+    pub id: String,
+    pub status: String,
+    pub transaction: Option<SwapUpdateTransaction>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwapUpdateTransaction {
+    pub hex: Option<String>,
+}
@@ -0,0 +1,336 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use elements::confidential::Value;
+use elements::Transaction;
+use tokio::time;
+use tracing::{debug, info, trace, warn};
+
+use crate::boltz::api::Client;
+use crate::boltz::error::BoltzError;
+use crate::claimer::tree::SwapTree;
+use crate::db;
+use crate::db::helpers::{get_unclaimed_covenants, insert_covenant_event};
+use crate::db::models::{NewCovenantEvent, PendingCovenant, PendingCovenantStatus};
+use crate::notifier;
+use crate::notifier::{Notification, NotificationKind, Notifier};
+use crate::utils::request_id;
+
+// Statuses of reverse swaps for which Boltz has broadcast the lockup transaction
+const LOCKUP_STATUSES: [&str; 2] = ["transaction.mempool", "transaction.confirmed"];
+
+// Statuses of reverse swaps whose covenants will never be claimable
+const EXPIRED_STATUSES: [&str; 4] = [
+    "swap.expired",
+    "invoice.expired",
+    "transaction.failed",
+    "transaction.refunded",
+];
+
+/// Compares pending covenants with the swaps Boltz reports, so that registrations
+/// that could never be claimed are flagged in the event log
+#[derive(Clone)]
+pub struct Verifier {
+    db: db::Pool,
+    client: Client,
+    interval: u64,
+    notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+
+    // Output scripts of covenants that were flagged already
+    flagged: Arc<Mutex<HashSet<Vec<u8>>>>,
+}
+
+impl Verifier {
+    pub fn new(
+        db: db::Pool,
+        client: Client,
+        interval: u64,
+        notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+    ) -> Verifier {
+        Verifier {
+            db,
+            client,
+            interval,
+            notifier,
+            flagged: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub fn start(&self) {
+        info!(
+            "Verifying covenants with Boltz every {} seconds",
+            self.interval
+        );
+
+        let clone = self.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(Duration::from_secs(clone.interval));
+
+            loop {
+                interval.tick().await;
+                clone.verify_pending().await;
+            }
+        });
+    }
+
+    pub fn verify_registered(&self, covenant: PendingCovenant) {
+        let clone = self.clone();
+        tokio::spawn(request_id::scope(request_id::current(), async move {
+            clone.verify(covenant).await;
+        }));
+    }
+
+    async fn verify_pending(&self) {
+        let covenants = match get_unclaimed_covenants(self.db.clone()).await {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not get covenants to verify: {}", err);
+                return;
+            }
+        };
+
+        let covenants: Vec<PendingCovenant> = covenants
+            .into_iter()
+            .filter(|covenant| {
+                covenant.status == PendingCovenantStatus::Pending.to_int()
+                    && covenant.swap_id.is_some()
+                    && !self
+                        .flagged
+                        .lock()
+                        .unwrap()
+                        .contains(&covenant.output_script)
+            })
+            .collect();
+
+        trace!("Verifying {} covenants", covenants.len());
+        for covenant in covenants {
+            self.verify(covenant).await;
+        }
+    }
+
+    async fn verify(&self, covenant: PendingCovenant) {
+        match self.check(&covenant).await {
+            Ok(None) => {}
+            Ok(Some((kind, mismatch))) => self.flag(covenant, kind, mismatch).await,
+            Err(err) => debug!(
+                "Could not verify covenant {}: {}",
+                hex::encode(&covenant.output_script),
+                err
+            ),
+        };
+    }
+
+    // Returns the mismatch with the swap of Boltz, if there is one
+    async fn check(
+        &self,
+        covenant: &PendingCovenant,
+    ) -> Result<Option<(NotificationKind, String)>, String> {
+        let swap_id = match &covenant.swap_id {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+
+        let status = match self.client.get_swap_status(swap_id).await {
+            Ok(res) => res,
+            Err(BoltzError::NotFound(_)) => {
+                return Ok(Some((
+                    NotificationKind::SwapMismatch,
+                    format!("unknown swap {}", swap_id),
+                )))
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+
+        if EXPIRED_STATUSES.contains(&status.as_str()) {
+            return Ok(Some((
+                NotificationKind::SwapExpired,
+                format!("swap expired with status {}", status),
+            )));
+        }
+
+        if !LOCKUP_STATUSES.contains(&status.as_str()) {
+            return Ok(None);
+        }
+
+        let tx_hex = match self.client.get_reverse_swap_transaction(swap_id).await {
+            Ok(Some(res)) => res,
+            Ok(None) => return Ok(None),
+            Err(err) => return Err(err.to_string()),
+        };
+        let lockup_tx = hex::decode(tx_hex)
+            .map_err(|err| err.to_string())
+            .and_then(|raw| {
+                elements::encode::deserialize::<Transaction>(&raw).map_err(|err| err.to_string())
+            })?;
+
+        Ok(lockup_mismatch(covenant, &lockup_tx)
+            .map(|mismatch| (NotificationKind::SwapMismatch, mismatch)))
+    }
+
+    async fn flag(&self, covenant: PendingCovenant, kind: NotificationKind, mismatch: String) {
+        if !self
+            .flagged
+            .lock()
+            .unwrap()
+            .insert(covenant.output_script.clone())
+        {
+            return;
+        }
+
+        let reason = format!("boltz mismatch: {}", mismatch);
+        warn!(
+            "Covenant {} does not match its swap: {}",
+            hex::encode(&covenant.output_script),
+            mismatch
+        );
+
+        notifier::send(
+            self.notifier.clone(),
+            Notification::new(
+                &covenant,
+                kind,
+                PendingCovenantStatus::Pending,
+                reason.clone(),
+                None,
+            ),
+        );
+
+        if let Err(err) = insert_covenant_event(
+            self.db.clone(),
+            NewCovenantEvent {
+                reason,
+                output_script: covenant.output_script,
+                status: covenant.status,
+                tx_id: None,
+            },
+        )
+        .await
+        {
+            warn!("Could not record covenant mismatch: {}", err);
+        }
+    }
+}
+
+// The value of blinded lockup outputs cannot be compared without unblinding them
+fn lockup_mismatch(covenant: &PendingCovenant, lockup_tx: &Transaction) -> Option<String> {
+    let out = match lockup_tx
+        .output
+        .iter()
+        .find(|out| out.script_pubkey.as_bytes() == covenant.output_script.as_slice())
+    {
+        Some(res) => res,
+        None => return Some("lockup transaction does not pay to covenant".to_string()),
+    };
+
+    let expected_amount = match serde_json::from_str::<SwapTree>(covenant.swap_tree.as_str())
+        .map_err(|err| err.to_string())
+        .and_then(|tree| tree.covenant_details().map_err(|err| err.to_string()))
+    {
+        Ok(res) => res.expected_amount,
+        Err(err) => return Some(format!("could not parse swap tree: {}", err)),
+    };
+
+    match out.value {
+        Value::Explicit(value) if value < expected_amount => Some(format!(
+            "lockup amount {} is less than expected amount {}",
+            value, expected_amount
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod verifier_test {
+    use elements::confidential::{Asset, Nonce, Value};
+    use elements::{LockTime, Script, Transaction, TxOut, TxOutWitness};
+
+    use crate::boltz::verifier::lockup_mismatch;
+    use crate::db::models::{PendingCovenant, PendingCovenantStatus};
+
+    // The covenant claim leaf expects an output of 100,000 sats
+    const TREE_JSON: &str = "{
+            \"claimLeaf\": {
+                \"version\": 196,
+                \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558820812910149e0e71209624487851f80a0cb97652efb0a836205628bc1b0e8e3aa7ac\"
+            },
+            \"refundLeaf\": {
+                \"version\": 196,
+                \"output\": \"201ec7adf6f1c40ad340533027d15952c0c5b7aa0dd6c4b38d838e62d32d4d0259ad020b06b1\"
+            },
+            \"covenantClaimLeaf\": {
+                \"version\": 196,
+                \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558800d1008814aff4f5af812e3db39024f2000db7e23091dc06038800ce51882025b251070e29ca19043cf33ccd7324e2ddab03ecc4ae0b5e77c4fc0e5cf6c95a8800cf7508a08601000000000087\"
+            }
+        }";
+
+    fn covenant() -> PendingCovenant {
+        PendingCovenant {
+            output_script: vec![0x51, 0x20, 0x01],
+            status: PendingCovenantStatus::Pending.to_int(),
+            internal_key: vec![],
+            preimage: vec![].into(),
+            swap_tree: TREE_JSON.to_string(),
+            address: vec![],
+            blinding_key: None,
+            tx_id: None,
+            tx_time: None,
+            swap_id: Some("swap".to_string()),
+            lease_until: None,
+            fee_rate: None,
+            max_fee: None,
+            op_return_data: None,
+            expected_lockup_amount: None,
+        }
+    }
+
+    fn lockup_tx(script: Vec<u8>, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: LockTime::from_consensus(0),
+            input: vec![],
+            output: vec![TxOut {
+                asset: Asset::Null,
+                nonce: Nonce::Null,
+                value: Value::Explicit(value),
+                script_pubkey: Script::from(script),
+                witness: TxOutWitness {
+                    rangeproof: None,
+                    surjection_proof: None,
+                },
+            }],
+        }
+    }
+
+    #[test]
+    fn test_lockup_match() {
+        let covenant = covenant();
+        assert_eq!(
+            lockup_mismatch(
+                &covenant,
+                &lockup_tx(covenant.output_script.clone(), 100_100)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_lockup_mismatch_output_script() {
+        assert_eq!(
+            lockup_mismatch(&covenant(), &lockup_tx(vec![0x51], 100_100)),
+            Some("lockup transaction does not pay to covenant".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lockup_mismatch_amount() {
+        let covenant = covenant();
+        assert_eq!(
+            lockup_mismatch(
+                &covenant,
+                &lockup_tx(covenant.output_script.clone(), 99_000)
+            ),
+            Some("lockup amount 99000 is less than expected amount 100000".to_string())
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use elements::Transaction;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::{task, time};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::boltz::error::BoltzError;
+use crate::boltz::types::{SwapUpdate, WsMessage};
+use crate::chain::types::ChainBackend;
+use crate::db;
+use crate::db::helpers::get_pending_covenant_swap_ids;
+use crate::utils::channel::{bounded, Overflow, Receiver, Sender, TRANSACTION_CHANNEL_SIZE};
+
+const RECONNECT_DELAY: u64 = 5;
+
+const SWAP_UPDATE_CHANNEL: &str = "swap.update";
+
+/// Subscribes to updates of the swaps of pending covenants via the Boltz WebSocket
+/// and forwards the lockup transactions Boltz reports once the chain backend knows them
+#[derive(Clone)]
+pub struct SwapUpdates {
+    ws_endpoint: String,
+    poll_interval: u64,
+
+    db: db::Pool,
+    chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+
+    tx_sender: Sender<Transaction>,
+    tx_receiver: Receiver<Transaction>,
+}
+
+impl SwapUpdates {
+    pub fn new(
+        endpoint: String,
+        poll_interval: u64,
+        db: db::Pool,
+        chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    ) -> Result<SwapUpdates, BoltzError> {
+        let (tx_sender, tx_receiver) =
+            bounded::<Transaction>("boltz_lockups", TRANSACTION_CHANNEL_SIZE, Overflow::Drop);
+
+        Ok(SwapUpdates {
+            db,
+            chain_backend,
+            tx_sender,
+            tx_receiver,
+            poll_interval,
+            ws_endpoint: websocket_endpoint(&crate::utils::string::trim_suffix(endpoint, '/'))?,
+        })
+    }
+
+    pub fn start(&self) {
+        let clone = self.clone();
+        task::spawn(async move {
+            loop {
+                match clone.run_websocket().await {
+                    Ok(_) => warn!("Boltz WebSocket closed"),
+                    Err(err) => error!("Boltz WebSocket failed: {}", err),
+                };
+
+                debug!("Reconnecting to Boltz WebSocket in {}s", RECONNECT_DELAY);
+                time::sleep(Duration::from_secs(RECONNECT_DELAY)).await;
+            }
+        });
+    }
+
+    pub fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.tx_receiver.clone()
+    }
+
+    async fn run_websocket(&self) -> Result<(), BoltzError> {
+        let (stream, _) = connect_async(self.ws_endpoint.as_str()).await?;
+        info!("Connected to Boltz WebSocket: {}", self.ws_endpoint);
+
+        let (mut write, mut read) = stream.split();
+
+        // Subscriptions are per connection
+        let mut subscribed = HashSet::<String>::new();
+        let mut interval = time::interval(Duration::from_secs(self.poll_interval));
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let swap_ids: HashSet<String> = match get_pending_covenant_swap_ids(self.db.clone()).await {
+                        Ok(res) => res.into_iter().collect(),
+                        Err(err) => {
+                            warn!("Could not get swap ids of pending covenants: {}", err);
+                            continue;
+                        }
+                    };
+
+                    let to_subscribe: Vec<&String> = swap_ids.difference(&subscribed).collect();
+                    if !to_subscribe.is_empty() {
+                        trace!("Subscribing to {} swaps", to_subscribe.len());
+                        write
+                            .send(Message::Text(
+                                json!({"op": "subscribe", "channel": SWAP_UPDATE_CHANNEL, "args": to_subscribe})
+                                    .to_string(),
+                            ))
+                            .await?;
+                    }
+
+                    let to_unsubscribe: Vec<&String> = subscribed.difference(&swap_ids).collect();
+                    if !to_unsubscribe.is_empty() {
+                        trace!("Unsubscribing from {} swaps", to_unsubscribe.len());
+                        write
+                            .send(Message::Text(
+                                json!({"op": "unsubscribe", "channel": SWAP_UPDATE_CHANNEL, "args": to_unsubscribe})
+                                    .to_string(),
+                            ))
+                            .await?;
+                    }
+
+                    subscribed = swap_ids;
+                }
+                msg = read.next() => {
+                    match msg {
+                        Some(Ok(Message::Text(text))) => self.handle_message(text).await,
+                        Some(Ok(Message::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_message(&self, text: String) {
+        let message = match serde_json::from_str::<WsMessage>(text.as_str()) {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not parse Boltz WebSocket message: {}", err);
+                return;
+            }
+        };
+
+        if message.event != "update" || message.channel.as_deref() != Some(SWAP_UPDATE_CHANNEL) {
+            trace!("Ignoring Boltz WebSocket message: {}", text);
+            return;
+        }
+
+        for update in message.args.unwrap_or_default() {
+            let update = match serde_json::from_value::<SwapUpdate>(update) {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("Could not parse swap update: {}", err);
+                    continue;
+                }
+            };
+            debug!("Swap {} has status: {}", update.id, update.status);
+
+            let tx = match lockup_transaction(&update) {
+                Some(res) => res,
+                None => continue,
+            };
+
+            // Claiming a forged lockup would reveal the preimage before any funds are locked,
+            // so only transactions the chain backend knows are claimed and none are broadcast
+            let tx = match self
+                .chain_backend
+                .get_transaction(tx.txid().to_string())
+                .await
+            {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!(
+                        "Ignoring lockup transaction {} of swap {} that was not found: {}",
+                        tx.txid(),
+                        update.id,
+                        err
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = self.tx_sender.send(tx).await {
+                warn!("Could not send transaction update: {}", err);
+            }
+        }
+    }
+}
+
+fn lockup_transaction(update: &SwapUpdate) -> Option<Transaction> {
+    let hex = update.transaction.as_ref()?.hex.as_ref()?;
+
+    match hex::decode(hex)
+        .map_err(|err| err.to_string())
+        .and_then(|raw| {
+            elements::encode::deserialize::<Transaction>(&raw).map_err(|err| err.to_string())
+        }) {
+        Ok(tx) => Some(tx),
+        Err(err) => {
+            warn!(
+                "Could not parse lockup transaction of swap {}: {}",
+                update.id, err
+            );
+            None
+        }
+    }
+}
+
+fn websocket_endpoint(endpoint: &str) -> Result<String, BoltzError> {
+    let ws_endpoint = if let Some(rest) = endpoint.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = endpoint.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        return Err(format!("invalid Boltz endpoint: {}", endpoint).into());
+    };
+
+    Ok(format!("{}/ws", ws_endpoint))
+}
+
+#[cfg(test)]
+mod ws_test {
+    use crate::boltz::types::{SwapUpdate, SwapUpdateTransaction};
+    use crate::boltz::ws::{lockup_transaction, websocket_endpoint};
+
+    #[test]
+    fn test_websocket_endpoint() {
+        assert_eq!(
+            websocket_endpoint("https://api.boltz.exchange/v2").unwrap(),
+            "wss://api.boltz.exchange/v2/ws"
+        );
+        assert_eq!(
+            websocket_endpoint("http://127.0.0.1:9001/v2").unwrap(),
+            "ws://127.0.0.1:9001/v2/ws"
+        );
+        assert_eq!(
+            websocket_endpoint("api.boltz.exchange/v2")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid Boltz endpoint: api.boltz.exchange/v2"
+        );
+    }
+
+    #[test]
+    fn test_lockup_transaction_missing() {
+        assert!(lockup_transaction(&SwapUpdate {
+            id: "swap".to_string(),
+            status: "swap.created".to_string(),
+            transaction: None,
+        })
+        .is_none());
+        assert!(lockup_transaction(&SwapUpdate {
+            id: "swap".to_string(),
+            status: "transaction.mempool".to_string(),
+            transaction: Some(SwapUpdateTransaction {
+                hex: Some("invalid".to_string()),
+            }),
+        })
+        .is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use elements::{Block, Transaction};
+use lru::LruCache;
+use tracing::trace;
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::types::{Broadcast, ChainBackend, NetworkInfo};
+use crate::utils::channel::Receiver;
+
+struct TtlCache<V: Clone> {
+    ttl: Duration,
+    entries: LruCache<String, (Instant, V)>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    fn new(size: NonZeroUsize, ttl: Duration) -> TtlCache<V> {
+        TtlCache {
+            ttl,
+            entries: LruCache::new(size),
+        }
+    }
+
+    fn get(&mut self, key: &String) -> Option<V> {
+        let (inserted, value) = self.entries.get(key)?;
+        if inserted.elapsed() > self.ttl {
+            self.entries.pop(key);
+            return None;
+        }
+
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        self.entries.put(key, (Instant::now(), value));
+    }
+}
+
+/// Caches transactions and blocks by their hash, because the constructor fetches
+/// the same lockup transactions on every sweep interval
+#[derive(Clone)]
+pub struct CachedBackend {
+    backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+
+    transactions: Arc<Mutex<TtlCache<Transaction>>>,
+    blocks: Arc<Mutex<TtlCache<Block>>>,
+}
+
+impl CachedBackend {
+    pub fn new(
+        backend: Box<dyn ChainBackend + Send + Sync>,
+        size: NonZeroUsize,
+        ttl_seconds: u64,
+    ) -> CachedBackend {
+        let ttl = Duration::from_secs(ttl_seconds);
+
+        CachedBackend {
+            backend: Arc::new(backend),
+            transactions: Arc::new(Mutex::new(TtlCache::new(size, ttl))),
+            blocks: Arc::new(Mutex::new(TtlCache::new(size, ttl))),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for CachedBackend {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
+        self.backend.get_network_info().await
+    }
+
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
+        self.backend.get_block_count().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
+        self.backend.get_block_hash(height).await
+    }
+
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
+        if let Some(block) = self.blocks.lock().unwrap().get(&hash) {
+            trace!("Using cached block: {}", hash);
+            return Ok(block);
+        }
+
+        let block = self.backend.get_block(hash.clone()).await?;
+        self.blocks.lock().unwrap().insert(hash, block.clone());
+
+        Ok(block)
+    }
+
+    // Blocks of a rescan are not requested again, so batches are not cached
+    async fn get_block_hashes(&self, heights: Vec<u64>) -> Result<Vec<String>, ChainError> {
+        self.backend.get_block_hashes(heights).await
+    }
+
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, ChainError> {
+        self.backend.get_blocks(hashes).await
+    }
+
+    async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
+        self.backend.send_raw_transaction(hex).await
+    }
+
+    async fn broadcast_transaction(
+        &self,
+        hex: String,
+    ) -> Result<Broadcast, TransactionBroadcastError> {
+        self.backend.broadcast_transaction(hex).await
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
+        if let Some(tx) = self.transactions.lock().unwrap().get(&hash) {
+            trace!("Using cached transaction: {}", hash);
+            return Ok(tx);
+        }
+
+        let tx = self.backend.get_transaction(hash.clone()).await?;
+        self.transactions.lock().unwrap().insert(hash, tx.clone());
+
+        Ok(tx)
+    }
+
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        self.backend.get_mempool_transactions().await
+    }
+
+    async fn get_script_transactions(
+        &self,
+        script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        self.backend.get_script_transactions(script).await
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        self.backend.estimate_fee(target_blocks).await
+    }
+
+    fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.backend.get_tx_receiver()
+    }
+
+    fn get_block_receiver(&self) -> Receiver<Block> {
+        self.backend.get_block_receiver()
+    }
+}
+
+#[cfg(test)]
+mod cache_test {
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    use crate::chain::cache::TtlCache;
+
+    #[test]
+    fn test_get() {
+        let mut cache =
+            TtlCache::<u64>::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(60));
+        cache.insert("a".to_string(), 1);
+
+        assert_eq!(cache.get(&"a".to_string()), Some(1));
+        assert_eq!(cache.get(&"b".to_string()), None);
+    }
+
+    #[test]
+    fn test_get_evicted() {
+        let mut cache =
+            TtlCache::<u64>::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(60));
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        cache.insert("c".to_string(), 3);
+
+        assert_eq!(cache.get(&"a".to_string()), None);
+        assert_eq!(cache.get(&"b".to_string()), Some(2));
+        assert_eq!(cache.get(&"c".to_string()), Some(3));
+    }
+
+    #[test]
+    fn test_get_expired() {
+        let mut cache = TtlCache::<u64>::new(NonZeroUsize::new(2).unwrap(), Duration::ZERO);
+        cache.insert("a".to_string(), 1);
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(cache.get(&"a".to_string()), None);
+    }
+}
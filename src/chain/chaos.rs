@@ -0,0 +1,318 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use elements::{Block, Transaction};
+use tokio::time;
+use tracing::{error, warn};
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::types::{Broadcast, ChainBackend, NetworkInfo};
+use crate::utils::channel::{self, Overflow, Receiver, Sender};
+
+/// Faults that are injected; the same seed injects the same faults in the same order
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChaosConfig {
+    pub seed: u64,
+    // Random latency of up to this many milliseconds is added to every call
+    pub max_latency: u64,
+    // Probability from 0 to 1 with which a call fails
+    pub error_rate: f64,
+    // Probability from 0 to 1 with which a new block is preceded by a reorg
+    pub reorg_rate: f64,
+    // Blocks below the tip that are replaced by a reorg
+    pub reorg_depth: usize,
+}
+
+// SplitMix64, because the faults have to be reproducible and not cryptographically secure
+struct Faults {
+    config: ChaosConfig,
+    state: Mutex<u64>,
+}
+
+impl Faults {
+    fn new(config: ChaosConfig) -> Faults {
+        Faults {
+            config,
+            state: Mutex::new(config.seed),
+        }
+    }
+
+    fn next(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        *state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniformly distributed from 0 to 1
+    fn roll(&self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn latency(&self) -> Duration {
+        match self.config.max_latency {
+            0 => Duration::ZERO,
+            max => Duration::from_millis(self.next() % (max + 1)),
+        }
+    }
+
+    fn should_fail(&self) -> bool {
+        self.roll() < self.config.error_rate
+    }
+
+    fn should_reorg(&self) -> bool {
+        self.config.reorg_depth > 0 && self.roll() < self.config.reorg_rate
+    }
+}
+
+/// Wraps a chain backend and injects latencies, errors and reorgs, so that the handling
+/// of them can be exercised deterministically in tests and staging.
+/// Reorgs are simulated by sending the transactions of the replaced blocks again, like
+/// they were returned to the mempool, and then the blocks again before the new one
+pub struct ChaosBackend {
+    backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    faults: Arc<Faults>,
+
+    tx_receiver: Receiver<Transaction>,
+    block_receiver: Receiver<Block>,
+}
+
+impl ChaosBackend {
+    pub fn new(backend: Box<dyn ChainBackend + Send + Sync>, config: ChaosConfig) -> ChaosBackend {
+        warn!("Injecting faults into chain backend: {:?}", config);
+
+        let backend = Arc::new(backend);
+        let faults = Arc::new(Faults::new(config));
+
+        let (tx_sender, tx_receiver) = channel::bounded(
+            "chaos_transactions",
+            channel::TRANSACTION_CHANNEL_SIZE,
+            Overflow::Drop,
+        );
+        let (block_sender, block_receiver) =
+            channel::bounded("chaos_blocks", channel::BLOCK_CHANNEL_SIZE, Overflow::Wait);
+
+        Self::relay_transactions(backend.get_tx_receiver(), tx_sender.clone());
+        Self::relay_blocks(
+            backend.get_block_receiver(),
+            block_sender,
+            tx_sender,
+            faults.clone(),
+        );
+
+        ChaosBackend {
+            backend,
+            faults,
+            tx_receiver,
+            block_receiver,
+        }
+    }
+
+    fn relay_transactions(receiver: Receiver<Transaction>, sender: Sender<Transaction>) {
+        tokio::spawn(async move {
+            while let Some(tx) = receiver.recv().await {
+                if let Err(err) = sender.send(tx).await {
+                    warn!("Could not relay transaction: {}", err);
+                }
+            }
+        });
+    }
+
+    fn relay_blocks(
+        receiver: Receiver<Block>,
+        block_sender: Sender<Block>,
+        tx_sender: Sender<Transaction>,
+        faults: Arc<Faults>,
+    ) {
+        tokio::spawn(async move {
+            let mut recent: VecDeque<Block> = VecDeque::new();
+
+            while let Some(block) = receiver.recv().await {
+                if !recent.is_empty() && faults.should_reorg() {
+                    warn!(
+                        "Injecting reorg of {} blocks below {}",
+                        recent.len(),
+                        block.header.height
+                    );
+
+                    for tx in recent.iter().flat_map(|block| block.txdata.iter()) {
+                        let _ = tx_sender.send(tx.clone()).await;
+                    }
+                    for replaced in recent.iter() {
+                        if let Err(err) = block_sender.send(replaced.clone()).await {
+                            error!("Could not relay block: {}", err);
+                        }
+                    }
+                }
+
+                recent.push_back(block.clone());
+                if recent.len() > faults.config.reorg_depth {
+                    recent.pop_front();
+                }
+
+                if let Err(err) = block_sender.send(block).await {
+                    error!("Could not relay block: {}", err);
+                }
+            }
+        });
+    }
+
+    async fn inject(&self, method: &str) -> Result<(), ChainError> {
+        let latency = self.faults.latency();
+        if !latency.is_zero() {
+            time::sleep(latency).await;
+        }
+
+        if self.faults.should_fail() {
+            return Err(ChainError::Other(format!("injected failure of {}", method)));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChainBackend for ChaosBackend {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
+        self.inject("get_network_info").await?;
+        self.backend.get_network_info().await
+    }
+
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
+        self.inject("get_block_count").await?;
+        self.backend.get_block_count().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
+        self.inject("get_block_hash").await?;
+        self.backend.get_block_hash(height).await
+    }
+
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
+        self.inject("get_block").await?;
+        self.backend.get_block(hash).await
+    }
+
+    async fn get_block_hashes(&self, heights: Vec<u64>) -> Result<Vec<String>, ChainError> {
+        self.inject("get_block_hashes").await?;
+        self.backend.get_block_hashes(heights).await
+    }
+
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, ChainError> {
+        self.inject("get_blocks").await?;
+        self.backend.get_blocks(hashes).await
+    }
+
+    async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
+        self.inject("send_raw_transaction").await?;
+        self.backend.send_raw_transaction(hex).await
+    }
+
+    async fn broadcast_transaction(
+        &self,
+        hex: String,
+    ) -> Result<Broadcast, TransactionBroadcastError> {
+        self.inject("broadcast_transaction").await?;
+        self.backend.broadcast_transaction(hex).await
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
+        self.inject("get_transaction").await?;
+        self.backend.get_transaction(hash).await
+    }
+
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        self.inject("get_mempool_transactions").await?;
+        self.backend.get_mempool_transactions().await
+    }
+
+    async fn get_script_transactions(
+        &self,
+        script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        self.inject("get_script_transactions").await?;
+        self.backend.get_script_transactions(script).await
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        self.inject("estimate_fee").await?;
+        self.backend.estimate_fee(target_blocks).await
+    }
+
+    fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.tx_receiver.clone()
+    }
+
+    fn get_block_receiver(&self) -> Receiver<Block> {
+        self.block_receiver.clone()
+    }
+}
+
+#[cfg(test)]
+mod chaos_test {
+    use std::time::Duration;
+
+    use crate::chain::chaos::{ChaosConfig, Faults};
+
+    fn faults(error_rate: f64, reorg_rate: f64) -> Faults {
+        Faults::new(ChaosConfig {
+            seed: 21,
+            max_latency: 100,
+            error_rate,
+            reorg_rate,
+            reorg_depth: 2,
+        })
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let (first, second) = (faults(0.5, 0.5), faults(0.5, 0.5));
+
+        for _ in 0..100 {
+            assert_eq!(first.latency(), second.latency());
+            assert_eq!(first.should_fail(), second.should_fail());
+            assert_eq!(first.should_reorg(), second.should_reorg());
+        }
+    }
+
+    #[test]
+    fn test_latency() {
+        let faults = faults(0.0, 0.0);
+        for _ in 0..100 {
+            assert!(faults.latency() <= Duration::from_millis(100));
+        }
+
+        let faults = Faults::new(ChaosConfig::default());
+        assert_eq!(faults.latency(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_rates() {
+        let never = faults(0.0, 0.0);
+        let always = faults(1.0, 1.0);
+        for _ in 0..100 {
+            assert!(!never.should_fail());
+            assert!(!never.should_reorg());
+            assert!(always.should_fail());
+            assert!(always.should_reorg());
+        }
+
+        let sometimes = faults(0.3, 0.0);
+        let failures = (0..10_000).filter(|_| sometimes.should_fail()).count();
+        assert!((2_500..3_500).contains(&failures));
+    }
+
+    #[test]
+    fn test_no_reorg_without_depth() {
+        let faults = Faults::new(ChaosConfig {
+            reorg_rate: 1.0,
+            ..Default::default()
+        });
+        assert!(!faults.should_reorg());
+    }
+}
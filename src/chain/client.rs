@@ -1,17 +1,23 @@
 use async_trait::async_trait;
 use base64::prelude::*;
-use crossbeam_channel::Receiver;
 use elements::{Block, Transaction};
-use log::{debug, trace};
 use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize, Serializer};
 use serde_json::json;
-use std::error::Error;
 use std::fs;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info, instrument, trace, warn};
 
-use crate::chain::types::{ChainBackend, NetworkInfo, TransactionBroadcastError, ZmqNotification};
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::fee::btc_per_kvb_to_sat_per_vbyte;
+use crate::chain::types::{ChainBackend, NetworkInfo, SmartFeeEstimation, ZmqNotification};
 use crate::chain::zmq::ZmqClient;
+use crate::utils::channel::Receiver;
+use crate::utils::rate_limit::RateLimit;
+use crate::utils::request_metrics::observe;
 
 enum StringOrU64 {
     Str(String),
@@ -32,30 +38,58 @@ impl Serialize for StringOrU64 {
 
 #[derive(Deserialize)]
 pub struct RpcError {
+    pub code: Option<i64>,
     pub message: String,
 }
 
+impl From<RpcError> for ChainError {
+    fn from(value: RpcError) -> Self {
+        ChainError::Rpc {
+            code: value.code,
+            message: value.message,
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct RpcResponse<T> {
+    id: Option<usize>,
     result: Option<T>,
     error: Option<RpcError>,
 }
 
+#[derive(Clone)]
+pub enum RpcAuth {
+    Cookie(String),
+    UserPassword { user: String, password: String },
+}
+
 #[derive(Clone)]
 pub struct ChainClient {
     url: String,
-    cookie_file_path: String,
+    auth: RpcAuth,
     zmq_client: ZmqClient,
+    http_client: reqwest::Client,
+    rate_limit: RateLimit,
 
-    cookie: Option<String>,
+    // Shared between clones, so that a re-read cookie is used everywhere
+    authorization: Arc<Mutex<Option<String>>>,
 }
 
 impl ChainClient {
-    pub fn new(host: String, port: u32, cookie_file_path: String) -> ChainClient {
+    pub fn new(
+        host: String,
+        port: u32,
+        auth: RpcAuth,
+        http_client: reqwest::Client,
+        rate_limit: RateLimit,
+    ) -> ChainClient {
         let client = ChainClient {
-            cookie_file_path,
-            cookie: None,
+            auth,
+            http_client,
+            rate_limit,
             zmq_client: ZmqClient::new(),
+            authorization: Arc::new(Mutex::new(None)),
             url: format!("http://{}:{}", host, port),
         };
         trace!("Using Elements endpoint: {}", client.url);
@@ -63,82 +97,313 @@ impl ChainClient {
         client
     }
 
-    pub async fn connect(mut self) -> Result<ChainClient, Box<dyn Error>> {
-        let file = fs::read(self.cookie_file_path.clone())?;
-        debug!("Read Elements cookie file: {}", self.cookie_file_path);
-        self.cookie = Some(format!("Basic {}", BASE64_STANDARD.encode(file)));
+    pub async fn connect(self) -> Result<ChainClient, ChainError> {
+        self.update_authorization()?;
 
         let notifications = self.clone().get_zmq_notifications().await?;
         self.zmq_client.clone().connect(notifications).await?;
 
+        let block_count = self.get_block_count().await?;
+        self.zmq_client
+            .last_block_height
+            .fetch_max(block_count, Ordering::SeqCst);
+        self.spawn_gap_recovery();
+        self.spawn_hash_fetchers();
+
         Ok(self)
     }
 
-    pub async fn get_zmq_notifications(self) -> Result<Vec<ZmqNotification>, Box<dyn Error>> {
+    // Nodes that only publish hashes on ZMQ need the transactions and blocks to be fetched
+    fn spawn_hash_fetchers(&self) {
+        let clone = self.clone();
+        tokio::spawn(async move {
+            while let Some(tx_id) = clone.zmq_client.tx_hash_receiver.recv().await {
+                match clone.get_transaction(tx_id.clone()).await {
+                    Ok(tx) => clone.zmq_client.forward_transaction(tx).await,
+                    Err(err) => warn!("Could not fetch transaction {}: {}", tx_id, err),
+                };
+            }
+        });
+
+        let clone = self.clone();
+        tokio::spawn(async move {
+            while let Some(block_hash) = clone.zmq_client.block_hash_receiver.recv().await {
+                match clone.get_block(block_hash.clone()).await {
+                    Ok(block) => clone.zmq_client.forward_block(block).await,
+                    Err(err) => warn!("Could not fetch block {}: {}", block_hash, err),
+                };
+            }
+        });
+    }
+
+    fn spawn_gap_recovery(&self) {
+        let clone = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                clone.zmq_client.missed_notifications.notified().await;
+
+                clone.fetch_missed_transactions().await;
+                if let Err(err) = clone.fetch_missed_blocks().await {
+                    warn!("Could not fetch blocks missed by ZMQ: {}", err);
+                }
+                if let Err(err) = clone.scan_mempool().await {
+                    warn!("Could not scan mempool: {}", err);
+                }
+            }
+        });
+    }
+
+    async fn fetch_missed_transactions(&self) {
+        let tx_ids: Vec<String> = self
+            .zmq_client
+            .missed_transactions
+            .lock()
+            .unwrap()
+            .drain(..)
+            .collect();
+
+        for tx_id in tx_ids {
+            let tx = match self.get_transaction(tx_id.clone()).await {
+                Ok(res) => res,
+                Err(err) => {
+                    // Transactions that left the mempool already will be found in their block
+                    warn!(
+                        "Could not fetch transaction {} missed by ZMQ: {}",
+                        tx_id, err
+                    );
+                    continue;
+                }
+            };
+
+            if let Err(err) = self.zmq_client.tx_sender.send(tx).await {
+                warn!("Could not send transaction to channel: {}", err);
+            }
+        }
+    }
+
+    async fn scan_mempool(&self) -> Result<(), ChainError> {
+        let txs = self.get_mempool_transactions().await?;
+        debug!("Scanning {} mempool transactions", txs.len());
+
+        for tx in txs {
+            self.zmq_client.tx_sender.send(tx).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn fetch_missed_blocks(&self) -> Result<(), ChainError> {
+        let block_count = self.get_block_count().await?;
+        let last_height = self.zmq_client.last_block_height.load(Ordering::SeqCst);
+
+        if block_count <= last_height {
+            debug!("No blocks were missed by ZMQ");
+            return Ok(());
+        }
+
+        info!(
+            "Fetching {} blocks missed by ZMQ",
+            block_count - last_height
+        );
+
+        for height in last_height + 1..block_count + 1 {
+            let block_hash = self.get_block_hash(height).await?;
+            let block = self.get_block(block_hash).await?;
+
+            self.zmq_client
+                .last_block_height
+                .fetch_max(height, Ordering::SeqCst);
+            self.zmq_client.block_sender.send(block).await?;
+        }
+
+        Ok(())
+    }
+
+    fn update_authorization(&self) -> Result<String, ChainError> {
+        let credentials = match &self.auth {
+            RpcAuth::Cookie(path) => {
+                let file = fs::read(path)?;
+                debug!("Read Elements cookie file: {}", path);
+                file
+            }
+            RpcAuth::UserPassword { user, password } => {
+                format!("{}:{}", user, password).into_bytes()
+            }
+        };
+
+        let authorization = format!("Basic {}", BASE64_STANDARD.encode(credentials));
+        *self.authorization.lock().unwrap() = Some(authorization.clone());
+
+        Ok(authorization)
+    }
+
+    pub async fn get_zmq_notifications(self) -> Result<Vec<ZmqNotification>, ChainError> {
         self.request::<Vec<ZmqNotification>>("getzmqnotifications")
             .await
     }
 
-    async fn request<T: DeserializeOwned>(self, method: &str) -> Result<T, Box<dyn Error>> {
+    async fn request<T: DeserializeOwned>(self, method: &str) -> Result<T, ChainError> {
         self.request_params(method, Vec::<String>::new()).await
     }
 
+    #[instrument(name = "elements_rpc", level = "debug", skip(self, params))]
     async fn request_params<T: DeserializeOwned>(
         self,
         method: &str,
         params: Vec<impl Serialize>,
-    ) -> Result<T, Box<dyn Error>> {
-        if self.cookie.is_none() {
-            return Err("client not connected".into());
-        }
-
-        let mut headers = HeaderMap::new();
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert(
-            "Authorization",
-            HeaderValue::from_str(self.cookie.unwrap().as_str())?,
-        );
-
+    ) -> Result<T, ChainError> {
         let data = json!({
             "method": method,
             "params": params,
         });
 
-        let client = reqwest::Client::new();
+        let res = self
+            .post(method, &data)
+            .await?
+            .json::<RpcResponse<T>>()
+            .await?;
+        if let Some(err) = res.error {
+            return Err(err.into());
+        }
 
-        let response = client
-            .post(self.url)
-            .headers(headers)
-            .json(&data)
-            .send()
+        Ok(res.result.unwrap())
+    }
+
+    // Sends all calls of the batch in a single HTTP request and returns
+    // the results in the order of the params
+    #[instrument(
+        name = "elements_rpc",
+        level = "debug",
+        skip(self, params),
+        fields(batch_size = params.len())
+    )]
+    async fn request_batch<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<Vec<StringOrU64>>,
+    ) -> Result<Vec<T>, ChainError> {
+        if params.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let calls: Vec<serde_json::Value> = params
+            .iter()
+            .enumerate()
+            .map(|(id, params)| {
+                json!({
+                    "id": id,
+                    "method": method,
+                    "params": params,
+                })
+            })
+            .collect();
+
+        let mut responses = self
+            .post(method, &json!(calls))
+            .await?
+            .json::<Vec<RpcResponse<T>>>()
             .await?;
+        if responses.len() != params.len() {
+            return Err(format!(
+                "expected {} responses to batch but got {}",
+                params.len(),
+                responses.len()
+            )
+            .into());
+        }
+
+        // Responses to a batch can be in any order
+        responses.sort_by_key(|res| res.id);
 
-        let res = response.json::<RpcResponse<T>>().await?;
-        if res.error.is_some() {
-            return Err(res.error.unwrap().message.into());
+        let mut results = Vec::with_capacity(responses.len());
+        for res in responses {
+            if let Some(err) = res.error {
+                return Err(err.into());
+            }
+
+            match res.result {
+                Some(result) => results.push(result),
+                None => return Err("missing result in batch response".into()),
+            }
         }
 
-        Ok(res.result.unwrap())
+        Ok(results)
+    }
+
+    async fn post(
+        &self,
+        method: &str,
+        data: &serde_json::Value,
+    ) -> Result<reqwest::Response, ChainError> {
+        observe("elements", method, self.post_authorized(data)).await
+    }
+
+    async fn post_authorized(
+        &self,
+        data: &serde_json::Value,
+    ) -> Result<reqwest::Response, ChainError> {
+        let authorization = match self.authorization.lock().unwrap().clone() {
+            Some(res) => res,
+            None => return Err("client not connected".into()),
+        };
+
+        let mut response = self.send_request(data, authorization.as_str()).await?;
+
+        // The cookie file is rewritten with new credentials when Elements restarts
+        if response.status() == StatusCode::UNAUTHORIZED {
+            if let RpcAuth::Cookie(_) = self.auth {
+                warn!("Elements RPC rejected credentials; reading cookie file again");
+                let authorization = self.update_authorization()?;
+                response = self.send_request(data, authorization.as_str()).await?;
+            }
+        }
+
+        if response.status() == StatusCode::UNAUTHORIZED {
+            return Err("Elements RPC authentication failed".into());
+        }
+
+        Ok(response)
+    }
+
+    async fn send_request(
+        &self,
+        data: &serde_json::Value,
+        authorization: &str,
+    ) -> Result<reqwest::Response, ChainError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert("Authorization", HeaderValue::from_str(authorization)?);
+
+        self.rate_limit.wait().await;
+
+        Ok(self
+            .http_client
+            .post(self.url.as_str())
+            .headers(headers)
+            .json(data)
+            .send()
+            .await?)
     }
 }
 
 #[async_trait]
 impl ChainBackend for ChainClient {
-    async fn get_network_info(&self) -> Result<NetworkInfo, Box<dyn Error>> {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
         self.clone().request::<NetworkInfo>("getnetworkinfo").await
     }
 
-    async fn get_block_count(&self) -> Result<u64, Box<dyn Error>> {
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
         self.clone().request::<u64>("getblockcount").await
     }
 
-    async fn get_block_hash(&self, height: u64) -> Result<String, Box<dyn Error>> {
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
         self.clone()
             .request_params::<String>("getblockhash", vec![height])
             .await
     }
 
-    async fn get_block(&self, hash: String) -> Result<Block, Box<dyn Error>> {
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
         let params = vec![StringOrU64::Str(hash), StringOrU64::Num(0)];
 
         let block_hex = self
@@ -149,6 +414,28 @@ impl ChainBackend for ChainClient {
         crate::chain::utils::parse_hex(block_hex)
     }
 
+    async fn get_block_hashes(&self, heights: Vec<u64>) -> Result<Vec<String>, ChainError> {
+        let params = heights
+            .into_iter()
+            .map(|height| vec![StringOrU64::Num(height)])
+            .collect();
+
+        self.request_batch::<String>("getblockhash", params).await
+    }
+
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, ChainError> {
+        let params = hashes
+            .into_iter()
+            .map(|hash| vec![StringOrU64::Str(hash), StringOrU64::Num(0)])
+            .collect();
+
+        self.request_batch::<String>("getblock", params)
+            .await?
+            .into_iter()
+            .map(crate::chain::utils::parse_hex)
+            .collect()
+    }
+
     async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
         match self
             .clone()
@@ -160,7 +447,7 @@ impl ChainBackend for ChainClient {
         }
     }
 
-    async fn get_transaction(&self, hash: String) -> Result<Transaction, Box<dyn Error>> {
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
         let tx_hex = self
             .clone()
             .request_params::<String>("getrawtransaction", vec![hash])
@@ -169,6 +456,39 @@ impl ChainBackend for ChainClient {
         crate::chain::utils::parse_hex(tx_hex)
     }
 
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        let tx_ids = self.clone().request::<Vec<String>>("getrawmempool").await?;
+
+        let mut txs = Vec::with_capacity(tx_ids.len());
+        for tx_id in tx_ids {
+            // Transactions can leave the mempool while we are fetching them
+            match self.get_transaction(tx_id.clone()).await {
+                Ok(tx) => txs.push(tx),
+                Err(err) => debug!("Could not get mempool transaction {}: {}", tx_id, err),
+            };
+        }
+
+        Ok(txs)
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        let estimation = self
+            .clone()
+            .request_params::<SmartFeeEstimation>("estimatesmartfee", vec![target_blocks])
+            .await?;
+
+        match estimation.feerate {
+            Some(feerate) => Ok(btc_per_kvb_to_sat_per_vbyte(feerate)),
+            None => Err(estimation
+                .errors
+                .unwrap_or_default()
+                .first()
+                .cloned()
+                .unwrap_or("no fee estimation available".to_string())
+                .into()),
+        }
+    }
+
     fn get_tx_receiver(&self) -> Receiver<Transaction> {
         self.zmq_client.tx_receiver.clone()
     }
@@ -0,0 +1,624 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use elements::{Block, BlockHeader, Transaction};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::{task, time};
+use tokio_native_tls::{native_tls, TlsConnector};
+use tracing::{debug, error, info, instrument, trace, warn};
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::fee::btc_per_kvb_to_sat_per_vbyte;
+use crate::chain::types::{ChainBackend, NetworkInfo};
+use crate::chain::utils::{parse_hex, script_hash};
+use crate::db;
+use crate::db::helpers::get_pending_covenant_scripts;
+use crate::utils::channel::{
+    bounded, Overflow, Receiver, Sender, BLOCK_CHANNEL_SIZE, TRANSACTION_CHANNEL_SIZE,
+};
+
+const CLIENT_NAME: &str = "covclaim";
+const PROTOCOL_VERSION: &str = "1.4";
+
+const REQUEST_TIMEOUT: u64 = 30;
+
+type ElectrumReader = Box<dyn AsyncRead + Send + Unpin>;
+type ElectrumWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+type PendingRequests = HashMap<u64, oneshot::Sender<Result<Value, ChainError>>>;
+
+#[derive(Deserialize)]
+struct ElectrumMessage {
+    id: Option<u64>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+    result: Option<Value>,
+    // Some servers send a string and others an object with a message
+    error: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct HeaderNotification {
+    height: u64,
+}
+
+#[derive(Deserialize)]
+struct HistoryEntry {
+    tx_hash: String,
+}
+
+#[derive(Clone)]
+pub struct ElectrumClient {
+    endpoint: String,
+    poll_interval: u64,
+    db: db::Pool,
+
+    request_id: Arc<AtomicU64>,
+    pending_requests: Arc<Mutex<PendingRequests>>,
+    writer: Arc<tokio::sync::Mutex<Option<ElectrumWriter>>>,
+    server_version: Arc<Mutex<String>>,
+
+    last_height: Arc<AtomicU64>,
+    // Electrum servers only serve headers; they are kept between
+    // get_block_hash and get_block, which is always called right after
+    headers: Arc<Mutex<HashMap<String, BlockHeader>>>,
+
+    subscribed_scripts: Arc<Mutex<HashSet<String>>>,
+    seen_transactions: Arc<Mutex<HashSet<String>>>,
+
+    tx_sender: Sender<Transaction>,
+    tx_receiver: Receiver<Transaction>,
+
+    block_sender: Sender<Block>,
+    block_receiver: Receiver<Block>,
+}
+
+impl ElectrumClient {
+    pub fn new(endpoint: String, poll_interval: u64, db: db::Pool) -> ElectrumClient {
+        let (tx_sender, tx_receiver) = bounded::<Transaction>(
+            "electrum_transactions",
+            TRANSACTION_CHANNEL_SIZE,
+            Overflow::Drop,
+        );
+        let (block_sender, block_receiver) =
+            bounded::<Block>("electrum_blocks", BLOCK_CHANNEL_SIZE, Overflow::Wait);
+
+        ElectrumClient {
+            db,
+            endpoint,
+            tx_sender,
+            tx_receiver,
+            block_sender,
+            poll_interval,
+            block_receiver,
+            request_id: Arc::new(AtomicU64::new(0)),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            writer: Arc::new(tokio::sync::Mutex::new(None)),
+            server_version: Arc::new(Mutex::new("Electrum".to_string())),
+            last_height: Arc::new(AtomicU64::new(0)),
+            headers: Arc::new(Mutex::new(HashMap::new())),
+            subscribed_scripts: Arc::new(Mutex::new(HashSet::new())),
+            seen_transactions: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub async fn connect(self) -> Result<ElectrumClient, ChainError> {
+        let (use_tls, host, port) = parse_endpoint(&self.endpoint)?;
+        info!(
+            "Connecting to Electrum server {}:{} ({})",
+            host,
+            port,
+            if use_tls { "SSL" } else { "TCP" }
+        );
+
+        let (reader, writer) = Self::open_stream(use_tls, host, port).await?;
+        *self.writer.lock().await = Some(writer);
+        self.spawn_reader(reader);
+
+        let version = self
+            .request::<Vec<String>>(
+                "server.version",
+                vec![json!(CLIENT_NAME), json!(PROTOCOL_VERSION)],
+            )
+            .await?;
+        if let Some(software) = version.first() {
+            *self.server_version.lock().unwrap() = software.clone();
+        }
+
+        let tip = self
+            .request::<HeaderNotification>("blockchain.headers.subscribe", vec![])
+            .await?;
+        self.last_height.store(tip.height, Ordering::SeqCst);
+        debug!("Subscribed to Electrum headers at height {}", tip.height);
+
+        self.spawn_script_subscriber();
+
+        Ok(self)
+    }
+
+    async fn open_stream(
+        use_tls: bool,
+        host: String,
+        port: u16,
+    ) -> Result<(ElectrumReader, ElectrumWriter), ChainError> {
+        let stream = TcpStream::connect((host.as_str(), port)).await?;
+
+        if use_tls {
+            let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+            let (reader, writer) = tokio::io::split(connector.connect(&host, stream).await?);
+            Ok((Box::new(reader), Box::new(writer)))
+        } else {
+            let (reader, writer) = tokio::io::split(stream);
+            Ok((Box::new(reader), Box::new(writer)))
+        }
+    }
+
+    fn spawn_reader(&self, reader: ElectrumReader) {
+        let clone = self.clone();
+
+        task::spawn(async move {
+            let mut lines = BufReader::new(reader).lines();
+
+            loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => {
+                        error!("Electrum server closed the connection");
+                        break;
+                    }
+                    Err(err) => {
+                        error!("Could not read from Electrum server: {}", err);
+                        break;
+                    }
+                };
+
+                let message = match serde_json::from_str::<ElectrumMessage>(line.as_str()) {
+                    Ok(res) => res,
+                    Err(err) => {
+                        warn!("Could not parse Electrum message: {}", err);
+                        continue;
+                    }
+                };
+
+                match message.id {
+                    Some(id) => clone.resolve_request(id, message),
+                    None => {
+                        if let Some(method) = message.method {
+                            let notification_clone = clone.clone();
+                            task::spawn(async move {
+                                notification_clone
+                                    .handle_notification(method, message.params)
+                                    .await;
+                            });
+                        }
+                    }
+                }
+            }
+
+            // Dropping the senders fails all requests that are still waiting for a response
+            clone.pending_requests.lock().unwrap().clear();
+            *clone.writer.lock().await = None;
+        });
+    }
+
+    fn spawn_script_subscriber(&self) {
+        let clone = self.clone();
+
+        task::spawn(async move {
+            info!(
+                "Checking for new covenants to subscribe to every {} seconds",
+                clone.poll_interval
+            );
+            let mut interval = time::interval(Duration::from_secs(clone.poll_interval));
+
+            loop {
+                interval.tick().await;
+
+                let scripts = match get_pending_covenant_scripts(clone.db.clone()).await {
+                    Ok(res) => res,
+                    Err(err) => {
+                        warn!("Could not get pending covenants: {}", err);
+                        continue;
+                    }
+                };
+
+                for script in scripts {
+                    let script_hash = script_hash(&script);
+                    if clone
+                        .subscribed_scripts
+                        .lock()
+                        .unwrap()
+                        .contains(&script_hash)
+                    {
+                        continue;
+                    }
+
+                    match clone.subscribe_script(script_hash.clone()).await {
+                        Ok(_) => {
+                            clone.subscribed_scripts.lock().unwrap().insert(script_hash);
+                        }
+                        Err(err) => {
+                            warn!("Could not subscribe to script {}: {}", script_hash, err);
+                        }
+                    };
+                }
+            }
+        });
+    }
+
+    async fn subscribe_script(&self, script_hash: String) -> Result<(), ChainError> {
+        trace!("Subscribing to script hash: {}", script_hash);
+        let status = self
+            .request::<Option<String>>("blockchain.scripthash.subscribe", vec![json!(script_hash)])
+            .await?;
+
+        // A status means that the script has history already
+        if status.is_some() {
+            self.handle_script_history(script_hash).await;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_notification(&self, method: String, params: Value) {
+        match method.as_str() {
+            "blockchain.headers.subscribe" => {
+                let header = match serde_json::from_value::<Vec<HeaderNotification>>(params) {
+                    Ok(mut res) if !res.is_empty() => res.remove(0),
+                    _ => {
+                        warn!("Could not parse Electrum header notification");
+                        return;
+                    }
+                };
+
+                self.handle_new_tip(header.height).await;
+            }
+            "blockchain.scripthash.subscribe" => {
+                let (script_hash, status) =
+                    match serde_json::from_value::<(String, Option<String>)>(params) {
+                        Ok(res) => res,
+                        Err(err) => {
+                            warn!("Could not parse Electrum script hash notification: {}", err);
+                            return;
+                        }
+                    };
+
+                if status.is_some() {
+                    self.handle_script_history(script_hash).await;
+                }
+            }
+            _ => {
+                trace!("Ignoring Electrum notification: {}", method);
+            }
+        }
+    }
+
+    async fn handle_new_tip(&self, height: u64) {
+        let last_height = self.last_height.fetch_max(height, Ordering::SeqCst);
+        // The initial tip has not been set yet
+        if last_height == 0 {
+            return;
+        }
+
+        for height in last_height + 1..height + 1 {
+            let block_hash = match self.get_block_hash(height).await {
+                Ok(hash) => hash,
+                Err(err) => {
+                    warn!("Could not get block hash for height {}: {}", height, err);
+                    continue;
+                }
+            };
+            let block = match self.get_block(block_hash.clone()).await {
+                Ok(block) => block,
+                Err(err) => {
+                    warn!("Could not get block with hash {}: {}", block_hash, err);
+                    continue;
+                }
+            };
+            trace!(
+                "Got block {} ({})",
+                block.header.height,
+                block.header.block_hash()
+            );
+            match self.block_sender.send(block).await {
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Could not send block update: {}", err);
+                    continue;
+                }
+            };
+        }
+    }
+
+    async fn handle_script_history(&self, script_hash: String) {
+        let history = match self
+            .request::<Vec<HistoryEntry>>(
+                "blockchain.scripthash.get_history",
+                vec![json!(script_hash)],
+            )
+            .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not get history of script {}: {}", script_hash, err);
+                return;
+            }
+        };
+
+        for entry in history {
+            if !self
+                .seen_transactions
+                .lock()
+                .unwrap()
+                .insert(entry.tx_hash.clone())
+            {
+                continue;
+            }
+
+            let tx = match self.get_transaction(entry.tx_hash.clone()).await {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("Could not get transaction {}: {}", entry.tx_hash, err);
+                    self.seen_transactions
+                        .lock()
+                        .unwrap()
+                        .remove(&entry.tx_hash);
+                    continue;
+                }
+            };
+
+            match self.tx_sender.send(tx).await {
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Could not send transaction update: {}", err);
+                }
+            };
+        }
+    }
+
+    fn resolve_request(&self, id: u64, message: ElectrumMessage) {
+        let sender = match self.pending_requests.lock().unwrap().remove(&id) {
+            Some(res) => res,
+            None => {
+                warn!("Got Electrum response for unknown request: {}", id);
+                return;
+            }
+        };
+
+        let res = match message.error {
+            Some(Value::String(err)) => Err(ChainError::rpc(None, err)),
+            Some(err) => Err(match err.get("message").and_then(|msg| msg.as_str()) {
+                Some(msg) => ChainError::rpc(
+                    err.get("code").and_then(|code| code.as_i64()),
+                    msg.to_string(),
+                ),
+                None => ChainError::rpc(None, err.to_string()),
+            }),
+            None => Ok(message.result.unwrap_or(Value::Null)),
+        };
+
+        // The requester gave up already when sending fails
+        let _ = sender.send(res);
+    }
+
+    #[instrument(name = "electrum_request", level = "debug", skip(self, params))]
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Vec<Value>,
+    ) -> Result<T, ChainError> {
+        let id = self.request_id.fetch_add(1, Ordering::SeqCst);
+
+        let mut data = serde_json::to_vec(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        data.push(b'\n');
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending_requests.lock().unwrap().insert(id, sender);
+
+        let write_res = match self.writer.lock().await.as_mut() {
+            Some(writer) => writer
+                .write_all(data.as_slice())
+                .await
+                .map_err(ChainError::from),
+            None => Err("client not connected".into()),
+        };
+        if let Err(err) = write_res {
+            self.pending_requests.lock().unwrap().remove(&id);
+            return Err(err);
+        }
+
+        let res = match time::timeout(Duration::from_secs(REQUEST_TIMEOUT), receiver).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(_)) => return Err("connection to Electrum server closed".into()),
+            Err(_) => {
+                self.pending_requests.lock().unwrap().remove(&id);
+                return Err(format!("Electrum request {} timed out", method).into());
+            }
+        };
+
+        match res {
+            Ok(value) => Ok(serde_json::from_value::<T>(value)?),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for ElectrumClient {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
+        self.request::<Value>("server.ping", vec![]).await?;
+
+        let subversion = self.server_version.lock().unwrap().clone();
+        Ok(NetworkInfo { subversion })
+    }
+
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
+        Ok(self
+            .request::<HeaderNotification>("blockchain.headers.subscribe", vec![])
+            .await?
+            .height)
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
+        let header_hex = self
+            .request::<String>("blockchain.block.header", vec![json!(height)])
+            .await?;
+        let header: BlockHeader = parse_hex(header_hex)?;
+
+        let hash = header.block_hash().to_string();
+        self.headers.lock().unwrap().insert(hash.clone(), header);
+
+        Ok(hash)
+    }
+
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
+        // Transactions of covenants are found with script hash subscriptions
+        match self.headers.lock().unwrap().remove(&hash) {
+            Some(header) => Ok(Block {
+                header,
+                txdata: vec![],
+            }),
+            None => Err(format!("unknown block header: {}", hash).into()),
+        }
+    }
+
+    async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
+        match self
+            .request::<String>("blockchain.transaction.broadcast", vec![json!(hex)])
+            .await
+        {
+            Ok(res) => Ok(res),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
+        let tx_hex = self
+            .request::<String>("blockchain.transaction.get", vec![json!(hash)])
+            .await?;
+
+        parse_hex(tx_hex)
+    }
+
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        // Electrum servers cannot list the mempool; unconfirmed transactions of
+        // covenants are part of the history of their script hash subscriptions
+        Ok(Vec::new())
+    }
+
+    async fn get_script_transactions(
+        &self,
+        script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        let history = self
+            .request::<Vec<HistoryEntry>>(
+                "blockchain.scripthash.get_history",
+                vec![json!(script_hash(&script))],
+            )
+            .await?;
+
+        let mut txs = Vec::with_capacity(history.len());
+        for entry in history {
+            txs.push(self.get_transaction(entry.tx_hash).await?);
+        }
+
+        Ok(Some(txs))
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        let fee = self
+            .request::<f64>("blockchain.estimatefee", vec![json!(target_blocks)])
+            .await?;
+
+        // The server responds with -1 when it cannot estimate fees
+        if fee < 0.0 {
+            return Err("no fee estimation available".into());
+        }
+
+        Ok(btc_per_kvb_to_sat_per_vbyte(fee))
+    }
+
+    fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.tx_receiver.clone()
+    }
+
+    fn get_block_receiver(&self) -> Receiver<Block> {
+        self.block_receiver.clone()
+    }
+}
+
+fn parse_endpoint(endpoint: &str) -> Result<(bool, String, u16), ChainError> {
+    let (use_tls, address) = match endpoint.split_once("://") {
+        Some(("ssl", address)) => (true, address),
+        Some(("tcp", address)) => (false, address),
+        Some((protocol, _)) => {
+            return Err(format!("unsupported Electrum protocol: {}", protocol).into())
+        }
+        None => return Err("Electrum endpoint has no protocol".into()),
+    };
+
+    match address.rsplit_once(':') {
+        Some((host, port)) => match port.parse::<u16>() {
+            Ok(port) => Ok((use_tls, host.to_string(), port)),
+            Err(err) => Err(format!("invalid Electrum port {}: {}", port, err).into()),
+        },
+        None => Err("Electrum endpoint has no port".into()),
+    }
+}
+
+#[cfg(test)]
+mod electrum_test {
+    use crate::chain::electrum::parse_endpoint;
+
+    #[test]
+    fn test_parse_endpoint() {
+        let (use_tls, host, port) = parse_endpoint("ssl://blockstream.info:995").unwrap();
+        assert!(use_tls);
+        assert_eq!(host, "blockstream.info");
+        assert_eq!(port, 995);
+
+        let (use_tls, host, port) = parse_endpoint("tcp://127.0.0.1:50001").unwrap();
+        assert!(!use_tls);
+        assert_eq!(host, "127.0.0.1");
+        assert_eq!(port, 50001);
+    }
+
+    #[test]
+    fn test_parse_endpoint_invalid() {
+        assert_eq!(
+            parse_endpoint("blockstream.info:995")
+                .err()
+                .unwrap()
+                .to_string(),
+            "Electrum endpoint has no protocol"
+        );
+        assert_eq!(
+            parse_endpoint("http://blockstream.info:995")
+                .err()
+                .unwrap()
+                .to_string(),
+            "unsupported Electrum protocol: http"
+        );
+        assert_eq!(
+            parse_endpoint("ssl://blockstream.info")
+                .err()
+                .unwrap()
+                .to_string(),
+            "Electrum endpoint has no port"
+        );
+    }
+}
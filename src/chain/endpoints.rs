@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+// Consecutive failures after which an endpoint is not used for a while
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+const EJECTION_DURATION: Duration = Duration::from_secs(60);
+
+struct Endpoint {
+    url: String,
+    consecutive_failures: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn is_available(&self) -> bool {
+        let mut ejected_until = self.ejected_until.lock().unwrap();
+        match *ejected_until {
+            Some(until) if until > Instant::now() => false,
+            Some(_) => {
+                info!("Using Esplora endpoint {} again", self.url);
+                *ejected_until = None;
+                self.consecutive_failures.store(0, Ordering::SeqCst);
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+/// Spreads requests across endpoints in a round-robin fashion and skips
+/// endpoints that failed repeatedly
+#[derive(Clone)]
+pub struct EndpointPool {
+    endpoints: Arc<Vec<Endpoint>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl EndpointPool {
+    /// Parses a comma separated list of endpoints
+    pub fn new(endpoints: &str) -> Result<EndpointPool, String> {
+        let endpoints: Vec<Endpoint> = endpoints
+            .split(',')
+            .map(|endpoint| endpoint.trim())
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(|endpoint| Endpoint {
+                url: crate::utils::string::trim_suffix(endpoint.to_string(), '/'),
+                consecutive_failures: AtomicU32::new(0),
+                ejected_until: Mutex::new(None),
+            })
+            .collect();
+
+        if endpoints.is_empty() {
+            return Err("no endpoint configured".to_string());
+        }
+
+        Ok(EndpointPool {
+            endpoints: Arc::new(endpoints),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.url.clone())
+            .collect()
+    }
+
+    pub fn url(&self, index: usize) -> &str {
+        self.endpoints[index].url.as_str()
+    }
+
+    /// Index of the next endpoint to use; when all endpoints are ejected,
+    /// the requests keep rotating through all of them
+    pub fn next(&self) -> usize {
+        let start = self.next.fetch_add(1, Ordering::SeqCst) % self.endpoints.len();
+
+        (0..self.endpoints.len())
+            .map(|offset| (start + offset) % self.endpoints.len())
+            .find(|index| self.endpoints[*index].is_available())
+            .unwrap_or(start)
+    }
+
+    pub fn mark_success(&self, index: usize) {
+        self.endpoints[index]
+            .consecutive_failures
+            .store(0, Ordering::SeqCst);
+    }
+
+    pub fn mark_failure(&self, index: usize) {
+        // Ejecting the only endpoint would not help
+        if self.endpoints.len() == 1 {
+            return;
+        }
+
+        let endpoint = &self.endpoints[index];
+        let failures = endpoint.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        if failures < MAX_CONSECUTIVE_FAILURES {
+            return;
+        }
+
+        let mut ejected_until = endpoint.ejected_until.lock().unwrap();
+        if ejected_until.is_none() {
+            warn!(
+                "Not using Esplora endpoint {} for {} seconds after {} failures",
+                endpoint.url,
+                EJECTION_DURATION.as_secs(),
+                failures
+            );
+            *ejected_until = Some(Instant::now() + EJECTION_DURATION);
+        }
+    }
+}
+
+#[cfg(test)]
+mod endpoints_test {
+    use crate::chain::endpoints::{EndpointPool, MAX_CONSECUTIVE_FAILURES};
+
+    #[test]
+    fn test_new() {
+        let pool = EndpointPool::new("https://one.com/api/, https://two.com/api").unwrap();
+        assert_eq!(
+            pool.urls(),
+            vec!["https://one.com/api", "https://two.com/api"]
+        );
+    }
+
+    #[test]
+    fn test_new_empty() {
+        assert_eq!(
+            EndpointPool::new(" , ").err().unwrap(),
+            "no endpoint configured"
+        );
+    }
+
+    #[test]
+    fn test_next_round_robin() {
+        let pool = EndpointPool::new("https://one.com,https://two.com").unwrap();
+
+        assert_eq!(pool.next(), 0);
+        assert_eq!(pool.next(), 1);
+        assert_eq!(pool.next(), 0);
+    }
+
+    #[test]
+    fn test_next_ejected() {
+        let pool = EndpointPool::new("https://one.com,https://two.com").unwrap();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            pool.mark_failure(0);
+        }
+
+        assert_eq!(pool.next(), 1);
+        assert_eq!(pool.next(), 1);
+    }
+
+    #[test]
+    fn test_mark_success() {
+        let pool = EndpointPool::new("https://one.com,https://two.com").unwrap();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES - 1 {
+            pool.mark_failure(0);
+        }
+        pool.mark_success(0);
+        pool.mark_failure(0);
+
+        assert_eq!(pool.next(), 0);
+    }
+
+    #[test]
+    fn test_single_endpoint_not_ejected() {
+        let pool = EndpointPool::new("https://one.com").unwrap();
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            pool.mark_failure(0);
+        }
+
+        assert_eq!(pool.next(), 0);
+    }
+}
@@ -0,0 +1,354 @@
+use serde::Deserialize;
+use thiserror::Error;
+
+// Error codes of the RPC of Elements
+pub const RPC_VERIFY_ERROR: i64 = -25;
+pub const RPC_VERIFY_REJECTED: i64 = -26;
+pub const RPC_VERIFY_ALREADY_IN_CHAIN: i64 = -27;
+
+#[derive(Debug, Error)]
+pub enum ChainError {
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Decode(#[from] elements::encode::Error),
+    #[error("{0}")]
+    Hex(#[from] hex::FromHexError),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Tls(#[from] tokio_native_tls::native_tls::Error),
+    // Boxed, because it is much larger than all other variants
+    #[error("{0}")]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error("{0}")]
+    Zmq(#[from] zeromq::ZmqError),
+    #[error("{0}")]
+    HeaderName(#[from] reqwest::header::InvalidHeaderName),
+    #[error("{0}")]
+    HeaderValue(#[from] reqwest::header::InvalidHeaderValue),
+    /// Error returned by the node or the API of a backend
+    #[error("{message}")]
+    Rpc { code: Option<i64>, message: String },
+    #[error("HTTP status code {0:?}")]
+    Status(reqwest::StatusCode),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ChainError {
+    /// Unwraps errors of the node that Esplora and Electrum servers embed in their
+    /// own errors, like `sendrawtransaction RPC error: {"code":-26,"message":"..."}`
+    pub fn rpc(code: Option<i64>, message: String) -> ChainError {
+        #[derive(Deserialize)]
+        struct NodeError {
+            code: i64,
+            message: String,
+        }
+
+        if let Some(start) = message.find('{') {
+            if let Ok(node_error) = serde_json::from_str::<NodeError>(&message[start..]) {
+                return ChainError::Rpc {
+                    code: Some(node_error.code),
+                    message: node_error.message,
+                };
+            }
+        }
+
+        ChainError::Rpc { code, message }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ChainError {
+    fn from(value: tokio_tungstenite::tungstenite::Error) -> Self {
+        ChainError::WebSocket(Box::new(value))
+    }
+}
+
+impl From<String> for ChainError {
+    fn from(value: String) -> Self {
+        ChainError::Other(value)
+    }
+}
+
+impl From<&str> for ChainError {
+    fn from(value: &str) -> Self {
+        ChainError::Other(value.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RejectionKind {
+    /// The transaction, or one spending the same inputs, is in the mempool or a block
+    AlreadyIncluded,
+    /// The inputs were spent already or do not exist
+    MissingInputs,
+    FeeTooLow,
+    /// Violates a rule of the node like the dust limit or standardness
+    Policy,
+    Unknown,
+}
+
+// Matched against the start of the reject reason, because nodes append details like
+// the fees that were paid; more specific prefixes have to come first
+const REJECT_REASONS: &[(&str, RejectionKind)] = &[
+    (
+        "transaction already in block chain",
+        RejectionKind::AlreadyIncluded,
+    ),
+    (
+        "transaction outputs already in utxo set",
+        RejectionKind::AlreadyIncluded,
+    ),
+    ("txn-already-in-mempool", RejectionKind::AlreadyIncluded),
+    ("txn-already-known", RejectionKind::AlreadyIncluded),
+    ("txn-mempool-conflict", RejectionKind::AlreadyIncluded),
+    // The outputs of claims are fixed, so the replaced transaction is a claim too
+    (
+        "insufficient fee, rejecting replacement",
+        RejectionKind::AlreadyIncluded,
+    ),
+    (
+        "bad-txns-inputs-missingorspent",
+        RejectionKind::MissingInputs,
+    ),
+    ("missing-inputs", RejectionKind::MissingInputs),
+    ("missing inputs", RejectionKind::MissingInputs),
+    ("min relay fee not met", RejectionKind::FeeTooLow),
+    ("mempool min fee not met", RejectionKind::FeeTooLow),
+    ("insufficient fee", RejectionKind::FeeTooLow),
+    ("dust", RejectionKind::Policy),
+    ("tx-size", RejectionKind::Policy),
+    ("scriptpubkey", RejectionKind::Policy),
+    ("non-mandatory-script-verify-flag", RejectionKind::Policy),
+    ("mandatory-script-verify-flag-failed", RejectionKind::Policy),
+    ("bad-txns-", RejectionKind::Policy),
+];
+
+/// Why a node refused to accept a transaction
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message}")]
+pub struct BroadcastRejection {
+    // Not known when the rejection was relayed by an API that only forwards the message
+    pub code: Option<i64>,
+    pub message: String,
+}
+
+impl BroadcastRejection {
+    pub fn kind(&self) -> RejectionKind {
+        if self.code == Some(RPC_VERIFY_ALREADY_IN_CHAIN) {
+            return RejectionKind::AlreadyIncluded;
+        }
+
+        let message = self.message.trim().to_lowercase();
+        if let Some((_, kind)) = REJECT_REASONS
+            .iter()
+            .find(|(reason, _)| message.starts_with(reason))
+        {
+            return *kind;
+        }
+
+        match self.code {
+            Some(RPC_VERIFY_REJECTED) => RejectionKind::Policy,
+            _ => RejectionKind::Unknown,
+        }
+    }
+
+    /// Whether the transaction, or one spending the same inputs, was included already
+    pub fn is_already_included(&self) -> bool {
+        // Only claims can spend the outputs of covenants
+        matches!(
+            self.kind(),
+            RejectionKind::AlreadyIncluded | RejectionKind::MissingInputs
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TransactionBroadcastError {
+    #[error("{0}")]
+    Rejected(BroadcastRejection),
+    #[error("{0}")]
+    Chain(ChainError),
+}
+
+impl TransactionBroadcastError {
+    pub fn kind(&self) -> Option<RejectionKind> {
+        match self {
+            TransactionBroadcastError::Rejected(rejection) => Some(rejection.kind()),
+            TransactionBroadcastError::Chain(_) => None,
+        }
+    }
+
+    pub fn is_already_included(&self) -> bool {
+        match self {
+            TransactionBroadcastError::Rejected(rejection) => rejection.is_already_included(),
+            TransactionBroadcastError::Chain(_) => false,
+        }
+    }
+
+    /// Whether another backend could accept the transaction; every node enforces the
+    /// same policy, but the minimal fee depends on the mempool of the node
+    pub fn is_retryable(&self) -> bool {
+        match self.kind() {
+            Some(RejectionKind::FeeTooLow) | Some(RejectionKind::Unknown) | None => true,
+            Some(_) => false,
+        }
+    }
+}
+
+// Errors the node responded with are rejections of the transaction
+impl From<ChainError> for TransactionBroadcastError {
+    fn from(value: ChainError) -> Self {
+        match value {
+            ChainError::Rpc { code, message } => {
+                TransactionBroadcastError::Rejected(BroadcastRejection { code, message })
+            }
+            err => TransactionBroadcastError::Chain(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod error_test {
+    use crate::chain::error::{
+        BroadcastRejection, ChainError, RejectionKind, TransactionBroadcastError,
+        RPC_VERIFY_ALREADY_IN_CHAIN, RPC_VERIFY_ERROR, RPC_VERIFY_REJECTED,
+    };
+
+    #[test]
+    fn test_rpc_embedded_node_error() {
+        match ChainError::rpc(
+            None,
+            "sendrawtransaction RPC error: {\"code\":-26,\"message\":\"bad-txns-inputs-missingorspent\"}"
+                .to_string(),
+        ) {
+            ChainError::Rpc { code, message } => {
+                assert_eq!(code, Some(RPC_VERIFY_REJECTED));
+                assert_eq!(message, "bad-txns-inputs-missingorspent");
+            }
+            _ => panic!("not an RPC error"),
+        };
+    }
+
+    #[test]
+    fn test_rpc_plain_message() {
+        match ChainError::rpc(Some(1), "unknown transaction".to_string()) {
+            ChainError::Rpc { code, message } => {
+                assert_eq!(code, Some(1));
+                assert_eq!(message, "unknown transaction");
+            }
+            _ => panic!("not an RPC error"),
+        };
+    }
+
+    #[test]
+    fn test_is_already_included() {
+        assert!(BroadcastRejection {
+            code: Some(RPC_VERIFY_ALREADY_IN_CHAIN),
+            message: "".to_string(),
+        }
+        .is_already_included());
+        assert!(BroadcastRejection {
+            code: None,
+            message: "bad-txns-inputs-missingorspent".to_string(),
+        }
+        .is_already_included());
+        assert!(!BroadcastRejection {
+            code: Some(RPC_VERIFY_REJECTED),
+            message: "min relay fee not met".to_string(),
+        }
+        .is_already_included());
+    }
+
+    #[test]
+    fn test_kind() {
+        let kind = |code: Option<i64>, message: &str| {
+            BroadcastRejection {
+                code,
+                message: message.to_string(),
+            }
+            .kind()
+        };
+
+        assert_eq!(
+            kind(
+                Some(RPC_VERIFY_ALREADY_IN_CHAIN),
+                "Transaction outputs already in utxo set"
+            ),
+            RejectionKind::AlreadyIncluded
+        );
+        assert_eq!(
+            kind(None, "txn-already-in-mempool"),
+            RejectionKind::AlreadyIncluded
+        );
+        assert_eq!(
+            kind(
+                Some(RPC_VERIFY_REJECTED),
+                "insufficient fee, rejecting replacement 1234; new feerate 0.001 <= old feerate 0.001"
+            ),
+            RejectionKind::AlreadyIncluded
+        );
+        assert_eq!(
+            kind(Some(RPC_VERIFY_ERROR), "bad-txns-inputs-missingorspent"),
+            RejectionKind::MissingInputs
+        );
+        assert_eq!(
+            kind(
+                Some(RPC_VERIFY_REJECTED),
+                "min relay fee not met, 100 < 141"
+            ),
+            RejectionKind::FeeTooLow
+        );
+        assert_eq!(
+            kind(None, "mempool min fee not met, 100 < 200"),
+            RejectionKind::FeeTooLow
+        );
+        assert_eq!(kind(None, "dust"), RejectionKind::Policy);
+        assert_eq!(
+            kind(Some(RPC_VERIFY_REJECTED), "something new"),
+            RejectionKind::Policy
+        );
+        assert_eq!(kind(None, "something new"), RejectionKind::Unknown);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(
+            TransactionBroadcastError::Chain(ChainError::Other("timeout".to_string()))
+                .is_retryable()
+        );
+        assert!(TransactionBroadcastError::Rejected(BroadcastRejection {
+            code: Some(RPC_VERIFY_REJECTED),
+            message: "mempool min fee not met".to_string(),
+        })
+        .is_retryable());
+        assert!(!TransactionBroadcastError::Rejected(BroadcastRejection {
+            code: Some(RPC_VERIFY_REJECTED),
+            message: "dust".to_string(),
+        })
+        .is_retryable());
+        assert!(!TransactionBroadcastError::Rejected(BroadcastRejection {
+            code: None,
+            message: "Transaction already in block chain".to_string(),
+        })
+        .is_retryable());
+    }
+
+    #[test]
+    fn test_broadcast_error_from_chain_error() {
+        assert!(matches!(
+            TransactionBroadcastError::from(ChainError::Rpc {
+                code: None,
+                message: "Transaction already in block chain".to_string(),
+            }),
+            TransactionBroadcastError::Rejected(_)
+        ));
+        assert!(matches!(
+            TransactionBroadcastError::from(ChainError::Other("timeout".to_string())),
+            TransactionBroadcastError::Chain(_)
+        ));
+    }
+}
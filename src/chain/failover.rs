@@ -0,0 +1,261 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use elements::{Block, Transaction};
+use tokio::{task, time};
+use tracing::{debug, error, info, warn};
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::types::{Broadcast, ChainBackend, NetworkInfo};
+use crate::chain::utils::RecentIds;
+use crate::utils::channel::{
+    bounded, Overflow, Receiver, Sender, BLOCK_CHANNEL_SIZE, TRANSACTION_CHANNEL_SIZE,
+};
+
+macro_rules! failover {
+    ($self:ident, $backend:ident => $call:expr) => {{
+        let mut errors = Vec::<String>::new();
+
+        for index in $self.backend_order() {
+            let $backend = &$self.backends[index];
+            match $call.await {
+                Ok(res) => return Ok(res),
+                Err(err) => {
+                    debug!("Chain backend {} failed: {}", $self.names[index], err);
+                    errors.push(format!("{}: {}", $self.names[index], err));
+                }
+            }
+        }
+
+        Err(errors.join(", ").into())
+    }};
+}
+
+#[derive(Clone)]
+pub struct FailoverBackend {
+    names: Arc<Vec<String>>,
+    backends: Arc<Vec<Box<dyn ChainBackend + Send + Sync>>>,
+
+    // Index of the backend that is tried first
+    active: Arc<AtomicUsize>,
+
+    tx_receiver: Receiver<Transaction>,
+    block_receiver: Receiver<Block>,
+}
+
+impl FailoverBackend {
+    pub fn new(
+        names: Vec<String>,
+        backends: Vec<Box<dyn ChainBackend + Send + Sync>>,
+    ) -> FailoverBackend {
+        let (tx_sender, tx_receiver) = bounded::<Transaction>(
+            "failover_transactions",
+            TRANSACTION_CHANNEL_SIZE,
+            Overflow::Drop,
+        );
+        let (block_sender, block_receiver) =
+            bounded::<Block>("failover_blocks", BLOCK_CHANNEL_SIZE, Overflow::Wait);
+
+        let recent_txs = Arc::new(Mutex::new(RecentIds::new()));
+        let recent_blocks = Arc::new(Mutex::new(RecentIds::new()));
+
+        // Notifications of all backends are forwarded, so none are missed while failing over
+        for backend in backends.iter() {
+            Self::forward(
+                backend.get_tx_receiver(),
+                tx_sender.clone(),
+                recent_txs.clone(),
+                |tx| tx.txid().to_string(),
+            );
+            Self::forward(
+                backend.get_block_receiver(),
+                block_sender.clone(),
+                recent_blocks.clone(),
+                |block| block.block_hash().to_string(),
+            );
+        }
+
+        FailoverBackend {
+            tx_receiver,
+            block_receiver,
+            names: Arc::new(names),
+            backends: Arc::new(backends),
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn start_health_check(&self, interval_seconds: u64) {
+        let clone = self.clone();
+
+        task::spawn(async move {
+            info!(
+                "Checking health of chain backends every {} seconds",
+                interval_seconds
+            );
+            let mut interval = time::interval(Duration::from_secs(interval_seconds));
+
+            loop {
+                interval.tick().await;
+
+                let mut healthy = None;
+
+                // The order of the configuration is the order of preference
+                for (index, backend) in clone.backends.iter().enumerate() {
+                    match backend.get_block_count().await {
+                        Ok(_) => {
+                            healthy = Some(index);
+                            break;
+                        }
+                        Err(err) => {
+                            warn!("Chain backend {} is unhealthy: {}", clone.names[index], err);
+                        }
+                    };
+                }
+
+                match healthy {
+                    Some(index) => {
+                        let previous = clone.active.swap(index, Ordering::SeqCst);
+                        if previous != index {
+                            info!(
+                                "Switched chain backend from {} to {}",
+                                clone.names[previous], clone.names[index]
+                            );
+                        }
+                    }
+                    None => {
+                        error!("No chain backend is healthy");
+                    }
+                }
+            }
+        });
+    }
+
+    fn backend_order(&self) -> Vec<usize> {
+        let active = self.active.load(Ordering::SeqCst);
+
+        let mut order = vec![active];
+        order.extend((0..self.backends.len()).filter(|index| *index != active));
+
+        order
+    }
+
+    fn forward<T: Send + 'static>(
+        receiver: Receiver<T>,
+        sender: Sender<T>,
+        recent: Arc<Mutex<RecentIds>>,
+        get_id: fn(&T) -> String,
+    ) {
+        task::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                if !recent.lock().unwrap().insert(get_id(&msg)) {
+                    continue;
+                }
+
+                if let Err(err) = sender.send(msg).await {
+                    warn!("Could not forward chain backend notification: {}", err);
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl ChainBackend for FailoverBackend {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
+        failover!(self, backend => backend.get_network_info())
+    }
+
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
+        failover!(self, backend => backend.get_block_count())
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
+        failover!(self, backend => backend.get_block_hash(height))
+    }
+
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
+        failover!(self, backend => backend.get_block(hash.clone()))
+    }
+
+    async fn get_block_hashes(&self, heights: Vec<u64>) -> Result<Vec<String>, ChainError> {
+        failover!(self, backend => backend.get_block_hashes(heights.clone()))
+    }
+
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, ChainError> {
+        failover!(self, backend => backend.get_blocks(hashes.clone()))
+    }
+
+    async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
+        Ok(self.broadcast_transaction(hex).await?.tx_id)
+    }
+
+    async fn broadcast_transaction(
+        &self,
+        hex: String,
+    ) -> Result<Broadcast, TransactionBroadcastError> {
+        let mut first_error = None;
+
+        for index in self.backend_order() {
+            match self.backends[index]
+                .broadcast_transaction(hex.clone())
+                .await
+            {
+                Ok(res) => {
+                    return Ok(Broadcast {
+                        tx_id: res.tx_id,
+                        path: Some(match res.path {
+                            Some(path) => format!("{} ({})", self.names[index], path),
+                            None => self.names[index].clone(),
+                        }),
+                    })
+                }
+                Err(err) => {
+                    // No point in trying another backend when it would reject the transaction too
+                    if !err.is_retryable() {
+                        return Err(err);
+                    }
+
+                    warn!(
+                        "Chain backend {} could not broadcast: {}",
+                        self.names[index], err
+                    );
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        // The error of the preferred backend is the most relevant one
+        Err(first_error.unwrap_or(TransactionBroadcastError::Chain(
+            "no chain backend configured".into(),
+        )))
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
+        failover!(self, backend => backend.get_transaction(hash.clone()))
+    }
+
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        failover!(self, backend => backend.get_mempool_transactions())
+    }
+
+    async fn get_script_transactions(
+        &self,
+        script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        failover!(self, backend => backend.get_script_transactions(script.clone()))
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        failover!(self, backend => backend.estimate_fee(target_blocks))
+    }
+
+    fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.tx_receiver.clone()
+    }
+
+    fn get_block_receiver(&self) -> Receiver<Block> {
+        self.block_receiver.clone()
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::{Arc, RwLock};
+
+use tracing::{trace, warn};
+
+use crate::chain::types::ChainBackend;
+
+// Conversion from BTC/kvB, which is used by Elements and Electrum, to sat/vbyte
+const BTC_PER_KVB_TO_SAT_PER_VBYTE: f64 = 100_000.0;
+
+pub const DEFAULT_TARGET_BLOCKS: u16 = 2;
+// Bounds in sat/vbyte; the floor is the default minimal relay fee of Elements
+pub const DEFAULT_FLOOR: f64 = 0.1;
+pub const DEFAULT_CEILING: f64 = 100.0;
+
+pub fn btc_per_kvb_to_sat_per_vbyte(fee: f64) -> f64 {
+    fee * BTC_PER_KVB_TO_SAT_PER_VBYTE
+}
+
+/// Cheap to clone; the settings are shared between the clones, so that updating them
+/// affects the claimer and the API alike
+#[derive(Clone)]
+pub struct FeeEstimator {
+    chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    settings: Arc<RwLock<FeeSettings>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FeeSettings {
+    target_blocks: u16,
+
+    // Bounds of the estimation in sat/vbyte
+    floor: f64,
+    ceiling: f64,
+}
+
+impl FeeEstimator {
+    pub fn new(
+        chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
+        target_blocks: u16,
+        floor: f64,
+        ceiling: f64,
+    ) -> FeeEstimator {
+        FeeEstimator {
+            chain_client,
+            settings: Arc::new(RwLock::new(FeeSettings {
+                target_blocks,
+                floor,
+                ceiling,
+            })),
+        }
+    }
+
+    /// Replaces the target and bounds of the estimation for all clones
+    pub fn update(&self, target_blocks: u16, floor: f64, ceiling: f64) {
+        *self.settings.write().unwrap() = FeeSettings {
+            target_blocks,
+            floor,
+            ceiling,
+        };
+    }
+
+    /// Fee estimation in sat/vbyte; falls back to the floor when the chain backend
+    /// cannot estimate fees
+    pub async fn estimate(&self) -> f64 {
+        let settings = *self.settings.read().unwrap();
+
+        let estimation = match self.chain_client.estimate_fee(settings.target_blocks).await {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not estimate fee: {}", err);
+                return settings.floor;
+            }
+        };
+        trace!(
+            "Estimated fee for {} blocks: {} sat/vbyte",
+            settings.target_blocks,
+            estimation
+        );
+
+        settings.clamp(estimation)
+    }
+
+    #[cfg(test)]
+    fn clamp(&self, fee: f64) -> f64 {
+        self.settings.read().unwrap().clamp(fee)
+    }
+}
+
+impl FeeSettings {
+    fn clamp(&self, fee: f64) -> f64 {
+        fee.max(self.floor).min(self.ceiling)
+    }
+}
+
+#[cfg(test)]
+mod fee_test {
+    use std::sync::Arc;
+
+    use crate::chain::esplora::EsploraClient;
+    use crate::chain::fee::{btc_per_kvb_to_sat_per_vbyte, FeeEstimator};
+    use crate::utils::retry::RetryPolicy;
+
+    #[test]
+    fn test_btc_per_kvb_to_sat_per_vbyte() {
+        assert!((btc_per_kvb_to_sat_per_vbyte(0.000001) - 0.1).abs() < 1e-9);
+        assert_eq!(btc_per_kvb_to_sat_per_vbyte(0.0001), 10.0);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let estimator = FeeEstimator::new(
+            Arc::new(Box::new(
+                EsploraClient::new(
+                    "http://127.0.0.1".to_string(),
+                    0,
+                    0,
+                    None,
+                    reqwest::Client::new(),
+                    RetryPolicy::default(),
+                )
+                .unwrap(),
+            )),
+            2,
+            0.1,
+            10.0,
+        );
+
+        assert_eq!(estimator.clamp(0.01), 0.1);
+        assert_eq!(estimator.clamp(1.5), 1.5);
+        assert_eq!(estimator.clamp(100.0), 10.0);
+
+        // Clones share the updated bounds
+        estimator.clone().update(2, 1.0, 5.0);
+        assert_eq!(estimator.clamp(0.01), 1.0);
+        assert_eq!(estimator.clamp(100.0), 5.0);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use elements::{Address, AddressParams, Block, Script, Transaction};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::{task, time};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::boltz::api::Client;
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::chain::esplora::EsploraClient;
+use crate::chain::network::{address_params, detect_network};
+use crate::chain::types::{Broadcast, ChainBackend, NetworkInfo};
+use crate::db;
+use crate::db::helpers::get_pending_covenant_scripts;
+use crate::utils::channel::{
+    bounded, Overflow, Receiver, Sender, BLOCK_CHANNEL_SIZE, TRANSACTION_CHANNEL_SIZE,
+};
+use crate::utils::retry::RetryPolicy;
+
+const RECONNECT_DELAY: u64 = 5;
+
+#[derive(Clone)]
+pub struct MempoolClient {
+    // The REST API of mempool.space is compatible with Esplora
+    esplora: EsploraClient,
+
+    ws_endpoint: String,
+    poll_interval: u64,
+
+    db: db::Pool,
+    address_params: Option<&'static AddressParams>,
+
+    last_height: Arc<AtomicU64>,
+    tracked_addresses: Arc<Mutex<HashSet<String>>>,
+
+    tx_sender: Sender<Transaction>,
+    tx_receiver: Receiver<Transaction>,
+
+    block_sender: Sender<Block>,
+    block_receiver: Receiver<Block>,
+}
+
+impl MempoolClient {
+    pub fn new(
+        endpoint: String,
+        poll_interval: u64,
+        max_reqs_per_second: u64,
+        boltz_client: Option<Client>,
+        db: db::Pool,
+        http_client: reqwest::Client,
+        retry_policy: RetryPolicy,
+    ) -> Result<Self, ChainError> {
+        let (tx_sender, tx_receiver) = bounded::<Transaction>(
+            "mempool_transactions",
+            TRANSACTION_CHANNEL_SIZE,
+            Overflow::Drop,
+        );
+        let (block_sender, block_receiver) =
+            bounded::<Block>("mempool_blocks", BLOCK_CHANNEL_SIZE, Overflow::Wait);
+
+        let endpoint = crate::utils::string::trim_suffix(endpoint, '/');
+
+        Ok(MempoolClient {
+            db,
+            tx_sender,
+            tx_receiver,
+            block_sender,
+            poll_interval,
+            block_receiver,
+            address_params: None,
+            ws_endpoint: websocket_endpoint(&endpoint)?,
+            last_height: Arc::new(AtomicU64::new(0)),
+            tracked_addresses: Arc::new(Mutex::new(HashSet::new())),
+            esplora: EsploraClient::new(
+                endpoint,
+                poll_interval,
+                max_reqs_per_second,
+                boltz_client,
+                http_client,
+                retry_policy,
+            )?,
+        })
+    }
+
+    /// Address params of the network; detected when connecting if not set
+    pub fn with_address_params(mut self, address_params: &'static AddressParams) -> Self {
+        self.address_params = Some(address_params);
+        self
+    }
+
+    pub async fn connect(mut self) -> Result<Self, ChainError> {
+        if self.address_params.is_none() {
+            let network = detect_network(&self.esplora).await?;
+            self.address_params = address_params(network);
+        }
+
+        let block_count = self.esplora.get_block_count().await?;
+        self.last_height.store(block_count, Ordering::SeqCst);
+
+        let clone = self.clone();
+        task::spawn(async move {
+            loop {
+                match clone.run_websocket().await {
+                    Ok(_) => warn!("mempool.space WebSocket closed"),
+                    Err(err) => error!("mempool.space WebSocket failed: {}", err),
+                };
+
+                debug!(
+                    "Reconnecting to mempool.space WebSocket in {}s",
+                    RECONNECT_DELAY
+                );
+                time::sleep(Duration::from_secs(RECONNECT_DELAY)).await;
+            }
+        });
+
+        Ok(self)
+    }
+
+    async fn run_websocket(&self) -> Result<(), ChainError> {
+        let (stream, _) = connect_async(self.ws_endpoint.as_str()).await?;
+        info!("Connected to mempool.space WebSocket: {}", self.ws_endpoint);
+
+        let (mut write, mut read) = stream.split();
+        write
+            .send(Message::Text(
+                json!({"action": "want", "data": ["blocks"]}).to_string(),
+            ))
+            .await?;
+
+        // Tracked addresses are per connection
+        self.tracked_addresses.lock().unwrap().clear();
+
+        // Blocks and transactions could have been missed while disconnected
+        self.handle_new_tip(self.esplora.get_block_count().await?)
+            .await;
+        for tx in self.esplora.get_mempool_transactions().await? {
+            if let Err(err) = self.tx_sender.send(tx).await {
+                warn!("Could not send transaction update: {}", err);
+            }
+        }
+
+        let mut interval = time::interval(Duration::from_secs(self.poll_interval));
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Some(addresses) = self.addresses_to_track().await {
+                        trace!("Tracking {} addresses", addresses.len());
+                        write
+                            .send(Message::Text(json!({"track-addresses": addresses}).to_string()))
+                            .await?;
+                    }
+                }
+                msg = read.next() => {
+                    match msg {
+                        Some(Ok(Message::Text(text))) => self.handle_message(text).await,
+                        Some(Ok(Message::Close(_))) | None => return Ok(()),
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    async fn addresses_to_track(&self) -> Option<Vec<String>> {
+        let address_params = self.address_params?;
+
+        let scripts = match get_pending_covenant_scripts(self.db.clone()).await {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not get pending covenants: {}", err);
+                return None;
+            }
+        };
+
+        let addresses: HashSet<String> = scripts
+            .into_iter()
+            .filter_map(|script| Address::from_script(&Script::from(script), None, address_params))
+            .map(|address| address.to_string())
+            .collect();
+
+        let mut tracked = self.tracked_addresses.lock().unwrap();
+        if *tracked == addresses {
+            return None;
+        }
+
+        // The list of tracked addresses is replaced with every message
+        *tracked = addresses.clone();
+        Some(addresses.into_iter().collect())
+    }
+
+    async fn handle_message(&self, text: String) {
+        let message = match serde_json::from_str::<Value>(text.as_str()) {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not parse mempool.space message: {}", err);
+                return;
+            }
+        };
+
+        if let Some(height) = message
+            .get("block")
+            .and_then(|block| block.get("height"))
+            .and_then(|height| height.as_u64())
+        {
+            self.handle_new_tip(height).await;
+        }
+
+        if let Some(Value::Object(addresses)) = message.get("multi-address-transactions") {
+            for changes in addresses.values() {
+                for key in ["mempool", "confirmed"] {
+                    if let Some(Value::Array(txs)) = changes.get(key) {
+                        self.handle_transactions(txs).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_transactions(&self, txs: &[Value]) {
+        for tx_id in txs.iter().filter_map(|tx| tx.get("txid")?.as_str()) {
+            let tx = match self.esplora.get_transaction(tx_id.to_string()).await {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("Could not get transaction {}: {}", tx_id, err);
+                    continue;
+                }
+            };
+
+            match self.tx_sender.send(tx).await {
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Could not send transaction update: {}", err);
+                }
+            };
+        }
+    }
+
+    async fn handle_new_tip(&self, height: u64) {
+        let last_height = self.last_height.fetch_max(height, Ordering::SeqCst);
+
+        for height in last_height + 1..height + 1 {
+            let block_hash = match self.esplora.get_block_hash(height).await {
+                Ok(hash) => hash,
+                Err(err) => {
+                    warn!("Could not get block hash for height {}: {}", height, err);
+                    continue;
+                }
+            };
+            let block = match self.esplora.get_block(block_hash.clone()).await {
+                Ok(block) => block,
+                Err(err) => {
+                    warn!("Could not get block with hash {}: {}", block_hash, err);
+                    continue;
+                }
+            };
+            trace!(
+                "Got block {} ({})",
+                block.header.height,
+                block.header.block_hash()
+            );
+            match self.block_sender.send(block).await {
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("Could not send block update: {}", err);
+                    continue;
+                }
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for MempoolClient {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError> {
+        // Send some request to make the endpoint is valid
+        self.get_block_count().await?;
+
+        Ok(NetworkInfo {
+            subversion: "mempool.space".to_string(),
+        })
+    }
+
+    async fn get_block_count(&self) -> Result<u64, ChainError> {
+        self.esplora.get_block_count().await
+    }
+
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError> {
+        self.esplora.get_block_hash(height).await
+    }
+
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError> {
+        self.esplora.get_block(hash).await
+    }
+
+    async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError> {
+        self.esplora.send_raw_transaction(hex).await
+    }
+
+    async fn broadcast_transaction(
+        &self,
+        hex: String,
+    ) -> Result<Broadcast, TransactionBroadcastError> {
+        self.esplora.broadcast_transaction(hex).await
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError> {
+        self.esplora.get_transaction(hash).await
+    }
+
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError> {
+        self.esplora.get_mempool_transactions().await
+    }
+
+    async fn get_script_transactions(
+        &self,
+        script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        self.esplora.get_script_transactions(script).await
+    }
+
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError> {
+        self.esplora.estimate_fee(target_blocks).await
+    }
+
+    fn get_tx_receiver(&self) -> Receiver<Transaction> {
+        self.tx_receiver.clone()
+    }
+
+    fn get_block_receiver(&self) -> Receiver<Block> {
+        self.block_receiver.clone()
+    }
+}
+
+fn websocket_endpoint(endpoint: &str) -> Result<String, ChainError> {
+    let ws_endpoint = if let Some(rest) = endpoint.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = endpoint.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        return Err(format!("invalid mempool.space endpoint: {}", endpoint).into());
+    };
+
+    Ok(format!("{}/v1/ws", ws_endpoint))
+}
+
+#[cfg(test)]
+mod mempool_test {
+    use crate::chain::mempool::websocket_endpoint;
+
+    #[test]
+    fn test_websocket_endpoint() {
+        assert_eq!(
+            websocket_endpoint("https://liquid.network/api").unwrap(),
+            "wss://liquid.network/api/v1/ws"
+        );
+        assert_eq!(
+            websocket_endpoint("http://127.0.0.1:8999/api").unwrap(),
+            "ws://127.0.0.1:8999/api/v1/ws"
+        );
+    }
+
+    #[test]
+    fn test_websocket_endpoint_invalid() {
+        assert_eq!(
+            websocket_endpoint("liquid.network/api")
+                .err()
+                .unwrap()
+                .to_string(),
+            "invalid mempool.space endpoint: liquid.network/api"
+        );
+    }
+}
@@ -1,5 +1,15 @@
+pub mod cache;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod client;
+pub mod electrum;
+pub mod endpoints;
+pub mod error;
 pub mod esplora;
+pub mod failover;
+pub mod fee;
+pub mod mempool;
+pub mod network;
 pub mod types;
 mod utils;
 mod zmq;
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+use elements::bech32::Hrp;
+use elements::{AddressParams, AssetId};
+use tracing::{debug, info, warn};
+
+use crate::chain::types::ChainBackend;
+
+pub const AUTO_DETECT: &str = "auto";
+pub const CUSTOM: &str = "custom";
+
+const LIQUID_GENESIS_HASH: &str =
+    "1466275836220db2944ca059a3a10ef6fd2ea684b0688d2c379296888a206003";
+const LIQUID_TESTNET_GENESIS_HASH: &str =
+    "a771da8e52ee6ad581ed1e9a99825e5b3b7992225534eaa2ae23244fe26ab1c1";
+
+// L-BTC, in which fees are paid
+const LIQUID_POLICY_ASSET: &str =
+    "6f0279e9ed041c3d710a9f57d0c02928416460c4b722ae3457a11eec381c526d";
+const LIQUID_TESTNET_POLICY_ASSET: &str =
+    "144c654344aa716d6f3abcc1ca90e5641e4e2a7f633bc09fe3baf64585819a49";
+const ELEMENTS_REGTEST_POLICY_ASSET: &str =
+    "5ac9f65c0efcc4775e0baec4ec03abdde22473cd3cf33c0419ca290e0751b225";
+
+pub fn policy_asset(network: &str) -> Option<AssetId> {
+    let asset = match network {
+        "mainnet" => LIQUID_POLICY_ASSET,
+        "testnet" => LIQUID_TESTNET_POLICY_ASSET,
+        "regtest" => ELEMENTS_REGTEST_POLICY_ASSET,
+        _ => return None,
+    };
+
+    Some(AssetId::from_str(asset).unwrap())
+}
+
+pub fn address_params(network: &str) -> Option<&'static AddressParams> {
+    match network {
+        "mainnet" => Some(&AddressParams::LIQUID),
+        "testnet" => Some(&AddressParams::LIQUID_TESTNET),
+        "regtest" => Some(&AddressParams::ELEMENTS),
+        _ => None,
+    }
+}
+
+/// Address parameters of chains other than Liquid and Elements regtest;
+/// leaked because they are needed for the whole lifetime of the process
+pub fn custom_address_params(
+    p2pkh_prefix: u8,
+    p2sh_prefix: u8,
+    blinded_prefix: u8,
+    bech_hrp: &str,
+    blech_hrp: &str,
+) -> Result<&'static AddressParams, String> {
+    let params = AddressParams {
+        p2pkh_prefix,
+        p2sh_prefix,
+        blinded_prefix,
+        bech_hrp: Hrp::parse(bech_hrp)
+            .map_err(|err| format!("invalid bech32 HRP {}: {}", bech_hrp, err))?,
+        blech_hrp: Hrp::parse(blech_hrp)
+            .map_err(|err| format!("invalid blech32 HRP {}: {}", blech_hrp, err))?,
+    };
+
+    Ok(Box::leak(Box::new(params)))
+}
+
+// The genesis block of regtest chains depends on their parameters
+fn expected_genesis_hash(network: &str) -> Option<&'static str> {
+    match network {
+        "mainnet" => Some(LIQUID_GENESIS_HASH),
+        "testnet" => Some(LIQUID_TESTNET_GENESIS_HASH),
+        _ => None,
+    }
+}
+
+fn network_of_genesis_hash(genesis_hash: &str) -> &'static str {
+    match genesis_hash {
+        LIQUID_GENESIS_HASH => "mainnet",
+        LIQUID_TESTNET_GENESIS_HASH => "testnet",
+        _ => "regtest",
+    }
+}
+
+async fn get_genesis_hash(
+    chain_client: &(dyn ChainBackend + Send + Sync),
+) -> Result<String, String> {
+    chain_client
+        .get_block_hash(0)
+        .await
+        .map_err(|err| format!("could not get genesis block: {}", err))
+}
+
+/// Makes sure the chain backend is on the configured network, because addresses
+/// of the wrong network would never match any lockup transaction
+pub async fn verify_network(
+    chain_client: &(dyn ChainBackend + Send + Sync),
+    network: &str,
+) -> Result<(), String> {
+    let expected = match expected_genesis_hash(network) {
+        Some(res) => res,
+        None => {
+            debug!("Not verifying genesis block of network {}", network);
+            return Ok(());
+        }
+    };
+
+    let genesis_hash = get_genesis_hash(chain_client).await?;
+    if genesis_hash != expected {
+        return Err(format!(
+            "genesis block {} of chain backend does not match network {}",
+            genesis_hash, network
+        ));
+    }
+
+    info!("Verified chain backend is on network {}", network);
+    Ok(())
+}
+
+/// Derives the network from the genesis block of the chain backend;
+/// unknown genesis blocks are assumed to be regtest chains
+pub async fn detect_network(
+    chain_client: &(dyn ChainBackend + Send + Sync),
+) -> Result<&'static str, String> {
+    let genesis_hash = get_genesis_hash(chain_client).await?;
+    let network = network_of_genesis_hash(genesis_hash.as_str());
+
+    if network == "regtest" {
+        warn!(
+            "Unknown genesis block {}; assuming regtest network",
+            genesis_hash
+        );
+    } else {
+        info!("Detected network {} of chain backend", network);
+    }
+
+    Ok(network)
+}
+
+#[cfg(test)]
+mod network_test {
+    use elements::{AddressParams, AssetId};
+
+    use crate::chain::network::{
+        address_params, custom_address_params, expected_genesis_hash, network_of_genesis_hash,
+        policy_asset, LIQUID_GENESIS_HASH, LIQUID_TESTNET_GENESIS_HASH,
+    };
+
+    #[test]
+    fn test_policy_asset() {
+        assert_eq!(policy_asset("mainnet"), Some(AssetId::LIQUID_BTC));
+        assert!(policy_asset("testnet").is_some());
+        assert!(policy_asset("regtest").is_some());
+        assert_eq!(policy_asset("custom"), None);
+    }
+
+    #[test]
+    fn test_address_params() {
+        assert_eq!(address_params("mainnet"), Some(&AddressParams::LIQUID));
+        assert_eq!(
+            address_params("testnet"),
+            Some(&AddressParams::LIQUID_TESTNET)
+        );
+        assert_eq!(address_params("regtest"), Some(&AddressParams::ELEMENTS));
+        assert_eq!(address_params("signet"), None);
+    }
+
+    #[test]
+    fn test_custom_address_params() {
+        let params = custom_address_params(57, 39, 12, "ex", "lq").unwrap();
+
+        assert_eq!(params.p2pkh_prefix, 57);
+        assert_eq!(params.p2sh_prefix, 39);
+        assert_eq!(params.blinded_prefix, 12);
+        assert_eq!(params.bech_hrp.to_string(), "ex");
+        assert_eq!(params.blech_hrp.to_string(), "lq");
+        assert_eq!(*params, AddressParams::LIQUID);
+    }
+
+    #[test]
+    fn test_custom_address_params_invalid_hrp() {
+        assert!(custom_address_params(57, 39, 12, "", "lq")
+            .err()
+            .unwrap()
+            .starts_with("invalid bech32 HRP"));
+    }
+
+    #[test]
+    fn test_expected_genesis_hash() {
+        assert_eq!(expected_genesis_hash("mainnet"), Some(LIQUID_GENESIS_HASH));
+        assert_eq!(
+            expected_genesis_hash("testnet"),
+            Some(LIQUID_TESTNET_GENESIS_HASH)
+        );
+        assert_eq!(expected_genesis_hash("regtest"), None);
+    }
+
+    #[test]
+    fn test_network_of_genesis_hash() {
+        assert_eq!(network_of_genesis_hash(LIQUID_GENESIS_HASH), "mainnet");
+        assert_eq!(
+            network_of_genesis_hash(LIQUID_TESTNET_GENESIS_HASH),
+            "testnet"
+        );
+        assert_eq!(
+            network_of_genesis_hash(
+                "00902a6b70c2ca83b5d9c815d96a0e2f4202179316970d14ea1847dae5b1ca21"
+            ),
+            "regtest"
+        );
+    }
+}
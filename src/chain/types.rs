@@ -1,46 +1,68 @@
 use axum::async_trait;
-use crossbeam_channel::Receiver;
 use elements::{Block, Transaction};
 use serde::Deserialize;
-use std::error::Error;
-use std::fmt;
 
-#[derive(Debug)]
-pub struct TransactionBroadcastError {
-    pub err: Box<dyn Error>,
-}
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::utils::channel::Receiver;
 
-impl TransactionBroadcastError {
-    pub fn is_already_included(&self) -> bool {
-        matches!(
-            format!("{}", self).as_str(),
-            "Transaction already in block chain"
-                | "bad-txns-inputs-missingorspent"
-                | "insufficient fee, rejecting replacement"
-        )
-    }
+pub struct Broadcast {
+    pub tx_id: String,
+    // Set by backends that can broadcast with more than one endpoint
+    pub path: Option<String>,
 }
 
-impl fmt::Display for TransactionBroadcastError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.err)
+#[async_trait]
+pub trait ChainBackend {
+    async fn get_network_info(&self) -> Result<NetworkInfo, ChainError>;
+    async fn get_block_count(&self) -> Result<u64, ChainError>;
+    async fn get_block_hash(&self, height: u64) -> Result<String, ChainError>;
+    async fn get_block(&self, hash: String) -> Result<Block, ChainError>;
+
+    // Backends that can batch requests should override these
+    async fn get_block_hashes(&self, heights: Vec<u64>) -> Result<Vec<String>, ChainError> {
+        let mut hashes = Vec::with_capacity(heights.len());
+        for height in heights {
+            hashes.push(self.get_block_hash(height).await?);
+        }
+
+        Ok(hashes)
     }
-}
 
-impl From<Box<dyn Error>> for TransactionBroadcastError {
-    fn from(value: Box<dyn Error>) -> Self {
-        TransactionBroadcastError { err: value }
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, ChainError> {
+        let mut blocks = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            blocks.push(self.get_block(hash).await?);
+        }
+
+        Ok(blocks)
     }
-}
 
-#[async_trait]
-pub trait ChainBackend {
-    async fn get_network_info(&self) -> Result<NetworkInfo, Box<dyn Error>>;
-    async fn get_block_count(&self) -> Result<u64, Box<dyn Error>>;
-    async fn get_block_hash(&self, height: u64) -> Result<String, Box<dyn Error>>;
-    async fn get_block(&self, hash: String) -> Result<Block, Box<dyn Error>>;
     async fn send_raw_transaction(&self, hex: String) -> Result<String, TransactionBroadcastError>;
-    async fn get_transaction(&self, hash: String) -> Result<Transaction, Box<dyn Error>>;
+
+    // Backends that fall back to other endpoints should override this to report which one was used
+    async fn broadcast_transaction(
+        &self,
+        hex: String,
+    ) -> Result<Broadcast, TransactionBroadcastError> {
+        Ok(Broadcast {
+            tx_id: self.send_raw_transaction(hex).await?,
+            path: None,
+        })
+    }
+
+    async fn get_transaction(&self, hash: String) -> Result<Transaction, ChainError>;
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, ChainError>;
+
+    // Transactions in the history of an output script; None when the backend has no index of scripts
+    async fn get_script_transactions(
+        &self,
+        _script: Vec<u8>,
+    ) -> Result<Option<Vec<Transaction>>, ChainError> {
+        Ok(None)
+    }
+
+    // Fee estimation in sat/vbyte for confirmation within the target
+    async fn estimate_fee(&self, target_blocks: u16) -> Result<f64, ChainError>;
 
     fn get_tx_receiver(&self) -> Receiver<Transaction>;
     fn get_block_receiver(&self) -> Receiver<Block>;
@@ -51,6 +73,12 @@ pub struct NetworkInfo {
     pub subversion: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct SmartFeeEstimation {
+    pub feerate: Option<f64>,
+    pub errors: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct ZmqNotification {
     #[serde(rename = "type")]
@@ -1,14 +1,73 @@
-use std::error::Error;
+use elements::hashes::{sha256, Hash};
+use std::collections::{HashSet, VecDeque};
 
-pub fn parse_hex<T: elements::encode::Decodable>(hex_str: String) -> Result<T, Box<dyn Error>> {
-    match elements::encode::deserialize(
-        match hex::decode(hex_str) {
-            Ok(res) => res,
-            Err(err) => return Err(Box::new(err)),
+use crate::chain::error::ChainError;
+
+// How many block hashes and transaction ids are remembered to deduplicate notifications
+const RECENT_IDS_SIZE: usize = 1024;
+
+pub fn parse_hex<T: elements::encode::Decodable>(hex_str: String) -> Result<T, ChainError> {
+    Ok(elements::encode::deserialize(
+        hex::decode(hex_str)?.as_ref(),
+    )?)
+}
+
+/// Script hash in the format of Electrum and Esplora: SHA256 with reversed byte order
+pub fn script_hash(script: &[u8]) -> String {
+    let mut hash = sha256::Hash::hash(script).to_byte_array();
+    hash.reverse();
+
+    hex::encode(hash)
+}
+
+/// Bounded set of the most recently seen block hashes or transaction ids
+pub struct RecentIds {
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl RecentIds {
+    pub fn new() -> RecentIds {
+        RecentIds {
+            ids: HashSet::new(),
+            order: VecDeque::new(),
         }
-        .as_ref(),
-    ) {
-        Ok(block) => Ok(block),
-        Err(e) => Err(Box::new(e)),
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    // Returns false if the id has been seen recently
+    pub fn insert(&mut self, id: String) -> bool {
+        if !self.ids.insert(id.clone()) {
+            return false;
+        }
+
+        self.order.push_back(id);
+        if self.order.len() > RECENT_IDS_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod utils_test {
+    use crate::chain::utils::script_hash;
+
+    #[test]
+    fn test_script_hash() {
+        assert_eq!(
+            script_hash(
+                hex::decode("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac")
+                    .unwrap()
+                    .as_slice()
+            ),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
     }
 }
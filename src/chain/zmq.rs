@@ -1,11 +1,32 @@
-use std::error::Error;
+use std::cmp;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crossbeam_channel::{unbounded, Receiver, Sender};
 use elements::{Block, Transaction};
-use log::{debug, error, trace, warn};
-use zeromq::{Socket, SocketRecv, ZmqError, ZmqMessage};
+use tokio::sync::Notify;
+use tokio::time;
+use tracing::{debug, error, info, trace, warn};
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqError, ZmqMessage};
 
+use crate::chain::error::ChainError;
 use crate::chain::types::ZmqNotification;
+use crate::chain::utils::RecentIds;
+use crate::utils::channel::{
+    bounded, Overflow, Receiver, Sender, BLOCK_CHANNEL_SIZE, TRANSACTION_CHANNEL_SIZE,
+};
+
+// Reconnection delays in seconds
+const RECONNECT_DELAY_MIN: u64 = 1;
+const RECONNECT_DELAY_MAX: u64 = 60;
+
+// Seconds to wait for the raw notification of an event announced on the sequence topic,
+// because there is no ordering between different ZMQ sockets
+const SEQUENCE_GRACE_PERIOD: u64 = 5;
+
+const SEQUENCE_BLOCK_CONNECTED: u8 = b'C';
+const SEQUENCE_TRANSACTION_ADDED: u8 = b'A';
 
 #[derive(Clone)]
 pub struct ZmqClient {
@@ -14,115 +35,312 @@ pub struct ZmqClient {
 
     pub tx_sender: Sender<Transaction>,
     pub tx_receiver: Receiver<Transaction>,
+
+    // Height of the latest block that was received
+    pub last_block_height: Arc<AtomicU64>,
+    // Notified after a subscription had to reconnect or a gap in sequence numbers was detected
+    pub missed_notifications: Arc<Notify>,
+    // Transactions announced on the sequence topic that were never received as raw transaction
+    pub missed_transactions: Arc<Mutex<Vec<String>>>,
+
+    // Hashes published by nodes without the raw topics; the transactions and blocks
+    // have to be fetched via RPC and forwarded with forward_transaction and forward_block
+    pub tx_hash_receiver: Receiver<String>,
+    pub block_hash_receiver: Receiver<String>,
+    tx_hash_sender: Sender<String>,
+    block_hash_sender: Sender<String>,
+
+    seen_blocks: Arc<Mutex<RecentIds>>,
+    seen_transactions: Arc<Mutex<RecentIds>>,
 }
 
 impl ZmqClient {
     pub fn new() -> ZmqClient {
-        let (tx_sender, tx_receiver) = unbounded::<Transaction>();
-        let (block_sender, block_receiver) = unbounded::<Block>();
+        let (tx_sender, tx_receiver) =
+            bounded::<Transaction>("zmq_transactions", TRANSACTION_CHANNEL_SIZE, Overflow::Drop);
+        let (block_sender, block_receiver) =
+            bounded::<Block>("zmq_blocks", BLOCK_CHANNEL_SIZE, Overflow::Wait);
+        let (tx_hash_sender, tx_hash_receiver) =
+            bounded::<String>("zmq_tx_hashes", TRANSACTION_CHANNEL_SIZE, Overflow::Drop);
+        let (block_hash_sender, block_hash_receiver) =
+            bounded::<String>("zmq_block_hashes", BLOCK_CHANNEL_SIZE, Overflow::Wait);
 
         ZmqClient {
             tx_sender,
             tx_receiver,
             block_sender,
             block_receiver,
+            tx_hash_sender,
+            tx_hash_receiver,
+            block_hash_sender,
+            block_hash_receiver,
+            last_block_height: Arc::new(AtomicU64::new(0)),
+            missed_notifications: Arc::new(Notify::new()),
+            missed_transactions: Arc::new(Mutex::new(Vec::new())),
+            seen_blocks: Arc::new(Mutex::new(RecentIds::new())),
+            seen_transactions: Arc::new(Mutex::new(RecentIds::new())),
         }
     }
 
-    pub async fn connect(self, notifications: Vec<ZmqNotification>) -> Result<(), Box<dyn Error>> {
-        let raw_tx = match Self::find_notification("pubrawtx", notifications.clone()) {
-            Some(data) => data,
-            None => return Err("pubrawtx ZMQ missing".into()),
-        };
+    pub async fn connect(self, notifications: Vec<ZmqNotification>) -> Result<(), ChainError> {
+        match Self::find_notification("pubrawtx", notifications.clone()) {
+            Some(raw_tx) => {
+                let clone = self.clone();
+                self.subscribe(raw_tx, "rawtx", move |msg| {
+                    let clone = clone.clone();
 
-        let tx_sender = self.tx_sender.clone();
+                    async move {
+                        let tx: Transaction =
+                            match elements::encode::deserialize(msg.get(1).unwrap()) {
+                                Ok(tx) => tx,
+                                Err(e) => {
+                                    warn!("Could not parse transaction: {}", e);
+                                    return;
+                                }
+                            };
 
-        Self::subscribe(raw_tx, "rawtx", move |msg| {
-            let tx: Transaction = match elements::encode::deserialize(msg.get(1).unwrap()) {
-                Ok(tx) => tx,
-                Err(e) => {
-                    warn!("Could not parse transaction: {}", e);
-                    return;
+                        clone.forward_transaction(tx).await;
+                    }
+                })
+                .await?;
+            }
+            None => match Self::find_notification("pubhashtx", notifications.clone()) {
+                Some(hash_tx) => {
+                    info!("pubrawtx ZMQ missing; fetching transactions of pubhashtx via RPC");
+                    let tx_hash_sender = self.tx_hash_sender.clone();
+                    self.subscribe(hash_tx, "hashtx", move |msg| {
+                        Self::forward_hash(tx_hash_sender.clone(), msg)
+                    })
+                    .await?;
                 }
-            };
+                None => return Err("pubrawtx or pubhashtx ZMQ missing".into()),
+            },
+        };
 
-            trace!("Got transaction: {}", tx.txid().to_string());
-            match tx_sender.send(tx) {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("Could not send transaction to channel: {}", e);
-                }
-            };
-        })
-        .await?;
+        match Self::find_notification("pubrawblock", notifications.clone()) {
+            Some(raw_block) => {
+                let clone = self.clone();
+                self.subscribe(raw_block, "rawblock", move |msg| {
+                    let clone = clone.clone();
 
-        let raw_block = match Self::find_notification("pubrawblock", notifications.clone()) {
-            Some(data) => data,
-            None => return Err("pubrawblock ZMQ missing".into()),
-        };
+                    async move {
+                        let block: Block = match elements::encode::deserialize(msg.get(1).unwrap())
+                        {
+                            Ok(block) => block,
+                            Err(e) => {
+                                warn!("Could not parse block: {}", e);
+                                return;
+                            }
+                        };
 
-        let block_sender = self.block_sender.clone();
-        Self::subscribe(raw_block, "rawblock", move |msg| {
-            let block: Block = match elements::encode::deserialize(msg.get(1).unwrap()) {
-                Ok(block) => block,
-                Err(e) => {
-                    warn!("Could not parse block: {}", e);
-                    return;
-                }
-            };
-
-            trace!(
-                "Got block {} ({})",
-                block.header.height,
-                block.header.block_hash()
-            );
-            match block_sender.send(block) {
-                Ok(_) => {}
-                Err(e) => {
-                    warn!("Could not send block to channel: {}", e);
+                        clone.forward_block(block).await;
+                    }
+                })
+                .await?;
+            }
+            None => match Self::find_notification("pubhashblock", notifications.clone()) {
+                Some(hash_block) => {
+                    info!("pubrawblock ZMQ missing; fetching blocks of pubhashblock via RPC");
+                    let block_hash_sender = self.block_hash_sender.clone();
+                    self.subscribe(hash_block, "hashblock", move |msg| {
+                        Self::forward_hash(block_hash_sender.clone(), msg)
+                    })
+                    .await?;
                 }
-            };
-        })
-        .await?;
+                None => return Err("pubrawblock or pubhashblock ZMQ missing".into()),
+            },
+        };
+
+        match Self::find_notification("pubsequence", notifications) {
+            Some(sequence) => {
+                let clone = self.clone();
+                self.subscribe(sequence, "sequence", move |msg| {
+                    clone.handle_sequence(msg);
+                    async {}
+                })
+                .await?;
+            }
+            None => {
+                debug!("pubsequence ZMQ not configured; not subscribing to sequence topic");
+            }
+        };
 
         Ok(())
     }
 
-    async fn subscribe<F>(
+    pub async fn forward_transaction(&self, tx: Transaction) {
+        trace!("Got transaction: {}", tx.txid().to_string());
+        self.seen_transactions
+            .lock()
+            .unwrap()
+            .insert(tx.txid().to_string());
+        match self.tx_sender.send(tx).await {
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Could not send transaction to channel: {}", e);
+            }
+        };
+    }
+
+    pub async fn forward_block(&self, block: Block) {
+        trace!(
+            "Got block {} ({})",
+            block.header.height,
+            block.header.block_hash()
+        );
+        self.last_block_height
+            .fetch_max(block.header.height as u64, Ordering::SeqCst);
+        self.seen_blocks
+            .lock()
+            .unwrap()
+            .insert(block.block_hash().to_string());
+        // Waits when the channel is full, which stops reading from the socket
+        match self.block_sender.send(block).await {
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Could not send block to channel: {}", e);
+            }
+        };
+    }
+
+    async fn forward_hash(sender: Sender<String>, msg: ZmqMessage) {
+        // The hash is sent in the byte order used by RPC
+        let hash = match msg.get(1) {
+            Some(body) if body.len() == 32 => hex::encode(body),
+            _ => {
+                warn!("Got invalid hash notification");
+                return;
+            }
+        };
+
+        trace!("Got hash: {}", hash);
+        if let Err(e) = sender.send(hash).await {
+            warn!("Could not send hash to channel: {}", e);
+        }
+    }
+
+    fn handle_sequence(&self, msg: ZmqMessage) {
+        let body = match msg.get(1) {
+            Some(body) if body.len() > 32 => body,
+            _ => {
+                warn!("Got invalid sequence notification");
+                return;
+            }
+        };
+
+        // The hash is sent in the byte order used by RPC
+        let hash = hex::encode(&body[..32]);
+        let (seen, is_transaction) = match body[32] {
+            SEQUENCE_BLOCK_CONNECTED => (self.seen_blocks.clone(), false),
+            SEQUENCE_TRANSACTION_ADDED => (self.seen_transactions.clone(), true),
+            _ => return,
+        };
+
+        let clone = self.clone();
+        tokio::spawn(async move {
+            time::sleep(Duration::from_secs(SEQUENCE_GRACE_PERIOD)).await;
+
+            if seen.lock().unwrap().contains(&hash) {
+                return;
+            }
+
+            if is_transaction {
+                warn!("Missed ZMQ notification for transaction: {}", hash);
+                clone.missed_transactions.lock().unwrap().push(hash);
+            } else {
+                warn!("Missed ZMQ notification for block: {}", hash);
+            }
+
+            clone.missed_notifications.notify_one();
+        });
+    }
+
+    // Every message ends with a sequence number that is incremented for each message of a topic
+    fn parse_sequence_number(msg: &ZmqMessage) -> Option<u32> {
+        let bytes: [u8; 4] = msg.get(2)?.as_ref().try_into().ok()?;
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    async fn subscribe<F, Fut>(
+        &self,
         notification: ZmqNotification,
         subscription: &str,
         handler: F,
     ) -> Result<(), ZmqError>
     where
-        F: Fn(ZmqMessage) + Send + 'static,
+        F: Fn(ZmqMessage) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
     {
-        debug!(
-            "Connecting to {} ZMQ at {}",
-            subscription, notification.address
-        );
-
-        let mut socket = zeromq::SubSocket::new();
-        socket.connect(notification.address.as_str()).await?;
-
-        socket.subscribe(subscription).await?;
+        let mut socket = Self::connect_socket(notification.address.as_str(), subscription).await?;
+        let subscription = subscription.to_string();
+        let missed_notifications = self.missed_notifications.clone();
 
         tokio::spawn(async move {
+            let mut last_sequence_number: Option<u32> = None;
+
             loop {
                 match socket.recv().await {
                     Ok(recv) => {
-                        handler(recv);
+                        if let Some(sequence_number) = Self::parse_sequence_number(&recv) {
+                            if let Some(last) = last_sequence_number {
+                                if sequence_number != last.wrapping_add(1) {
+                                    warn!(
+                                        "Gap in {} ZMQ sequence numbers: {} -> {}",
+                                        subscription, last, sequence_number
+                                    );
+                                    missed_notifications.notify_one();
+                                }
+                            }
+
+                            last_sequence_number = Some(sequence_number);
+                        }
+
+                        handler(recv).await;
+                        continue;
                     }
                     Err(e) => {
-                        error!("Error receiving data: {}", e);
-                        break;
+                        error!("Error receiving {} data: {}", subscription, e);
                     }
-                }
+                };
+
+                socket = Self::reconnect(notification.address.as_str(), &subscription).await;
+                missed_notifications.notify_one();
             }
         });
 
         Ok(())
     }
 
+    async fn reconnect(address: &str, subscription: &str) -> SubSocket {
+        let mut delay = RECONNECT_DELAY_MIN;
+
+        loop {
+            debug!("Reconnecting to {} ZMQ in {} seconds", subscription, delay);
+            time::sleep(Duration::from_secs(delay)).await;
+
+            match Self::connect_socket(address, subscription).await {
+                Ok(socket) => {
+                    info!("Reconnected to {} ZMQ", subscription);
+                    return socket;
+                }
+                Err(e) => {
+                    warn!("Could not reconnect to {} ZMQ: {}", subscription, e);
+                    delay = cmp::min(delay * 2, RECONNECT_DELAY_MAX);
+                }
+            }
+        }
+    }
+
+    async fn connect_socket(address: &str, subscription: &str) -> Result<SubSocket, ZmqError> {
+        debug!("Connecting to {} ZMQ at {}", subscription, address);
+
+        let mut socket = SubSocket::new();
+        socket.connect(address).await?;
+        socket.subscribe(subscription).await?;
+
+        Ok(socket)
+    }
+
     fn find_notification(
         to_find: &str,
         notifications: Vec<ZmqNotification>,
@@ -1,358 +1,1210 @@
-use std::error::Error;
 use std::ops::Sub;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use diesel::internal::derives::multiconnection::chrono::{TimeDelta, Utc};
 use elements::bitcoin::Witness;
 use elements::confidential::{Asset, AssetBlindingFactor, Nonce, Value, ValueBlindingFactor};
 use elements::script::Builder;
 use elements::secp256k1_zkp::rand::rngs::OsRng;
-use elements::secp256k1_zkp::SecretKey;
+use elements::secp256k1_zkp::rand::Rng;
+use elements::secp256k1_zkp::{All, Generator, PedersenCommitment, Secp256k1, SecretKey};
 use elements::{
-    opcodes, AddressParams, LockTime, OutPoint, Script, Sequence, Transaction, TxIn, TxInWitness,
-    TxOut, TxOutWitness,
+    opcodes, AddressParams, AssetId, LockTime, OutPoint, Script, Sequence, Transaction, TxIn,
+    TxInWitness, TxOut, TxOutSecrets, TxOutWitness, Txid,
 };
-use log::{debug, error, info, trace, warn};
+use metrics::{counter, histogram};
+use tokio::sync::Notify;
 use tokio::time;
+use tracing::{debug, error, info, instrument, trace, warn};
 
-use crate::chain::types::ChainBackend;
+use crate::chain::fee::FeeEstimator;
+use crate::chain::types::{Broadcast, ChainBackend};
+use crate::claimer::error::ClaimError;
+use crate::claimer::find_spend;
 use crate::claimer::tree::SwapTree;
 use crate::db;
-use crate::db::models::PendingCovenant;
+use crate::db::models::{LockupOutput, NewCovenantEvent, PendingCovenant, PendingCovenantStatus};
+use crate::notifier;
+use crate::notifier::{Notification, NotificationKind, Notifier};
+use crate::utils::systemd::Heartbeat;
 
+// Seconds for which other instances sharing the database do not broadcast a claim
+const CLAIM_LEASE_TIME: i64 = 60;
+
+// Nodes only relay OP_RETURN scripts of up to 83 bytes
+pub const MAX_OP_RETURN_DATA_LENGTH: usize = 80;
+
+// Default -minrelaytxfee of Elements in sat/vbyte
+pub const MIN_RELAY_FEE_RATE: f64 = 0.1;
+// Default -dustrelayfee of Elements in sat/kvB
+const DUST_RELAY_FEE: u64 = 3_000;
+
+/// Cheap to clone, because everything is shared between the clones
 #[derive(Clone)]
 pub struct Constructor {
+    inner: Arc<ConstructorInner>,
+}
+
+#[derive(Clone)]
+struct ConstructorInner {
     db: db::Pool,
     chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
-    sweep_time: u64,
-    sweep_interval: u64,
+    fee_estimator: FeeEstimator,
+    // Shared between the clones, so that they can be updated while running
+    sweep_time: Arc<AtomicU64>,
+    sweep_interval: Arc<AtomicU64>,
+    // Claims broadcast per round at most; 0 for no limit
+    sweep_max_per_round: usize,
+    // Maximal random seconds added to the sweep interval, so that instances do not tick together
+    sweep_jitter: u64,
     address_params: &'static AddressParams,
+    // Asset lockups have to be in, because the fees of claims are paid in it
+    policy_asset: Option<AssetId>,
+    // Embedded in the OP_RETURN of claims of covenants that do not set their own
+    op_return_data: Option<Vec<u8>>,
+    // Confirmations lockups need before their claim reveals the preimage; 0 to claim
+    // lockups in the mempool already
+    lockup_confirmations: u64,
+
+    // Notified when covenants were registered or lockups found by any instance
+    wakeup: Option<Arc<Notify>>,
+    // Marked as busy while broadcasting, so that the systemd watchdog notices stuck rounds
+    heartbeat: Heartbeat,
+
+    notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
 }
 
 impl Constructor {
     pub fn new(
         db: db::Pool,
         chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
+        fee_estimator: FeeEstimator,
         sweep_time: u64,
         sweep_interval: u64,
         address_params: &'static AddressParams,
     ) -> Constructor {
         Constructor {
-            db,
-            sweep_time,
-            chain_client,
-            address_params,
-            sweep_interval,
+            inner: Arc::new(ConstructorInner {
+                db,
+                chain_client,
+                fee_estimator,
+                address_params,
+                sweep_time: Arc::new(AtomicU64::new(sweep_time)),
+                sweep_interval: Arc::new(AtomicU64::new(sweep_interval)),
+                sweep_max_per_round: 0,
+                sweep_jitter: 0,
+                policy_asset: None,
+                op_return_data: None,
+                lockup_confirmations: 0,
+                wakeup: None,
+                heartbeat: Heartbeat::default(),
+                notifier: None,
+            }),
+        }
+    }
+
+    pub fn with_sweep_batching(mut self, max_per_round: usize, jitter: u64) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.sweep_max_per_round = max_per_round;
+        inner.sweep_jitter = jitter;
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<Box<dyn Notifier + Send + Sync>>) -> Self {
+        Arc::make_mut(&mut self.inner).notifier = Some(notifier);
+        self
+    }
+
+    pub fn with_policy_asset(mut self, policy_asset: AssetId) -> Self {
+        Arc::make_mut(&mut self.inner).policy_asset = Some(policy_asset);
+        self
+    }
+
+    pub fn with_op_return_data(mut self, op_return_data: Vec<u8>) -> Self {
+        Arc::make_mut(&mut self.inner).op_return_data = Some(op_return_data);
+        self
+    }
+
+    pub fn with_wakeup(mut self, wakeup: Arc<Notify>) -> Self {
+        Arc::make_mut(&mut self.inner).wakeup = Some(wakeup);
+        self
+    }
+
+    pub fn with_lockup_confirmations(mut self, confirmations: u64) -> Self {
+        Arc::make_mut(&mut self.inner).lockup_confirmations = confirmations;
+        self
+    }
+
+    /// Updates the sweep time and interval of the running claimer; the new interval is used
+    /// from the next round on. Switching between claiming instantly and on an interval
+    /// requires a restart, so only the sweep time is updated in that case
+    pub fn update_sweep(&self, sweep_time: u64, sweep_interval: u64) {
+        self.inner.sweep_time.store(sweep_time, Ordering::Relaxed);
+
+        if self.claim_instantly() != (sweep_interval == 0) {
+            warn!("Switching between instant claims and a sweep interval requires a restart");
+            return;
         }
+        self.inner
+            .sweep_interval
+            .store(sweep_interval, Ordering::Relaxed);
     }
 
-    pub async fn start_interval(self) {
-        if self.clone().claim_instantly() {
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        Arc::make_mut(&mut self.inner).heartbeat = heartbeat;
+        self
+    }
+
+    pub async fn start_interval(&self) {
+        if self.claim_instantly() {
             info!("Broadcasting sweeps instantly");
             return;
         }
 
         info!(
-            "Broadcasting claims {} seconds after lockup transactions and checking on interval of {} seconds",
-            self.sweep_time,
-            self.sweep_interval
+            "Broadcasting claims {} seconds after lockup transactions and checking on interval of {} seconds with up to {} seconds of jitter",
+            self.sweep_time(),
+            self.inner.sweep_interval.load(Ordering::Relaxed),
+            self.inner.sweep_jitter
         );
-        let mut interval = time::interval(time::Duration::from_secs(self.sweep_interval));
 
-        self.clone().broadcast().await;
+        self.broadcast().await;
 
+        let mut next_round = time::Instant::now() + self.round_delay();
         loop {
-            interval.tick().await;
+            match &self.inner.wakeup {
+                Some(wakeup) => {
+                    tokio::select! {
+                        _ = time::sleep_until(next_round) => {}
+                        _ = wakeup.notified() => {
+                            debug!("Woken up by database notification");
+
+                            // Lockups found just now can be claimed after the sweep time
+                            let constructor = self.clone();
+                            tokio::spawn(async move {
+                                time::sleep(time::Duration::from_secs(constructor.sweep_time())).await;
+                                constructor.broadcast().await;
+                            });
+                        }
+                    }
+                }
+                None => {
+                    time::sleep_until(next_round).await;
+                }
+            };
+
+            // Wakeups do not delay the next round
+            if time::Instant::now() >= next_round {
+                next_round = time::Instant::now() + self.round_delay();
+            }
 
             trace!("Checking for claims to broadcast");
-            self.clone().broadcast().await;
+            self.broadcast().await;
         }
     }
 
-    pub async fn schedule_broadcast(self, covenant: PendingCovenant, lockup_tx: Transaction) {
-        if self.clone().claim_instantly() {
-            self.broadcast_covenant(covenant, lockup_tx).await;
+    #[instrument(name = "lockup", skip_all, fields(
+        swap_id = covenant.swap_id.as_deref(),
+        output_script = %hex::encode(&covenant.output_script),
+        lockup_txid = %lockup_tx.txid(),
+    ))]
+    pub async fn schedule_broadcast(
+        &self,
+        covenant: PendingCovenant,
+        lockup_tx: &Transaction,
+        vout: usize,
+        height: Option<u64>,
+    ) {
+        let lockup = LockupOutput {
+            vout: vout as u32,
+            amount: lockup_amount(&covenant, &lockup_tx.output[vout]),
+            height,
+        };
+
+        if self.inner.lockup_confirmations > 0 {
+            if let Some(mismatch) = lockup_amount_mismatch(covenant.expected_lockup_amount, &lockup)
+            {
+                self.ignore_lockup(covenant, lockup_tx, mismatch).await;
+                return;
+            }
+        }
+
+        // Lockups that need confirmations are claimed by broadcast_confirmed
+        if self.claim_instantly() && self.inner.lockup_confirmations == 0 {
+            match db::helpers::lease_covenant(
+                self.inner.db.clone(),
+                covenant.output_script.clone(),
+                TimeDelta::seconds(CLAIM_LEASE_TIME),
+            )
+            .await
+            {
+                Ok(true) => {
+                    if let Err(err) = db::helpers::set_lockup_output(
+                        self.inner.db.clone(),
+                        covenant.output_script.clone(),
+                        lockup_tx.txid(),
+                        lockup,
+                    )
+                    .await
+                    {
+                        warn!("Could not save lockup output: {}", err);
+                    }
+
+                    self.notify_lockup(&covenant, lockup_tx, &lockup);
+                    if self.broadcast_covenant(covenant.clone(), lockup_tx).await {
+                        // Only scheduled claims are retried by the sweep
+                        debug!(
+                            "Scheduling claim of {} to retry it",
+                            hex::encode(&covenant.output_script)
+                        );
+                        if let Err(err) = db::helpers::set_covenant_transaction(
+                            self.inner.db.clone(),
+                            covenant.output_script.clone(),
+                            lockup_tx.txid(),
+                            lockup,
+                            Utc::now().naive_utc(),
+                        )
+                        .await
+                        {
+                            warn!("Could not schedule covenant claim: {}", err);
+                        }
+                    }
+                }
+                Ok(false) => debug!(
+                    "Claim of {} is broadcast by another instance",
+                    hex::encode(covenant.output_script)
+                ),
+                Err(err) => warn!("Could not lease covenant claim: {}", err),
+            };
             return;
         }
 
         debug!(
             "Scheduling claim of {}",
-            hex::encode(covenant.output_script.clone())
+            hex::encode(&covenant.output_script)
         );
         match db::helpers::set_covenant_transaction(
-            self.db,
-            covenant.output_script,
-            hex::decode(lockup_tx.txid().to_string()).unwrap(),
+            self.inner.db.clone(),
+            covenant.output_script.clone(),
+            lockup_tx.txid(),
+            lockup,
             Utc::now().naive_utc(),
-        ) {
-            Ok(_) => {}
+        )
+        .await
+        {
+            Ok(_) => self.notify_lockup(&covenant, lockup_tx, &lockup),
             Err(err) => {
                 warn!("Could not schedule covenant claim: {}", err);
             }
         };
     }
 
-    async fn broadcast(self) {
-        let covenants = match db::helpers::get_covenants_to_claim(
-            self.clone().db,
+    /// Claims the lockups that reached the required confirmations with a new block when
+    /// claiming instantly; on an interval, the next round claims them
+    pub async fn broadcast_confirmed(&self) {
+        if self.inner.lockup_confirmations > 0 && self.claim_instantly() {
+            self.broadcast().await;
+        }
+    }
+
+    // Lockups the covenant does not expect must not make it reveal the preimage, but the
+    // expected one can still be found later
+    async fn ignore_lockup(
+        &self,
+        covenant: PendingCovenant,
+        lockup_tx: &Transaction,
+        reason: String,
+    ) {
+        warn!(
+            "Ignoring lockup {} of {}: {}",
+            lockup_tx.txid(),
+            hex::encode(&covenant.output_script),
+            reason
+        );
+
+        if let Err(err) = db::helpers::insert_covenant_event(
+            self.inner.db.clone(),
+            NewCovenantEvent {
+                output_script: covenant.output_script,
+                status: covenant.status,
+                reason: format!("lockup ignored: {}", reason),
+                tx_id: Some(lockup_tx.txid().into()),
+            },
+        )
+        .await
+        {
+            warn!("Could not record ignored lockup: {}", err);
+        }
+    }
+
+    /// Broadcasts the claim of a covenant whose lockup transaction was found already,
+    /// regardless of the sweep time
+    #[instrument(name = "claim", skip_all, fields(
+        swap_id = covenant.swap_id.as_deref(),
+        output_script = %hex::encode(&covenant.output_script),
+    ))]
+    pub async fn claim(
+        &self,
+        covenant: PendingCovenant,
+    ) -> Result<Option<Transaction>, ClaimError> {
+        let tx_id = match &covenant.tx_id {
+            Some(res) => res.to_string(),
+            None => return Err(ClaimError::LockupNotFound),
+        };
+
+        if !db::helpers::lease_covenant(
+            self.inner.db.clone(),
+            covenant.output_script.clone(),
+            TimeDelta::seconds(CLAIM_LEASE_TIME),
+        )
+        .await?
+        {
+            return Err(ClaimError::Leased);
+        }
+
+        let lockup_tx = self.inner.chain_client.get_transaction(tx_id).await?;
+        self.broadcast_tx(&covenant, &lockup_tx).await
+    }
+
+    fn round_delay(&self) -> time::Duration {
+        let jitter = match self.inner.sweep_jitter {
+            0 => 0,
+            jitter => OsRng.gen_range(0..=jitter),
+        };
+
+        time::Duration::from_secs(self.inner.sweep_interval.load(Ordering::Relaxed) + jitter)
+    }
+
+    async fn broadcast(&self) {
+        let _busy = self.inner.heartbeat.busy();
+        let start = Instant::now();
+        let limit = match self.inner.sweep_max_per_round {
+            0 => i64::MAX,
+            max => max as i64,
+        };
+
+        let max_lockup_height = match self.max_lockup_height().await {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not get block height: {}", err);
+                counter!("covclaim_sweep_rounds_total", "result" => "error").increment(1);
+                return;
+            }
+        };
+
+        let covenants = match db::helpers::lease_covenants_to_claim(
+            self.inner.db.clone(),
             Utc::now()
-                .sub(TimeDelta::seconds(self.sweep_time as i64))
+                .sub(TimeDelta::seconds(self.sweep_time() as i64))
                 .naive_utc(),
-        ) {
+            max_lockup_height,
+            TimeDelta::seconds(CLAIM_LEASE_TIME),
+            limit,
+        )
+        .await
+        {
             Ok(res) => res,
             Err(err) => {
                 warn!("Could not fetch covenants to claim: {}", err);
+                counter!("covclaim_sweep_rounds_total", "result" => "error").increment(1);
                 return;
             }
         };
 
+        counter!("covclaim_sweep_rounds_total", "result" => "ok").increment(1);
+        histogram!("covclaim_sweep_round_claims").record(covenants.len() as f64);
+
         if covenants.is_empty() {
             return;
         }
 
-        debug!("Broadcasting {} claims", covenants.len());
+        if covenants.len() as i64 == limit {
+            // The rest is claimed in the next rounds
+            debug!("Broadcasting the maximum of {} claims in this round", limit);
+        } else {
+            debug!("Broadcasting {} claims", covenants.len());
+        }
 
-        let self_clone = self.clone();
         for cov in covenants {
-            let tx = match self_clone
-                .clone()
+            let tx = match self
+                .inner
                 .chain_client
-                .get_transaction(hex::encode(cov.tx_id.clone().unwrap()))
+                .get_transaction(cov.tx_id.unwrap().to_string())
                 .await
             {
                 Ok(res) => res,
+                // The lease of the covenant expires, so it is retried in a later round,
+                // while the rest of the leased covenants are still claimed in this one
                 Err(err) => {
                     error!(
                         "Could not fetch transaction for {}: {}",
-                        hex::encode(cov.clone().output_script),
+                        hex::encode(&cov.output_script),
                         err
                     );
-                    return;
+                    continue;
                 }
             };
 
-            self_clone.clone().broadcast_covenant(cov, tx).await;
+            self.broadcast_covenant(cov, &tx).await;
         }
+
+        histogram!("covclaim_sweep_round_duration_seconds").record(start.elapsed().as_secs_f64());
     }
 
-    async fn broadcast_covenant(self, cov: PendingCovenant, tx: Transaction) {
-        match self.clone().broadcast_tx(cov.clone(), tx).await {
-            Ok(tx) => match tx {
-                Some(tx) => {
-                    info!(
-                        "Broadcast claim for {}: {}",
-                        hex::encode(cov.clone().output_script),
-                        tx.txid().to_string(),
-                    )
-                }
-                None => {
-                    info!(
-                        "Output of {} already spent",
-                        hex::encode(cov.clone().output_script),
-                    )
+    // Returns whether the claim failed in a way that trying again can fix
+    #[instrument(name = "claim", skip_all, fields(
+        swap_id = cov.swap_id.as_deref(),
+        output_script = %hex::encode(&cov.output_script),
+        lockup_txid = %tx.txid(),
+    ))]
+    async fn broadcast_covenant(&self, cov: PendingCovenant, tx: &Transaction) -> bool {
+        match self.broadcast_tx(&cov, tx).await {
+            Ok(tx) => {
+                match tx {
+                    Some(tx) => {
+                        info!(
+                            txid = %tx.txid(),
+                            "Broadcast claim for {}: {}",
+                            hex::encode(&cov.output_script),
+                            tx.txid().to_string(),
+                        )
+                    }
+                    None => {
+                        info!(
+                            "Output of {} already spent",
+                            hex::encode(&cov.output_script),
+                        )
+                    }
+                };
+                false
+            }
+            // Nothing about these changes when trying again
+            Err(
+                err @ (ClaimError::WrongAsset { .. }
+                | ClaimError::DustOutput { .. }
+                | ClaimError::FeeBelowMinimum { .. }),
+            ) => {
+                error!(
+                    "Covenant {} cannot be claimed: {}",
+                    hex::encode(&cov.output_script),
+                    err
+                );
+
+                notifier::send(
+                    self.inner.notifier.clone(),
+                    Notification::new(
+                        &cov,
+                        NotificationKind::Unclaimable,
+                        PendingCovenantStatus::Unclaimable,
+                        format!("covenant cannot be claimed: {}", err),
+                        None,
+                    ),
+                );
+
+                if let Err(err) = db::helpers::set_covenant_unclaimable(
+                    self.inner.db.clone(),
+                    cov.output_script,
+                    format!("covenant cannot be claimed: {}", err),
+                )
+                .await
+                {
+                    warn!("Could not mark covenant as unclaimable: {}", err);
                 }
-            },
+                false
+            }
             Err(err) => {
                 error!(
                     "Could not broadcast claim for {}: {}",
-                    hex::encode(cov.clone().output_script),
+                    hex::encode(&cov.output_script),
                     err
+                );
+
+                notifier::send(
+                    self.inner.notifier.clone(),
+                    Notification::new(
+                        &cov,
+                        NotificationKind::ClaimFailed,
+                        PendingCovenantStatus::TransactionFound,
+                        format!("claim failed: {}", err),
+                        None,
+                    ),
+                );
+
+                if let Err(err) = db::helpers::insert_covenant_event(
+                    self.inner.db.clone(),
+                    NewCovenantEvent {
+                        output_script: cov.output_script,
+                        status: cov.status,
+                        reason: format!("claim failed: {}", err),
+                        tx_id: None,
+                    },
                 )
+                .await
+                {
+                    warn!("Could not record failed claim: {}", err);
+                }
+                true
             }
         }
     }
 
     async fn broadcast_tx(
-        self,
-        covenant: PendingCovenant,
-        lockup_tx: Transaction,
-    ) -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
-        let tree = serde_json::from_str::<SwapTree>(covenant.swap_tree.as_str()).unwrap();
-        let (prevout, vout) = match tree.clone().find_output(
-            lockup_tx.clone(),
-            covenant.clone().internal_key,
-            self.address_params,
-        ) {
+        &self,
+        covenant: &PendingCovenant,
+        lockup_tx: &Transaction,
+    ) -> Result<Option<Transaction>, ClaimError> {
+        debug!(
+            "Broadcasting claim for: {}",
+            hex::encode(&covenant.output_script)
+        );
+        let (tx, fee) = construct_claim(
+            covenant,
+            lockup_tx,
+            self.inner.address_params,
+            self.inner.policy_asset,
+            self.inner.op_return_data.as_deref(),
+        )?;
+
+        // The fee is committed to in the covenant, so it can only be compared to the estimation
+        let fee_rate = fee as f64 / tx.weight().div_ceil(4) as f64;
+        let fee_target = match covenant.fee_rate {
             Some(res) => res,
-            None => {
-                return Err(format!(
-                    "could not find swap output for {}",
-                    hex::encode(covenant.output_script)
-                )
-                .into());
+            None => self.inner.fee_estimator.estimate().await,
+        };
+        if fee_rate < fee_target {
+            warn!(
+                "Claim for {} pays {:.2} sat/vbyte which is less than the target of {:.2} sat/vbyte",
+                hex::encode(&covenant.output_script),
+                fee_rate,
+                fee_target
+            );
+        }
+
+        let tx_hex = hex::encode(elements::pset::serialize::Serialize::serialize(&tx));
+        trace!("Broadcasting transaction {}", tx_hex);
+
+        let broadcast = match self.inner.chain_client.broadcast_transaction(tx_hex).await {
+            Ok(res) => Ok(Some(res)),
+            Err(err) => {
+                if err.is_already_included() {
+                    Ok(None)
+                } else {
+                    Err(err)
+                }
             }
         };
 
-        debug!(
-            "Broadcasting claim for: {}",
-            hex::encode(covenant.clone().output_script)
+        match broadcast {
+            Ok(broadcast) => {
+                let (reason, claim_tx_id) = match &broadcast {
+                    Some(Broadcast {
+                        path: Some(path), ..
+                    }) => (format!("claim broadcast via {}", path), Some(tx.txid())),
+                    Some(_) => ("claim broadcast".to_string(), Some(tx.txid())),
+                    // Blinded claims are different every time they are constructed,
+                    // so the one that was included is not the one that was just built
+                    None => (
+                        "claim already included".to_string(),
+                        self.find_claim(covenant, lockup_tx).await,
+                    ),
+                };
+
+                match db::helpers::set_covenant_claimed(
+                    self.inner.db.clone(),
+                    covenant.output_script.clone(),
+                    claim_tx_id,
+                    reason.as_str(),
+                )
+                .await
+                {
+                    Ok(_) => {
+                        notifier::send(
+                            self.inner.notifier.clone(),
+                            Notification::new(
+                                covenant,
+                                NotificationKind::Claimed,
+                                PendingCovenantStatus::Claimed,
+                                reason,
+                                claim_tx_id.map(|tx_id| tx_id.to_string()),
+                            ),
+                        );
+                        Ok(broadcast.map(|_| tx))
+                    }
+                    Err(err) => Err(err.into()),
+                }
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Claims that are not found here are looked up again when claims are repaired on startup
+    async fn find_claim(
+        &self,
+        covenant: &PendingCovenant,
+        lockup_tx: &Transaction,
+    ) -> Option<Txid> {
+        let vout = lockup_tx
+            .output
+            .iter()
+            .position(|out| out.script_pubkey.as_bytes() == covenant.output_script.as_slice())?;
+        let outpoint = OutPoint::new(lockup_tx.txid(), vout as u32);
+        // The tree was parsed when the claim was constructed
+        let tree = serde_json::from_str::<SwapTree>(covenant.swap_tree.as_str()).ok()?;
+
+        match self
+            .inner
+            .chain_client
+            .get_script_transactions(covenant.output_script.clone())
+            .await
+        {
+            Ok(txs) => {
+                txs.and_then(|txs| find_spend(&txs, &outpoint, &tree.covenant_claim_leaf.output))
+            }
+            Err(err) => {
+                warn!(
+                    "Could not look up claim of {}: {}",
+                    hex::encode(&covenant.output_script),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    fn notify_lockup(
+        &self,
+        covenant: &PendingCovenant,
+        lockup_tx: &Transaction,
+        lockup: &LockupOutput,
+    ) {
+        notifier::send(
+            self.inner.notifier.clone(),
+            Notification::new(
+                covenant,
+                NotificationKind::LockupDetected,
+                PendingCovenantStatus::TransactionFound,
+                lockup.reason(),
+                Some(lockup_tx.txid().to_string()),
+            ),
         );
+    }
 
-        let cov_details = tree.clone().covenant_details().unwrap();
+    // Lockups up to this height have the required confirmations; None when they need none
+    async fn max_lockup_height(&self) -> Result<Option<i64>, db::Error> {
+        if self.inner.lockup_confirmations == 0 {
+            return Ok(None);
+        }
 
-        let mut witness = Witness::new();
-        witness.push(covenant.clone().preimage);
-        witness.push(Script::from(tree.clone().covenant_claim_leaf.output).as_bytes());
-        witness.push(tree.control_block(covenant.clone().internal_key));
+        Ok(Some(
+            match db::helpers::get_block_height(self.inner.db.clone()).await? {
+                Some(height) => height as i64 - self.inner.lockup_confirmations as i64 + 1,
+                // No lockup is known to be confirmed before the first block was processed
+                None => -1,
+            },
+        ))
+    }
 
-        let secp = &SwapTree::secp();
+    fn sweep_time(&self) -> u64 {
+        self.inner.sweep_time.load(Ordering::Relaxed)
+    }
 
-        let is_blinded = prevout.asset.is_confidential() && prevout.value.is_confidential();
-        let tx_secrets = match is_blinded {
-            true => match prevout.unblind(
-                secp,
-                match SecretKey::from_slice(
-                    match covenant.clone().blinding_key {
-                        Some(res) => res,
-                        None => return Err("no blinding key for blinded swap".into()),
-                    }
-                    .as_slice(),
-                ) {
-                    Ok(res) => res,
-                    Err(err) => return Err(err.into()),
-                },
-            ) {
-                Ok(res) => Some(res),
-                Err(err) => return Err(err.into()),
+    fn claim_instantly(&self) -> bool {
+        self.inner.sweep_interval.load(Ordering::Relaxed) == 0
+    }
+}
+
+/// Builds the claim of a covenant without broadcasting it; returns the transaction and its fee.
+/// Lockups in another asset than the policy asset are rejected, when it is set, and the
+/// OP_RETURN data of the covenant takes precedence over the default one
+pub fn construct_claim(
+    covenant: &PendingCovenant,
+    lockup_tx: &Transaction,
+    address_params: &'static AddressParams,
+    policy_asset: Option<AssetId>,
+    default_op_return_data: Option<&[u8]>,
+) -> Result<(Transaction, u64), ClaimError> {
+    let tree = serde_json::from_str::<SwapTree>(covenant.swap_tree.as_str())
+        .map_err(|err| ClaimError::InvalidSwapTree(err.to_string()))?;
+    let cov_details = tree
+        .clone()
+        .covenant_details()
+        .map_err(|err| ClaimError::InvalidSwapTree(err.to_string()))?;
+
+    let (prevout, vout) = match tree
+        .clone()
+        .find_output(
+            lockup_tx.clone(),
+            covenant.internal_key.clone(),
+            address_params,
+        )
+        .map_err(|err| ClaimError::InvalidSwapTree(err.to_string()))?
+    {
+        Some(res) => res,
+        None => {
+            return Err(ClaimError::OutputNotFound(hex::encode(
+                &covenant.output_script,
+            )));
+        }
+    };
+
+    let mut witness = Witness::new();
+    witness.push(&*covenant.preimage);
+    witness.push(Script::from(tree.clone().covenant_claim_leaf.output).as_bytes());
+    witness.push(
+        tree.control_block(covenant.internal_key.clone())
+            .map_err(|err| ClaimError::InvalidSwapTree(err.to_string()))?,
+    );
+
+    let secp = SwapTree::secp();
+
+    let tx_secrets = unblind_prevout(&prevout, covenant.blinding_key.as_deref())?;
+    let is_blinded = tx_secrets.is_some();
+    let (utxo_asset, utxo_value) = match tx_secrets {
+        // Leave 1 sat for a blinded OP_RETURN
+        Some(secrets) => (secrets.asset, secrets.value.saturating_sub(1)),
+        None => match (prevout.asset.explicit(), prevout.value.explicit()) {
+            (Some(asset), Some(value)) => (asset, value),
+            _ => {
+                return Err(ClaimError::InvalidLockup(
+                    "output has no asset or value".into(),
+                ))
+            }
+        },
+    };
+    if let Some(policy_asset) = policy_asset {
+        if utxo_asset != policy_asset {
+            return Err(ClaimError::WrongAsset {
+                asset: utxo_asset.to_string(),
+                expected: policy_asset.to_string(),
+            });
+        }
+    }
+
+    // The covenant fixes the amount of the claim, so the fee can only be limited
+    let fee = match utxo_value.checked_sub(cov_details.expected_amount) {
+        Some(res) => res,
+        None => {
+            return Err(ClaimError::InvalidLockup(format!(
+                "output of {} is less than the {} of the covenant",
+                utxo_value, cov_details.expected_amount
+            )))
+        }
+    };
+    if let Some(max_fee) = covenant.max_fee {
+        if fee > max_fee as u64 {
+            return Err(ClaimError::FeeTooHigh {
+                fee,
+                max_fee: max_fee as u64,
+            });
+        }
+    }
+
+    let claim_script = Script::from(covenant.address.clone());
+    let threshold = dust_threshold(&claim_script);
+    if cov_details.expected_amount < threshold {
+        return Err(ClaimError::DustOutput {
+            amount: cov_details.expected_amount,
+            threshold,
+        });
+    }
+
+    let mut outs = Vec::<TxOut>::new();
+    outs.push(TxOut {
+        nonce: Nonce::Null,
+        asset: Asset::Explicit(utxo_asset),
+        value: Value::Explicit(cov_details.expected_amount),
+        script_pubkey: claim_script,
+        witness: TxOutWitness {
+            rangeproof: None,
+            surjection_proof: None,
+        },
+    });
+
+    let op_return_data = covenant
+        .op_return_data
+        .as_deref()
+        .or(default_op_return_data);
+    let mut op_return_builder = Builder::new().push_opcode(opcodes::all::OP_RETURN);
+    if let Some(data) = op_return_data {
+        op_return_builder = op_return_builder.push_slice(data);
+    }
+    let op_return_script = op_return_builder.into_script();
+
+    if is_blinded {
+        let mut rng = OsRng;
+
+        let out_abf = AssetBlindingFactor::new(&mut rng);
+        let (blinded_asset, surjection_proof) =
+            Asset::Explicit(utxo_asset).blind(&mut rng, secp, out_abf, &[tx_secrets.unwrap()])?;
+
+        let final_vbf = ValueBlindingFactor::last(
+            secp,
+            1,
+            out_abf,
+            &[(
+                tx_secrets.unwrap().value,
+                tx_secrets.unwrap().asset_bf,
+                tx_secrets.unwrap().value_bf,
+            )],
+            &[
+                (
+                    cov_details.expected_amount,
+                    AssetBlindingFactor::zero(),
+                    ValueBlindingFactor::zero(),
+                ),
+                (
+                    fee,
+                    AssetBlindingFactor::zero(),
+                    ValueBlindingFactor::zero(),
+                ),
+            ],
+        );
+        let mut receiver_key = SecretKey::new(&mut rng);
+        let mut ephemeral_key = SecretKey::new(&mut rng);
+        let blinded = Value::Explicit(1).blind(
+            secp,
+            final_vbf,
+            receiver_key.public_key(secp),
+            ephemeral_key,
+            &op_return_script.clone(),
+            &elements::RangeProofMessage {
+                asset: utxo_asset,
+                bf: out_abf,
             },
-            false => None,
-        };
-        let utxo_value = match tx_secrets {
-            // Leave 1 sat for a blinded OP_RETURN
-            Some(secrets) => secrets.value - 1,
-            None => prevout.value.explicit().unwrap(),
-        };
-        let utxo_asset = match tx_secrets {
-            Some(secrets) => secrets.asset,
-            None => prevout.asset.explicit().unwrap(),
-        };
+        );
+        receiver_key.non_secure_erase();
+        ephemeral_key.non_secure_erase();
+        let (blinded_value, nonce, rangeproof) = blinded?;
 
-        let mut outs = Vec::<TxOut>::new();
+        outs.push(TxOut {
+            nonce,
+            value: blinded_value,
+            asset: blinded_asset,
+            script_pubkey: op_return_script,
+            witness: TxOutWitness {
+                rangeproof: Some(Box::new(rangeproof)),
+                surjection_proof: Some(Box::new(surjection_proof)),
+            },
+        });
+    } else if op_return_data.is_some() {
+        // Unblinded claims only need an OP_RETURN for the data
         outs.push(TxOut {
             nonce: Nonce::Null,
             asset: Asset::Explicit(utxo_asset),
-            value: Value::Explicit(cov_details.expected_amount),
-            script_pubkey: Script::from(covenant.clone().address),
+            value: Value::Explicit(0),
+            script_pubkey: op_return_script,
             witness: TxOutWitness {
                 rangeproof: None,
                 surjection_proof: None,
             },
         });
+    }
 
-        if is_blinded {
-            let mut rng = OsRng;
+    outs.push(TxOut::new_fee(fee, utxo_asset));
 
-            let op_return_script = Builder::new()
-                .push_opcode(opcodes::all::OP_RETURN)
-                .into_script();
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::from_consensus(0),
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                vout,
+                txid: lockup_tx.txid(),
+            },
+            is_pegin: false,
+            script_sig: Default::default(),
+            sequence: Sequence::from_consensus(0xFFFFFFFD),
+            witness: TxInWitness {
+                pegin_witness: vec![],
+                amount_rangeproof: None,
+                inflation_keys_rangeproof: None,
+                script_witness: witness.to_vec(),
+            },
+            asset_issuance: Default::default(),
+        }],
+        output: outs,
+    };
 
-            let out_abf = AssetBlindingFactor::new(&mut rng);
-            let (blinded_asset, surjection_proof) = Asset::Explicit(utxo_asset).blind(
-                &mut rng,
-                secp,
-                out_abf,
-                &[tx_secrets.unwrap()],
-            )?;
+    // Nodes would not relay the claim, and the covenant fixes the fee
+    let fee_rate = fee as f64 / tx.weight().div_ceil(4) as f64;
+    if fee_rate < MIN_RELAY_FEE_RATE {
+        return Err(ClaimError::FeeBelowMinimum {
+            fee_rate,
+            min_fee_rate: MIN_RELAY_FEE_RATE,
+        });
+    }
 
-            let final_vbf = ValueBlindingFactor::last(
-                secp,
-                1,
-                out_abf,
-                &[(
-                    tx_secrets.unwrap().value,
-                    tx_secrets.unwrap().asset_bf,
-                    tx_secrets.unwrap().value_bf,
-                )],
-                &[
-                    (
-                        cov_details.expected_amount,
-                        AssetBlindingFactor::zero(),
-                        ValueBlindingFactor::zero(),
-                    ),
-                    (
-                        utxo_value - cov_details.expected_amount,
-                        AssetBlindingFactor::zero(),
-                        ValueBlindingFactor::zero(),
-                    ),
-                ],
-            );
-            let (blinded_value, nonce, rangeproof) = Value::Explicit(1).blind(
-                secp,
-                final_vbf,
-                SecretKey::new(&mut rng).public_key(secp),
-                SecretKey::new(&mut rng),
-                &op_return_script.clone(),
-                &elements::RangeProofMessage {
-                    asset: utxo_asset,
-                    bf: out_abf,
-                },
-            )?;
-
-            outs.push(TxOut {
-                nonce,
-                value: blinded_value,
-                asset: blinded_asset,
-                script_pubkey: op_return_script,
-                witness: TxOutWitness {
-                    rangeproof: Some(Box::new(rangeproof)),
-                    surjection_proof: Some(Box::new(surjection_proof)),
-                },
-            });
+    Ok((tx, fee))
+}
+
+/// Amount below which nodes do not relay explicit outputs with the script, calculated like
+/// Elements does: the fee at the dust relay fee rate for creating and spending the output
+pub fn dust_threshold(script_pubkey: &Script) -> u64 {
+    if script_pubkey.is_provably_unspendable() {
+        return 0;
+    }
+
+    let output = TxOut {
+        nonce: Nonce::Null,
+        // Every explicit asset has the same size
+        asset: Asset::Explicit(AssetId::LIQUID_BTC),
+        value: Value::Explicit(0),
+        script_pubkey: script_pubkey.clone(),
+        witness: TxOutWitness::default(),
+    };
+    let mut size = elements::encode::serialize(&output).len() as u64;
+    // Outpoint, script length, sequence and the signature and public key of the input,
+    // which are discounted for SegWit outputs
+    size += 32
+        + 4
+        + 1
+        + 4
+        + match script_pubkey.is_witness_program() {
+            true => 107 / 4,
+            false => 107,
+        };
+
+    size * DUST_RELAY_FEE / 1_000
+}
+
+/// Why a lockup is not the one the covenant expects; only lockups with exactly the
+/// expected amount are claimed when lockups need confirmations
+fn lockup_amount_mismatch(expected: Option<i64>, lockup: &LockupOutput) -> Option<String> {
+    let expected = match expected {
+        Some(res) => res as u64,
+        None => return Some("covenant has no expected lockup amount".to_string()),
+    };
+
+    match lockup.amount {
+        Some(amount) if amount == expected => None,
+        Some(amount) => Some(format!(
+            "amount of {} sats does not match the expected {} sats",
+            amount, expected
+        )),
+        None => Some("amount could not be unblinded".to_string()),
+    }
+}
+
+/// Amount of a lockup output; None when it is blinded and cannot be unblinded
+pub fn lockup_amount(covenant: &PendingCovenant, prevout: &TxOut) -> Option<u64> {
+    match unblind_prevout(prevout, covenant.blinding_key.as_deref()) {
+        Ok(Some(secrets)) => Some(secrets.value),
+        Ok(None) => prevout.value.explicit(),
+        Err(_) => None,
+    }
+}
+
+/// Secrets of blinded lockup outputs; wallets also create outputs with an explicit asset and
+/// a confidential value, which are claimed like fully blinded ones
+fn unblind_prevout(
+    prevout: &TxOut,
+    blinding_key: Option<&[u8]>,
+) -> Result<Option<TxOutSecrets>, ClaimError> {
+    match (prevout.asset, prevout.value) {
+        (Asset::Explicit(_), Value::Explicit(_)) => return Ok(None),
+        // The asset blinding factor of the input would be needed to balance the claim
+        (Asset::Confidential(_), Value::Explicit(_)) => {
+            return Err(ClaimError::InvalidLockup(
+                "output with confidential asset and explicit value cannot be claimed".into(),
+            ))
+        }
+        (Asset::Confidential(_), Value::Confidential(_))
+        | (Asset::Explicit(_), Value::Confidential(_)) => {}
+        _ => {
+            return Err(ClaimError::InvalidLockup(
+                "output has no asset or value".into(),
+            ))
         }
+    };
 
-        outs.push(TxOut::new_fee(
-            utxo_value - cov_details.expected_amount,
-            utxo_asset,
+    let mut blinding_key = match blinding_key {
+        Some(res) => SecretKey::from_slice(res).map_err(elements::secp256k1_zkp::Error::from)?,
+        None => {
+            return Err(match prevout.asset.is_confidential() {
+                true => ClaimError::MissingBlindingKey,
+                false => ClaimError::InvalidLockup(
+                    "no blinding key for output with confidential value".into(),
+                ),
+            })
+        }
+    };
+
+    let secp = SwapTree::secp();
+    let secrets = match (prevout.asset, prevout.value) {
+        (Asset::Explicit(asset), Value::Confidential(commitment)) => {
+            unblind_value(secp, prevout, asset, commitment, blinding_key)
+        }
+        _ => prevout
+            .unblind(secp, blinding_key)
+            .map_err(ClaimError::from),
+    };
+    blinding_key.non_secure_erase();
+
+    Ok(Some(secrets?))
+}
+
+// TxOut::unblind only handles outputs with a confidential asset
+fn unblind_value(
+    secp: &Secp256k1<All>,
+    prevout: &TxOut,
+    asset: AssetId,
+    commitment: PedersenCommitment,
+    blinding_key: SecretKey,
+) -> Result<TxOutSecrets, ClaimError> {
+    let shared_secret =
+        prevout
+            .nonce
+            .shared_secret(&blinding_key)
+            .ok_or(ClaimError::InvalidLockup(
+                "output with confidential value has no nonce".into(),
+            ))?;
+    let rangeproof = prevout
+        .witness
+        .rangeproof
+        .as_ref()
+        .ok_or(ClaimError::InvalidLockup(
+            "output with confidential value has no range proof".into(),
+        ))?;
+
+    let (opening, _) = rangeproof.rewind(
+        secp,
+        commitment,
+        shared_secret,
+        prevout.script_pubkey.as_bytes(),
+        Generator::new_unblinded(secp, asset.into_tag()),
+    )?;
+
+    Ok(TxOutSecrets::new(
+        asset,
+        AssetBlindingFactor::zero(),
+        opening.value,
+        ValueBlindingFactor::from_slice(opening.blinding_factor.as_ref())?,
+    ))
+}
+
+#[cfg(test)]
+mod constructor_test {
+    use elements::confidential::{Asset, AssetBlindingFactor, Nonce, Value, ValueBlindingFactor};
+    use elements::secp256k1_zkp::rand::rngs::OsRng;
+    use elements::secp256k1_zkp::{Generator, SecretKey};
+    use elements::{AssetId, RangeProofMessage, Script, TxOut, TxOutWitness};
+
+    use crate::claimer::constructor::{dust_threshold, lockup_amount_mismatch, unblind_prevout};
+    use crate::claimer::error::ClaimError;
+    use crate::claimer::tree::SwapTree;
+    use crate::db::models::LockupOutput;
+
+    fn output(asset: Asset, value: Value) -> TxOut {
+        TxOut {
+            asset,
+            value,
+            nonce: Nonce::Null,
+            script_pubkey: Script::from(vec![0x51, 0x20]),
+            witness: TxOutWitness::default(),
+        }
+    }
+
+    fn asset() -> AssetId {
+        AssetId::from_slice(&[1; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_unblind_prevout_explicit() {
+        let prevout = output(Asset::Explicit(asset()), Value::Explicit(1));
+        assert!(unblind_prevout(&prevout, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unblind_prevout_confidential_asset_explicit_value() {
+        let prevout = output(
+            Asset::Confidential(Generator::new_unblinded(
+                SwapTree::secp(),
+                asset().into_tag(),
+            )),
+            Value::Explicit(1),
+        );
+
+        assert!(matches!(
+            unblind_prevout(&prevout, Some(&[1; 32])),
+            Err(ClaimError::InvalidLockup(_))
         ));
+    }
 
-        let tx = Transaction {
-            version: 2,
-            lock_time: LockTime::from_consensus(0),
-            input: vec![TxIn {
-                previous_output: OutPoint {
-                    vout,
-                    txid: lockup_tx.txid(),
-                },
-                is_pegin: false,
-                script_sig: Default::default(),
-                sequence: Sequence::from_consensus(0xFFFFFFFD),
-                witness: TxInWitness {
-                    pegin_witness: vec![],
-                    amount_rangeproof: None,
-                    inflation_keys_rangeproof: None,
-                    script_witness: witness.to_vec(),
+    #[test]
+    fn test_unblind_prevout_explicit_asset_confidential_value() {
+        let secp = SwapTree::secp();
+        let blinding_key = SecretKey::new(&mut OsRng);
+        let script_pubkey = Script::from(vec![0x51, 0x20]);
+
+        let (value, nonce, rangeproof) = Value::Explicit(100_000)
+            .blind(
+                secp,
+                ValueBlindingFactor::new(&mut OsRng),
+                blinding_key.public_key(secp),
+                SecretKey::new(&mut OsRng),
+                &script_pubkey,
+                &RangeProofMessage {
+                    asset: asset(),
+                    bf: AssetBlindingFactor::zero(),
                 },
-                asset_issuance: Default::default(),
-            }],
-            output: outs,
-        };
+            )
+            .unwrap();
 
-        let tx_hex = hex::encode(elements::pset::serialize::Serialize::serialize(&tx));
-        trace!("Broadcasting transaction {}", tx_hex);
+        let mut prevout = output(Asset::Explicit(asset()), value);
+        prevout.nonce = nonce;
+        prevout.witness.rangeproof = Some(Box::new(rangeproof));
 
-        let has_been_included = match self.chain_client.send_raw_transaction(tx_hex).await {
-            Ok(_) => Ok(false),
-            Err(err) => {
-                if err.is_already_included() {
-                    Ok(true)
-                } else {
-                    Err(err)
-                }
-            }
-        };
+        assert!(matches!(
+            unblind_prevout(&prevout, None),
+            Err(ClaimError::InvalidLockup(_))
+        ));
 
-        match has_been_included {
-            Ok(already_included) => {
-                match db::helpers::set_covenant_claimed(self.db, covenant.output_script) {
-                    Ok(_) => Ok(if already_included { None } else { Some(tx) }),
-                    Err(err) => Err(Box::new(err)),
-                }
-            }
-            Err(err) => Err(err.to_string().into()),
-        }
+        let secrets = unblind_prevout(&prevout, Some(&blinding_key.secret_bytes()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(secrets.asset, asset());
+        assert_eq!(secrets.value, 100_000);
+        assert_eq!(secrets.asset_bf, AssetBlindingFactor::zero());
+    }
+
+    #[test]
+    fn test_dust_threshold() {
+        let mut p2wpkh = vec![0x00, 0x14];
+        p2wpkh.extend([1; 20]);
+        assert_eq!(dust_threshold(&Script::from(p2wpkh)), 399);
+
+        let mut p2pkh = vec![0x76, 0xa9, 0x14];
+        p2pkh.extend([1; 20]);
+        p2pkh.extend([0x88, 0xac]);
+        assert_eq!(dust_threshold(&Script::from(p2pkh)), 651);
+
+        assert_eq!(dust_threshold(&Script::from(vec![0x6a, 0x01, 0x01])), 0);
     }
 
-    fn claim_instantly(self) -> bool {
-        self.sweep_interval == 0
+    #[test]
+    fn test_lockup_amount_mismatch() {
+        let lockup = |amount: Option<u64>| LockupOutput {
+            vout: 0,
+            amount,
+            height: Some(1),
+        };
+
+        assert_eq!(
+            lockup_amount_mismatch(Some(100_000), &lockup(Some(100_000))),
+            None
+        );
+        assert_eq!(
+            lockup_amount_mismatch(Some(100_000), &lockup(Some(100_001))),
+            Some("amount of 100001 sats does not match the expected 100000 sats".to_string())
+        );
+        assert_eq!(
+            lockup_amount_mismatch(Some(100_000), &lockup(None)),
+            Some("amount could not be unblinded".to_string())
+        );
+        assert_eq!(
+            lockup_amount_mismatch(None, &lockup(Some(100_000))),
+            Some("covenant has no expected lockup amount".to_string())
+        );
     }
 }
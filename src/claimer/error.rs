@@ -0,0 +1,46 @@
+use thiserror::Error;
+
+use crate::chain::error::{ChainError, TransactionBroadcastError};
+use crate::db;
+
+#[derive(Debug, Error)]
+pub enum ClaimError {
+    #[error("{0}")]
+    Database(#[from] db::Error),
+    #[error("{0}")]
+    Chain(#[from] ChainError),
+    #[error("{0}")]
+    Broadcast(#[from] TransactionBroadcastError),
+    #[error("{0}")]
+    Key(#[from] elements::secp256k1_zkp::Error),
+    #[error("{0}")]
+    Unblind(#[from] elements::UnblindError),
+    #[error("{0}")]
+    Blind(#[from] elements::ConfidentialTxOutError),
+    #[error("could not find covenant")]
+    CovenantNotFound,
+    #[error("covenant was claimed already")]
+    AlreadyClaimed,
+    #[error("lockup transaction of covenant was not found yet")]
+    LockupNotFound,
+    #[error("claim is broadcast by another instance")]
+    Leased,
+    #[error("could not parse swap tree: {0}")]
+    InvalidSwapTree(String),
+    #[error("invalid lockup transaction: {0}")]
+    InvalidLockup(String),
+    #[error("could not find swap output for {0}")]
+    OutputNotFound(String),
+    #[error("lockup is in asset {asset} instead of the policy asset {expected}")]
+    WrongAsset { asset: String, expected: String },
+    #[error("no blinding key for blinded swap")]
+    MissingBlindingKey,
+    #[error("claim output of {amount} sats is below the dust threshold of {threshold} sats")]
+    DustOutput { amount: u64, threshold: u64 },
+    #[error("claim would pay {fee_rate:.3} sat/vbyte which is below the minimal relay fee of {min_fee_rate} sat/vbyte")]
+    FeeBelowMinimum { fee_rate: f64, min_fee_rate: f64 },
+    #[error("claim would pay {fee} sats of fees which is more than the maximum of {max_fee}")]
+    FeeTooHigh { fee: u64, max_fee: u64 },
+    #[error("height {height} is greater than the block height {block_count}")]
+    InvalidRescanHeight { height: u64, block_count: u64 },
+}
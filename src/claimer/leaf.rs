@@ -0,0 +1,357 @@
+use elements::hashes::{hash160, sha256, Hash};
+use elements::opcodes::all::{
+    OP_DROP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160, OP_INSPECTOUTPUTASSET,
+    OP_INSPECTOUTPUTSCRIPTPUBKEY, OP_INSPECTOUTPUTVALUE, OP_PUSHNUM_1, OP_PUSHNUM_16,
+    OP_PUSHNUM_NEG1, OP_SHA256, OP_SIZE,
+};
+use elements::opcodes::All;
+use elements::script::Instruction;
+use elements::Script;
+use thiserror::Error;
+
+const PREIMAGE_LENGTH: u8 = 32;
+const ASSET_LENGTH: usize = 32;
+const AMOUNT_LENGTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreimageHashType {
+    Hash160,
+    Sha256,
+}
+
+impl PreimageHashType {
+    pub fn hash(self, preimage: &[u8]) -> Vec<u8> {
+        match self {
+            PreimageHashType::Hash160 => {
+                let hash: hash160::Hash = Hash::hash(preimage);
+                hash.as_byte_array().to_vec()
+            }
+            PreimageHashType::Sha256 => {
+                let hash: sha256::Hash = Hash::hash(preimage);
+                hash.as_byte_array().to_vec()
+            }
+        }
+    }
+
+    fn hash_length(self) -> usize {
+        match self {
+            PreimageHashType::Hash160 => 20,
+            PreimageHashType::Sha256 => 32,
+        }
+    }
+}
+
+/// Known layouts of the covenant claim leaf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CovenantLeafVersion {
+    /// Preimage is committed to with OP_HASH160
+    Legacy,
+    /// Preimage is committed to with OP_SHA256
+    Current,
+}
+
+impl CovenantLeafVersion {
+    pub fn preimage_hash_type(self) -> PreimageHashType {
+        match self {
+            CovenantLeafVersion::Legacy => PreimageHashType::Hash160,
+            CovenantLeafVersion::Current => PreimageHashType::Sha256,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CovenantParseError {
+    #[error("could not iterate over covenant claim script: {0}")]
+    InvalidScript(String),
+    #[error("covenant claim script ended unexpectedly")]
+    UnexpectedEnd,
+    #[error(
+        "unexpected instruction at position {position} of covenant claim script; expected {expected}"
+    )]
+    UnexpectedInstruction { position: usize, expected: String },
+    #[error("unknown preimage hash opcode: 0x{0:02x}")]
+    UnknownPreimageHash(u8),
+    #[error("covenant claim script has {0} unexpected trailing instructions")]
+    TrailingInstructions(usize),
+}
+
+#[derive(Debug)]
+pub struct CovenantDetails {
+    pub version: CovenantLeafVersion,
+    pub preimage_hash: Vec<u8>,
+    /// Witness version of the expected output; -1 for outputs that are not SegWit
+    pub expected_output_version: i8,
+    pub expected_output: Vec<u8>,
+    pub expected_asset: Vec<u8>,
+    pub expected_amount: u64,
+}
+
+impl CovenantDetails {
+    /// Parses a covenant claim leaf of the shape:
+    ///
+    /// ```text
+    /// OP_SIZE 32 OP_EQUALVERIFY <OP_HASH160|OP_SHA256> <hash> OP_EQUALVERIFY
+    /// 0 OP_INSPECTOUTPUTSCRIPTPUBKEY <version> OP_EQUALVERIFY <program> OP_EQUALVERIFY
+    /// 0 OP_INSPECTOUTPUTASSET 1 OP_EQUALVERIFY <asset> OP_EQUALVERIFY
+    /// 0 OP_INSPECTOUTPUTVALUE OP_DROP <amount> OP_EQUAL
+    /// ```
+    pub fn parse(script: &Script) -> Result<CovenantDetails, CovenantParseError> {
+        let mut parser = LeafParser::new(script)?;
+
+        parser.expect_op(OP_SIZE)?;
+        parser.expect_data(&[PREIMAGE_LENGTH])?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+
+        let version = parser.preimage_hash_version()?;
+        let preimage_hash = parser.expect_push(Some(version.preimage_hash_type().hash_length()))?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+
+        parser.expect_data(&[])?;
+        parser.expect_op(OP_INSPECTOUTPUTSCRIPTPUBKEY)?;
+        let expected_output_version = parser.expect_witness_version()?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+        let expected_output = parser.expect_push(None)?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+
+        parser.expect_data(&[])?;
+        parser.expect_op(OP_INSPECTOUTPUTASSET)?;
+        parser.expect_op(OP_PUSHNUM_1)?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+        let expected_asset = parser.expect_push(Some(ASSET_LENGTH))?;
+        parser.expect_op(OP_EQUALVERIFY)?;
+
+        parser.expect_data(&[])?;
+        parser.expect_op(OP_INSPECTOUTPUTVALUE)?;
+        parser.expect_op(OP_DROP)?;
+        let amount = parser.expect_push(Some(AMOUNT_LENGTH))?;
+        parser.expect_op(OP_EQUAL)?;
+
+        parser.expect_end()?;
+
+        let mut amount_bytes = [0u8; AMOUNT_LENGTH];
+        amount_bytes.copy_from_slice(amount);
+
+        Ok(CovenantDetails {
+            version,
+            expected_output_version,
+            preimage_hash: preimage_hash.to_vec(),
+            expected_output: expected_output.to_vec(),
+            expected_asset: expected_asset.to_vec(),
+            expected_amount: u64::from_le_bytes(amount_bytes),
+        })
+    }
+
+    pub fn is_preimage_valid(&self, preimage: &[u8]) -> bool {
+        self.version.preimage_hash_type().hash(preimage) == self.preimage_hash
+    }
+
+    /// Whether the covenant allows claiming to the script; like OP_INSPECTOUTPUTSCRIPTPUBKEY,
+    /// compares the witness program or the SHA256 hash of scripts that are not SegWit
+    pub fn is_expected_output(&self, script_pubkey: &Script) -> bool {
+        let bytes = script_pubkey.as_bytes();
+
+        if script_pubkey.is_witness_program() {
+            let version = match bytes[0] {
+                0 => 0,
+                op => (op - OP_PUSHNUM_1.into_u8() + 1) as i8,
+            };
+            return version == self.expected_output_version && bytes[2..] == self.expected_output;
+        }
+
+        let hash: sha256::Hash = Hash::hash(bytes);
+        self.expected_output_version == -1 && hash.as_byte_array()[..] == self.expected_output
+    }
+}
+
+struct LeafParser<'a> {
+    instructions: Vec<Instruction<'a>>,
+    position: usize,
+}
+
+impl<'a> LeafParser<'a> {
+    fn new(script: &'a Script) -> Result<LeafParser<'a>, CovenantParseError> {
+        let instructions = script
+            .instructions()
+            .collect::<Result<Vec<Instruction>, _>>()
+            .map_err(|err| CovenantParseError::InvalidScript(err.to_string()))?;
+
+        Ok(LeafParser {
+            instructions,
+            position: 0,
+        })
+    }
+
+    fn next(&mut self) -> Result<Instruction<'a>, CovenantParseError> {
+        let instruction = self
+            .instructions
+            .get(self.position)
+            .cloned()
+            .ok_or(CovenantParseError::UnexpectedEnd)?;
+        self.position += 1;
+
+        Ok(instruction)
+    }
+
+    fn unexpected(&self, expected: String) -> CovenantParseError {
+        CovenantParseError::UnexpectedInstruction {
+            expected,
+            position: self.position - 1,
+        }
+    }
+
+    fn expect_op(&mut self, expected: All) -> Result<(), CovenantParseError> {
+        match self.next()? {
+            Instruction::Op(op) if op.into_u8() == expected.into_u8() => Ok(()),
+            _ => Err(self.unexpected(format!("{:?}", expected))),
+        }
+    }
+
+    fn expect_push(&mut self, length: Option<usize>) -> Result<&'a [u8], CovenantParseError> {
+        match self.next()? {
+            Instruction::PushBytes(data) if length.is_none_or(|len| data.len() == len) => Ok(data),
+            _ => Err(self.unexpected(match length {
+                Some(len) => format!("push of {} bytes", len),
+                None => "data push".to_string(),
+            })),
+        }
+    }
+
+    fn expect_data(&mut self, expected: &[u8]) -> Result<(), CovenantParseError> {
+        match self.next()? {
+            Instruction::PushBytes(data) if data == expected => Ok(()),
+            _ => Err(self.unexpected(format!("push of {}", hex::encode(expected)))),
+        }
+    }
+
+    fn preimage_hash_version(&mut self) -> Result<CovenantLeafVersion, CovenantParseError> {
+        match self.next()? {
+            Instruction::Op(op) if op.into_u8() == OP_HASH160.into_u8() => {
+                Ok(CovenantLeafVersion::Legacy)
+            }
+            Instruction::Op(op) if op.into_u8() == OP_SHA256.into_u8() => {
+                Ok(CovenantLeafVersion::Current)
+            }
+            Instruction::Op(op) => Err(CovenantParseError::UnknownPreimageHash(op.into_u8())),
+            _ => Err(self.unexpected("preimage hash opcode".to_string())),
+        }
+    }
+
+    fn expect_witness_version(&mut self) -> Result<i8, CovenantParseError> {
+        match self.next()? {
+            // OP_0 is parsed as an empty push
+            Instruction::PushBytes([]) => Ok(0),
+            Instruction::Op(op) if op.into_u8() == OP_PUSHNUM_NEG1.into_u8() => Ok(-1),
+            Instruction::Op(op)
+                if op.into_u8() >= OP_PUSHNUM_1.into_u8()
+                    && op.into_u8() <= OP_PUSHNUM_16.into_u8() =>
+            {
+                Ok((op.into_u8() - OP_PUSHNUM_1.into_u8() + 1) as i8)
+            }
+            _ => Err(self.unexpected("witness version".to_string())),
+        }
+    }
+
+    fn expect_end(&self) -> Result<(), CovenantParseError> {
+        let remaining = self.instructions.len() - self.position;
+        if remaining != 0 {
+            return Err(CovenantParseError::TrailingInstructions(remaining));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod leaf_tests {
+    use elements::hashes::{sha256, Hash};
+    use elements::Script;
+
+    use crate::claimer::leaf::{CovenantDetails, CovenantLeafVersion, CovenantParseError};
+
+    fn details(expected_output_version: i8, expected_output: Vec<u8>) -> CovenantDetails {
+        CovenantDetails {
+            version: CovenantLeafVersion::Current,
+            preimage_hash: vec![],
+            expected_output_version,
+            expected_output,
+            expected_asset: vec![],
+            expected_amount: 0,
+        }
+    }
+
+    #[test]
+    fn is_expected_output_witness() {
+        let program = hex::decode("aff4f5af812e3db39024f2000db7e23091dc0603").unwrap();
+        let script =
+            Script::from(hex::decode("0014aff4f5af812e3db39024f2000db7e23091dc0603").unwrap());
+
+        assert!(details(0, program.clone()).is_expected_output(&script));
+        assert!(!details(1, program).is_expected_output(&script));
+        assert!(!details(0, vec![1; 20]).is_expected_output(&script));
+    }
+
+    #[test]
+    fn is_expected_output_taproot() {
+        let program =
+            hex::decode("c86ebce80e66ef0f55c12f781aa60f812dacb5518c00b665be40eda073196c9e")
+                .unwrap();
+        let mut script = vec![0x51, 0x20];
+        script.extend(&program);
+
+        assert!(details(1, program).is_expected_output(&Script::from(script)));
+    }
+
+    #[test]
+    fn is_expected_output_legacy() {
+        let script =
+            Script::from(hex::decode("a914b6e8026a881fcbe566aa2517af4a9191a1ae3d6b87").unwrap());
+        let hash: sha256::Hash = Hash::hash(script.as_bytes());
+
+        assert!(details(-1, hash.as_byte_array().to_vec()).is_expected_output(&script));
+        assert!(!details(0, hash.as_byte_array().to_vec()).is_expected_output(&script));
+    }
+
+    #[test]
+    fn parse_unknown_preimage_hash() {
+        let script = Script::from(
+            hex::decode("82012088a614af8b5215948249f6e10adddc531ffe5d4428b917").unwrap(),
+        );
+        let res = CovenantDetails::parse(&script);
+        assert!(matches!(
+            res.err().unwrap(),
+            CovenantParseError::UnknownPreimageHash(0xa6)
+        ));
+    }
+
+    #[test]
+    fn parse_unexpected_end() {
+        let script = Script::from(
+            hex::decode("82012088a914af8b5215948249f6e10adddc531ffe5d4428b91788").unwrap(),
+        );
+        let res = CovenantDetails::parse(&script);
+        assert!(matches!(
+            res.err().unwrap(),
+            CovenantParseError::UnexpectedEnd
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_amount_length() {
+        let script = Script::from(hex::decode("82012088a914af8b5215948249f6e10adddc531ffe5d4428b9178800d1008814aff4f5af812e3db39024f2000db7e23091dc06038800ce51882025b251070e29ca19043cf33ccd7324e2ddab03ecc4ae0b5e77c4fc0e5cf6c95a8800cf7507a086010000000087").unwrap());
+        let res = CovenantDetails::parse(&script);
+        assert_eq!(
+            res.err().unwrap().to_string(),
+            "unexpected instruction at position 21 of covenant claim script; expected push of 8 bytes"
+        );
+    }
+
+    #[test]
+    fn parse_trailing_instructions() {
+        let script = Script::from(hex::decode("82012088a914af8b5215948249f6e10adddc531ffe5d4428b9178800d1008814aff4f5af812e3db39024f2000db7e23091dc06038800ce51882025b251070e29ca19043cf33ccd7324e2ddab03ecc4ae0b5e77c4fc0e5cf6c95a8800cf7508a0860100000000008775").unwrap());
+        let res = CovenantDetails::parse(&script);
+        assert!(matches!(
+            res.err().unwrap(),
+            CovenantParseError::TrailingInstructions(1)
+        ));
+    }
+}
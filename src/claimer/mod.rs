@@ -1,78 +1,237 @@
-use crossbeam_channel::Receiver;
-use elements::{AddressParams, Transaction};
-use log::{debug, error, info, trace, warn};
-use rayon::iter::IntoParallelRefIterator;
-use rayon::iter::ParallelIterator;
+use diesel::internal::derives::multiconnection::chrono::{TimeDelta, Utc};
+use elements::{AddressParams, AssetId, Block, OutPoint, Transaction, Txid};
+use futures_util::{stream, StreamExt};
+use metrics::{counter, histogram};
 use std::cmp;
-use std::error::Error;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::runtime::Builder;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time;
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
+use crate::chain::fee::FeeEstimator;
 use crate::chain::types::ChainBackend;
 use crate::claimer::constructor::Constructor;
+use crate::claimer::error::ClaimError;
+use crate::claimer::tree::SwapTree;
 use crate::db;
-use crate::db::helpers::get_pending_covenant_for_output;
+use crate::db::helpers::get_pending_covenants_for_outputs;
+use crate::db::models::{ClaimState, PendingCovenant, PendingCovenantStatus};
+use crate::notifier;
+use crate::notifier::{Notification, Notifier};
+use crate::utils::channel::Receiver;
+use crate::utils::systemd::Watchdog;
 
 pub mod constructor;
+pub mod error;
+pub mod leaf;
 pub mod tree;
 
-const MAX_PARALLEL_REQUESTS: usize = 15;
+// Blocks that are fetched with a single batch request when rescanning
+const RESCAN_BATCH_SIZE: usize = 50;
+pub const DEFAULT_RESCAN_PREFETCH: usize = 10;
 
+const DB_RETRIES: u32 = 3;
+// Seconds between retries of database queries
+const DB_RETRY_DELAY: u64 = 1;
+
+// Claims that were broadcast more recently could still be propagating
+const REPAIR_MIN_CLAIM_AGE: i64 = 600;
+
+/// Where to start rescanning the chain on startup
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RescanMode {
+    Off,
+    // From the height at which the previous run stopped
+    Auto,
+    From(u64),
+}
+
+impl RescanMode {
+    pub fn parse(value: &str) -> Result<RescanMode, String> {
+        match value {
+            "off" => Ok(RescanMode::Off),
+            "auto" => Ok(RescanMode::Auto),
+            _ => match value.strip_prefix("from:") {
+                Some(height) => match height.parse::<u64>() {
+                    Ok(height) => Ok(RescanMode::From(height)),
+                    Err(err) => Err(format!("invalid rescan height {}: {}", height, err)),
+                },
+                None => Err(format!("invalid rescan mode: {}", value)),
+            },
+        }
+    }
+}
+
+/// Cheap to clone, because everything is shared between the clones
 #[derive(Clone)]
 pub struct Claimer {
+    inner: Arc<ClaimerInner>,
+}
+
+#[derive(Clone)]
+struct ClaimerInner {
     db: db::Pool,
     chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
     constructor: Constructor,
+
+    rescan_mode: RescanMode,
+    // Blocks below the tip that are rescanned at most; 0 for no limit
+    max_rescan_depth: u64,
+    // Pending covenants up to which their script histories are looked up instead of scanning blocks
+    script_rescan_threshold: usize,
+    // Batches of blocks that are fetched ahead of processing them when rescanning
+    rescan_prefetch: usize,
+    // Script histories have no block heights, so blocks are scanned when lockups need confirmations
+    lockup_confirmations: u64,
+
+    // Lockup transactions reported by sources other than the chain backend
+    lockup_receivers: Vec<Receiver<Transaction>>,
+
+    notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+    watchdog: Watchdog,
 }
 
 impl Claimer {
     pub fn new(
         db: db::Pool,
         chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
+        fee_estimator: FeeEstimator,
         sweep_time: u64,
         sweep_interval: u64,
         address_param: &'static AddressParams,
     ) -> Claimer {
         Claimer {
-            constructor: Constructor::new(
-                db.clone(),
-                chain_client.clone(),
-                sweep_time,
-                sweep_interval,
-                address_param,
-            ),
-            db,
-            chain_client,
+            inner: Arc::new(ClaimerInner {
+                constructor: Constructor::new(
+                    db.clone(),
+                    chain_client.clone(),
+                    fee_estimator,
+                    sweep_time,
+                    sweep_interval,
+                    address_param,
+                ),
+                db,
+                chain_client,
+                lockup_receivers: Vec::new(),
+                notifier: None,
+                watchdog: Watchdog::default(),
+                rescan_mode: RescanMode::Auto,
+                max_rescan_depth: 0,
+                script_rescan_threshold: 0,
+                rescan_prefetch: DEFAULT_RESCAN_PREFETCH,
+                lockup_confirmations: 0,
+            }),
         }
     }
 
-    pub fn start(self) {
+    pub fn with_rescan(mut self, rescan_mode: RescanMode, max_rescan_depth: u64) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.rescan_mode = rescan_mode;
+        inner.max_rescan_depth = max_rescan_depth;
+        self
+    }
+
+    pub fn with_script_rescan_threshold(mut self, script_rescan_threshold: usize) -> Self {
+        Arc::make_mut(&mut self.inner).script_rescan_threshold = script_rescan_threshold;
+        self
+    }
+
+    pub fn with_rescan_prefetch(mut self, rescan_prefetch: usize) -> Self {
+        Arc::make_mut(&mut self.inner).rescan_prefetch = cmp::max(rescan_prefetch, 1);
+        self
+    }
+
+    pub fn with_lockup_receiver(mut self, lockup_receiver: Receiver<Transaction>) -> Self {
+        Arc::make_mut(&mut self.inner)
+            .lockup_receivers
+            .push(lockup_receiver);
+        self
+    }
+
+    pub fn with_sweep_batching(mut self, max_per_round: usize, jitter: u64) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner
+            .constructor
+            .clone()
+            .with_sweep_batching(max_per_round, jitter);
+        self
+    }
+
+    /// Registers the loops of the claimer with the watchdog
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner
+            .constructor
+            .clone()
+            .with_heartbeat(watchdog.heartbeat("sweep"));
+        inner.watchdog = watchdog;
+        self
+    }
+
+    pub fn with_wakeup(mut self, wakeup: Arc<Notify>) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner.constructor.clone().with_wakeup(wakeup);
+        self
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<Box<dyn Notifier + Send + Sync>>) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner.constructor.clone().with_notifier(notifier.clone());
+        inner.notifier = Some(notifier);
+        self
+    }
+
+    pub fn with_policy_asset(mut self, policy_asset: AssetId) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner.constructor.clone().with_policy_asset(policy_asset);
+        self
+    }
+
+    pub fn with_op_return_data(mut self, op_return_data: Vec<u8>) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.constructor = inner
+            .constructor
+            .clone()
+            .with_op_return_data(op_return_data);
+        self
+    }
+
+    pub fn with_lockup_confirmations(mut self, confirmations: u64) -> Self {
+        let inner = Arc::make_mut(&mut self.inner);
+        inner.lockup_confirmations = confirmations;
+        inner.constructor = inner
+            .constructor
+            .clone()
+            .with_lockup_confirmations(confirmations);
+        self
+    }
+
+    /// Updates the sweep time and interval without restarting the claimer
+    pub fn update_sweep(&self, sweep_time: u64, sweep_interval: u64) {
+        self.inner
+            .constructor
+            .update_sweep(sweep_time, sweep_interval);
+    }
+
+    pub fn start(&self) {
         debug!("Starting claimer");
-        let constructor_clone = self.constructor.clone();
+        let constructor = self.inner.constructor.clone();
         tokio::spawn(async move {
-            constructor_clone.start_interval().await;
+            constructor.start_interval().await;
         });
 
-        let tx_clone = self.clone();
-        let tx_receiver = self.clone().chain_client.get_tx_receiver();
-        tokio::spawn(async move {
-            loop {
-                match tx_receiver.recv() {
-                    Ok(tx) => {
-                        tx_clone.clone().handle_tx(tx).await;
-                    }
-                    Err(e) => {
-                        warn!("Could not read from transaction channel: {}", e);
-                    }
-                }
-            }
-        });
+        self.handle_tx_receiver(self.inner.chain_client.get_tx_receiver());
+        for lockup_receiver in &self.inner.lockup_receivers {
+            self.handle_tx_receiver(lockup_receiver.clone());
+        }
 
-        let block_clone = self.clone();
-        let block_receiver = self.clone().chain_client.get_block_receiver();
+        let claimer = self.clone();
+        let block_receiver = self.inner.chain_client.get_block_receiver();
+        let heartbeat = self.inner.watchdog.heartbeat("blocks");
         tokio::spawn(async move {
-            match self.rescan().await {
+            match claimer.rescan().await {
                 Ok(height) => {
                     info!("Rescanned to height: {}", height);
                 }
@@ -81,17 +240,42 @@ impl Claimer {
                 }
             };
 
+            match claimer.repair_claims().await {
+                Ok(0) => debug!("All claims are consistent with the chain"),
+                Ok(repaired) => info!("Repaired {} claims", repaired),
+                Err(err) => error!("Repairing claims failed: {}", err),
+            };
+
+            // Lockups could have been broadcast while we were not running
+            let mempool_txs = match claimer.inner.chain_client.get_mempool_transactions().await {
+                Ok(res) => res,
+                Err(err) => {
+                    error!("Scanning mempool failed: {}", err);
+                    Vec::new()
+                }
+            };
+
+            debug!("Scanning {} mempool transactions", mempool_txs.len());
+            for tx in &mempool_txs {
+                claimer.handle_tx(tx, None).await;
+            }
+
             loop {
-                match block_receiver.recv() {
-                    Ok(block) => {
-                        for tx in block.txdata {
-                            block_clone.clone().handle_tx(tx).await;
+                match block_receiver.recv().await {
+                    Some(block) => {
+                        let _busy = heartbeat.busy();
+                        let height = block.header.height as u64;
+                        claimer.handle_confirmations(&block.txdata, height).await;
+                        for tx in &block.txdata {
+                            claimer.handle_tx(tx, Some(height)).await;
                         }
 
                         match db::helpers::upsert_block_height(
-                            block_clone.clone().db,
+                            claimer.inner.db.clone(),
                             block.header.height as u64,
-                        ) {
+                        )
+                        .await
+                        {
                             Ok(_) => {
                                 debug!(
                                     "Updated block height {} ({})",
@@ -104,133 +288,629 @@ impl Claimer {
                                 continue;
                             }
                         };
+
+                        claimer.inner.constructor.broadcast_confirmed().await;
                     }
-                    Err(e) => {
-                        warn!("Could not read from block channel: {}", e);
+                    None => {
+                        warn!("Block channel was closed");
+                        break;
                     }
                 }
             }
         });
     }
 
-    async fn rescan(self) -> Result<u64, Box<dyn Error>> {
-        let block_count = self.chain_client.get_block_count().await?;
+    fn handle_tx_receiver(&self, tx_receiver: Receiver<Transaction>) {
+        let claimer = self.clone();
+        let heartbeat = self.inner.watchdog.heartbeat("transactions");
+        tokio::spawn(async move {
+            while let Some(tx) = tx_receiver.recv().await {
+                let _busy = heartbeat.busy();
+                claimer.handle_tx(&tx, None).await;
+            }
+
+            warn!("Transaction channel was closed");
+        });
+    }
+
+    pub async fn claim(
+        &self,
+        covenant: PendingCovenant,
+    ) -> Result<Option<Transaction>, ClaimError> {
+        self.inner.constructor.claim(covenant).await
+    }
+
+    /// Rescans the chain from the height on, regardless of the height in the database
+    pub async fn rescan_from(&self, height: u64) -> Result<u64, ClaimError> {
+        let block_count = self.inner.chain_client.get_block_count().await?;
+        if height > block_count {
+            return Err(ClaimError::InvalidRescanHeight {
+                height,
+                block_count,
+            });
+        }
+
+        self.rescan_range(height, block_count).await
+    }
+
+    async fn rescan(&self) -> Result<u64, ClaimError> {
+        let block_count = self.inner.chain_client.get_block_count().await?;
         trace!("Current block height: {}", block_count);
 
-        let rescan_height = match db::helpers::get_block_height(self.db.clone()) {
-            Some(res) => res,
-            None => {
-                db::helpers::upsert_block_height(self.db, block_count)?;
-                info!("No block height in database");
-                debug!("Not rescanning");
+        let rescan_height = match self.inner.rescan_mode {
+            RescanMode::Off => {
+                db::helpers::upsert_block_height(self.inner.db.clone(), block_count).await?;
+                info!("Rescanning is disabled");
                 return Ok(block_count);
             }
+            RescanMode::From(height) => {
+                info!("Rescanning from configured height: {}", height);
+                height
+            }
+            RescanMode::Auto => match db::helpers::get_block_height(self.inner.db.clone()).await? {
+                Some(res) => {
+                    info!("Found block height in database: {}", res);
+                    res
+                }
+                None => {
+                    db::helpers::upsert_block_height(self.inner.db.clone(), block_count).await?;
+                    info!("No block height in database");
+                    debug!("Not rescanning");
+                    return Ok(block_count);
+                }
+            },
         };
 
-        info!("Found block height in database: {}", rescan_height);
+        let bounded_height =
+            bound_rescan_height(rescan_height, block_count, self.inner.max_rescan_depth);
+        if bounded_height != rescan_height {
+            warn!(
+                "Rescanning from {} instead of {} because of the maximal rescan depth of {} blocks",
+                bounded_height, rescan_height, self.inner.max_rescan_depth
+            );
+        }
 
-        let block_range: Vec<u64> = (rescan_height..block_count + 1).collect();
+        if self.inner.script_rescan_threshold > 0
+            && self.inner.lockup_confirmations == 0
+            && self.rescan_scripts().await?
+        {
+            db::helpers::upsert_block_height(self.inner.db.clone(), block_count).await?;
+            return Ok(block_count);
+        }
 
-        let (sender, receiver) = crossbeam_channel::bounded(block_range.len());
-        for task in IntoIterator::into_iter(block_range.clone()) {
-            sender.send(task).unwrap();
+        self.rescan_range(bounded_height, block_count).await
+    }
+
+    // Returns false when there are too many pending covenants or the chain backend
+    // cannot look up scripts, in which case the blocks have to be scanned
+    async fn rescan_scripts(&self) -> Result<bool, ClaimError> {
+        let scripts = db::helpers::get_pending_covenant_scripts(self.inner.db.clone()).await?;
+        if scripts.len() > self.inner.script_rescan_threshold {
+            debug!(
+                "Not rescanning scripts of {} pending covenants",
+                scripts.len()
+            );
+            return Ok(false);
         }
 
-        drop(sender);
+        info!("Rescanning scripts of {} pending covenants", scripts.len());
+        for script in scripts {
+            let txs = match self
+                .inner
+                .chain_client
+                .get_script_transactions(script.clone())
+                .await?
+            {
+                Some(res) => res,
+                None => {
+                    debug!("Chain backend cannot look up scripts");
+                    return Ok(false);
+                }
+            };
 
-        let rescan_threads = cmp::min(MAX_PARALLEL_REQUESTS, num_cpus::get());
-        trace!("Rescanning with {} threads", rescan_threads);
+            trace!(
+                "Found {} transactions of script {}",
+                txs.len(),
+                hex::encode(&script)
+            );
+            for tx in &txs {
+                self.handle_tx(tx, None).await;
+            }
+        }
 
-        let runtime = Builder::new_multi_thread()
-            .worker_threads(rescan_threads)
-            .enable_all()
-            .build()
-            .unwrap();
+        debug!("Finished rescanning scripts");
+        Ok(true)
+    }
 
-        let processed_blocks = AtomicU64::new(0);
+    async fn rescan_range(&self, rescan_height: u64, block_count: u64) -> Result<u64, ClaimError> {
+        let block_range: Vec<u64> = (rescan_height..block_count + 1).collect();
         let blocks_to_rescan = block_range.len();
 
-        (0..rescan_threads)
-            .map(|_| receiver.clone())
-            .collect::<Vec<Receiver<u64>>>()
-            .par_iter()
-            .for_each(|receiver| {
-                let self_clone = self.clone();
-
-                while let Ok(height) = receiver.recv() {
-                    let self_clone = self_clone.clone();
-                    runtime.block_on(async move {
-                        let block_hash = match self_clone.chain_client.get_block_hash(height).await
-                        {
-                            Ok(res) => res,
-                            Err(err) => {
-                                error!("Could not get block hash of {}: {}", height, err);
-                                return;
-                            }
-                        };
-                        let block =
-                            match self_clone.chain_client.get_block(block_hash.clone()).await {
-                                Ok(res) => res,
-                                Err(err) => {
-                                    error!("Could not get block {}: {}", block_hash, err);
-                                    return;
-                                }
-                            };
-
-                        debug!(
-                            "Rescanning block {} ({}) with {} transactions",
-                            block.header.height,
-                            hex::encode(block.header.block_hash()),
-                            block.txdata.len()
-                        );
-
-                        for tx in block.txdata {
-                            self_clone.clone().handle_tx(tx).await;
-                        }
-                    });
+        let batches: Vec<Vec<u64>> = block_range
+            .chunks(RESCAN_BATCH_SIZE)
+            .map(|batch| batch.to_vec())
+            .collect();
 
-                    let processed = processed_blocks.fetch_add(1, Ordering::SeqCst) + 1;
+        trace!(
+            "Rescanning with a prefetch of {} batches",
+            self.inner.rescan_prefetch
+        );
 
-                    if processed % 10 == 0 {
-                        let processed_perc = processed as f64 / blocks_to_rescan as f64;
-                        info!("Rescan progress: {:.2}%", processed_perc * 100.0);
-                    }
+        // Batches are fetched in the background while earlier ones are processed,
+        // so that processing never has to wait for the network when it keeps up
+        let mut prefetched = stream::iter(batches)
+            .map(|heights| {
+                let chain_client = self.inner.chain_client.clone();
+                tokio::spawn(async move { fetch_blocks(chain_client, heights).await })
+            })
+            .buffered(self.inner.rescan_prefetch);
+
+        let mut processed_blocks = 0;
+        loop {
+            let wait_start = Instant::now();
+            let fetched = match prefetched.next().await {
+                Some(res) => res,
+                None => break,
+            };
+            histogram!("covclaim_rescan_wait_seconds").record(wait_start.elapsed().as_secs_f64());
+
+            let (first, last, blocks) = match fetched {
+                Ok(Ok(res)) => res,
+                Ok(Err(err)) => {
+                    error!("{}", err);
+                    continue;
                 }
-            });
+                Err(err) => {
+                    error!("Could not fetch blocks: {}", err);
+                    continue;
+                }
+            };
 
-        runtime.shutdown_background();
+            let batch_size = blocks.len();
+            let process_start = Instant::now();
+            async {
+                for block in blocks {
+                    debug!(
+                        "Rescanning block {} ({}) with {} transactions",
+                        block.header.height,
+                        hex::encode(block.header.block_hash()),
+                        block.txdata.len()
+                    );
+
+                    let height = block.header.height as u64;
+                    self.handle_confirmations(&block.txdata, height).await;
+                    for tx in &block.txdata {
+                        self.handle_tx(tx, Some(height)).await;
+                    }
+                }
+            }
+            .instrument(info_span!("rescan_batch", first, last))
+            .await;
+            histogram!("covclaim_rescan_process_seconds")
+                .record(process_start.elapsed().as_secs_f64());
+            counter!("covclaim_rescan_blocks_total").increment(batch_size as u64);
+
+            processed_blocks += batch_size;
+            let processed_perc = processed_blocks as f64 / blocks_to_rescan as f64;
+            info!("Rescan progress: {:.2}%", processed_perc * 100.0);
+        }
 
-        db::helpers::upsert_block_height(self.db, block_count)?;
+        db::helpers::upsert_block_height(self.inner.db.clone(), block_count).await?;
         debug!("Finished rescanning");
 
         Ok(block_count)
     }
 
-    async fn handle_tx(self, tx: Transaction) {
+    /// Reconciles claims that are not known to be confirmed with the chain, because a crash
+    /// between broadcasting a claim and updating the database leaves them inconsistent;
+    /// returns how many claims were repaired
+    async fn repair_claims(&self) -> Result<usize, ClaimError> {
+        let claims = db::helpers::get_unsettled_claims(self.inner.db.clone()).await?;
+        debug!("Checking {} unsettled claims", claims.len());
+
+        let mut repaired = 0;
+        for claim in claims {
+            match self.repair_claim(&claim).await {
+                Ok(true) => repaired += 1,
+                Ok(false) => {}
+                Err(err) => warn!(
+                    "Could not check claim of {}: {}",
+                    hex::encode(&claim.output_script),
+                    err
+                ),
+            };
+        }
+
+        Ok(repaired)
+    }
+
+    async fn repair_claim(&self, claim: &ClaimState) -> Result<bool, ClaimError> {
+        let lockup_tx_id = match &claim.tx_id {
+            Some(res) => res.txid(),
+            None => return Ok(false),
+        };
+        let claim_tx_id = claim.claim_tx_id.as_ref().map(|tx_id| tx_id.txid());
+
+        let lockup_tx = self
+            .inner
+            .chain_client
+            .get_transaction(lockup_tx_id.to_string())
+            .await?;
+        let vout = match lockup_tx
+            .output
+            .iter()
+            .position(|out| out.script_pubkey.as_bytes() == claim.output_script.as_slice())
+        {
+            Some(res) => res,
+            None => return Err(ClaimError::OutputNotFound(lockup_tx_id.to_string())),
+        };
+        let outpoint = OutPoint::new(lockup_tx_id, vout as u32);
+
+        let spend = match self
+            .inner
+            .chain_client
+            .get_script_transactions(claim.output_script.clone())
+            .await?
+        {
+            Some(txs) => {
+                let tree = serde_json::from_str::<SwapTree>(claim.swap_tree.as_str())
+                    .map_err(|err| ClaimError::InvalidSwapTree(err.to_string()))?;
+                find_spend(&txs, &outpoint, &tree.covenant_claim_leaf.output)
+            }
+            // Without an index of scripts, only the claim we know of can be looked up
+            None => match claim_tx_id {
+                Some(claim_tx_id) => match self
+                    .inner
+                    .chain_client
+                    .get_transaction(claim_tx_id.to_string())
+                    .await
+                {
+                    Ok(_) => Some(claim_tx_id),
+                    Err(_) => None,
+                },
+                None => return Ok(false),
+            },
+        };
+
+        match spend {
+            // Blinded claims are different every time they are constructed,
+            // so the claim that was included can differ from the one in the database
+            Some(spend) => {
+                let found = claim_tx_id != Some(spend);
+                if found {
+                    info!(
+                        "Found claim {} of {} in the chain",
+                        spend,
+                        hex::encode(&claim.output_script)
+                    );
+                    db::helpers::set_covenant_claimed(
+                        self.inner.db.clone(),
+                        claim.output_script.clone(),
+                        Some(spend),
+                        "claim found on startup",
+                    )
+                    .await?;
+                }
+
+                // Otherwise the claim would be checked again on every start
+                let confirmed = db::helpers::set_claims_confirmed(
+                    self.inner.db.clone(),
+                    vec![spend],
+                    Utc::now().naive_utc(),
+                )
+                .await?;
+                for timing in &confirmed {
+                    notifier::send(
+                        self.inner.notifier.clone(),
+                        Notification::claim_confirmed(timing),
+                    );
+                }
+                Ok(found || !confirmed.is_empty())
+            }
+            None => {
+                // Claims that are still propagating are not claimed again
+                let min_claimed_at =
+                    Utc::now().naive_utc() - TimeDelta::seconds(REPAIR_MIN_CLAIM_AGE);
+                match claim.claimed_at {
+                    Some(claimed_at)
+                        if claim.status == PendingCovenantStatus::Claimed.to_int()
+                            && claimed_at <= min_claimed_at => {}
+                    _ => return Ok(false),
+                };
+
+                warn!(
+                    "Claim of {} was not found in the chain; claiming again",
+                    hex::encode(&claim.output_script)
+                );
+                db::helpers::requeue_claim(
+                    self.inner.db.clone(),
+                    claim.output_script.clone(),
+                    "claim not found on startup".to_string(),
+                )
+                .await?;
+                Ok(true)
+            }
+        }
+    }
+
+    // The height is None for transactions that are not known to be confirmed
+    async fn handle_tx(&self, tx: &Transaction, height: Option<u64>) {
         trace!(
             "Checking {} outputs of transaction: {}",
             tx.output.len(),
             tx.txid().to_string()
         );
 
-        for vout in 0..tx.output.len() {
-            let out = &tx.output[vout];
+        let mut covenants = self.get_pending_covenants(tx).await;
+        if covenants.is_empty() {
+            return;
+        }
 
-            if let Some(covenant) =
-                get_pending_covenant_for_output(self.db.clone(), out.script_pubkey.as_bytes())
-            {
+        for (vout, out) in tx.output.iter().enumerate() {
+            // Removed, so that a covenant is only scheduled once when a transaction pays it twice
+            if let Some(covenant) = covenants.remove(out.script_pubkey.as_bytes()) {
                 info!(
+                    swap_id = covenant.swap_id.as_deref(),
+                    txid = %tx.txid(),
                     "Found covenant {} to claim in {}:{}",
-                    hex::encode(covenant.clone().output_script),
+                    hex::encode(&covenant.output_script),
                     tx.txid().to_string(),
                     vout
                 );
 
-                self.clone()
+                self.inner
                     .constructor
-                    .schedule_broadcast(covenant, tx.clone())
+                    .schedule_broadcast(covenant, tx, vout, height)
                     .await;
             }
         }
     }
+
+    // Claims are confirmed once their transactions are included in a block
+    async fn handle_confirmations(&self, txs: &[Transaction], height: u64) {
+        let tx_ids: Vec<_> = txs.iter().map(|tx| tx.txid()).collect();
+
+        // Lockups that were found in the mempool
+        if let Err(err) =
+            db::helpers::set_lockups_confirmed(self.inner.db.clone(), tx_ids.clone(), height).await
+        {
+            warn!("Could not set block height of lockups: {}", err);
+        }
+
+        let timings = match db::helpers::set_claims_confirmed(
+            self.inner.db.clone(),
+            tx_ids,
+            Utc::now().naive_utc(),
+        )
+        .await
+        {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Could not check for confirmed claims: {}", err);
+                return;
+            }
+        };
+
+        for timing in timings {
+            info!(
+                swap_id = timing.swap_id.as_deref(),
+                "Claim of {} confirmed",
+                hex::encode(&timing.output_script)
+            );
+            for (stage, seconds) in timing.stages() {
+                histogram!("covclaim_claim_stage_seconds", "stage" => stage).record(seconds);
+            }
+            notifier::send(
+                self.inner.notifier.clone(),
+                Notification::claim_confirmed(&timing),
+            );
+        }
+    }
+
+    // Liquid transactions can have hundreds of outputs, so they are all looked up with a
+    // single query. A lockup must not be missed because of a transient database error
+    async fn get_pending_covenants(&self, tx: &Transaction) -> HashMap<Vec<u8>, PendingCovenant> {
+        let scripts = output_scripts(tx);
+        if scripts.is_empty() {
+            return HashMap::new();
+        }
+
+        let mut attempt = 0;
+
+        loop {
+            match get_pending_covenants_for_outputs(self.inner.db.clone(), scripts.clone()).await {
+                Ok(res) => {
+                    return res
+                        .into_iter()
+                        .map(|covenant| (covenant.output_script.clone(), covenant))
+                        .collect()
+                }
+                Err(err) => {
+                    attempt += 1;
+                    if attempt > DB_RETRIES {
+                        error!("Could not check outputs of {}: {}", tx.txid(), err);
+                        return HashMap::new();
+                    }
+
+                    warn!(
+                        "Could not check outputs of {} (attempt {}/{}): {}",
+                        tx.txid(),
+                        attempt,
+                        DB_RETRIES,
+                        err
+                    );
+                }
+            };
+
+            time::sleep(Duration::from_secs(DB_RETRY_DELAY)).await;
+        }
+    }
+}
+
+// Distinct output scripts that could belong to a covenant; fee outputs have none
+fn output_scripts(tx: &Transaction) -> Vec<Vec<u8>> {
+    let mut scripts: Vec<Vec<u8>> = tx
+        .output
+        .iter()
+        .filter(|out| !out.is_fee())
+        .map(|out| out.script_pubkey.to_bytes())
+        .collect();
+    scripts.sort_unstable();
+    scripts.dedup();
+    scripts
+}
+
+fn bound_rescan_height(rescan_height: u64, block_count: u64, max_rescan_depth: u64) -> u64 {
+    if max_rescan_depth == 0 {
+        return rescan_height;
+    }
+
+    cmp::max(rescan_height, block_count.saturating_sub(max_rescan_depth))
+}
+
+// Refunds spend the outputs of covenants too, so only spends with the covenant claim leaf
+// as script, which is the second to last element of the witness, are claims
+fn find_spend(txs: &[Transaction], outpoint: &OutPoint, claim_leaf: &[u8]) -> Option<Txid> {
+    txs.iter()
+        .find(|tx| {
+            tx.input.iter().any(|input| {
+                let witness = &input.witness.script_witness;
+                input.previous_output == *outpoint
+                    && witness.len() >= 2
+                    && witness[witness.len() - 2] == claim_leaf
+            })
+        })
+        .map(|tx| tx.txid())
+}
+
+// Errors are returned as strings because they have to be sent between tasks
+async fn fetch_blocks(
+    chain_client: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    heights: Vec<u64>,
+) -> Result<(u64, u64, Vec<Block>), String> {
+    let (first, last) = (heights[0], heights[heights.len() - 1]);
+    let start = Instant::now();
+
+    let block_hashes = chain_client
+        .get_block_hashes(heights)
+        .await
+        .map_err(|err| {
+            format!(
+                "Could not get block hashes of {} to {}: {}",
+                first, last, err
+            )
+        })?;
+    let blocks = chain_client
+        .get_blocks(block_hashes)
+        .await
+        .map_err(|err| format!("Could not get blocks {} to {}: {}", first, last, err))?;
+
+    histogram!("covclaim_rescan_fetch_seconds").record(start.elapsed().as_secs_f64());
+    Ok((first, last, blocks))
+}
+
+#[cfg(test)]
+mod claimer_test {
+    use elements::confidential::{Asset, Nonce, Value};
+    use elements::hashes::Hash;
+    use elements::{
+        AssetId, LockTime, OutPoint, Script, Sequence, Transaction, TxIn, TxInWitness, TxOut,
+        TxOutWitness, Txid,
+    };
+
+    use crate::claimer::{bound_rescan_height, find_spend, output_scripts, RescanMode};
+
+    #[test]
+    fn test_parse_rescan_mode() {
+        assert_eq!(RescanMode::parse("off").unwrap(), RescanMode::Off);
+        assert_eq!(RescanMode::parse("auto").unwrap(), RescanMode::Auto);
+        assert_eq!(
+            RescanMode::parse("from:2900000").unwrap(),
+            RescanMode::From(2_900_000)
+        );
+
+        assert!(RescanMode::parse("from:").is_err());
+        assert!(RescanMode::parse("from:-1").is_err());
+        assert_eq!(
+            RescanMode::parse("always").err().unwrap(),
+            "invalid rescan mode: always"
+        );
+    }
+
+    #[test]
+    fn test_bound_rescan_height() {
+        assert_eq!(bound_rescan_height(100, 1_000, 0), 100);
+        assert_eq!(bound_rescan_height(100, 1_000, 500), 500);
+        assert_eq!(bound_rescan_height(900, 1_000, 500), 900);
+        assert_eq!(bound_rescan_height(0, 100, 500), 0);
+    }
+
+    #[test]
+    fn test_find_spend() {
+        let lockup_tx_id = Txid::from_byte_array([1; 32]);
+        let claim_leaf = vec![1, 2, 3];
+        let refund_leaf = vec![4, 5, 6];
+        let spending = |vout: u32, leaf: &[u8]| Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(lockup_tx_id, vout),
+                is_pegin: false,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                asset_issuance: Default::default(),
+                witness: TxInWitness {
+                    script_witness: vec![vec![0; 32], leaf.to_vec(), vec![0xc4; 33]],
+                    ..Default::default()
+                },
+            }],
+            output: vec![],
+        };
+
+        let txs = vec![
+            spending(0, &claim_leaf),
+            spending(1, &claim_leaf),
+            spending(3, &refund_leaf),
+        ];
+        assert_eq!(
+            find_spend(&txs, &OutPoint::new(lockup_tx_id, 1), &claim_leaf),
+            Some(txs[1].txid())
+        );
+        assert_eq!(
+            find_spend(&txs, &OutPoint::new(lockup_tx_id, 2), &claim_leaf),
+            None
+        );
+        assert_eq!(
+            find_spend(&txs, &OutPoint::new(lockup_tx_id, 3), &claim_leaf),
+            None
+        );
+    }
+
+    #[test]
+    fn test_output_scripts() {
+        let output = |script: &[u8]| TxOut {
+            asset: Asset::Explicit(AssetId::LIQUID_BTC),
+            value: Value::Explicit(1_000),
+            nonce: Nonce::Null,
+            script_pubkey: Script::from(script.to_vec()),
+            witness: TxOutWitness::default(),
+        };
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                output(&[0x51, 0x20, 2]),
+                output(&[0x51, 0x20, 1]),
+                output(&[0x51, 0x20, 2]),
+                TxOut::new_fee(100, AssetId::LIQUID_BTC),
+            ],
+        };
+
+        assert_eq!(
+            output_scripts(&tx),
+            vec![vec![0x51, 0x20, 1], vec![0x51, 0x20, 2]]
+        );
+    }
 }
@@ -1,12 +1,13 @@
-use std::error::Error;
+use std::sync::OnceLock;
 
-use elements::bitcoin::opcodes::all::OP_PUSHNUM_NEG1;
 use elements::bitcoin::XOnlyPublicKey;
-use elements::script::Instruction;
 use elements::secp256k1_zkp::{All, Secp256k1};
-use elements::taproot::{LeafVersion, TaprootBuilder};
+use elements::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
 use elements::{Address, AddressParams, Script, Transaction, TxOut};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::claimer::leaf::{CovenantDetails, CovenantParseError};
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct TreeScript {
@@ -14,6 +15,24 @@ pub struct TreeScript {
     pub output: Vec<u8>,
 }
 
+/// Leaf of a taproot tree that is described by all its leaves in depth-first order
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TreeLeaf {
+    pub depth: u8,
+    #[serde(with = "hex::serde")]
+    pub output: Vec<u8>,
+}
+
+#[derive(Debug, Error)]
+pub enum SwapTreeError {
+    #[error("invalid internal key: {0}")]
+    InvalidInternalKey(String),
+    #[error("could not build taproot tree: {0}")]
+    InvalidTree(String),
+    #[error("covenant claim leaf is not in the taproot tree")]
+    LeafNotFound,
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct SwapTree {
     #[serde(rename = "claimLeaf")]
@@ -24,74 +43,33 @@ pub struct SwapTree {
 
     #[serde(rename = "covenantClaimLeaf")]
     pub covenant_claim_leaf: TreeScript,
-}
 
-#[derive(Debug)]
-pub struct CovenantDetails {
-    pub expected_amount: u64,
-    pub expected_output: Vec<u8>,
-    pub preimage_hash: Vec<u8>,
+    // Every leaf of trees with another layout than the covenant claim leaf at depth 1 and
+    // the claim and refund leaves at depth 2; has to include the covenant claim leaf
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaves: Option<Vec<TreeLeaf>>,
 }
 
 impl SwapTree {
-    pub fn covenant_details(self) -> Result<CovenantDetails, Box<dyn Error>> {
-        let claim_script = Script::from(self.covenant_claim_leaf.output);
-
-        let mut details = CovenantDetails {
-            expected_amount: 0,
-            preimage_hash: Vec::new(),
-            expected_output: Vec::new(),
-        };
-
-        let mut position = 0;
-
-        for op in claim_script.instructions() {
-            match op {
-                Ok(instr) => match instr {
-                    Instruction::PushBytes(data) => match position {
-                        3 => details.preimage_hash = Vec::from(data),
-                        6 => details.expected_output = Vec::from(data),
-                        13 => {
-                            if let Ok(array) = data.try_into() {
-                                details.expected_amount = u64::from_le_bytes(array);
-                            } else {
-                                return Err("could not parse covenant output amount".into());
-                            }
-                        }
-                        _ => {}
-                    },
-                    Instruction::Op(op) => {
-                        // For SegWit addresses that is a push operation;
-                        // we skip incrementing the counter so that we can use the same match statement
-                        if op.into_u8() != OP_PUSHNUM_NEG1.to_u8() {
-                            position += 1;
-                        }
-                    }
-                },
-                Err(err) => {
-                    return Err(
-                        format!("could not iterate over covenant claim script: {}", err).into(),
-                    );
-                }
-            }
-        }
-
-        Ok(details)
+    pub fn covenant_details(self) -> Result<CovenantDetails, CovenantParseError> {
+        CovenantDetails::parse(&Script::from(self.covenant_claim_leaf.output))
     }
 
-    pub fn address(self, internal_key: Vec<u8>, params: &'static AddressParams) -> Address {
-        let key = Self::parse_key(internal_key);
+    pub fn address(
+        self,
+        internal_key: Vec<u8>,
+        params: &'static AddressParams,
+    ) -> Result<Address, SwapTreeError> {
+        let key = Self::parse_key(internal_key)?;
+        let spend_info = self.spend_info(key)?;
 
-        Address::p2tr(
-            &Self::secp(),
+        Ok(Address::p2tr(
+            Self::secp(),
             key,
-            self.tree_builder()
-                .finalize(&Self::secp(), key)
-                .unwrap()
-                .merkle_root(),
+            spend_info.merkle_root(),
             None,
             params,
-        )
+        ))
     }
 
     pub fn find_output(
@@ -99,50 +77,77 @@ impl SwapTree {
         lockup_tx: Transaction,
         internal_key: Vec<u8>,
         params: &'static AddressParams,
-    ) -> Option<(TxOut, u32)> {
-        let script_pubkey = self.address(internal_key, params).script_pubkey();
+    ) -> Result<Option<(TxOut, u32)>, SwapTreeError> {
+        let script_pubkey = self.address(internal_key, params)?.script_pubkey();
 
-        for (vout, out) in (0_u32..).zip(lockup_tx.output.into_iter()) {
+        for (vout, out) in (0_u32..).zip(lockup_tx.output) {
             if out.script_pubkey.eq(&script_pubkey) {
-                return Some((out, vout));
+                return Ok(Some((out, vout)));
             }
         }
 
-        None
+        Ok(None)
     }
 
-    pub fn control_block(self, internal_key: Vec<u8>) -> Vec<u8> {
-        let spend_info = self
-            .clone()
-            .tree_builder()
-            .finalize(&Self::secp(), Self::parse_key(internal_key))
-            .unwrap();
+    pub fn control_block(self, internal_key: Vec<u8>) -> Result<Vec<u8>, SwapTreeError> {
+        let spend_info = self.clone().spend_info(Self::parse_key(internal_key)?)?;
 
-        spend_info
+        Ok(spend_info
             .control_block(&(
                 Script::from(self.covenant_claim_leaf.output),
                 LeafVersion::default(),
             ))
-            .unwrap()
-            .serialize()
+            .ok_or(SwapTreeError::LeafNotFound)?
+            .serialize())
     }
 
-    fn tree_builder(self) -> TaprootBuilder {
-        TaprootBuilder::new()
-            .add_leaf(1, Script::from(self.covenant_claim_leaf.output))
-            .unwrap()
-            .add_leaf(2, Script::from(self.claim_leaf.output))
-            .unwrap()
-            .add_leaf(2, Script::from(self.refund_leaf.output))
-            .unwrap()
+    fn spend_info(self, internal_key: XOnlyPublicKey) -> Result<TaprootSpendInfo, SwapTreeError> {
+        self.leaves()?
+            .into_iter()
+            .try_fold(TaprootBuilder::new(), |builder, (depth, script)| {
+                builder.add_leaf(usize::from(depth), script)
+            })
+            .map_err(|err| SwapTreeError::InvalidTree(err.to_string()))?
+            .finalize(Self::secp(), internal_key)
+            .map_err(|_| SwapTreeError::InvalidTree("tree is incomplete".to_string()))
+    }
+
+    // Depths and scripts of the leaves in the order they are added to the tree
+    fn leaves(self) -> Result<Vec<(u8, Script)>, SwapTreeError> {
+        let leaves = match self.leaves {
+            Some(leaves) => leaves,
+            None => {
+                return Ok(vec![
+                    (1, Script::from(self.covenant_claim_leaf.output)),
+                    (2, Script::from(self.claim_leaf.output)),
+                    (2, Script::from(self.refund_leaf.output)),
+                ])
+            }
+        };
+
+        // Otherwise, the address would commit to a tree the covenant cannot be claimed with
+        if !leaves
+            .iter()
+            .any(|leaf| leaf.output == self.covenant_claim_leaf.output)
+        {
+            return Err(SwapTreeError::LeafNotFound);
+        }
+
+        Ok(leaves
+            .into_iter()
+            .map(|leaf| (leaf.depth, Script::from(leaf.output)))
+            .collect())
     }
 
-    pub fn secp() -> Secp256k1<All> {
-        Secp256k1::new()
+    /// Context shared by all callers, because creating one is expensive
+    pub fn secp() -> &'static Secp256k1<All> {
+        static SECP: OnceLock<Secp256k1<All>> = OnceLock::new();
+        SECP.get_or_init(Secp256k1::new)
     }
 
-    fn parse_key(internal_key: Vec<u8>) -> XOnlyPublicKey {
-        XOnlyPublicKey::from_slice(internal_key.as_slice()).unwrap()
+    fn parse_key(internal_key: Vec<u8>) -> Result<XOnlyPublicKey, SwapTreeError> {
+        XOnlyPublicKey::from_slice(internal_key.as_slice())
+            .map_err(|err| SwapTreeError::InvalidInternalKey(err.to_string()))
     }
 }
 
@@ -151,7 +156,8 @@ mod swap_tree_tests {
     use elements::pset::serialize::Serialize;
     use elements::AddressParams;
 
-    use crate::claimer::tree::SwapTree;
+    use crate::claimer::leaf::CovenantLeafVersion;
+    use crate::claimer::tree::{SwapTree, SwapTreeError, TreeLeaf};
 
     const INTERNAL_KEY: &str = "816963af90d4b882ccbcaacc920ba8e4fdd35c083a052a08d5c1732272ffccd8";
 
@@ -181,7 +187,8 @@ mod swap_tree_tests {
 
         let address = serde_json::from_str::<SwapTree>(TREE_JSON)
             .unwrap()
-            .address(internal_key, &AddressParams::ELEMENTS);
+            .address(internal_key, &AddressParams::ELEMENTS)
+            .unwrap();
 
         assert_eq!(
             address.to_string(),
@@ -207,6 +214,39 @@ mod swap_tree_tests {
             hex::encode(details.preimage_hash),
             "af8b5215948249f6e10adddc531ffe5d4428b917"
         );
+        assert_eq!(details.version, CovenantLeafVersion::Legacy);
+        assert_eq!(details.expected_output_version, 0);
+    }
+
+    #[test]
+    fn covenant_details_sha256() {
+        let swap: SwapTree = serde_json::from_str("{
+    \"claimLeaf\": {
+      \"version\": 196,
+      \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558820812910149e0e71209624487851f80a0cb97652efb0a836205628bc1b0e8e3aa7ac\"
+    },
+    \"refundLeaf\": {
+      \"version\": 196,
+      \"output\": \"201ec7adf6f1c40ad340533027d15952c0c5b7aa0dd6c4b38d838e62d32d4d0259ad020b06b1\"
+    },
+    \"covenantClaimLeaf\": {
+      \"version\": 196,
+      \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558800d1008814aff4f5af812e3db39024f2000db7e23091dc06038800ce51882025b251070e29ca19043cf33ccd7324e2ddab03ecc4ae0b5e77c4fc0e5cf6c95a8800cf7508a08601000000000087\"
+    }
+  }").unwrap();
+        let details = swap.covenant_details().unwrap();
+
+        assert_eq!(details.expected_amount, 100_000);
+        assert_eq!(
+            hex::encode(&details.expected_output),
+            "aff4f5af812e3db39024f2000db7e23091dc0603"
+        );
+        assert_eq!(
+            hex::encode(&details.preimage_hash),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(details.version, CovenantLeafVersion::Current);
+        assert!(details.is_preimage_valid(&[]));
     }
 
     #[test]
@@ -228,6 +268,7 @@ mod swap_tree_tests {
         let details = swap.covenant_details().unwrap();
 
         assert_eq!(details.expected_amount, 9556);
+        assert_eq!(details.expected_output_version, -1);
         assert_eq!(
             hex::encode(details.expected_output),
             "b80f397fe1edcb87e54ce9cd5b4a5896b19e7d577b3bb868c4eb7ff1c3a5bb93"
@@ -238,13 +279,137 @@ mod swap_tree_tests {
         );
     }
 
+    #[test]
+    fn address_leaves() {
+        let internal_key = hex::decode(INTERNAL_KEY).unwrap();
+        let tree = serde_json::from_str::<SwapTree>(TREE_JSON).unwrap();
+        let leaf = |depth: u8, output: &[u8]| TreeLeaf {
+            depth,
+            output: output.to_vec(),
+        };
+
+        // The default layout described by its leaves
+        let mut explicit = tree.clone();
+        explicit.leaves = Some(vec![
+            leaf(1, &tree.covenant_claim_leaf.output),
+            leaf(2, &tree.claim_leaf.output),
+            leaf(2, &tree.refund_leaf.output),
+        ]);
+        assert_eq!(
+            explicit
+                .clone()
+                .address(internal_key.clone(), &AddressParams::ELEMENTS)
+                .unwrap()
+                .to_string(),
+            "ert1pephte6qwvmhs74wp9aup4fs0syk6ed233sqtved7grk6qucedj0qksw749"
+        );
+        assert_eq!(
+            explicit.control_block(internal_key.clone()).unwrap(),
+            tree.clone().control_block(internal_key.clone()).unwrap()
+        );
+
+        // With an additional path, all leaves are at depth 2
+        let mut extended = tree.clone();
+        extended.leaves = Some(vec![
+            leaf(2, &tree.claim_leaf.output),
+            leaf(2, &tree.refund_leaf.output),
+            leaf(2, &[0x51]),
+            leaf(2, &tree.covenant_claim_leaf.output),
+        ]);
+        assert_ne!(
+            extended
+                .clone()
+                .address(internal_key.clone(), &AddressParams::ELEMENTS)
+                .unwrap()
+                .to_string(),
+            "ert1pephte6qwvmhs74wp9aup4fs0syk6ed233sqtved7grk6qucedj0qksw749"
+        );
+        // Control byte and internal key followed by one hash per level
+        assert_eq!(
+            extended.control_block(internal_key).unwrap().len(),
+            33 + 2 * 32
+        );
+    }
+
+    #[test]
+    fn address_leaves_invalid() {
+        let internal_key = hex::decode(INTERNAL_KEY).unwrap();
+        let tree = serde_json::from_str::<SwapTree>(TREE_JSON).unwrap();
+
+        let mut missing = tree.clone();
+        missing.leaves = Some(vec![
+            TreeLeaf {
+                depth: 1,
+                output: tree.claim_leaf.output.clone(),
+            },
+            TreeLeaf {
+                depth: 1,
+                output: tree.refund_leaf.output.clone(),
+            },
+        ]);
+        assert!(matches!(
+            missing.address(internal_key.clone(), &AddressParams::ELEMENTS),
+            Err(SwapTreeError::LeafNotFound)
+        ));
+
+        let mut incomplete = tree.clone();
+        incomplete.leaves = Some(vec![
+            TreeLeaf {
+                depth: 1,
+                output: tree.covenant_claim_leaf.output.clone(),
+            },
+            TreeLeaf {
+                depth: 2,
+                output: tree.claim_leaf.output.clone(),
+            },
+        ]);
+        assert!(matches!(
+            incomplete.address(internal_key, &AddressParams::ELEMENTS),
+            Err(SwapTreeError::InvalidTree(_))
+        ));
+    }
+
+    #[test]
+    fn address_invalid_internal_key() {
+        let tree = serde_json::from_str::<SwapTree>(TREE_JSON).unwrap();
+
+        assert!(matches!(
+            tree.clone()
+                .address(vec![1, 2, 3], &AddressParams::ELEMENTS),
+            Err(SwapTreeError::InvalidInternalKey(_))
+        ));
+        assert!(matches!(
+            tree.control_block(vec![0; 32]),
+            Err(SwapTreeError::InvalidInternalKey(_))
+        ));
+    }
+
+    #[test]
+    fn parse_malformed_swap_tree() {
+        assert!(serde_json::from_str::<SwapTree>("{}").is_err());
+        assert!(serde_json::from_str::<SwapTree>(
+            "{\"claimLeaf\":{\"output\":\"0\"},\"refundLeaf\":{\"output\":\"\"},\"covenantClaimLeaf\":{\"output\":\"\"}}"
+        )
+        .is_err());
+
+        for output in ["", "82", "4c", "4dff", "8201208800d1"] {
+            let tree = serde_json::from_str::<SwapTree>(&format!(
+                "{{\"claimLeaf\":{{\"output\":\"\"}},\"refundLeaf\":{{\"output\":\"\"}},\"covenantClaimLeaf\":{{\"output\":\"{}\"}}}}",
+                output
+            ))
+            .unwrap();
+            assert!(tree.covenant_details().is_err());
+        }
+    }
+
     #[test]
     fn control_block() {
         let internal_key = hex::decode(INTERNAL_KEY).unwrap();
 
         let control_block = serde_json::from_str::<SwapTree>(TREE_JSON)
             .unwrap()
-            .control_block(internal_key);
+            .control_block(internal_key)
+            .unwrap();
 
         assert_eq!(hex::encode(control_block), "c4816963af90d4b882ccbcaacc920ba8e4fdd35c083a052a08d5c1732272ffccd8d6350677678c01dd2e3e90f67a0728a81e263d08b36623747ad9c811faf2fc42");
     }
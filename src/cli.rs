@@ -0,0 +1,92 @@
+use clap::{Parser, Subcommand};
+
+/// Watches the chain for claimable covenants of Boltz swaps and broadcasts their claims
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the daemon; the default when no command is given
+    Run,
+
+    /// Rescan the chain for lockup transactions of pending covenants
+    Rescan {
+        /// Block height from which to rescan
+        #[arg(long)]
+        from_height: u64,
+    },
+
+    /// List the covenants that were not claimed yet
+    ListCovenants,
+
+    /// Claim the covenant of a swap whose lockup transaction was found already
+    Claim { swap_id: String },
+
+    /// Run the database migrations
+    Migrate,
+
+    /// Validate the configuration and connect to the database and chain backend
+    CheckConfig,
+
+    /// Export the covenants that were not claimed yet to a file
+    Export { path: String },
+
+    /// Import covenants from a file that was exported by another instance
+    Import { path: String },
+}
+
+#[cfg(test)]
+mod cli_test {
+    use clap::Parser;
+
+    use crate::cli::{Cli, Command};
+
+    #[test]
+    fn test_default_command() {
+        assert!(Cli::try_parse_from(["covclaim"]).unwrap().command.is_none());
+    }
+
+    #[test]
+    fn test_rescan() {
+        match Cli::try_parse_from(["covclaim", "rescan", "--from-height", "21"])
+            .unwrap()
+            .command
+        {
+            Some(Command::Rescan { from_height }) => assert_eq!(from_height, 21),
+            _ => panic!("not a rescan command"),
+        };
+
+        assert!(Cli::try_parse_from(["covclaim", "rescan"]).is_err());
+    }
+
+    #[test]
+    fn test_claim() {
+        match Cli::try_parse_from(["covclaim", "claim", "swap"])
+            .unwrap()
+            .command
+        {
+            Some(Command::Claim { swap_id }) => assert_eq!(swap_id, "swap"),
+            _ => panic!("not a claim command"),
+        };
+    }
+
+    #[test]
+    fn test_export_import() {
+        assert!(matches!(
+            Cli::try_parse_from(["covclaim", "export", "covenants.json"])
+                .unwrap()
+                .command,
+            Some(Command::Export { path }) if path == "covenants.json"
+        ));
+        assert!(matches!(
+            Cli::try_parse_from(["covclaim", "import", "covenants.json"])
+                .unwrap()
+                .command,
+            Some(Command::Import { path }) if path == "covenants.json"
+        ));
+    }
+}
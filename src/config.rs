@@ -0,0 +1,797 @@
+use std::env;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use elements::{AddressParams, AssetId};
+use reqwest::header::HeaderMap;
+use tracing::{debug, info};
+
+use crate::api::destinations::AllowedDestinations;
+use crate::chain::cache::CachedBackend;
+use crate::chain::client::{ChainClient, RpcAuth};
+use crate::chain::electrum::ElectrumClient;
+use crate::chain::esplora::{parse_headers, EsploraClient};
+use crate::chain::failover::FailoverBackend;
+use crate::chain::fee;
+use crate::chain::mempool::MempoolClient;
+use crate::chain::types::ChainBackend;
+use crate::claimer::{RescanMode, DEFAULT_RESCAN_PREFETCH};
+use crate::notifier::chat::{ChatNotifier, ChatTarget};
+use crate::notifier::composite::{CompositeNotifier, SinkToggles};
+use crate::notifier::Notifier;
+use crate::utils::http::HttpConfig;
+use crate::utils::rate_limit::RateLimit;
+use crate::utils::retry::RetryPolicy;
+use crate::{boltz, chain, db, notifier};
+
+pub const DEFAULT_CHAIN_BACKEND: &str = "elements";
+
+/// Settings of the covclaim daemon; see the .env file for what the variables do
+#[derive(Clone)]
+pub struct Config {
+    pub network: String,
+    // None when the network is detected from the chain backend
+    pub address_params: Option<&'static AddressParams>,
+    pub database_url: String,
+    pub database_health_check_interval: u64,
+    pub http: HttpConfig,
+
+    pub chain_backends: Vec<ChainBackendConfig>,
+    pub chain_backend_health_check_interval: u64,
+    pub chain_cache_size: usize,
+    pub chain_cache_ttl: u64,
+    #[cfg(feature = "chaos")]
+    pub chaos: chain::chaos::ChaosConfig,
+
+    pub boltz: Option<BoltzConfig>,
+    pub boltz_websocket_poll_interval: u64,
+    pub boltz_verify_interval: u64,
+
+    pub sweep_time: u64,
+    pub sweep_interval: u64,
+    pub sweep_max_per_round: usize,
+    pub sweep_jitter: u64,
+
+    pub rescan_mode: RescanMode,
+    pub max_rescan_depth: u64,
+    pub script_rescan_threshold: usize,
+    pub rescan_prefetch: usize,
+
+    pub fee_target_blocks: u16,
+    pub fee_floor: f64,
+    pub fee_ceiling: f64,
+
+    // Overrides the policy asset of the network
+    pub policy_asset: Option<AssetId>,
+    pub lockup_confirmations: u64,
+    pub op_return_data: Option<Vec<u8>>,
+    // Parsed once the address params are known
+    pub allowed_destinations: String,
+
+    pub sinks: SinkConfig,
+
+    pub prune_interval: u64,
+    pub prune_retention_days: u64,
+    pub prune_archive_path: Option<String>,
+
+    pub api_host: String,
+    pub api_port: u32,
+    pub api_read_only: bool,
+    pub api_admin_token: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum ChainBackendConfig {
+    Elements {
+        host: String,
+        port: u32,
+        auth: RpcAuth,
+        max_requests_per_second: u64,
+    },
+    Esplora {
+        endpoint: String,
+        poll_interval: u64,
+        max_requests_per_second: u64,
+        headers: HeaderMap,
+        mempool_poll_interval: u64,
+        retry_policy: RetryPolicy,
+    },
+    Mempool {
+        endpoint: String,
+        poll_interval: u64,
+        max_requests_per_second: u64,
+        retry_policy: RetryPolicy,
+    },
+    Electrum {
+        endpoint: String,
+        poll_interval: u64,
+    },
+}
+
+#[derive(Clone)]
+pub struct BoltzConfig {
+    pub endpoint: String,
+    pub headers: HeaderMap,
+    pub timeout: u64,
+    pub retry_policy: RetryPolicy,
+}
+
+#[derive(Clone, Default)]
+pub struct SinkConfig {
+    pub nats: Option<NatsConfig>,
+    pub amqp: Option<AmqpConfig>,
+    pub webhook: Option<WebhookConfig>,
+    pub chats: Vec<ChatTarget>,
+    // Names of sinks that are muted; can be changed without restarting
+    pub disabled: Vec<String>,
+    // Whether the network is appended to the NATS subject and the AMQP routing key
+    pub network_suffix: bool,
+}
+
+#[derive(Clone)]
+pub struct NatsConfig {
+    pub url: String,
+    pub subject: String,
+}
+
+#[derive(Clone)]
+pub struct AmqpConfig {
+    pub url: String,
+    pub exchange: String,
+    pub routing_key: String,
+}
+
+#[derive(Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: String,
+    pub retry_policy: RetryPolicy,
+    pub redelivery_interval: u64,
+}
+
+impl Config {
+    /// Parses the settings from the environment; fails on the first setting that is
+    /// missing or invalid
+    pub fn from_env() -> Result<Config, String> {
+        let network = required_env("NETWORK")?;
+        let address_params = match network.as_str() {
+            chain::network::AUTO_DETECT => None,
+            _ => Some(address_params(network.as_str())?),
+        };
+
+        let boltz = match optional_env("BOLTZ_ENDPOINT") {
+            Some(endpoint) => Some(BoltzConfig {
+                endpoint,
+                headers: parse_headers(env::var("BOLTZ_HEADERS").unwrap_or_default().as_str(), "")
+                    .map_err(|err| format!("BOLTZ_HEADERS invalid: {}", err))?,
+                timeout: parse_env_or("BOLTZ_TIMEOUT", 0)?,
+                retry_policy: RetryPolicy::new(
+                    parse_env_or("BOLTZ_MAX_RETRIES", 0)?,
+                    parse_env_or("BOLTZ_RETRY_DELAY", 500)?,
+                ),
+            }),
+            None => None,
+        };
+        let boltz_websocket_poll_interval = parse_env_or("BOLTZ_WEBSOCKET_POLL_INTERVAL", 0)?;
+        if boltz_websocket_poll_interval > 0 && boltz.is_none() {
+            return Err("BOLTZ_ENDPOINT must be set for the Boltz WebSocket".to_string());
+        }
+        let boltz_verify_interval = parse_env_or("BOLTZ_VERIFY_INTERVAL", 0)?;
+        if boltz_verify_interval > 0 && boltz.is_none() {
+            return Err("BOLTZ_ENDPOINT must be set to verify covenants".to_string());
+        }
+
+        Ok(Config {
+            network,
+            address_params,
+            database_url: required_env("DATABASE_URL")?,
+            database_health_check_interval: parse_env_or("DATABASE_HEALTH_CHECK_INTERVAL", 0)?,
+            http: HttpConfig {
+                timeout: parse_env_or("HTTP_TIMEOUT", 0)?,
+                connect_timeout: parse_env_or("HTTP_CONNECT_TIMEOUT", 0)?,
+                proxy: optional_env("HTTP_PROXY_URL"),
+            },
+
+            chain_backends: env::var("CHAIN_BACKEND")
+                .unwrap_or(DEFAULT_CHAIN_BACKEND.to_string())
+                .split(',')
+                .map(|name| ChainBackendConfig::from_env(name.trim()))
+                .collect::<Result<Vec<ChainBackendConfig>, String>>()?,
+            chain_backend_health_check_interval: parse_env_or(
+                "CHAIN_BACKEND_HEALTH_CHECK_INTERVAL",
+                30,
+            )?,
+            chain_cache_size: parse_env_or("CHAIN_CACHE_SIZE", 0)?,
+            chain_cache_ttl: parse_env_or("CHAIN_CACHE_TTL", 3600)?,
+            #[cfg(feature = "chaos")]
+            chaos: chain::chaos::ChaosConfig {
+                seed: parse_env_or("CHAOS_SEED", 0)?,
+                max_latency: parse_env_or("CHAOS_MAX_LATENCY", 0)?,
+                error_rate: parse_env_or("CHAOS_ERROR_RATE", 0.0)?,
+                reorg_rate: parse_env_or("CHAOS_REORG_RATE", 0.0)?,
+                reorg_depth: parse_env_or("CHAOS_REORG_DEPTH", 0)?,
+            },
+
+            boltz,
+            boltz_websocket_poll_interval,
+            boltz_verify_interval,
+
+            sweep_time: parse_env("SWEEP_TIME")?,
+            sweep_interval: parse_env("SWEEP_INTERVAL")?,
+            sweep_max_per_round: parse_env_or("SWEEP_MAX_PER_ROUND", 0)?,
+            sweep_jitter: parse_env_or("SWEEP_JITTER", 0)?,
+
+            rescan_mode: RescanMode::parse(parse_env_or("RESCAN", "auto".to_string())?.as_str())
+                .map_err(|err| format!("RESCAN invalid: {}", err))?,
+            max_rescan_depth: parse_env_or("MAX_RESCAN_DEPTH", 0)?,
+            script_rescan_threshold: parse_env_or("SCRIPT_RESCAN_THRESHOLD", 0)?,
+            rescan_prefetch: parse_env_or("RESCAN_PREFETCH", DEFAULT_RESCAN_PREFETCH)?,
+
+            fee_target_blocks: parse_env_or("FEE_TARGET_BLOCKS", fee::DEFAULT_TARGET_BLOCKS)?,
+            fee_floor: parse_env_or("FEE_FLOOR", fee::DEFAULT_FLOOR)?,
+            fee_ceiling: parse_env_or("FEE_CEILING", fee::DEFAULT_CEILING)?,
+
+            policy_asset: match optional_env("POLICY_ASSET") {
+                Some(asset) => Some(AssetId::from_str(&asset).map_err(|_| "POLICY_ASSET invalid")?),
+                None => None,
+            },
+            lockup_confirmations: parse_env_or("LOCKUP_CONFIRMATIONS", 0)?,
+            op_return_data: match optional_env("CLAIM_OP_RETURN_DATA") {
+                Some(data) => Some(hex::decode(data).map_err(|_| "CLAIM_OP_RETURN_DATA invalid")?),
+                None => None,
+            },
+            allowed_destinations: env::var("ALLOWED_DESTINATIONS").unwrap_or_default(),
+
+            sinks: SinkConfig::from_env()?,
+
+            prune_interval: parse_env_or("PRUNE_INTERVAL", 0)?,
+            prune_retention_days: parse_env_or("PRUNE_RETENTION_DAYS", 30)?,
+            prune_archive_path: optional_env("PRUNE_ARCHIVE_PATH"),
+
+            api_host: required_env("API_HOST")?,
+            api_port: parse_env("API_PORT")?,
+            api_read_only: parse_env_or("API_READ_ONLY", false)?,
+            api_admin_token: optional_env("API_ADMIN_TOKEN"),
+        })
+    }
+
+    /// Names of the chain backends, in the order they are failed over between
+    pub fn chain_backend_names(&self) -> Vec<&'static str> {
+        self.chain_backends
+            .iter()
+            .map(|backend| backend.name())
+            .collect()
+    }
+
+    pub fn allowed_destinations(
+        &self,
+        params: &'static AddressParams,
+    ) -> Result<AllowedDestinations, String> {
+        AllowedDestinations::parse(params, &self.allowed_destinations)
+            .map_err(|err| format!("ALLOWED_DESTINATIONS invalid: {}", err))
+    }
+
+    /// The configured policy asset or the one of the network
+    pub fn policy_asset(&self, network: &str) -> Option<AssetId> {
+        self.policy_asset
+            .or_else(|| chain::network::policy_asset(network))
+    }
+
+    /// Makes sure the chain backend is on the configured network or detects the network
+    /// when it is set to auto
+    pub async fn resolve_network(
+        &self,
+        chain_backend: &(dyn ChainBackend + Send + Sync),
+    ) -> Result<(String, &'static AddressParams), String> {
+        match self.address_params {
+            Some(params) => {
+                chain::network::verify_network(chain_backend, self.network.as_str())
+                    .await
+                    .map_err(|err| format!("chain backend is on wrong network: {}", err))?;
+
+                Ok((self.network.clone(), params))
+            }
+            None => {
+                let detected = chain::network::detect_network(chain_backend)
+                    .await
+                    .map_err(|err| format!("could not detect network: {}", err))?;
+
+                Ok((detected.to_string(), address_params(detected)?))
+            }
+        }
+    }
+
+    pub fn boltz_client(&self, http_client: reqwest::Client) -> Option<boltz::api::Client> {
+        self.boltz.as_ref().map(|config| {
+            boltz::api::Client::new(config.endpoint.clone(), http_client)
+                .with_headers(config.headers.clone())
+                .with_timeout(config.timeout)
+                .with_retry_policy(config.retry_policy)
+        })
+    }
+
+    pub async fn chain_backend(
+        &self,
+        db: db::Pool,
+        http_client: reqwest::Client,
+        boltz_client: Option<boltz::api::Client>,
+    ) -> Result<Arc<Box<dyn ChainBackend + Send + Sync>>, String> {
+        let mut backends = Vec::new();
+        for backend in self.chain_backends.iter() {
+            backends.push(
+                backend
+                    .connect(
+                        db.clone(),
+                        self.address_params,
+                        http_client.clone(),
+                        boltz_client.clone(),
+                    )
+                    .await?,
+            );
+        }
+
+        let backend: Box<dyn ChainBackend + Send + Sync> = if backends.len() == 1 {
+            backends.remove(0)
+        } else {
+            let names: Vec<String> = self
+                .chain_backend_names()
+                .iter()
+                .map(|name| name.to_string())
+                .collect();
+            info!("Failing over between chain backends: {}", names.join(", "));
+            let failover = FailoverBackend::new(names, backends);
+            failover.start_health_check(self.chain_backend_health_check_interval);
+
+            Box::new(failover)
+        };
+        #[cfg(feature = "chaos")]
+        let backend = self.with_chaos(backend);
+
+        Ok(match NonZeroUsize::new(self.chain_cache_size) {
+            Some(size) => {
+                info!(
+                    "Caching {} transactions and blocks for {} seconds",
+                    size, self.chain_cache_ttl
+                );
+
+                Arc::new(Box::new(CachedBackend::new(
+                    backend,
+                    size,
+                    self.chain_cache_ttl,
+                )))
+            }
+            None => {
+                info!("Not caching transactions and blocks");
+                Arc::new(backend)
+            }
+        })
+    }
+
+    // Only for tests and staging; faults are injected when any of the CHAOS_* variables is set
+    #[cfg(feature = "chaos")]
+    fn with_chaos(
+        &self,
+        backend: Box<dyn ChainBackend + Send + Sync>,
+    ) -> Box<dyn ChainBackend + Send + Sync> {
+        if self.chaos == chain::chaos::ChaosConfig::default() {
+            return backend;
+        }
+
+        Box::new(chain::chaos::ChaosBackend::new(backend, self.chaos))
+    }
+
+    pub async fn notifier(
+        &self,
+        db: db::Pool,
+        http_client: reqwest::Client,
+        sink_toggles: SinkToggles,
+        network: String,
+    ) -> Result<Option<Arc<Box<dyn Notifier + Send + Sync>>>, String> {
+        let mut notifiers: Vec<Box<dyn Notifier + Send + Sync>> = Vec::new();
+
+        if let Some(nats) = &self.sinks.nats {
+            notifiers.push(Box::new(
+                notifier::nats::NatsNotifier::connect(
+                    nats.url.clone(),
+                    self.sinks.topic(nats.subject.as_str(), network.as_str()),
+                )
+                .await
+                .map_err(|err| format!("could not connect to NATS: {}", err))?,
+            ));
+        }
+
+        if let Some(amqp) = &self.sinks.amqp {
+            notifiers.push(Box::new(
+                notifier::amqp::AmqpNotifier::connect(
+                    amqp.url.clone(),
+                    amqp.exchange.clone(),
+                    self.sinks
+                        .topic(amqp.routing_key.as_str(), network.as_str()),
+                )
+                .await
+                .map_err(|err| format!("could not connect to AMQP broker: {}", err))?,
+            ));
+        }
+
+        if let Some(webhook) = &self.sinks.webhook {
+            let notifier = notifier::webhook::WebhookNotifier::new(
+                db,
+                webhook.url.clone(),
+                webhook.secret.clone(),
+                http_client.clone(),
+                webhook.retry_policy,
+            );
+            notifier.start_redelivery(webhook.redelivery_interval);
+            notifiers.push(Box::new(notifier));
+        }
+
+        for target in self.sinks.chats.iter() {
+            notifiers.push(Box::new(ChatNotifier::new(
+                target.clone(),
+                http_client.clone(),
+            )));
+        }
+
+        sink_toggles.set_disabled(self.sinks.disabled.clone());
+        if notifiers.is_empty() {
+            debug!("No event sinks configured");
+            return Ok(None);
+        }
+
+        info!(
+            "Publishing covenant events to: {}",
+            notifiers
+                .iter()
+                .map(|notifier| notifier.name())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+        Ok(Some(Arc::new(Box::new(
+            CompositeNotifier::new(notifiers)
+                .with_toggles(sink_toggles)
+                .with_network(network),
+        ))))
+    }
+
+    /// Started verifier of covenants against the Boltz API when enabled
+    pub fn verifier(
+        &self,
+        db: db::Pool,
+        boltz_client: Option<boltz::api::Client>,
+        notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+    ) -> Option<boltz::verifier::Verifier> {
+        if self.boltz_verify_interval == 0 {
+            return None;
+        }
+
+        let verifier =
+            boltz::verifier::Verifier::new(db, boltz_client?, self.boltz_verify_interval, notifier);
+        verifier.start();
+
+        Some(verifier)
+    }
+
+    /// Started subscription to swap updates of the Boltz WebSocket when enabled
+    pub fn swap_updates(
+        &self,
+        db: db::Pool,
+        chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    ) -> Result<Option<boltz::ws::SwapUpdates>, String> {
+        let boltz = match &self.boltz {
+            Some(boltz) if self.boltz_websocket_poll_interval > 0 => boltz,
+            _ => return Ok(None),
+        };
+
+        let swap_updates = boltz::ws::SwapUpdates::new(
+            boltz.endpoint.clone(),
+            self.boltz_websocket_poll_interval,
+            db,
+            chain_backend,
+        )
+        .map_err(|err| format!("could not create Boltz WebSocket client: {}", err))?;
+        swap_updates.start();
+
+        Ok(Some(swap_updates))
+    }
+}
+
+impl ChainBackendConfig {
+    fn from_env(name: &str) -> Result<ChainBackendConfig, String> {
+        match name {
+            "elements" => Ok(ChainBackendConfig::Elements {
+                host: required_env("ELEMENTS_HOST")?,
+                port: parse_env("ELEMENTS_PORT")?,
+                auth: match optional_env("ELEMENTS_COOKIE") {
+                    Some(cookie) => RpcAuth::Cookie(cookie),
+                    None => RpcAuth::UserPassword {
+                        user: env::var("ELEMENTS_USER")
+                            .map_err(|_| "ELEMENTS_COOKIE or ELEMENTS_USER must be set")?,
+                        password: required_env("ELEMENTS_PASSWORD")?,
+                    },
+                },
+                max_requests_per_second: parse_env_or("ELEMENTS_MAX_REQUESTS_PER_SECOND", 0)?,
+            }),
+            "esplora" => Ok(ChainBackendConfig::Esplora {
+                endpoint: required_env("ESPLORA_ENDPOINT")?,
+                poll_interval: parse_env("ESPLORA_POLL_INTERVAL")?,
+                max_requests_per_second: parse_env("ESPLORA_MAX_REQUESTS_PER_SECOND")?,
+                headers: parse_headers(
+                    env::var("ESPLORA_HEADERS").unwrap_or_default().as_str(),
+                    env::var("ESPLORA_BASIC_AUTH").unwrap_or_default().as_str(),
+                )
+                .map_err(|err| format!("ESPLORA_HEADERS invalid: {}", err))?,
+                mempool_poll_interval: parse_env_or("ESPLORA_MEMPOOL_POLL_INTERVAL", 0)?,
+                retry_policy: esplora_retry_policy()?,
+            }),
+            "mempool" => Ok(ChainBackendConfig::Mempool {
+                endpoint: required_env("MEMPOOL_ENDPOINT")?,
+                poll_interval: parse_env("MEMPOOL_POLL_INTERVAL")?,
+                max_requests_per_second: parse_env("MEMPOOL_MAX_REQUESTS_PER_SECOND")?,
+                retry_policy: esplora_retry_policy()?,
+            }),
+            "electrum" => Ok(ChainBackendConfig::Electrum {
+                endpoint: required_env("ELECTRUM_ENDPOINT")?,
+                poll_interval: parse_env("ELECTRUM_POLL_INTERVAL")?,
+            }),
+            _ => Err(format!("unknown chain backend: {}", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChainBackendConfig::Elements { .. } => "elements",
+            ChainBackendConfig::Esplora { .. } => "esplora",
+            ChainBackendConfig::Mempool { .. } => "mempool",
+            ChainBackendConfig::Electrum { .. } => "electrum",
+        }
+    }
+
+    async fn connect(
+        &self,
+        db: db::Pool,
+        network_params: Option<&'static AddressParams>,
+        http_client: reqwest::Client,
+        boltz_client: Option<boltz::api::Client>,
+    ) -> Result<Box<dyn ChainBackend + Send + Sync>, String> {
+        info!("Using {} chain backend", self.name());
+        match self.clone() {
+            ChainBackendConfig::Elements {
+                host,
+                port,
+                auth,
+                max_requests_per_second,
+            } => {
+                let rate_limit = RateLimit::new("Elements RPC", max_requests_per_second)
+                    .map_err(|err| format!("could not create Elements rate limit: {}", err))?;
+
+                Ok(Box::new(
+                    ChainClient::new(host, port, auth, http_client, rate_limit)
+                        .connect()
+                        .await
+                        .map_err(|err| format!("could not connect to Elements client: {}", err))?,
+                ))
+            }
+            ChainBackendConfig::Esplora {
+                endpoint,
+                poll_interval,
+                max_requests_per_second,
+                headers,
+                mempool_poll_interval,
+                retry_policy,
+            } => {
+                let client = EsploraClient::new(
+                    endpoint,
+                    poll_interval,
+                    max_requests_per_second,
+                    boltz_client,
+                    http_client,
+                    retry_policy,
+                )
+                .map_err(|err| format!("could not create Esplora client: {}", err))?
+                .with_headers(headers);
+                client.connect();
+
+                if mempool_poll_interval > 0 {
+                    client.monitor_mempool(db, mempool_poll_interval);
+                }
+
+                Ok(Box::new(client))
+            }
+            ChainBackendConfig::Mempool {
+                endpoint,
+                poll_interval,
+                max_requests_per_second,
+                retry_policy,
+            } => {
+                let mut client = MempoolClient::new(
+                    endpoint,
+                    poll_interval,
+                    max_requests_per_second,
+                    boltz_client,
+                    db,
+                    http_client,
+                    retry_policy,
+                )
+                .map_err(|err| format!("could not create mempool.space client: {}", err))?;
+                if let Some(network_params) = network_params {
+                    client = client.with_address_params(network_params);
+                }
+
+                Ok(Box::new(client.connect().await.map_err(|err| {
+                    format!("could not connect to mempool.space: {}", err)
+                })?))
+            }
+            ChainBackendConfig::Electrum {
+                endpoint,
+                poll_interval,
+            } => Ok(Box::new(
+                ElectrumClient::new(endpoint, poll_interval, db)
+                    .connect()
+                    .await
+                    .map_err(|err| format!("could not connect to Electrum server: {}", err))?,
+            )),
+        }
+    }
+}
+
+impl SinkConfig {
+    fn from_env() -> Result<SinkConfig, String> {
+        let mut chats = Vec::new();
+        if let Some(bot_token) = optional_env("TELEGRAM_BOT_TOKEN") {
+            chats.push(ChatTarget::Telegram {
+                bot_token,
+                chat_id: required_env("TELEGRAM_CHAT_ID")?,
+            });
+        }
+        if let Some(webhook_url) = optional_env("SLACK_WEBHOOK_URL") {
+            chats.push(ChatTarget::Slack { webhook_url });
+        }
+
+        Ok(SinkConfig {
+            nats: match optional_env("NATS_URL") {
+                Some(url) => Some(NatsConfig {
+                    url,
+                    subject: required_env("NATS_SUBJECT")?,
+                }),
+                None => None,
+            },
+            amqp: match optional_env("AMQP_URL") {
+                Some(url) => Some(AmqpConfig {
+                    url,
+                    exchange: required_env("AMQP_EXCHANGE")?,
+                    routing_key: required_env("AMQP_ROUTING_KEY")?,
+                }),
+                None => None,
+            },
+            webhook: match optional_env("WEBHOOK_URL") {
+                Some(url) => Some(WebhookConfig {
+                    url,
+                    secret: required_env("WEBHOOK_SECRET")?,
+                    retry_policy: RetryPolicy::new(
+                        parse_env_or("WEBHOOK_MAX_RETRIES", 3)?,
+                        parse_env_or("WEBHOOK_RETRY_DELAY", 1000)?,
+                    ),
+                    redelivery_interval: parse_env_or("WEBHOOK_REDELIVERY_INTERVAL", 60)?,
+                }),
+                None => None,
+            },
+            chats,
+            disabled: disabled_sinks(),
+            network_suffix: parse_env_or("SINK_NETWORK_SUFFIX", false)?,
+        })
+    }
+
+    /// NATS subject or AMQP routing key, namespaced with the network when configured
+    pub fn topic(&self, topic: &str, network: &str) -> String {
+        if self.network_suffix {
+            format!("{}.{}", topic, network)
+        } else {
+            topic.to_string()
+        }
+    }
+}
+
+pub fn parse_env<T: FromStr>(name: &str) -> Result<T, String> {
+    required_env(name)?
+        .parse::<T>()
+        .map_err(|_| format!("{} invalid", name))
+}
+
+// Optional settings that are not set keep the behaviour from before they were added
+pub fn parse_env_or<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match optional_env(name) {
+        Some(value) => value.parse::<T>().map_err(|_| format!("{} invalid", name)),
+        None => Ok(default),
+    }
+}
+
+/// Event sinks that are muted with DISABLED_SINKS
+pub fn disabled_sinks() -> Vec<String> {
+    env::var("DISABLED_SINKS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn required_env(name: &str) -> Result<String, String> {
+    env::var(name).map_err(|_| format!("{} must be set", name))
+}
+
+fn optional_env(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn esplora_retry_policy() -> Result<RetryPolicy, String> {
+    Ok(RetryPolicy::new(
+        parse_env_or("ESPLORA_MAX_RETRIES", 0)?,
+        parse_env_or("ESPLORA_RETRY_DELAY", 500)?,
+    ))
+}
+
+fn address_params(network: &str) -> Result<&'static AddressParams, String> {
+    if network == chain::network::CUSTOM {
+        return chain::network::custom_address_params(
+            parse_env("NETWORK_P2PKH_PREFIX")?,
+            parse_env("NETWORK_P2SH_PREFIX")?,
+            parse_env("NETWORK_BLINDED_PREFIX")?,
+            required_env("NETWORK_BECH32_HRP")?.as_str(),
+            required_env("NETWORK_BLECH32_HRP")?.as_str(),
+        )
+        .map_err(|err| format!("could not parse custom network: {}", err));
+    }
+
+    chain::network::address_params(network).ok_or_else(|| format!("unknown network: {}", network))
+}
+
+#[cfg(test)]
+mod config_test {
+    use std::env;
+
+    use crate::config::{parse_env, parse_env_or, SinkConfig};
+
+    // Every test uses its own variables, because tests run in parallel
+    #[test]
+    fn test_parse_env() {
+        assert_eq!(
+            parse_env::<u64>("CONFIG_TEST_MISSING"),
+            Err("CONFIG_TEST_MISSING must be set".to_string())
+        );
+
+        env::set_var("CONFIG_TEST_PARSE", "21");
+        assert_eq!(parse_env::<u64>("CONFIG_TEST_PARSE"), Ok(21));
+        assert_eq!(
+            parse_env::<bool>("CONFIG_TEST_PARSE"),
+            Err("CONFIG_TEST_PARSE invalid".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_env_or() {
+        assert_eq!(parse_env_or::<u64>("CONFIG_TEST_DEFAULT", 30), Ok(30));
+
+        env::set_var("CONFIG_TEST_EMPTY", "");
+        assert_eq!(parse_env_or::<u64>("CONFIG_TEST_EMPTY", 30), Ok(30));
+
+        env::set_var("CONFIG_TEST_SET", "true");
+        assert_eq!(parse_env_or("CONFIG_TEST_SET", false), Ok(true));
+        assert_eq!(
+            parse_env_or::<u64>("CONFIG_TEST_SET", 30),
+            Err("CONFIG_TEST_SET invalid".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sink_topic() {
+        let mut sinks = SinkConfig::default();
+        assert_eq!(sinks.topic("covclaim.events", "liquid"), "covclaim.events");
+
+        sinks.network_suffix = true;
+        assert_eq!(
+            sinks.topic("covclaim.events", "liquid"),
+            "covclaim.events.liquid"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use diesel::sql_query;
+use metrics::{counter, gauge};
+use tokio::time;
+use tracing::{debug, error, info, warn};
+
+use crate::db;
+use crate::db::run_blocking;
+
+// Consecutive failed checks after which the database is reported as unhealthy
+const MAX_FAILURES: u64 = 3;
+
+/// Checks the database periodically and records the utilization of the connection pool.
+/// Broken connections are replaced by the pool when they are checked out, so it reconnects
+/// on its own once the database is reachable again
+#[derive(Clone)]
+pub struct HealthCheck {
+    db: db::Pool,
+    interval: u64,
+    healthy: Arc<AtomicBool>,
+}
+
+impl HealthCheck {
+    pub fn new(db: db::Pool, interval: u64) -> HealthCheck {
+        HealthCheck {
+            db,
+            interval,
+            healthy: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
+    }
+
+    pub fn start(&self) {
+        if self.interval == 0 {
+            info!("Not checking database health");
+            return;
+        }
+
+        let clone = self.clone();
+        tokio::spawn(async move {
+            info!("Checking database health every {} seconds", clone.interval);
+            let mut interval = time::interval(Duration::from_secs(clone.interval));
+            let mut failures = 0;
+
+            loop {
+                interval.tick().await;
+
+                match clone.check().await {
+                    Ok(_) => {
+                        failures = 0;
+                        if !clone.healthy.swap(true, Ordering::SeqCst) {
+                            info!("Database is healthy again");
+                        }
+                        debug!("Database is healthy");
+                    }
+                    Err(err) => {
+                        failures += 1;
+                        counter!("covclaim_db_health_check_failures_total").increment(1);
+                        warn!(
+                            "Database health check failed ({}/{}): {}",
+                            failures, MAX_FAILURES, err
+                        );
+
+                        if failures >= MAX_FAILURES && clone.healthy.swap(false, Ordering::SeqCst) {
+                            error!(
+                                "Database is unhealthy after {} failed health checks",
+                                failures
+                            );
+                        }
+                    }
+                };
+
+                clone.record_metrics();
+            }
+        });
+    }
+
+    async fn check(&self) -> Result<(), db::Error> {
+        run_blocking(self.db.clone(), |con| {
+            sql_query("SELECT 1").execute(&mut con.get()?)?;
+            Ok(())
+        })
+        .await
+    }
+
+    fn record_metrics(&self) {
+        let state = self.db.state();
+
+        gauge!("covclaim_db_healthy").set(if self.is_healthy() { 1.0 } else { 0.0 });
+        gauge!("covclaim_db_pool_connections").set(state.connections);
+        gauge!("covclaim_db_pool_idle_connections").set(state.idle_connections);
+        gauge!("covclaim_db_pool_max_size").set(self.db.max_size());
+    }
+}
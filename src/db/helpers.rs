@@ -1,122 +1,916 @@
+use diesel::dsl::count_star;
 use diesel::internal::derives::multiconnection::chrono;
+use diesel::internal::derives::multiconnection::chrono::{TimeDelta, Utc};
 use diesel::prelude::*;
-use diesel::{insert_into, update};
+use diesel::sql_types::Bool;
+use diesel::{delete, insert_into, sql_query, update};
+use elements::Txid;
+use tracing::instrument;
 
 use crate::db;
-use crate::db::models::{Parameter, PendingCovenant, PendingCovenantStatus};
+use crate::db::listener::CLAIM_CHANNEL;
+use crate::db::models::{
+    ClaimState, ClaimTiming, CovenantEvent, CovenantStatus, LockupOutput, NewCovenantEvent,
+    Parameter, PendingCovenant, PendingCovenantStatus, Secret, TxId, UndeliveredNotification,
+};
+use crate::db::schema::covenant_events;
 use crate::db::schema::parameters;
 use crate::db::schema::pending_covenants;
+use crate::db::schema::undelivered_notifications;
+use crate::db::{run_blocking, AnyConnection, Error};
+use crate::utils::request_id;
 
 const BLOCK_HEIGHT_NAME: &str = "block_height";
 
-pub fn upsert_block_height(con: db::Pool, height: u64) -> Result<(), diesel::result::Error> {
-    let values = Parameter {
-        name: BLOCK_HEIGHT_NAME.to_string(),
-        value: height.to_string(),
-    };
-
-    match parameters::dsl::parameters
-        .select(Parameter::as_select())
-        .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME.to_string()))
-        .limit(1)
-        .load(&mut con.get().unwrap())
-    {
-        Ok(res) => {
-            if res.is_empty() {
-                match insert_into(parameters::dsl::parameters)
-                    .values(&values)
-                    .execute(&mut con.get().unwrap())
-                {
-                    Ok(_) => Ok(()),
-                    Err(err) => Err(err),
-                }
-            } else {
-                match update(parameters::dsl::parameters)
-                    .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME.to_string()))
-                    .set((parameters::dsl::value.eq(height.to_string()),))
-                    .execute(&mut con.get().unwrap())
-                {
-                    Ok(_) => Ok(()),
-                    Err(err) => Err(err),
-                }
-            }
+#[instrument(level = "debug", skip_all)]
+pub async fn upsert_block_height(con: db::Pool, height: u64) -> Result<(), Error> {
+    run_blocking(con, move |con| {
+        let values = Parameter {
+            name: BLOCK_HEIGHT_NAME.to_string(),
+            value: height.to_string(),
+        };
+
+        let res = parameters::dsl::parameters
+            .select(Parameter::as_select())
+            .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME.to_string()))
+            .limit(1)
+            .load(&mut con.get()?)?;
+
+        if res.is_empty() {
+            insert_into(parameters::dsl::parameters)
+                .values(&values)
+                .execute(&mut con.get()?)?;
+        } else {
+            update(parameters::dsl::parameters)
+                .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME.to_string()))
+                .set((parameters::dsl::value.eq(height.to_string()),))
+                .execute(&mut con.get()?)?;
         }
-        Err(err) => Err(err),
-    }
+
+        Ok(())
+    })
+    .await
 }
 
-pub fn get_block_height(con: db::Pool) -> Option<u64> {
-    match parameters::dsl::parameters
-        .select(Parameter::as_select())
-        .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME))
-        .load(&mut con.get().unwrap())
-    {
-        Ok(res) => {
-            if res.is_empty() {
-                return None;
-            }
+#[instrument(level = "debug", skip_all)]
+pub async fn get_block_height(con: db::Pool) -> Result<Option<u64>, Error> {
+    run_blocking(con, |con| {
+        let res = parameters::dsl::parameters
+            .select(Parameter::as_select())
+            .filter(parameters::dsl::name.eq(BLOCK_HEIGHT_NAME))
+            .load(&mut con.get()?)?;
 
-            Some(res[0].value.parse::<u64>().unwrap())
+        match res.first() {
+            Some(param) => match param.value.parse::<u64>() {
+                Ok(height) => Ok(Some(height)),
+                Err(err) => Err(Error::InvalidValue(format!(
+                    "{} {}: {}",
+                    BLOCK_HEIGHT_NAME, param.value, err
+                ))),
+            },
+            None => Ok(None),
         }
-        Err(_) => None,
-    }
+    })
+    .await
 }
 
-pub fn insert_covenant(con: db::Pool, covenant: PendingCovenant) -> QueryResult<usize> {
-    insert_into(pending_covenants::dsl::pending_covenants)
-        .values(&covenant)
-        .execute(&mut con.get().unwrap())
+#[instrument(level = "debug", skip_all)]
+pub async fn insert_covenant(con: db::Pool, covenant: PendingCovenant) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = insert_into(pending_covenants::dsl::pending_covenants)
+                .values(&covenant)
+                .execute(con)?;
+
+            insert_event(
+                con,
+                NewCovenantEvent {
+                    output_script: covenant.output_script.clone(),
+                    status: covenant.status,
+                    reason: "covenant registered".to_string(),
+                    tx_id: None,
+                },
+            )?;
+            notify_claimers(con)?;
+
+            Ok(res)
+        })
+    })
+    .await
 }
 
-pub fn set_covenant_transaction(
+#[instrument(level = "debug", skip_all)]
+pub async fn set_covenant_transaction(
     con: db::Pool,
     output_script: Vec<u8>,
-    tx_id: Vec<u8>,
+    tx_id: Txid,
+    lockup: LockupOutput,
     time: chrono::NaiveDateTime,
-) -> QueryResult<usize> {
-    update(pending_covenants::dsl::pending_covenants)
-        .filter(pending_covenants::dsl::output_script.eq(output_script))
-        .set((
-            pending_covenants::dsl::status.eq(PendingCovenantStatus::TransactionFound.to_int()),
-            pending_covenants::dsl::tx_id.eq(tx_id),
-            pending_covenants::dsl::tx_time.eq(time),
-        ))
-        .execute(&mut con.get().unwrap())
+) -> Result<usize, Error> {
+    let tx_id = TxId::from(tx_id);
+
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .set((
+                    pending_covenants::dsl::status
+                        .eq(PendingCovenantStatus::TransactionFound.to_int()),
+                    pending_covenants::dsl::tx_id.eq(tx_id),
+                    pending_covenants::dsl::tx_time.eq(time),
+                    pending_covenants::dsl::lockup_vout.eq(lockup.vout as i32),
+                    pending_covenants::dsl::lockup_amount
+                        .eq(lockup.amount.map(|amount| amount as i64)),
+                    pending_covenants::dsl::lockup_height
+                        .eq(lockup.height.map(|height| height as i64)),
+                ))
+                .execute(con)?;
+
+            insert_event(
+                con,
+                NewCovenantEvent {
+                    output_script,
+                    status: PendingCovenantStatus::TransactionFound.to_int(),
+                    reason: lockup.reason(),
+                    tx_id: Some(tx_id),
+                },
+            )?;
+            notify_claimers(con)?;
+
+            Ok(res)
+        })
+    })
+    .await
 }
 
-pub fn set_covenant_claimed(con: db::Pool, output_script: Vec<u8>) -> QueryResult<usize> {
-    update(pending_covenants::dsl::pending_covenants)
-        .filter(pending_covenants::dsl::output_script.eq(output_script))
-        .set(pending_covenants::dsl::status.eq(PendingCovenantStatus::Claimed.to_int()))
-        .execute(&mut con.get().unwrap())
+/// Stores the lockup of a covenant that is claimed right away without scheduling the claim
+#[instrument(level = "debug", skip_all)]
+pub async fn set_lockup_output(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    tx_id: Txid,
+    lockup: LockupOutput,
+) -> Result<usize, Error> {
+    let tx_id = TxId::from(tx_id);
+
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .set((
+                    pending_covenants::dsl::tx_id.eq(tx_id),
+                    pending_covenants::dsl::lockup_vout.eq(lockup.vout as i32),
+                    pending_covenants::dsl::lockup_amount
+                        .eq(lockup.amount.map(|amount| amount as i64)),
+                    pending_covenants::dsl::lockup_height
+                        .eq(lockup.height.map(|height| height as i64)),
+                ))
+                .execute(con)?;
+
+            insert_event(
+                con,
+                NewCovenantEvent {
+                    output_script,
+                    status: PendingCovenantStatus::TransactionFound.to_int(),
+                    reason: lockup.reason(),
+                    tx_id: Some(tx_id),
+                },
+            )?;
+
+            Ok(res)
+        })
+    })
+    .await
 }
 
-pub fn get_covenants_to_claim(
+/// Sets the block height of lockups that were found in the mempool once they confirm
+#[instrument(level = "debug", skip_all)]
+pub async fn set_lockups_confirmed(
     con: db::Pool,
-    max_time: chrono::NaiveDateTime,
-) -> QueryResult<Vec<PendingCovenant>> {
-    pending_covenants::dsl::pending_covenants
-        .select(PendingCovenant::as_select())
-        .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::TransactionFound.to_int()))
-        .filter(pending_covenants::dsl::tx_time.le(max_time))
-        .load(&mut con.get().unwrap())
-}
-
-pub fn get_pending_covenant_for_output(con: db::Pool, script: &[u8]) -> Option<PendingCovenant> {
-    match pending_covenants::dsl::pending_covenants
-        .select(PendingCovenant::as_select())
-        .filter(pending_covenants::dsl::output_script.eq(script))
-        .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
-        .limit(1)
-        .load(&mut con.get().unwrap())
-    {
-        Ok(res) => {
-            if res.is_empty() {
-                return None;
+    tx_ids: Vec<Txid>,
+    height: u64,
+) -> Result<usize, Error> {
+    let tx_ids: Vec<TxId> = tx_ids.into_iter().map(TxId::from).collect();
+
+    run_blocking(con, move |con| {
+        Ok(update(pending_covenants::dsl::pending_covenants)
+            .filter(pending_covenants::dsl::tx_id.eq_any(tx_ids))
+            .filter(pending_covenants::dsl::lockup_height.is_null())
+            .set(pending_covenants::dsl::lockup_height.eq(height as i64))
+            .execute(&mut con.get()?)?)
+    })
+    .await
+}
+
+/// The claim transaction is None when the covenant was claimed by a transaction that is not known
+#[instrument(level = "debug", skip_all)]
+pub async fn set_covenant_claimed(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    claim_tx_id: Option<Txid>,
+    reason: &str,
+) -> Result<usize, Error> {
+    let reason = reason.to_string();
+    let claim_tx_id = claim_tx_id.map(TxId::from);
+
+    run_blocking(con, move |con| {
+        let now = Utc::now().naive_utc();
+
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .set((
+                    pending_covenants::dsl::status.eq(PendingCovenantStatus::Claimed.to_int()),
+                    pending_covenants::dsl::claim_tx_id.eq(claim_tx_id),
+                    pending_covenants::dsl::claimed_at.eq(now),
+                ))
+                .execute(con)?;
+
+            // Covenants that are claimed instantly skip the scheduling that sets the time of the lockup
+            update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .filter(pending_covenants::dsl::tx_time.is_null())
+                .set(pending_covenants::dsl::tx_time.eq(now))
+                .execute(con)?;
+
+            insert_event(
+                con,
+                NewCovenantEvent {
+                    output_script,
+                    reason,
+                    status: PendingCovenantStatus::Claimed.to_int(),
+                    tx_id: claim_tx_id,
+                },
+            )?;
+
+            Ok(res)
+        })
+    })
+    .await
+}
+
+/// Stops claiming a covenant whose lockup transaction cannot be claimed
+#[instrument(level = "debug", skip_all)]
+pub async fn set_covenant_unclaimable(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    reason: String,
+) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .set((
+                    pending_covenants::dsl::status.eq(PendingCovenantStatus::Unclaimable.to_int()),
+                    pending_covenants::dsl::lease_until.eq(None::<chrono::NaiveDateTime>),
+                ))
+                .execute(con)?;
+
+            insert_event(
+                con,
+                NewCovenantEvent {
+                    output_script,
+                    reason,
+                    status: PendingCovenantStatus::Unclaimable.to_int(),
+                    tx_id: None,
+                },
+            )?;
+
+            Ok(res)
+        })
+    })
+    .await
+}
+
+/// Claims that are not known to be confirmed: covenants whose lockup transaction was found
+/// and covenants that were claimed without the claim being seen in a block yet
+#[instrument(level = "debug", skip_all)]
+pub async fn get_unsettled_claims(con: db::Pool) -> Result<Vec<ClaimState>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(ClaimState::as_select())
+            .filter(
+                pending_covenants::dsl::status
+                    .eq(PendingCovenantStatus::TransactionFound.to_int())
+                    .or(pending_covenants::dsl::status
+                        .eq(PendingCovenantStatus::Claimed.to_int())
+                        .and(pending_covenants::dsl::confirmed_at.is_null())),
+            )
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+/// Sets a claimed covenant back to be claimed again, like when its claim was dropped
+#[instrument(level = "debug", skip_all)]
+pub async fn requeue_claim(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    reason: String,
+) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Claimed.to_int()))
+                .set((
+                    pending_covenants::dsl::status
+                        .eq(PendingCovenantStatus::TransactionFound.to_int()),
+                    pending_covenants::dsl::claim_tx_id.eq(None::<TxId>),
+                    pending_covenants::dsl::claimed_at.eq(None::<chrono::NaiveDateTime>),
+                    pending_covenants::dsl::lease_until.eq(None::<chrono::NaiveDateTime>),
+                ))
+                .execute(con)?;
+
+            if res > 0 {
+                insert_event(
+                    con,
+                    NewCovenantEvent {
+                        output_script,
+                        reason,
+                        status: PendingCovenantStatus::TransactionFound.to_int(),
+                        tx_id: None,
+                    },
+                )?;
+                notify_claimers(con)?;
+            }
+
+            Ok(res)
+        })
+    })
+    .await
+}
+
+/// Only updates covenants whose lockup transaction was not found yet
+#[instrument(level = "debug", skip_all)]
+pub async fn update_covenant_address(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    address: Vec<u8>,
+    blinding_key: Option<Secret>,
+) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let res = update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq(output_script.clone()))
+                .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+                .set((
+                    pending_covenants::dsl::address.eq(address),
+                    pending_covenants::dsl::blinding_key.eq(blinding_key),
+                ))
+                .execute(con)?;
+
+            if res > 0 {
+                insert_event(
+                    con,
+                    NewCovenantEvent {
+                        output_script,
+                        status: PendingCovenantStatus::Pending.to_int(),
+                        reason: "address updated".to_string(),
+                        tx_id: None,
+                    },
+                )?;
+            }
+
+            Ok(res)
+        })
+    })
+    .await
+}
+
+/// Marks the claims with the transaction ids as confirmed; returns the timings of the claims
+/// that were not confirmed before
+#[instrument(level = "debug", skip_all)]
+pub async fn set_claims_confirmed(
+    con: db::Pool,
+    claim_tx_ids: Vec<Txid>,
+    time: chrono::NaiveDateTime,
+) -> Result<Vec<ClaimTiming>, Error> {
+    let claim_tx_ids: Vec<TxId> = claim_tx_ids.into_iter().map(TxId::from).collect();
+
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let timings: Vec<ClaimTiming> = pending_covenants::dsl::pending_covenants
+                .select(ClaimTiming::as_select())
+                .filter(pending_covenants::dsl::claim_tx_id.eq_any(claim_tx_ids))
+                .filter(pending_covenants::dsl::confirmed_at.is_null())
+                .load(con)?;
+            if timings.is_empty() {
+                return Ok(timings);
             }
 
-            Some(res[0].clone())
+            let output_scripts: Vec<Vec<u8>> = timings
+                .iter()
+                .map(|timing| timing.output_script.clone())
+                .collect();
+            update(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq_any(output_scripts))
+                .set(pending_covenants::dsl::confirmed_at.eq(time))
+                .execute(con)?;
+
+            for timing in &timings {
+                insert_event(
+                    con,
+                    NewCovenantEvent {
+                        output_script: timing.output_script.clone(),
+                        status: PendingCovenantStatus::Claimed.to_int(),
+                        reason: "claim confirmed".to_string(),
+                        tx_id: None,
+                    },
+                )?;
+            }
+
+            Ok(timings
+                .into_iter()
+                .map(|timing| ClaimTiming {
+                    confirmed_at: Some(time),
+                    ..timing
+                })
+                .collect())
+        })
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_claim_timing_by_swap_id(
+    con: db::Pool,
+    swap_id: &str,
+) -> Result<Option<ClaimTiming>, Error> {
+    let swap_id = swap_id.to_string();
+
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(ClaimTiming::as_select())
+            .filter(pending_covenants::dsl::swap_id.eq(swap_id))
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn insert_covenant_event(con: db::Pool, event: NewCovenantEvent) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        Ok(insert_event(&mut con, event)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_covenant_events(
+    con: db::Pool,
+    output_script: Vec<u8>,
+) -> Result<Vec<CovenantEvent>, Error> {
+    run_blocking(con, move |con| {
+        Ok(covenant_events::dsl::covenant_events
+            .select(CovenantEvent::as_select())
+            .filter(covenant_events::dsl::output_script.eq(output_script))
+            .order(covenant_events::dsl::id.asc())
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+/// Gets the covenants that are ready to be claimed and leases them, so that other
+/// instances sharing the database skip them until the lease expires
+#[instrument(level = "debug", skip_all)]
+pub async fn lease_covenants_to_claim(
+    con: db::Pool,
+    max_time: chrono::NaiveDateTime,
+    // Lockups confirmed above it or not at all are skipped; None to claim every lockup
+    max_lockup_height: Option<i64>,
+    lease_time: TimeDelta,
+    limit: i64,
+) -> Result<Vec<PendingCovenant>, Error> {
+    run_blocking(con, move |con| {
+        let now = Utc::now().naive_utc();
+        // The oldest lockups are claimed first when there are more than the limit; the selection
+        // is added for each backend separately, because its type depends on the backend
+        let query = pending_covenants::dsl::pending_covenants
+            .filter(
+                pending_covenants::dsl::status.eq(PendingCovenantStatus::TransactionFound.to_int()),
+            )
+            .filter(pending_covenants::dsl::tx_time.le(max_time))
+            .filter(
+                pending_covenants::dsl::lockup_height
+                    .is_null()
+                    .and(max_lockup_height.is_none().into_sql::<Bool>())
+                    .or(pending_covenants::dsl::lockup_height
+                        .le(max_lockup_height.unwrap_or(i64::MAX))),
+            )
+            .filter(
+                pending_covenants::dsl::lease_until
+                    .is_null()
+                    .or(pending_covenants::dsl::lease_until.lt(now)),
+            )
+            .order(pending_covenants::dsl::tx_time.asc())
+            .limit(limit);
+
+        let mut con = con.get()?;
+        match &mut *con {
+            // Rows locked by another instance are skipped instead of waited for
+            AnyConnection::Postgresql(con) => con.transaction(|con| {
+                let covenants: Vec<PendingCovenant> = query
+                    .select(PendingCovenant::as_select())
+                    .for_update()
+                    .skip_locked()
+                    .load(con)?;
+
+                update(pending_covenants::dsl::pending_covenants)
+                    .filter(
+                        pending_covenants::dsl::output_script.eq_any(output_scripts(&covenants)),
+                    )
+                    .set(pending_covenants::dsl::lease_until.eq(now + lease_time))
+                    .execute(con)?;
+
+                Ok(covenants)
+            }),
+            // SQLite has no row locks, but an immediate transaction locks the database for writes
+            AnyConnection::Sqlite(con) => con.immediate_transaction(|con| {
+                let covenants: Vec<PendingCovenant> =
+                    query.select(PendingCovenant::as_select()).load(con)?;
+
+                update(pending_covenants::dsl::pending_covenants)
+                    .filter(
+                        pending_covenants::dsl::output_script.eq_any(output_scripts(&covenants)),
+                    )
+                    .set(pending_covenants::dsl::lease_until.eq(now + lease_time))
+                    .execute(con)?;
+
+                Ok(covenants)
+            }),
         }
-        Err(_) => None,
+    })
+    .await
+}
+
+/// Leases a covenant that is still to be claimed and returns whether the lease was acquired
+#[instrument(level = "debug", skip_all)]
+pub async fn lease_covenant(
+    con: db::Pool,
+    output_script: Vec<u8>,
+    lease_time: TimeDelta,
+) -> Result<bool, Error> {
+    run_blocking(con, move |con| {
+        let now = Utc::now().naive_utc();
+
+        let updated = update(pending_covenants::dsl::pending_covenants)
+            .filter(pending_covenants::dsl::output_script.eq(output_script))
+            .filter(pending_covenants::dsl::status.eq_any([
+                PendingCovenantStatus::Pending.to_int(),
+                PendingCovenantStatus::TransactionFound.to_int(),
+            ]))
+            .filter(
+                pending_covenants::dsl::lease_until
+                    .is_null()
+                    .or(pending_covenants::dsl::lease_until.lt(now)),
+            )
+            .set(pending_covenants::dsl::lease_until.eq(now + lease_time))
+            .execute(&mut con.get()?)?;
+
+        Ok(updated == 1)
+    })
+    .await
+}
+
+/// Covenants that were claimed or became unclaimable before the time
+#[instrument(level = "debug", skip_all)]
+pub async fn get_finished_covenants_before(
+    con: db::Pool,
+    max_time: chrono::NaiveDateTime,
+) -> Result<Vec<PendingCovenant>, Error> {
+    run_blocking(con, move |con| {
+        // Unclaimable covenants only record when they became unclaimable in their events
+        let unclaimable = covenant_events::dsl::covenant_events
+            .select(covenant_events::dsl::output_script)
+            .filter(covenant_events::dsl::status.eq(PendingCovenantStatus::Unclaimable.to_int()))
+            .filter(covenant_events::dsl::created_at.le(max_time));
+
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(
+                pending_covenants::dsl::status
+                    .eq(PendingCovenantStatus::Claimed.to_int())
+                    .and(
+                        pending_covenants::dsl::claimed_at.le(max_time).or(
+                            // Claimed before the time of claims was recorded
+                            pending_covenants::dsl::claimed_at
+                                .is_null()
+                                .and(pending_covenants::dsl::created_at.le(max_time)),
+                        ),
+                    )
+                    .or(pending_covenants::dsl::status
+                        .eq(PendingCovenantStatus::Unclaimable.to_int())
+                        .and(pending_covenants::dsl::output_script.eq_any(unclaimable))),
+            )
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn delete_covenants(con: db::Pool, output_scripts: Vec<Vec<u8>>) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            delete(covenant_events::dsl::covenant_events)
+                .filter(covenant_events::dsl::output_script.eq_any(output_scripts.clone()))
+                .execute(con)?;
+
+            Ok(delete(pending_covenants::dsl::pending_covenants)
+                .filter(pending_covenants::dsl::output_script.eq_any(output_scripts))
+                .execute(con)?)
+        })
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_pending_covenant_for_output(
+    con: db::Pool,
+    script: &[u8],
+) -> Result<Option<PendingCovenant>, Error> {
+    let script = script.to_vec();
+
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(pending_covenants::dsl::output_script.eq(script))
+            .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+/// Looks up the pending covenants of all output scripts of a transaction in one query
+#[instrument(level = "debug", skip_all, fields(scripts = scripts.len()))]
+pub async fn get_pending_covenants_for_outputs(
+    con: db::Pool,
+    scripts: Vec<Vec<u8>>,
+) -> Result<Vec<PendingCovenant>, Error> {
+    if scripts.is_empty() {
+        return Ok(Vec::new());
     }
+
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(pending_covenants::dsl::output_script.eq_any(scripts))
+            .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_unclaimed_covenants(con: db::Pool) -> Result<Vec<PendingCovenant>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(pending_covenants::dsl::status.ne(PendingCovenantStatus::Claimed.to_int()))
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+/// Inserts the covenants that do not exist yet in a single transaction
+/// and returns how many were inserted
+#[instrument(level = "debug", skip_all)]
+pub async fn import_covenants(
+    con: db::Pool,
+    covenants: Vec<PendingCovenant>,
+) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        let mut con = con.get()?;
+        con.transaction(|con| {
+            let mut imported = 0;
+
+            for covenant in covenants {
+                let existing: i64 = pending_covenants::dsl::pending_covenants
+                    .filter(
+                        pending_covenants::dsl::output_script.eq(covenant.output_script.clone()),
+                    )
+                    .count()
+                    .get_result(con)?;
+                if existing > 0 {
+                    continue;
+                }
+
+                insert_into(pending_covenants::dsl::pending_covenants)
+                    .values(&covenant)
+                    .execute(con)?;
+                insert_event(
+                    con,
+                    NewCovenantEvent {
+                        output_script: covenant.output_script,
+                        status: covenant.status,
+                        reason: "covenant imported".to_string(),
+                        tx_id: covenant.tx_id,
+                    },
+                )?;
+
+                imported += 1;
+            }
+
+            if imported > 0 {
+                notify_claimers(con)?;
+            }
+
+            Ok(imported)
+        })
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_covenant_by_swap_id(
+    con: db::Pool,
+    swap_id: &str,
+) -> Result<Option<PendingCovenant>, Error> {
+    let swap_id = swap_id.to_string();
+
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(pending_covenants::dsl::swap_id.eq(swap_id))
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_covenant_status_by_swap_id(
+    con: db::Pool,
+    swap_id: &str,
+) -> Result<Option<CovenantStatus>, Error> {
+    let swap_id = swap_id.to_string();
+
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(CovenantStatus::as_select())
+            .filter(pending_covenants::dsl::swap_id.eq(swap_id))
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_covenant_by_output_script(
+    con: db::Pool,
+    output_script: Vec<u8>,
+) -> Result<Option<PendingCovenant>, Error> {
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(PendingCovenant::as_select())
+            .filter(pending_covenants::dsl::output_script.eq(output_script))
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_pending_covenant_scripts(con: db::Pool) -> Result<Vec<Vec<u8>>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(pending_covenants::dsl::output_script)
+            .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_pending_covenant_swap_ids(con: db::Pool) -> Result<Vec<String>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .select(pending_covenants::dsl::swap_id)
+            .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+            .filter(pending_covenants::dsl::swap_id.is_not_null())
+            .load::<Option<String>>(&mut con.get()?)?
+            .into_iter()
+            .flatten()
+            .collect())
+    })
+    .await
+}
+
+/// Number of covenants in every status that any covenant is in
+#[instrument(level = "debug", skip_all)]
+pub async fn get_covenant_status_counts(con: db::Pool) -> Result<Vec<(i32, i64)>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .group_by(pending_covenants::dsl::status)
+            .select((pending_covenants::dsl::status, count_star()))
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+/// When the oldest covenant whose lockup transaction was not found yet was registered
+#[instrument(level = "debug", skip_all)]
+pub async fn get_oldest_pending_covenant_time(
+    con: db::Pool,
+) -> Result<Option<chrono::NaiveDateTime>, Error> {
+    run_blocking(con, |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .filter(pending_covenants::dsl::status.eq(PendingCovenantStatus::Pending.to_int()))
+            .select(pending_covenants::dsl::created_at)
+            .order(pending_covenants::dsl::created_at.asc())
+            .first(&mut con.get()?)
+            .optional()?)
+    })
+    .await
+}
+
+/// Claims broadcast since the time that were not set back to be claimed again
+#[instrument(level = "debug", skip_all)]
+pub async fn count_claims_since(con: db::Pool, since: chrono::NaiveDateTime) -> Result<i64, Error> {
+    run_blocking(con, move |con| {
+        Ok(pending_covenants::dsl::pending_covenants
+            .filter(pending_covenants::dsl::claimed_at.ge(since))
+            .count()
+            .get_result(&mut con.get()?)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn insert_undelivered_notification(
+    con: db::Pool,
+    payload: String,
+) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        Ok(
+            insert_into(undelivered_notifications::dsl::undelivered_notifications)
+                .values(undelivered_notifications::dsl::payload.eq(payload))
+                .execute(&mut con.get()?)?,
+        )
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn get_undelivered_notifications(
+    con: db::Pool,
+    limit: i64,
+) -> Result<Vec<UndeliveredNotification>, Error> {
+    run_blocking(con, move |con| {
+        Ok(undelivered_notifications::dsl::undelivered_notifications
+            .select(UndeliveredNotification::as_select())
+            .order(undelivered_notifications::dsl::id.asc())
+            .limit(limit)
+            .load(&mut con.get()?)?)
+    })
+    .await
+}
+
+#[instrument(level = "debug", skip_all)]
+pub async fn delete_undelivered_notification(con: db::Pool, id: i32) -> Result<usize, Error> {
+    run_blocking(con, move |con| {
+        Ok(
+            delete(undelivered_notifications::dsl::undelivered_notifications)
+                .filter(undelivered_notifications::dsl::id.eq(id))
+                .execute(&mut con.get()?)?,
+        )
+    })
+    .await
+}
+
+fn insert_event(con: &mut AnyConnection, event: NewCovenantEvent) -> QueryResult<usize> {
+    insert_into(covenant_events::dsl::covenant_events)
+        .values((
+            &event,
+            covenant_events::dsl::request_id.eq(request_id::current()),
+        ))
+        .execute(con)
+}
+
+// Sent when the transaction commits, which wakes up the claimers of all instances
+fn notify_claimers(con: &mut AnyConnection) -> QueryResult<()> {
+    if let AnyConnection::Postgresql(con) = con {
+        sql_query(format!("NOTIFY {}", CLAIM_CHANNEL)).execute(con)?;
+    }
+
+    Ok(())
+}
+
+fn output_scripts(covenants: &[PendingCovenant]) -> Vec<Vec<u8>> {
+    covenants
+        .iter()
+        .map(|covenant| covenant.output_script.clone())
+        .collect()
 }
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{stream, StreamExt};
+use postgres_native_tls::MakeTlsConnector;
+use tokio::sync::{mpsc, Notify};
+use tokio::time;
+use tokio_native_tls::native_tls::TlsConnector;
+use tokio_postgres::AsyncMessage;
+use tracing::{debug, error, info, trace, warn};
+
+pub const CLAIM_CHANNEL: &str = "covclaim_claims";
+
+const RECONNECT_DELAY: u64 = 5;
+
+/// Listens for notifications about registered covenants and found lockup
+/// transactions on PostgreSQL and wakes up the returned `Notify` for each
+pub fn listen(url: String) -> Arc<Notify> {
+    let notify = Arc::new(Notify::new());
+
+    let clone = notify.clone();
+    tokio::spawn(async move {
+        loop {
+            match run(url.as_str(), &clone).await {
+                Ok(_) => warn!("PostgreSQL notification connection closed"),
+                Err(err) => error!("PostgreSQL notification connection failed: {}", err),
+            };
+
+            // Notifications could have been missed while disconnected
+            clone.notify_one();
+
+            debug!(
+                "Reconnecting to PostgreSQL for notifications in {}s",
+                RECONNECT_DELAY
+            );
+            time::sleep(Duration::from_secs(RECONNECT_DELAY)).await;
+        }
+    });
+
+    notify
+}
+
+async fn run(url: &str, notify: &Notify) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (client, mut connection) =
+        tokio_postgres::connect(url, MakeTlsConnector::new(TlsConnector::new()?)).await?;
+
+    // The connection only makes progress while its messages are polled
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+    tokio::spawn(async move {
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(notification)) => {
+                    if sender.send(notification).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    warn!("PostgreSQL notification connection error: {}", err);
+                    break;
+                }
+            }
+        }
+    });
+
+    client
+        .batch_execute(format!("LISTEN {}", CLAIM_CHANNEL).as_str())
+        .await?;
+    info!(
+        "Listening for notifications on PostgreSQL channel: {}",
+        CLAIM_CHANNEL
+    );
+
+    while let Some(notification) = receiver.recv().await {
+        trace!(
+            "Got notification on PostgreSQL channel: {}",
+            notification.channel()
+        );
+        notify.notify_one();
+    }
+
+    Ok(())
+}
@@ -1,18 +1,24 @@
-use std::error::Error;
+use std::error::Error as StdError;
 
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
 use diesel::Connection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use log::info;
+use tracing::info;
+
+use crate::utils::request_id;
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 pub const MIGRATIONS_POSTGRES: EmbeddedMigrations = embed_migrations!("./migrations_postgres");
 
+pub mod health;
 pub mod helpers;
 
+pub mod listener;
 pub mod models;
+pub mod pruner;
 mod schema;
+pub mod transfer;
 
 #[derive(diesel::MultiConnection)]
 pub enum AnyConnection {
@@ -22,7 +28,19 @@ pub enum AnyConnection {
 
 pub type Pool = r2d2::Pool<ConnectionManager<AnyConnection>>;
 
-pub fn establish_connection(url: &str) -> Result<Pool, Box<dyn Error + Send + Sync>> {
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("could not get database connection: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error("database query failed: {0}")]
+    Query(#[from] diesel::result::Error),
+    #[error("invalid value in database: {0}")]
+    InvalidValue(String),
+    #[error("database task failed: {0}")]
+    Task(tokio::task::JoinError),
+}
+
+pub fn establish_connection(url: &str) -> Result<Pool, Box<dyn StdError + Send + Sync>> {
     info!(
         "Using {} database",
         if is_postgres_connection_url(url) {
@@ -40,10 +58,27 @@ pub fn establish_connection(url: &str) -> Result<Pool, Box<dyn Error + Send + Sy
     Ok(pool)
 }
 
+/// Runs the query on the blocking thread pool, so that waiting for the database
+/// does not stall the async runtime
+pub async fn run_blocking<T, F>(pool: Pool, query: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(&Pool) -> Result<T, Error> + Send + 'static,
+{
+    // The blocking pool does not inherit the request id of the task
+    let request_id = request_id::current();
+    match tokio::task::spawn_blocking(move || request_id::sync_scope(request_id, || query(&pool)))
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => Err(Error::Task(err)),
+    }
+}
+
 fn run_migrations(
     is_postgres: bool,
     pool: &Pool,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+) -> Result<(), Box<dyn StdError + Send + Sync + 'static>> {
     let mut con = pool.get()?;
     con.run_pending_migrations(if is_postgres {
         MIGRATIONS_POSTGRES
@@ -53,6 +88,6 @@ fn run_migrations(
     Ok(())
 }
 
-fn is_postgres_connection_url(url: &str) -> bool {
+pub fn is_postgres_connection_url(url: &str) -> bool {
     url.starts_with("postgresql")
 }
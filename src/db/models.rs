@@ -1,17 +1,155 @@
+use std::fmt;
+use std::ops::Deref;
+
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
 use diesel::internal::derives::multiconnection::chrono;
 use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Binary;
+use elements::hashes::Hash;
+use elements::Txid;
+use zeroize::Zeroizing;
 
 #[derive(Copy, Clone)]
 pub enum PendingCovenantStatus {
     Pending = 0,
     TransactionFound = 1,
     Claimed = 2,
+    // Funded in a way that cannot be claimed, like with the wrong asset
+    Unclaimable = 3,
 }
 
 impl PendingCovenantStatus {
     pub fn to_int(self) -> i32 {
         self as i32
     }
+
+    pub fn name(status: i32) -> &'static str {
+        match status {
+            0 => "pending",
+            1 => "transactionFound",
+            2 => "claimed",
+            3 => "unclaimable",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Bytes of preimages and blinding keys that are wiped from memory when dropped
+#[derive(Clone, PartialEq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Binary)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl From<Vec<u8>> for Secret {
+    fn from(value: Vec<u8>) -> Self {
+        Secret(Zeroizing::new(value))
+    }
+}
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+// Never printed, so that secrets do not end up in logs
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+impl AsRef<[u8]> for Secret {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl<DB> ToSql<Binary, DB> for Secret
+where
+    DB: Backend,
+    [u8]: ToSql<Binary, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        <[u8] as ToSql<Binary, DB>>::to_sql(self.0.as_slice(), out)
+    }
+}
+
+impl<DB> FromSql<Binary, DB> for Secret
+where
+    DB: Backend,
+    Vec<u8>: FromSql<Binary, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        Ok(Secret::from(<Vec<u8> as FromSql<Binary, DB>>::from_sql(
+            bytes,
+        )?))
+    }
+}
+
+/// Transaction id that is stored in the byte order in which it is displayed,
+/// which is the reverse of the order in which it is hashed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Binary)]
+pub struct TxId([u8; 32]);
+
+impl TxId {
+    pub fn txid(&self) -> Txid {
+        let mut bytes = self.0;
+        bytes.reverse();
+        Txid::from_byte_array(bytes)
+    }
+}
+
+impl From<Txid> for TxId {
+    fn from(value: Txid) -> Self {
+        let mut bytes = value.to_byte_array();
+        bytes.reverse();
+        TxId(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for TxId {
+    type Error = String;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match <[u8; 32]>::try_from(value) {
+            Ok(bytes) => Ok(TxId(bytes)),
+            Err(_) => Err(format!("invalid transaction id length: {}", value.len())),
+        }
+    }
+}
+
+impl fmt::Display for TxId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl<DB> ToSql<Binary, DB> for TxId
+where
+    DB: Backend,
+    [u8]: ToSql<Binary, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        <[u8] as ToSql<Binary, DB>>::to_sql(self.0.as_slice(), out)
+    }
+}
+
+impl<DB> FromSql<Binary, DB> for TxId
+where
+    DB: Backend,
+    Vec<u8>: FromSql<Binary, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        Ok(TxId::try_from(
+            <Vec<u8> as FromSql<Binary, DB>>::from_sql(bytes)?.as_slice(),
+        )?)
+    }
 }
 
 #[derive(Queryable, Selectable, Insertable, AsChangeset)]
@@ -27,10 +165,211 @@ pub struct PendingCovenant {
     pub output_script: Vec<u8>,
     pub status: i32,
     pub internal_key: Vec<u8>,
-    pub preimage: Vec<u8>,
+    pub preimage: Secret,
     pub swap_tree: String,
     pub address: Vec<u8>,
-    pub blinding_key: Option<Vec<u8>>,
-    pub tx_id: Option<Vec<u8>>,
+    pub blinding_key: Option<Secret>,
+    pub tx_id: Option<TxId>,
     pub tx_time: Option<chrono::NaiveDateTime>,
+    pub swap_id: Option<String>,
+    pub lease_until: Option<chrono::NaiveDateTime>,
+    // Fee rate in sat/vbyte the claim is compared to instead of the estimation
+    pub fee_rate: Option<f64>,
+    // Claims that would pay more fees in sats are not broadcast
+    pub max_fee: Option<i64>,
+    // Embedded in an OP_RETURN output of the claim instead of the default data
+    pub op_return_data: Option<Vec<u8>>,
+    // Lockups with another amount are ignored when lockups need confirmations
+    pub expected_lockup_amount: Option<i64>,
+}
+
+/// When a covenant reached each stage of its claim
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::db::schema::pending_covenants)]
+pub struct ClaimTiming {
+    pub output_script: Vec<u8>,
+    pub swap_id: Option<String>,
+    pub claim_tx_id: Option<TxId>,
+    pub created_at: chrono::NaiveDateTime,
+    // When the lockup transaction was found
+    pub tx_time: Option<chrono::NaiveDateTime>,
+    pub claimed_at: Option<chrono::NaiveDateTime>,
+    pub confirmed_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ClaimTiming {
+    /// Seconds each stage took, for the stages that were completed already
+    pub fn stages(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("lockup", Some(self.created_at), self.tx_time),
+            ("broadcast", self.tx_time, self.claimed_at),
+            ("confirmation", self.claimed_at, self.confirmed_at),
+            ("total", Some(self.created_at), self.confirmed_at),
+        ]
+        .into_iter()
+        .filter_map(|(stage, start, end)| match (start, end) {
+            (Some(start), Some(end)) => Some((
+                stage,
+                (end - start).num_milliseconds().max(0) as f64 / 1000.0,
+            )),
+            _ => None,
+        })
+        .collect()
+    }
+}
+
+/// Output of a lockup transaction that funds a covenant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LockupOutput {
+    pub vout: u32,
+    // Unblinded when the covenant has the blinding key; None when it could not be unblinded
+    pub amount: Option<u64>,
+    // None while the lockup transaction is in the mempool
+    pub height: Option<u64>,
+}
+
+impl LockupOutput {
+    /// Reason of the event that records the lockup
+    pub fn reason(&self) -> String {
+        match self.amount {
+            Some(amount) => format!(
+                "lockup transaction found in output {} with {} sats",
+                self.vout, amount
+            ),
+            None => format!("lockup transaction found in output {}", self.vout),
+        }
+    }
+}
+
+/// Status of a covenant with the details of its lockup and claim
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::db::schema::pending_covenants)]
+pub struct CovenantStatus {
+    pub output_script: Vec<u8>,
+    pub status: i32,
+    pub tx_id: Option<TxId>,
+    pub tx_time: Option<chrono::NaiveDateTime>,
+    pub lockup_vout: Option<i32>,
+    pub lockup_amount: Option<i64>,
+    pub lockup_height: Option<i64>,
+    pub claim_tx_id: Option<TxId>,
+}
+
+/// Where the claim of a covenant whose lockup transaction was found stands
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::db::schema::pending_covenants)]
+pub struct ClaimState {
+    pub output_script: Vec<u8>,
+    pub status: i32,
+    pub tx_id: Option<TxId>,
+    pub claim_tx_id: Option<TxId>,
+    pub claimed_at: Option<chrono::NaiveDateTime>,
+    pub swap_tree: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::db::schema::covenant_events)]
+pub struct NewCovenantEvent {
+    pub output_script: Vec<u8>,
+    pub status: i32,
+    pub reason: String,
+    pub tx_id: Option<TxId>,
+}
+
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::db::schema::covenant_events)]
+pub struct CovenantEvent {
+    pub id: i32,
+    pub output_script: Vec<u8>,
+    pub status: i32,
+    pub reason: String,
+    pub tx_id: Option<TxId>,
+    pub created_at: chrono::NaiveDateTime,
+    // Of the API call that caused the event
+    pub request_id: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Clone)]
+#[diesel(table_name = crate::db::schema::undelivered_notifications)]
+pub struct UndeliveredNotification {
+    pub id: i32,
+    pub payload: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[cfg(test)]
+mod models_test {
+    use diesel::internal::derives::multiconnection::chrono::{NaiveDateTime, TimeDelta};
+
+    use std::str::FromStr;
+
+    use elements::Txid;
+
+    use crate::db::models::{ClaimTiming, LockupOutput, TxId};
+
+    #[test]
+    fn test_tx_id_byte_order() {
+        let txid =
+            Txid::from_str("1c8d2ba8ed1c09dc9c8e4d4b2ff8e5dbb2c6a0d0e2f8f6b7f6f1e5e6a3d2c1b0")
+                .unwrap();
+        let tx_id = TxId::from(txid);
+
+        // Rows written before the type existed stored the decoded hex of the displayed id
+        assert_eq!(
+            TxId::try_from(hex::decode(txid.to_string()).unwrap().as_slice()).unwrap(),
+            tx_id
+        );
+        assert_eq!(tx_id.to_string(), txid.to_string());
+        assert_eq!(tx_id.txid(), txid);
+
+        assert_eq!(
+            TxId::try_from([0u8; 31].as_slice()).err().unwrap(),
+            "invalid transaction id length: 31"
+        );
+    }
+
+    #[test]
+    fn test_claim_timing_stages() {
+        let created_at = NaiveDateTime::default();
+        let mut timing = ClaimTiming {
+            output_script: vec![],
+            swap_id: None,
+            claim_tx_id: None,
+            created_at,
+            tx_time: Some(created_at + TimeDelta::seconds(30)),
+            claimed_at: Some(created_at + TimeDelta::milliseconds(150_500)),
+            confirmed_at: None,
+        };
+        assert_eq!(
+            timing.stages(),
+            vec![("lockup", 30.0), ("broadcast", 120.5)]
+        );
+
+        timing.confirmed_at = Some(created_at + TimeDelta::seconds(210));
+        assert_eq!(
+            timing.stages(),
+            vec![
+                ("lockup", 30.0),
+                ("broadcast", 120.5),
+                ("confirmation", 59.5),
+                ("total", 210.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lockup_output_reason() {
+        let mut lockup = LockupOutput {
+            vout: 1,
+            amount: Some(100_000),
+            height: None,
+        };
+        assert_eq!(
+            lockup.reason(),
+            "lockup transaction found in output 1 with 100000 sats"
+        );
+
+        lockup.amount = None;
+        assert_eq!(lockup.reason(), "lockup transaction found in output 1");
+    }
 }
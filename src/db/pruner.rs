@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::ops::Sub;
+use std::time::Duration;
+
+use diesel::internal::derives::multiconnection::chrono::{TimeDelta, Utc};
+use serde::Serialize;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+use crate::db;
+use crate::db::helpers::{delete_covenants, get_finished_covenants_before};
+use crate::db::models::PendingCovenant;
+
+// Secrets like the preimage and blinding key are not archived
+#[derive(Serialize)]
+struct ArchivedCovenant {
+    #[serde(rename = "swapId")]
+    swap_id: Option<String>,
+    #[serde(rename = "outputScript")]
+    output_script: String,
+    address: String,
+    #[serde(rename = "lockupTransactionId")]
+    tx_id: Option<String>,
+    #[serde(rename = "lockupTransactionTime")]
+    tx_time: Option<String>,
+}
+
+impl From<&PendingCovenant> for ArchivedCovenant {
+    fn from(covenant: &PendingCovenant) -> Self {
+        ArchivedCovenant {
+            swap_id: covenant.swap_id.clone(),
+            output_script: hex::encode(&covenant.output_script),
+            address: hex::encode(&covenant.address),
+            tx_id: covenant.tx_id.map(|tx_id| tx_id.to_string()),
+            tx_time: covenant.tx_time.map(|time| time.to_string()),
+        }
+    }
+}
+
+/// Deletes claimed and unclaimable covenants after the retention period and optionally
+/// appends them to a JSON lines archive before
+#[derive(Clone)]
+pub struct Pruner {
+    db: db::Pool,
+    interval: u64,
+    retention_days: u64,
+    archive_path: Option<String>,
+}
+
+impl Pruner {
+    pub fn new(
+        db: db::Pool,
+        interval: u64,
+        retention_days: u64,
+        archive_path: Option<String>,
+    ) -> Pruner {
+        Pruner {
+            db,
+            interval,
+            retention_days,
+            archive_path,
+        }
+    }
+
+    pub fn start(self) {
+        if self.interval == 0 {
+            info!("Not pruning covenants");
+            return;
+        }
+
+        tokio::spawn(async move {
+            info!(
+                "Pruning covenants claimed or unclaimable for more than {} days every {} seconds",
+                self.retention_days, self.interval
+            );
+            let mut interval = time::interval(Duration::from_secs(self.interval));
+
+            loop {
+                interval.tick().await;
+
+                match self.prune().await {
+                    Ok(count) => {
+                        if count > 0 {
+                            info!("Pruned {} covenants", count);
+                        } else {
+                            debug!("No covenants to prune");
+                        }
+                    }
+                    Err(err) => warn!("Could not prune covenants: {}", err),
+                };
+            }
+        });
+    }
+
+    async fn prune(&self) -> Result<usize, Box<dyn Error>> {
+        let covenants = get_finished_covenants_before(
+            self.db.clone(),
+            Utc::now()
+                .sub(TimeDelta::days(self.retention_days as i64))
+                .naive_utc(),
+        )
+        .await?;
+        if covenants.is_empty() {
+            return Ok(0);
+        }
+
+        if let Some(path) = &self.archive_path {
+            self.archive(path, &covenants)?;
+        }
+
+        Ok(delete_covenants(
+            self.db.clone(),
+            covenants
+                .into_iter()
+                .map(|covenant| covenant.output_script)
+                .collect(),
+        )
+        .await?)
+    }
+
+    fn archive(&self, path: &str, covenants: &[PendingCovenant]) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        for covenant in covenants {
+            writeln!(
+                file,
+                "{}",
+                serde_json::to_string(&ArchivedCovenant::from(covenant))?
+            )?;
+        }
+
+        file.sync_all()?;
+        debug!("Archived {} covenants to {}", covenants.len(), path);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,17 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    covenant_events (id) {
+        id -> Integer,
+        output_script -> Binary,
+        status -> Integer,
+        reason -> Text,
+        tx_id -> Nullable<Binary>,
+        created_at -> Timestamp,
+        request_id -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     parameters (name) {
         name -> Text,
@@ -19,7 +31,32 @@ diesel::table! {
         tx_id -> Nullable<Binary>,
         tx_time -> Nullable<Timestamp>,
         created_at -> Timestamp,
+        swap_id -> Nullable<Text>,
+        lease_until -> Nullable<Timestamp>,
+        fee_rate -> Nullable<Double>,
+        max_fee -> Nullable<BigInt>,
+        claim_tx_id -> Nullable<Binary>,
+        claimed_at -> Nullable<Timestamp>,
+        confirmed_at -> Nullable<Timestamp>,
+        op_return_data -> Nullable<Binary>,
+        lockup_vout -> Nullable<Integer>,
+        lockup_amount -> Nullable<BigInt>,
+        lockup_height -> Nullable<BigInt>,
+        expected_lockup_amount -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    undelivered_notifications (id) {
+        id -> Integer,
+        payload -> Text,
+        created_at -> Timestamp,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(parameters, pending_covenants,);
+diesel::allow_tables_to_appear_in_same_query!(
+    covenant_events,
+    parameters,
+    pending_covenants,
+    undelivered_notifications,
+);
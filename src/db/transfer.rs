@@ -0,0 +1,499 @@
+use std::error::Error;
+use std::fs;
+use std::str::FromStr;
+
+use diesel::internal::derives::multiconnection::chrono::DateTime;
+use elements::secp256k1_zkp::SecretKey;
+use elements::{AddressParams, Script, Txid};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::info;
+
+use crate::api::destinations::AllowedDestinations;
+use crate::claimer::constructor::{dust_threshold, MAX_OP_RETURN_DATA_LENGTH};
+use crate::claimer::tree::SwapTree;
+use crate::db;
+use crate::db::helpers::{get_unclaimed_covenants, import_covenants};
+use crate::db::models::{PendingCovenant, PendingCovenantStatus, Secret, TxId};
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("covenant {0}: address is not an allowed claim destination")]
+    DestinationNotAllowed(usize),
+    #[error("covenant {0}: {1}")]
+    InvalidCovenant(usize, String),
+    #[error("{0}")]
+    Database(#[from] db::Error),
+}
+
+/// Everything needed to claim a covenant on another instance, including its secrets
+#[derive(Serialize, Deserialize)]
+pub struct ExportedCovenant {
+    #[serde(rename = "swapId")]
+    pub swap_id: Option<String>,
+
+    #[serde(with = "hex::serde")]
+    #[serde(rename = "internalKey")]
+    pub internal_key: Vec<u8>,
+
+    #[serde(with = "hex::serde")]
+    pub preimage: Vec<u8>,
+
+    #[serde(rename = "blindingKey")]
+    pub blinding_key: Option<String>,
+
+    // Output script the covenant is claimed to
+    #[serde(with = "hex::serde")]
+    pub address: Vec<u8>,
+
+    pub tree: SwapTree,
+    pub status: i32,
+
+    #[serde(rename = "txId")]
+    pub tx_id: Option<String>,
+
+    // UNIX timestamp in seconds
+    #[serde(rename = "txTime")]
+    pub tx_time: Option<i64>,
+
+    #[serde(rename = "feeRate", default)]
+    pub fee_rate: Option<f64>,
+
+    #[serde(rename = "maxFee", default)]
+    pub max_fee: Option<i64>,
+
+    #[serde(rename = "opReturnData", default)]
+    pub op_return_data: Option<String>,
+
+    #[serde(rename = "expectedLockupAmount", default)]
+    pub expected_lockup_amount: Option<i64>,
+}
+
+impl ExportedCovenant {
+    pub fn export(covenant: PendingCovenant) -> Result<ExportedCovenant, Box<dyn Error>> {
+        Ok(ExportedCovenant {
+            swap_id: covenant.swap_id,
+            internal_key: covenant.internal_key,
+            preimage: covenant.preimage.to_vec(),
+            blinding_key: covenant.blinding_key.map(hex::encode),
+            address: covenant.address,
+            tree: serde_json::from_str(covenant.swap_tree.as_str())?,
+            status: covenant.status,
+            tx_id: covenant.tx_id.map(|tx_id| tx_id.to_string()),
+            tx_time: covenant.tx_time.map(|time| time.and_utc().timestamp()),
+            fee_rate: covenant.fee_rate,
+            max_fee: covenant.max_fee,
+            op_return_data: covenant.op_return_data.map(hex::encode),
+            expected_lockup_amount: covenant.expected_lockup_amount,
+        })
+    }
+
+    /// Validates the covenant and derives its output script again instead of trusting the export
+    pub fn import(self, params: &'static AddressParams) -> Result<PendingCovenant, String> {
+        if self.status != PendingCovenantStatus::Pending.to_int()
+            && self.status != PendingCovenantStatus::TransactionFound.to_int()
+        {
+            return Err(format!("invalid status: {}", self.status));
+        }
+
+        let output_script = match self.tree.clone().address(self.internal_key.clone(), params) {
+            Ok(res) => res.script_pubkey(),
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let covenant_details = match self.tree.clone().covenant_details() {
+            Ok(res) => res,
+            Err(err) => return Err(format!("could not parse swap tree: {}", err)),
+        };
+        if !covenant_details.is_preimage_valid(self.preimage.as_ref()) {
+            return Err("invalid preimage".to_string());
+        }
+        let address = Script::from(self.address.clone());
+        if !covenant_details.is_expected_output(&address) {
+            return Err("address does not match the output of the covenant".to_string());
+        }
+
+        // Nodes would never relay the claim
+        let dust_threshold = dust_threshold(&address);
+        if covenant_details.expected_amount < dust_threshold {
+            return Err(format!(
+                "amount of the covenant is below the dust threshold of {} sats",
+                dust_threshold
+            ));
+        }
+
+        let op_return_data = match self.op_return_data {
+            Some(data) => match hex::decode(data) {
+                Ok(res) if res.len() <= MAX_OP_RETURN_DATA_LENGTH => Some(res),
+                Ok(_) => {
+                    return Err(format!(
+                        "OP_RETURN data is longer than {} bytes",
+                        MAX_OP_RETURN_DATA_LENGTH
+                    ))
+                }
+                Err(err) => return Err(format!("invalid OP_RETURN data: {}", err)),
+            },
+            None => None,
+        };
+
+        let blinding_key = match self.blinding_key {
+            Some(key) => match hex::decode(key) {
+                Ok(res) => match SecretKey::from_slice(res.as_slice()) {
+                    Ok(_) => Some(res),
+                    Err(err) => return Err(format!("invalid blinding key: {}", err)),
+                },
+                Err(err) => return Err(format!("invalid blinding key: {}", err)),
+            },
+            None => None,
+        };
+
+        let tx_id = match self.tx_id {
+            Some(tx_id) => match Txid::from_str(&tx_id) {
+                Ok(res) => Some(TxId::from(res)),
+                Err(err) => return Err(format!("invalid transaction id: {}", err)),
+            },
+            None => None,
+        };
+        let tx_time = match self.tx_time {
+            Some(time) => match DateTime::from_timestamp(time, 0) {
+                Some(res) => Some(res.naive_utc()),
+                None => return Err(format!("invalid transaction time: {}", time)),
+            },
+            None => None,
+        };
+        if self.status == PendingCovenantStatus::TransactionFound.to_int()
+            && (tx_id.is_none() || tx_time.is_none())
+        {
+            return Err("lockup transaction of covenant missing".to_string());
+        }
+
+        Ok(PendingCovenant {
+            tx_id,
+            tx_time,
+            blinding_key: blinding_key.map(Secret::from),
+            status: self.status,
+            swap_id: self.swap_id,
+            preimage: self.preimage.into(),
+            address: self.address,
+            swap_tree: serde_json::json!(self.tree).to_string(),
+            output_script: elements::pset::serialize::Serialize::serialize(&output_script),
+            internal_key: self.internal_key,
+            lease_until: None,
+            fee_rate: self.fee_rate,
+            max_fee: self.max_fee,
+            op_return_data,
+            expected_lockup_amount: self.expected_lockup_amount,
+        })
+    }
+}
+
+pub async fn export_covenants(con: db::Pool) -> Result<Vec<ExportedCovenant>, Box<dyn Error>> {
+    get_unclaimed_covenants(con)
+        .await?
+        .into_iter()
+        // Could not be claimed on another instance either
+        .filter(|covenant| covenant.status != PendingCovenantStatus::Unclaimable.to_int())
+        .map(ExportedCovenant::export)
+        .collect()
+}
+
+/// Imports all covenants or none if one of them is invalid and returns how many
+/// were imported; covenants that exist already are skipped
+pub async fn import(
+    con: db::Pool,
+    params: &'static AddressParams,
+    allowed_destinations: &AllowedDestinations,
+    lockup_confirmations: u64,
+    covenants: Vec<ExportedCovenant>,
+) -> Result<usize, ImportError> {
+    let covenants = validate(
+        params,
+        allowed_destinations,
+        lockup_confirmations,
+        covenants,
+    )?;
+    Ok(import_covenants(con, covenants).await?)
+}
+
+fn validate(
+    params: &'static AddressParams,
+    allowed_destinations: &AllowedDestinations,
+    lockup_confirmations: u64,
+    covenants: Vec<ExportedCovenant>,
+) -> Result<Vec<PendingCovenant>, ImportError> {
+    covenants
+        .into_iter()
+        .enumerate()
+        .map(|(index, covenant)| {
+            let covenant = covenant
+                .import(params)
+                .map_err(|err| ImportError::InvalidCovenant(index, err))?;
+            if !allowed_destinations.is_allowed(&Script::from(covenant.address.clone())) {
+                return Err(ImportError::DestinationNotAllowed(index));
+            }
+            // Without it, any lockup with enough confirmations would reveal the preimage
+            if lockup_confirmations > 0 && covenant.expected_lockup_amount.is_none() {
+                return Err(ImportError::InvalidCovenant(
+                    index,
+                    "expectedLockupAmount is required when lockups need confirmations".to_string(),
+                ));
+            }
+
+            Ok(covenant)
+        })
+        .collect()
+}
+
+pub async fn export_to_file(con: db::Pool, path: &str) -> Result<(), Box<dyn Error>> {
+    let covenants = export_covenants(con).await?;
+    fs::write(path, serde_json::to_string_pretty(&covenants)?)?;
+
+    info!("Exported {} covenants to: {}", covenants.len(), path);
+    Ok(())
+}
+
+pub async fn import_from_file(
+    con: db::Pool,
+    params: &'static AddressParams,
+    allowed_destinations: &AllowedDestinations,
+    lockup_confirmations: u64,
+    path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let covenants: Vec<ExportedCovenant> =
+        serde_json::from_str(fs::read_to_string(path)?.as_str())?;
+    let count = covenants.len();
+
+    let imported = import(
+        con,
+        params,
+        allowed_destinations,
+        lockup_confirmations,
+        covenants,
+    )
+    .await?;
+    info!(
+        "Imported {} of {} covenants from: {}",
+        imported, count, path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod transfer_test {
+    use elements::pset::serialize::Serialize;
+    use elements::AddressParams;
+
+    use crate::api::destinations::AllowedDestinations;
+    use crate::db::models::PendingCovenantStatus;
+    use crate::db::transfer::{validate, ExportedCovenant, ImportError};
+
+    const INTERNAL_KEY: &str = "816963af90d4b882ccbcaacc920ba8e4fdd35c083a052a08d5c1732272ffccd8";
+    // P2WPKH output script that the covenant claim leaf expects
+    const ADDRESS: &str = "0014aff4f5af812e3db39024f2000db7e23091dc0603";
+
+    // The covenant claim leaf commits to the SHA256 hash of an empty preimage
+    const TREE_JSON: &str = "{
+            \"claimLeaf\": {
+                \"version\": 196,
+                \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558820812910149e0e71209624487851f80a0cb97652efb0a836205628bc1b0e8e3aa7ac\"
+            },
+            \"refundLeaf\": {
+                \"version\": 196,
+                \"output\": \"201ec7adf6f1c40ad340533027d15952c0c5b7aa0dd6c4b38d838e62d32d4d0259ad020b06b1\"
+            },
+            \"covenantClaimLeaf\": {
+                \"version\": 196,
+                \"output\": \"82012088a820e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b8558800d1008814aff4f5af812e3db39024f2000db7e23091dc06038800ce51882025b251070e29ca19043cf33ccd7324e2ddab03ecc4ae0b5e77c4fc0e5cf6c95a8800cf7508a08601000000000087\"
+            }
+        }";
+
+    fn exported_covenant() -> ExportedCovenant {
+        ExportedCovenant {
+            swap_id: Some("swap".to_string()),
+            internal_key: hex::decode(INTERNAL_KEY).unwrap(),
+            preimage: vec![],
+            blinding_key: None,
+            address: hex::decode(ADDRESS).unwrap(),
+            tree: serde_json::from_str(TREE_JSON).unwrap(),
+            status: PendingCovenantStatus::Pending.to_int(),
+            tx_id: None,
+            tx_time: None,
+            fee_rate: None,
+            max_fee: None,
+            op_return_data: None,
+            expected_lockup_amount: None,
+        }
+    }
+
+    #[test]
+    fn test_import_derives_output_script() {
+        let exported = exported_covenant();
+        let expected = exported
+            .tree
+            .clone()
+            .address(exported.internal_key.clone(), &AddressParams::ELEMENTS)
+            .unwrap()
+            .script_pubkey();
+
+        let covenant = exported.import(&AddressParams::ELEMENTS).unwrap();
+        assert_eq!(covenant.output_script, expected.serialize());
+        assert_eq!(covenant.swap_id, Some("swap".to_string()));
+    }
+
+    #[test]
+    fn test_import_roundtrip() {
+        let mut exported = exported_covenant();
+        exported.status = PendingCovenantStatus::TransactionFound.to_int();
+        exported.tx_id = Some("00".repeat(32));
+        exported.tx_time = Some(1_700_000_000);
+
+        let reexported =
+            ExportedCovenant::export(exported.import(&AddressParams::ELEMENTS).unwrap()).unwrap();
+        assert_eq!(reexported.tx_id, Some("00".repeat(32)));
+        assert_eq!(reexported.tx_time, Some(1_700_000_000));
+        assert_eq!(hex::encode(reexported.internal_key), INTERNAL_KEY);
+    }
+
+    #[test]
+    fn test_import_invalid_status() {
+        let mut exported = exported_covenant();
+        exported.status = PendingCovenantStatus::Claimed.to_int();
+
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "invalid status: 2"
+        );
+    }
+
+    #[test]
+    fn test_import_missing_transaction() {
+        let mut exported = exported_covenant();
+        exported.status = PendingCovenantStatus::TransactionFound.to_int();
+
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "lockup transaction of covenant missing"
+        );
+    }
+
+    #[test]
+    fn test_import_invalid_preimage() {
+        let mut exported = exported_covenant();
+        exported.preimage = vec![1];
+
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "invalid preimage"
+        );
+    }
+
+    #[test]
+    fn test_import_address_mismatch() {
+        let mut exported = exported_covenant();
+        exported.address = hex::decode("0014b6e8026a881fcbe566aa2517af4a9191a1ae3d6b").unwrap();
+
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "address does not match the output of the covenant"
+        );
+    }
+
+    #[test]
+    fn test_import_dust() {
+        let mut exported = exported_covenant();
+        // Covenant claim leaf that expects an output of 1 sat
+        exported.tree =
+            serde_json::from_str(&TREE_JSON.replace("a086010000000000", "0100000000000000"))
+                .unwrap();
+
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "amount of the covenant is below the dust threshold of 399 sats"
+        );
+    }
+
+    #[test]
+    fn test_import_invalid_internal_key() {
+        let mut exported = exported_covenant();
+        exported.internal_key = vec![1, 2, 3];
+
+        assert!(exported
+            .import(&AddressParams::ELEMENTS)
+            .err()
+            .unwrap()
+            .starts_with("invalid internal key"));
+    }
+
+    #[test]
+    fn test_validate_allowed_destinations() {
+        let allowed =
+            AllowedDestinations::parse(&AddressParams::ELEMENTS, &format!("script:{}", ADDRESS))
+                .unwrap();
+        assert_eq!(
+            validate(
+                &AddressParams::ELEMENTS,
+                &allowed,
+                0,
+                vec![exported_covenant()]
+            )
+            .unwrap()
+            .len(),
+            1
+        );
+
+        let other =
+            AllowedDestinations::parse(&AddressParams::ELEMENTS, "script:0014aaaa").unwrap();
+        assert!(matches!(
+            validate(
+                &AddressParams::ELEMENTS,
+                &other,
+                0,
+                vec![exported_covenant(), exported_covenant()]
+            )
+            .err()
+            .unwrap(),
+            ImportError::DestinationNotAllowed(0)
+        ));
+    }
+
+    #[test]
+    fn test_validate_expected_lockup_amount() {
+        let allowed = AllowedDestinations::default();
+        assert_eq!(
+            validate(
+                &AddressParams::ELEMENTS,
+                &allowed,
+                1,
+                vec![exported_covenant()]
+            )
+            .err()
+            .unwrap()
+            .to_string(),
+            "covenant 0: expectedLockupAmount is required when lockups need confirmations"
+        );
+
+        let mut exported = exported_covenant();
+        exported.expected_lockup_amount = Some(100_000);
+        assert!(validate(&AddressParams::ELEMENTS, &allowed, 1, vec![exported]).is_ok());
+    }
+
+    #[test]
+    fn test_import_op_return_data() {
+        let mut exported = exported_covenant();
+        exported.op_return_data = Some("00".repeat(80));
+        assert_eq!(
+            exported
+                .import(&AddressParams::ELEMENTS)
+                .unwrap()
+                .op_return_data,
+            Some(vec![0; 80])
+        );
+
+        let mut exported = exported_covenant();
+        exported.op_return_data = Some("00".repeat(81));
+        assert_eq!(
+            exported.import(&AddressParams::ELEMENTS).err().unwrap(),
+            "OP_RETURN data is longer than 80 bytes"
+        );
+    }
+}
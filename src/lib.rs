@@ -0,0 +1,388 @@
+//! Watches the chain for claimable covenants of Boltz swaps and broadcasts their claims.
+//!
+//! The covclaim daemon configures everything with environment variables, which are parsed by
+//! [`config::Config::from_env`]. Services that want to claim covenants themselves can embed
+//! the claimer with [`Covclaim::builder`]:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use covclaim::chain::types::ChainBackend;
+//! use covclaim::Covclaim;
+//!
+//! async fn claim_covenants(
+//!     db: covclaim::db::Pool,
+//!     chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+//! ) {
+//!     let covclaim = Covclaim::builder()
+//!         .with_db(db)
+//!         .with_chain_backend(chain_backend)
+//!         .with_address_params(&elements::AddressParams::LIQUID)
+//!         .build()
+//!         .unwrap();
+//!     covclaim.start();
+//! }
+//! ```
+
+use std::sync::Arc;
+
+use elements::{AddressParams, AssetId, Transaction};
+use tokio::sync::Notify;
+
+use crate::api::destinations::AllowedDestinations;
+use crate::chain::fee;
+use crate::chain::fee::FeeEstimator;
+use crate::chain::types::ChainBackend;
+use crate::claimer::constructor::MAX_OP_RETURN_DATA_LENGTH;
+use crate::claimer::error::ClaimError;
+use crate::claimer::{Claimer, RescanMode, DEFAULT_RESCAN_PREFETCH};
+use crate::db::models::PendingCovenantStatus;
+use crate::db::transfer::ExportedCovenant;
+use crate::notifier::Notifier;
+use crate::utils::channel::Receiver;
+use crate::utils::systemd::Watchdog;
+
+pub mod api;
+pub mod boltz;
+pub mod chain;
+pub mod claimer;
+pub mod config;
+pub mod db;
+pub mod notifier;
+pub mod utils;
+
+/// Claims the covenants in the database once their lockup transactions are found
+#[derive(Clone)]
+pub struct Covclaim {
+    db: db::Pool,
+    address_params: &'static AddressParams,
+    allowed_destinations: AllowedDestinations,
+    lockup_confirmations: u64,
+    claimer: Claimer,
+    fee_estimator: FeeEstimator,
+}
+
+impl Covclaim {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Rescans the chain according to the rescan mode, starts watching the chain
+    /// and broadcasts claims in the background
+    pub fn start(&self) {
+        self.claimer.start();
+    }
+
+    /// Fee estimation that claims are compared to
+    pub fn fee_estimator(&self) -> FeeEstimator {
+        self.fee_estimator.clone()
+    }
+
+    /// Updates the sweep time and interval without restarting the claimer; the fee estimation
+    /// can be updated with [`FeeEstimator::update`]
+    pub fn update_sweep(&self, sweep_time: u64, sweep_interval: u64) {
+        self.claimer.update_sweep(sweep_time, sweep_interval);
+    }
+
+    /// Validates the covenant and stores it to be claimed; returns false when it exists already
+    pub async fn register(&self, covenant: ExportedCovenant) -> Result<bool, String> {
+        db::transfer::import(
+            self.db.clone(),
+            self.address_params,
+            &self.allowed_destinations,
+            self.lockup_confirmations,
+            vec![covenant],
+        )
+        .await
+        .map(|imported| imported == 1)
+        .map_err(|err| err.to_string())
+    }
+
+    pub async fn rescan_from(&self, height: u64) -> Result<u64, ClaimError> {
+        self.claimer.rescan_from(height).await
+    }
+
+    /// Broadcasts the claim of the covenant of a swap without waiting for the sweep time;
+    /// returns None when the claim was included already
+    pub async fn claim(&self, swap_id: &str) -> Result<Option<Transaction>, ClaimError> {
+        let covenant = match db::helpers::get_covenant_by_swap_id(self.db.clone(), swap_id).await? {
+            Some(res) => res,
+            None => return Err(ClaimError::CovenantNotFound),
+        };
+        if covenant.status == PendingCovenantStatus::Claimed.to_int() {
+            return Err(ClaimError::AlreadyClaimed);
+        }
+
+        self.claimer.claim(covenant).await
+    }
+}
+
+pub struct Builder {
+    db: Option<db::Pool>,
+    chain_backend: Option<Arc<Box<dyn ChainBackend + Send + Sync>>>,
+    address_params: Option<&'static AddressParams>,
+
+    sweep_time: u64,
+    sweep_interval: u64,
+    sweep_max_per_round: usize,
+    sweep_jitter: u64,
+
+    rescan_mode: RescanMode,
+    max_rescan_depth: u64,
+    script_rescan_threshold: usize,
+    rescan_prefetch: usize,
+
+    fee_target_blocks: u16,
+    fee_floor: f64,
+    fee_ceiling: f64,
+
+    policy_asset: Option<AssetId>,
+    op_return_data: Option<Vec<u8>>,
+    lockup_confirmations: u64,
+    allowed_destinations: AllowedDestinations,
+
+    notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>,
+    wakeup: Option<Arc<Notify>>,
+    watchdog: Option<Watchdog>,
+    lockup_receivers: Vec<Receiver<Transaction>>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            db: None,
+            chain_backend: None,
+            address_params: None,
+            sweep_time: 120,
+            sweep_interval: 30,
+            sweep_max_per_round: 0,
+            sweep_jitter: 0,
+            rescan_mode: RescanMode::Auto,
+            max_rescan_depth: 0,
+            script_rescan_threshold: 0,
+            rescan_prefetch: DEFAULT_RESCAN_PREFETCH,
+            fee_target_blocks: fee::DEFAULT_TARGET_BLOCKS,
+            fee_floor: fee::DEFAULT_FLOOR,
+            fee_ceiling: fee::DEFAULT_CEILING,
+            policy_asset: None,
+            op_return_data: None,
+            lockup_confirmations: 0,
+            allowed_destinations: AllowedDestinations::default(),
+            notifier: None,
+            wakeup: None,
+            watchdog: None,
+            lockup_receivers: Vec::new(),
+        }
+    }
+}
+
+impl Builder {
+    pub fn with_db(mut self, db: db::Pool) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn with_chain_backend(
+        mut self,
+        chain_backend: Arc<Box<dyn ChainBackend + Send + Sync>>,
+    ) -> Self {
+        self.chain_backend = Some(chain_backend);
+        self
+    }
+
+    pub fn with_address_params(mut self, address_params: &'static AddressParams) -> Self {
+        self.address_params = Some(address_params);
+        self
+    }
+
+    /// Seconds to wait after a lockup before claiming it and how often to check for claims;
+    /// an interval of 0 claims instantly
+    pub fn with_sweep(mut self, sweep_time: u64, sweep_interval: u64) -> Self {
+        self.sweep_time = sweep_time;
+        self.sweep_interval = sweep_interval;
+        self
+    }
+
+    /// Claims broadcast per sweep round at most, with 0 for no limit, and the maximal random
+    /// seconds added to the sweep interval
+    pub fn with_sweep_batching(mut self, max_per_round: usize, jitter: u64) -> Self {
+        self.sweep_max_per_round = max_per_round;
+        self.sweep_jitter = jitter;
+        self
+    }
+
+    /// Where to start rescanning on startup and how many blocks below the tip to rescan at most;
+    /// a depth of 0 does not limit the rescan
+    pub fn with_rescan(mut self, rescan_mode: RescanMode, max_rescan_depth: u64) -> Self {
+        self.rescan_mode = rescan_mode;
+        self.max_rescan_depth = max_rescan_depth;
+        self
+    }
+
+    /// Looks up the script histories of pending covenants instead of scanning blocks when there are
+    /// at most that many and the chain backend can look up scripts; 0 always scans blocks
+    pub fn with_script_rescan_threshold(mut self, script_rescan_threshold: usize) -> Self {
+        self.script_rescan_threshold = script_rescan_threshold;
+        self
+    }
+
+    /// Batches of blocks that are fetched ahead of processing them when rescanning
+    pub fn with_rescan_prefetch(mut self, rescan_prefetch: usize) -> Self {
+        self.rescan_prefetch = rescan_prefetch;
+        self
+    }
+
+    /// Confirmation target and bounds in sat/vbyte of the fee estimation
+    pub fn with_fee_estimation(mut self, target_blocks: u16, floor: f64, ceiling: f64) -> Self {
+        self.fee_target_blocks = target_blocks;
+        self.fee_floor = floor;
+        self.fee_ceiling = ceiling;
+        self
+    }
+
+    /// Asset lockups have to be in; lockups in other assets are marked as unclaimable
+    pub fn with_policy_asset(mut self, policy_asset: AssetId) -> Self {
+        self.policy_asset = Some(policy_asset);
+        self
+    }
+
+    /// Data embedded in an OP_RETURN output of claims of covenants that do not set their own
+    pub fn with_op_return_data(mut self, op_return_data: Vec<u8>) -> Self {
+        self.op_return_data = Some(op_return_data);
+        self
+    }
+
+    /// Confirmations lockups need before they are claimed, which reveals the preimage; only
+    /// lockups with exactly the expected amount of the covenant are claimed when not 0
+    pub fn with_lockup_confirmations(mut self, confirmations: u64) -> Self {
+        self.lockup_confirmations = confirmations;
+        self
+    }
+
+    /// Output scripts covenants registered with [`Covclaim::register`] may pay to
+    pub fn with_allowed_destinations(mut self, allowed_destinations: AllowedDestinations) -> Self {
+        self.allowed_destinations = allowed_destinations;
+        self
+    }
+
+    /// Applies the claimer settings of a [`config::Config`]; the address params, policy asset
+    /// and allowed destinations depend on the network and are set separately
+    pub fn with_config(self, config: &config::Config) -> Self {
+        let mut builder = self
+            .with_sweep(config.sweep_time, config.sweep_interval)
+            .with_sweep_batching(config.sweep_max_per_round, config.sweep_jitter)
+            .with_rescan(config.rescan_mode, config.max_rescan_depth)
+            .with_script_rescan_threshold(config.script_rescan_threshold)
+            .with_rescan_prefetch(config.rescan_prefetch)
+            .with_fee_estimation(
+                config.fee_target_blocks,
+                config.fee_floor,
+                config.fee_ceiling,
+            )
+            .with_lockup_confirmations(config.lockup_confirmations);
+        if let Some(op_return_data) = config.op_return_data.clone() {
+            builder = builder.with_op_return_data(op_return_data);
+        }
+
+        builder
+    }
+
+    pub fn with_notifier(mut self, notifier: Arc<Box<dyn Notifier + Send + Sync>>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Checks for claims to broadcast whenever notified, in addition to the sweep interval
+    pub fn with_wakeup(mut self, wakeup: Arc<Notify>) -> Self {
+        self.wakeup = Some(wakeup);
+        self
+    }
+
+    /// Watchdog that is told when a loop of the claimer is stuck; it still has to be started
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
+
+    /// Lockup transactions reported by sources other than the chain backend;
+    /// can be called multiple times to add more sources
+    pub fn with_lockup_receiver(mut self, lockup_receiver: Receiver<Transaction>) -> Self {
+        self.lockup_receivers.push(lockup_receiver);
+        self
+    }
+
+    pub fn build(self) -> Result<Covclaim, String> {
+        let db = self.db.ok_or("database must be set")?;
+        let chain_backend = self.chain_backend.ok_or("chain backend must be set")?;
+        let address_params = self.address_params.ok_or("address params must be set")?;
+        if let Some(op_return_data) = &self.op_return_data {
+            if op_return_data.len() > MAX_OP_RETURN_DATA_LENGTH {
+                return Err(format!(
+                    "OP_RETURN data is longer than {} bytes",
+                    MAX_OP_RETURN_DATA_LENGTH
+                ));
+            }
+        }
+
+        let fee_estimator = FeeEstimator::new(
+            chain_backend.clone(),
+            self.fee_target_blocks,
+            self.fee_floor,
+            self.fee_ceiling,
+        );
+
+        let mut claimer = Claimer::new(
+            db.clone(),
+            chain_backend,
+            fee_estimator.clone(),
+            self.sweep_time,
+            self.sweep_interval,
+            address_params,
+        )
+        .with_sweep_batching(self.sweep_max_per_round, self.sweep_jitter)
+        .with_rescan(self.rescan_mode, self.max_rescan_depth)
+        .with_script_rescan_threshold(self.script_rescan_threshold)
+        .with_rescan_prefetch(self.rescan_prefetch)
+        .with_lockup_confirmations(self.lockup_confirmations);
+        if let Some(policy_asset) = self.policy_asset {
+            claimer = claimer.with_policy_asset(policy_asset);
+        }
+        if let Some(op_return_data) = self.op_return_data {
+            claimer = claimer.with_op_return_data(op_return_data);
+        }
+        if let Some(notifier) = self.notifier {
+            claimer = claimer.with_notifier(notifier);
+        }
+        if let Some(wakeup) = self.wakeup {
+            claimer = claimer.with_wakeup(wakeup);
+        }
+        if let Some(watchdog) = self.watchdog {
+            claimer = claimer.with_watchdog(watchdog);
+        }
+        for lockup_receiver in self.lockup_receivers {
+            claimer = claimer.with_lockup_receiver(lockup_receiver);
+        }
+
+        Ok(Covclaim {
+            db,
+            address_params,
+            allowed_destinations: self.allowed_destinations,
+            lockup_confirmations: self.lockup_confirmations,
+            claimer,
+            fee_estimator,
+        })
+    }
+}
+
+#[cfg(test)]
+mod lib_test {
+    use crate::Covclaim;
+
+    #[test]
+    fn test_build_missing_db() {
+        assert_eq!(
+            Covclaim::builder().build().err().unwrap(),
+            "database must be set"
+        );
+    }
+}
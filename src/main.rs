@@ -1,30 +1,38 @@
 use std::env;
-use std::sync::Arc;
+use std::error::Error;
 
-use crate::chain::esplora::EsploraClient;
-use crate::chain::types::ChainBackend;
+use crate::cli::Command;
+use clap::Parser;
+use covclaim::config::{self, Config};
+use covclaim::db::models::PendingCovenantStatus;
+use covclaim::notifier::composite::SinkToggles;
+use covclaim::{api, chain, db, utils, Covclaim};
 use dotenvy::dotenv;
-use elements::AddressParams;
-use log::{debug, error, info};
+use metrics_exporter_prometheus::PrometheusBuilder;
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
 
-mod api;
-mod boltz;
-mod chain;
-mod claimer;
-mod db;
-mod utils;
+mod cli;
 
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
 #[tokio::main]
 async fn main() {
+    let command = cli::Cli::parse().command.unwrap_or(Command::Run);
+
     match dotenv() {
         Ok(_) => {}
-        Err(err) => println!("Could not read .env file: {}", err),
+        Err(err) => eprintln!("Could not read .env file: {}", err),
     };
-    env_logger::init();
+    // Errors are only reported while the guard is alive
+    let (_sentry, log_filter) = init_logging();
 
     info!(
         "Starting {} v{}-{}{}",
@@ -45,126 +53,425 @@ async fn main() {
         built_info::TARGET
     );
 
-    let network_params = get_address_params();
+    let config = exit_on_error("Invalid configuration", Config::from_env());
+    debug!("Using network: {}", config.network);
 
-    let db = match db::establish_connection(
-        env::var("DATABASE_URL")
-            .expect("DATABASE_URL must be set")
-            .as_str(),
-    ) {
-        Ok(res) => res,
-        Err(err) => {
-            error!("Could not connect to database: {}", err);
-            std::process::exit(1);
-        }
-    };
+    let db = exit_on_error(
+        "Could not connect to database",
+        db::establish_connection(config.database_url.as_str()),
+    );
     info!("Connected to database");
 
-    let elements = get_chain_backend().await;
-
-    let connect_res = match elements.get_network_info().await {
-        Ok(res) => res,
-        Err(err) => {
-            error!("Could not connect to chain backend: {}", err);
-            std::process::exit(1);
+    // Commands that only need the database
+    let res = match &command {
+        // Pending migrations were run when connecting
+        Command::Migrate => Some(Ok(())),
+        Command::ListCovenants => Some(list_covenants(db.clone()).await),
+        Command::Export { path } => Some(db::transfer::export_to_file(db.clone(), path).await),
+        Command::Import { path } => {
+            let params = match config.address_params {
+                Some(params) => params,
+                None => {
+                    error!("NETWORK must not be auto to import covenants");
+                    std::process::exit(1);
+                }
+            };
+            Some(
+                db::transfer::import_from_file(
+                    db.clone(),
+                    params,
+                    &exit_on_error("Invalid configuration", config.allowed_destinations(params)),
+                    config.lockup_confirmations,
+                    path,
+                )
+                .await,
+            )
         }
+        _ => None,
     };
+    if let Some(res) = res {
+        if let Err(err) = res {
+            error!("Command failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let metrics = exit_on_error(
+        "Could not install metrics recorder",
+        PrometheusBuilder::new().install_recorder(),
+    );
 
+    let db_health = db::health::HealthCheck::new(db.clone(), config.database_health_check_interval);
+
+    let http_client = exit_on_error("Could not create HTTP client", config.http.build_client());
+    let boltz_client = config.boltz_client(http_client.clone());
+
+    let elements = exit_on_error(
+        "Could not create chain backend",
+        config
+            .chain_backend(db.clone(), http_client.clone(), boltz_client.clone())
+            .await,
+    );
+
+    let connect_res = exit_on_error(
+        "Could not connect to chain backend",
+        elements.get_network_info().await,
+    );
     info!("Connected to chain backend: {}", connect_res.subversion);
 
-    let claimer = claimer::Claimer::new(
-        db.clone(),
-        elements,
-        env::var("SWEEP_TIME")
-            .expect("SWEEP_TIME must be set")
-            .parse::<u64>()
-            .expect("SWEEP_TIME invalid"),
-        env::var("SWEEP_INTERVAL")
-            .expect("SWEEP_INTERVAL must be set")
-            .parse::<u64>()
-            .expect("SWEEP_INTERVAL invalid"),
-        network_params,
+    let (network, network_params) = exit_on_error(
+        "Could not verify network",
+        config.resolve_network(&**elements).await,
     );
-    claimer.start();
 
-    let server_host = env::var("API_HOST").expect("API_HOST must be set");
-    let server_port = env::var("API_PORT")
-        .expect("API_PORT must be set")
-        .parse::<u32>()
-        .expect("API_PORT invalid");
+    let sink_toggles = SinkToggles::default();
+    let notifier = exit_on_error(
+        "Could not create event sinks",
+        config
+            .notifier(
+                db.clone(),
+                http_client.clone(),
+                sink_toggles.clone(),
+                network.clone(),
+            )
+            .await,
+    );
 
-    let server = api::server::start_server(db, network_params, server_host.as_str(), server_port);
-    info!("Started API server on: {}:{}", server_host, server_port);
+    let (lockup_sender, lockup_receiver) = utils::channel::bounded(
+        "api_lockups",
+        utils::channel::TRANSACTION_CHANNEL_SIZE,
+        utils::channel::Overflow::Wait,
+    );
 
-    server.await.unwrap().expect("could not start server");
-}
+    let watchdog = utils::systemd::Watchdog::default();
+    let mut builder = Covclaim::builder()
+        .with_db(db.clone())
+        .with_chain_backend(elements.clone())
+        .with_address_params(network_params)
+        .with_config(&config);
+    let policy_asset = config.policy_asset(network.as_str());
+    match policy_asset {
+        Some(policy_asset) => {
+            debug!("Using policy asset: {}", policy_asset);
+            builder = builder.with_policy_asset(policy_asset);
+        }
+        None => warn!(
+            "No policy asset for network {}; not checking assets of lockups",
+            network
+        ),
+    };
+    if config.lockup_confirmations > 0 {
+        info!(
+            "Claiming lockups with the expected amount after {} confirmations",
+            config.lockup_confirmations
+        );
+    }
+    let allowed_destinations = exit_on_error(
+        "Invalid configuration",
+        config.allowed_destinations(network_params),
+    );
+    if !allowed_destinations.is_empty() {
+        info!(
+            "Only allowing {} claim destinations",
+            allowed_destinations.len()
+        );
+    }
+    builder = builder.with_allowed_destinations(allowed_destinations.clone());
+    if db::is_postgres_connection_url(config.database_url.as_str()) {
+        builder = builder.with_wakeup(db::listener::listen(config.database_url.clone()));
+    }
+    if let Some(notifier) = notifier.clone() {
+        builder = builder.with_notifier(notifier);
+    }
+    // The WebSocket is only connected to when running the daemon
+    if let Command::Run = command {
+        let swap_updates = exit_on_error(
+            "Could not subscribe to swap updates",
+            config.swap_updates(db.clone(), elements.clone()),
+        );
+        if let Some(swap_updates) = swap_updates {
+            builder = builder.with_lockup_receiver(swap_updates.get_tx_receiver());
+        }
+    }
+    builder = builder
+        .with_lockup_receiver(lockup_receiver)
+        .with_watchdog(watchdog.clone());
 
-async fn get_chain_backend() -> Arc<Box<dyn ChainBackend + Send + Sync>> {
-    let backend = env::var("CHAIN_BACKEND").unwrap_or("elements".to_string());
-    info!("Using {} chain backend", backend);
-    let client: Box<dyn ChainBackend + Send + Sync> = match backend.as_str() {
-        "elements" => {
-            match chain::client::ChainClient::new(
-                env::var("ELEMENTS_HOST").expect("ELEMENTS_HOST must be set"),
-                env::var("ELEMENTS_PORT")
-                    .expect("ELEMENTS_PORT must be est")
-                    .parse::<u32>()
-                    .expect("ELEMENTS_PORT invalid"),
-                env::var("ELEMENTS_COOKIE").expect("ELEMENTS_COOKIE must be set"),
-            )
-            .connect()
-            .await
-            {
-                Ok(client) => Box::new(client),
+    let covclaim = exit_on_error("Could not create claimer", builder.build());
+
+    match command {
+        Command::CheckConfig => {
+            info!("Configuration is valid");
+            return;
+        }
+        Command::Rescan { from_height } => {
+            match covclaim.rescan_from(from_height).await {
+                Ok(height) => info!("Rescanned from {} to height: {}", from_height, height),
                 Err(err) => {
-                    error!("Could not connect to Elements client: {}", err);
+                    error!("Rescanning failed: {}", err);
                     std::process::exit(1);
                 }
-            }
+            };
+            return;
         }
-        "esplora" => {
-            match EsploraClient::new(
-                env::var("ESPLORA_ENDPOINT").expect("ESPLORA_ENDPOINT must be set"),
-                env::var("ESPLORA_POLL_INTERVAL")
-                    .expect("ESPLORA_POLL_INTERVAL must be set")
-                    .parse::<u64>()
-                    .expect("ESPLORA_POLL_INTERVAL invalid"),
-                env::var("ESPLORA_MAX_REQUESTS_PER_SECOND")
-                    .expect("ESPLORA_MAX_REQUESTS_PER_SECOND must be set")
-                    .parse::<u64>()
-                    .expect("ESPLORA_MAX_REQUESTS_PER_SECOND invalid"),
-                env::var("BOLTZ_ENDPOINT").expect("BOLTZ_ENDPOINT must be set"),
-            ) {
-                Ok(client) => {
-                    client.connect();
-                    Box::new(client)
-                }
+        Command::Claim { swap_id } => {
+            match covclaim.claim(swap_id.as_str()).await {
+                Ok(Some(tx)) => info!("Broadcast claim: {}", tx.txid()),
+                Ok(None) => info!("Claim was included already"),
                 Err(err) => {
-                    error!("Could not create Esplora client: {}", err);
+                    error!("Could not claim covenant of swap {}: {}", swap_id, err);
                     std::process::exit(1);
                 }
-            }
+            };
+            return;
         }
-        &_ => {
-            error!("Unknown chain backend: {}", backend);
+        _ => {}
+    };
+
+    covclaim.start();
+    watchdog.start();
+    listen_for_reload(covclaim.clone(), log_filter, sink_toggles);
+    listen_for_shutdown();
+
+    db::pruner::Pruner::new(
+        db.clone(),
+        config.prune_interval,
+        config.prune_retention_days,
+        config.prune_archive_path.clone(),
+    )
+    .start();
+
+    db_health.start();
+
+    let verifier = config.verifier(db.clone(), boltz_client, notifier.clone());
+
+    let server = api::server::start_server(
+        api::types::RouterState {
+            db,
+            address_params: network_params,
+            chain_backend: elements,
+            fee_estimator: covclaim.fee_estimator(),
+            policy_asset,
+            allowed_destinations,
+            lockup_confirmations: config.lockup_confirmations,
+            op_return_data: config.op_return_data.clone(),
+            lockup_sender,
+            health: db_health,
+            metrics,
+            notifier,
+            verifier,
+            version: api::types::VersionInfo {
+                network,
+                version: built_info::PKG_VERSION.to_string(),
+                git_commit: built_info::GIT_COMMIT_HASH.map(|hash| hash.to_string()),
+                dirty: built_info::GIT_DIRTY,
+                rustc: built_info::RUSTC_VERSION.to_string(),
+                profile: built_info::PROFILE.to_string(),
+                chain_backend: config.chain_backend_names().join(","),
+            },
+        },
+        config.api_host.as_str(),
+        config.api_port,
+        config.api_read_only,
+        config.api_admin_token.clone(),
+    );
+    info!(
+        "Started API server on: {}:{}",
+        config.api_host, config.api_port
+    );
+
+    server.await.unwrap().expect("could not start server");
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(message: &str, res: Result<T, E>) -> T {
+    match res {
+        Ok(res) => res,
+        Err(err) => {
+            error!("{}: {}", message, err);
             std::process::exit(1);
         }
+    }
+}
+
+fn init_logging() -> (Option<sentry::ClientInitGuard>, LogFilterHandle) {
+    let sentry = env::var("SENTRY_DSN")
+        .ok()
+        .filter(|dsn| !dsn.is_empty())
+        .map(|dsn| {
+            sentry::init((
+                dsn,
+                sentry::ClientOptions {
+                    release: sentry::release_name!(),
+                    environment: env::var("SENTRY_ENVIRONMENT")
+                        .ok()
+                        .filter(|environment| !environment.is_empty())
+                        .map(Into::into),
+                    ..Default::default()
+                },
+            ))
+        });
+
+    // Configured with the standard OTEL_* environment variables, like OTEL_SERVICE_NAME
+    let tracer = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+    {
+        Some(_) => match opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(opentelemetry_otlp::new_exporter().tonic())
+            .install_batch(opentelemetry_sdk::runtime::Tokio)
+        {
+            Ok(res) => Some(res),
+            Err(err) => {
+                eprintln!("Could not create OpenTelemetry exporter: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => None,
     };
 
-    Arc::new(client)
+    let logger = tracing_subscriber::fmt::layer();
+    let logger = match env::var("LOG_FORMAT").unwrap_or_default().as_str() {
+        "json" => logger.json().boxed(),
+        _ => logger.boxed(),
+    };
+
+    // Errors are captured as events with the fields of their spans, like the swap ID of claims;
+    // everything else is attached to them as breadcrumbs
+    let (log_filter, log_filter_handle) = reload::Layer::new(EnvFilter::from_default_env());
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(logger)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+        .with(
+            sentry
+                .as_ref()
+                .map(|_| sentry::integrations::tracing::layer().enable_span_attributes()),
+        )
+        .init();
+
+    if sentry.is_some() {
+        info!("Reporting errors to Sentry");
+    }
+
+    (sentry, log_filter_handle)
 }
 
-fn get_address_params() -> &'static AddressParams {
-    let network = env::var("NETWORK").expect("NETWORK must be set");
-    debug!("Using network: {network}");
+// Restarting would interrupt rescans and claims that are being broadcast
+fn listen_for_reload(covclaim: Covclaim, log_filter: LogFilterHandle, sink_toggles: SinkToggles) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(res) => res,
+        Err(err) => {
+            warn!("Could not listen for SIGHUP: {}", err);
+            return;
+        }
+    };
 
-    match network.as_str() {
-        "mainnet" => &AddressParams::LIQUID,
-        "testnet" => &AddressParams::LIQUID_TESTNET,
-        "regtest" => &AddressParams::ELEMENTS,
-        &_ => {
-            error!("Could not parse network: {}", network);
-            std::process::exit(1);
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP; reloading settings");
+            reload_settings(&covclaim, &log_filter, &sink_toggles);
+        }
+    });
+}
+
+fn listen_for_shutdown() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(res) => res,
+        Err(err) => {
+            warn!("Could not listen for SIGTERM: {}", err);
+            return;
         }
+    };
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        };
+
+        info!("Shutting down");
+        utils::systemd::notify_stopping();
+        std::process::exit(0);
+    });
+}
+
+// Invalid values are logged and the previous ones kept, so that a typo does not stop the daemon
+fn reload_settings(covclaim: &Covclaim, log_filter: &LogFilterHandle, sink_toggles: &SinkToggles) {
+    if let Err(err) = dotenvy::dotenv_override() {
+        warn!("Could not read .env file: {}", err);
     }
+
+    match EnvFilter::try_from_default_env() {
+        Ok(filter) => {
+            if let Err(err) = log_filter.reload(filter) {
+                warn!("Could not update log level: {}", err);
+            }
+        }
+        Err(err) => warn!("Could not parse RUST_LOG: {}", err),
+    };
+
+    match (
+        config::parse_env::<u64>("SWEEP_TIME"),
+        config::parse_env::<u64>("SWEEP_INTERVAL"),
+    ) {
+        (Ok(sweep_time), Ok(sweep_interval)) => {
+            covclaim.update_sweep(sweep_time, sweep_interval);
+            info!(
+                "Using sweep time of {} seconds and interval of {} seconds",
+                sweep_time, sweep_interval
+            );
+        }
+        (Err(err), _) | (_, Err(err)) => warn!("Not updating sweep settings: {}", err),
+    };
+
+    match (
+        config::parse_env_or("FEE_TARGET_BLOCKS", chain::fee::DEFAULT_TARGET_BLOCKS),
+        config::parse_env_or("FEE_FLOOR", chain::fee::DEFAULT_FLOOR),
+        config::parse_env_or("FEE_CEILING", chain::fee::DEFAULT_CEILING),
+    ) {
+        (Ok(target_blocks), Ok(floor), Ok(ceiling)) => {
+            covclaim
+                .fee_estimator()
+                .update(target_blocks, floor, ceiling);
+            info!(
+                "Estimating fees for {} blocks between {} and {} sat/vbyte",
+                target_blocks, floor, ceiling
+            );
+        }
+        (Err(err), _, _) | (_, Err(err), _) | (_, _, Err(err)) => {
+            warn!("Not updating fee estimation: {}", err)
+        }
+    };
+
+    let disabled_sinks = config::disabled_sinks();
+    if !disabled_sinks.is_empty() {
+        info!("Muted event sinks: {}", disabled_sinks.join(", "));
+    }
+    sink_toggles.set_disabled(disabled_sinks);
+}
+
+async fn list_covenants(db: db::Pool) -> Result<(), Box<dyn Error>> {
+    let covenants = db::helpers::get_unclaimed_covenants(db).await?;
+
+    for covenant in covenants.iter() {
+        println!(
+            "{}\t{}\t{}\t{}",
+            covenant.swap_id.as_deref().unwrap_or("-"),
+            PendingCovenantStatus::name(covenant.status),
+            hex::encode(&covenant.output_script),
+            covenant
+                .tx_id
+                .as_ref()
+                .map(|tx_id| tx_id.to_string())
+                .unwrap_or("-".to_string()),
+        );
+    }
+
+    println!(
+        "Found {} covenants that were not claimed yet",
+        covenants.len()
+    );
+    Ok(())
 }
@@ -0,0 +1,94 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use lapin::options::{BasicPublishOptions, ConfirmSelectOptions};
+use lapin::publisher_confirm::Confirmation;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::notifier::{Notification, Notifier};
+
+// Messages survive restarts of the broker when they are routed to durable queues
+const DELIVERY_MODE_PERSISTENT: u8 = 2;
+
+/// Publishes notifications to an AMQP 0.9.1 exchange with publisher confirms
+pub struct AmqpNotifier {
+    url: String,
+    exchange: String,
+    routing_key: String,
+
+    // Opened again on the next notification after the connection was lost
+    channel: Mutex<Option<Channel>>,
+}
+
+impl AmqpNotifier {
+    pub async fn connect(
+        url: String,
+        exchange: String,
+        routing_key: String,
+    ) -> Result<AmqpNotifier, Box<dyn Error + Send + Sync>> {
+        let channel = Self::open_channel(url.as_str()).await?;
+        info!("Connected to AMQP broker");
+
+        Ok(AmqpNotifier {
+            url,
+            exchange,
+            routing_key,
+            channel: Mutex::new(Some(channel)),
+        })
+    }
+
+    async fn open_channel(url: &str) -> Result<Channel, lapin::Error> {
+        let connection = Connection::connect(url, ConnectionProperties::default()).await?;
+        let channel = connection.create_channel().await?;
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await?;
+
+        Ok(channel)
+    }
+}
+
+#[async_trait]
+impl Notifier for AmqpNotifier {
+    fn name(&self) -> &'static str {
+        "AMQP"
+    }
+
+    async fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut channel = self.channel.lock().await;
+
+        if !channel
+            .as_ref()
+            .map(|channel| channel.status().connected())
+            .unwrap_or(false)
+        {
+            debug!("Reconnecting to AMQP broker");
+            *channel = Some(Self::open_channel(self.url.as_str()).await?);
+        }
+
+        let confirmation = channel
+            .as_ref()
+            .unwrap()
+            .basic_publish(
+                self.exchange.as_str(),
+                self.routing_key.as_str(),
+                BasicPublishOptions::default(),
+                serde_json::to_vec(notification)?.as_slice(),
+                BasicProperties::default()
+                    .with_content_type("application/json".into())
+                    .with_delivery_mode(DELIVERY_MODE_PERSISTENT),
+            )
+            .await?
+            .await?;
+
+        match confirmation {
+            Confirmation::Nack(_) => Err("broker did not confirm notification".into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::notifier::{Notification, NotificationKind, Notifier};
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+#[derive(Clone)]
+pub enum ChatTarget {
+    Telegram { bot_token: String, chat_id: String },
+    Slack { webhook_url: String },
+}
+
+/// Posts human-readable messages about successful and failed claims and expired swaps
+/// to a chat; all other notifications are skipped
+#[derive(Clone)]
+pub struct ChatNotifier {
+    target: ChatTarget,
+    http_client: reqwest::Client,
+}
+
+impl ChatNotifier {
+    pub fn new(target: ChatTarget, http_client: reqwest::Client) -> ChatNotifier {
+        ChatNotifier {
+            target,
+            http_client,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for ChatNotifier {
+    fn name(&self) -> &'static str {
+        match self.target {
+            ChatTarget::Telegram { .. } => "telegram",
+            ChatTarget::Slack { .. } => "slack",
+        }
+    }
+
+    async fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let text = match message(notification) {
+            Some(res) => res,
+            None => return Ok(()),
+        };
+
+        let req = match &self.target {
+            ChatTarget::Telegram { bot_token, chat_id } => self
+                .http_client
+                .post(format!("{}/bot{}/sendMessage", TELEGRAM_API, bot_token))
+                .json(&json!({
+                    "chat_id": chat_id,
+                    "text": text,
+                })),
+            ChatTarget::Slack { webhook_url } => {
+                self.http_client.post(webhook_url.as_str()).json(&json!({
+                    "text": text,
+                }))
+            }
+        };
+
+        // The URLs contain the credentials, so they must not end up in logs
+        req.send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|err| err.without_url())?;
+
+        Ok(())
+    }
+}
+
+fn message(notification: &Notification) -> Option<String> {
+    let covenant = match &notification.swap_id {
+        Some(swap_id) => format!("swap {}", swap_id),
+        None => format!("covenant {}", notification.output_script),
+    };
+
+    let text = match notification.kind {
+        NotificationKind::Claimed => match &notification.tx_id {
+            Some(tx_id) => format!("Claimed {} in {}", covenant, tx_id),
+            None => format!("Claimed {}", covenant),
+        },
+        NotificationKind::ClaimFailed => {
+            format!("Could not claim {}: {}", covenant, notification.reason)
+        }
+        NotificationKind::SwapExpired => format!("{} expired before it was claimed", covenant),
+        _ => return None,
+    };
+
+    Some(match &notification.network {
+        Some(network) => format!("[{}] {}", network, text),
+        None => text,
+    })
+}
+
+#[cfg(test)]
+mod chat_test {
+    use crate::notifier::chat::message;
+    use crate::notifier::{Notification, NotificationKind};
+
+    fn notification(
+        kind: NotificationKind,
+        status: &'static str,
+        reason: &str,
+        tx_id: Option<&str>,
+    ) -> Notification {
+        Notification {
+            kind,
+            status,
+            swap_id: Some("swap".to_string()),
+            output_script: "0014".to_string(),
+            reason: reason.to_string(),
+            tx_id: tx_id.map(|tx_id| tx_id.to_string()),
+            network: None,
+            time: 0,
+        }
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            message(&notification(
+                NotificationKind::Claimed,
+                "claimed",
+                "claim broadcast",
+                Some("txid")
+            )),
+            Some("Claimed swap swap in txid".to_string())
+        );
+        assert_eq!(
+            message(&notification(
+                NotificationKind::ClaimFailed,
+                "transactionFound",
+                "claim failed: dust",
+                None
+            )),
+            Some("Could not claim swap swap: claim failed: dust".to_string())
+        );
+        assert_eq!(
+            message(&notification(
+                NotificationKind::SwapExpired,
+                "pending",
+                "boltz mismatch: swap expired with status swap.expired",
+                None
+            )),
+            Some("swap swap expired before it was claimed".to_string())
+        );
+        assert_eq!(
+            message(&notification(
+                NotificationKind::LockupDetected,
+                "transactionFound",
+                "lockup transaction found",
+                None
+            )),
+            None
+        );
+    }
+
+    #[test]
+    fn test_message_without_swap_id() {
+        let mut notification = notification(
+            NotificationKind::Claimed,
+            "claimed",
+            "claim broadcast",
+            None,
+        );
+        notification.swap_id = None;
+
+        assert_eq!(
+            message(&notification),
+            Some("Claimed covenant 0014".to_string())
+        );
+    }
+
+    #[test]
+    fn test_message_with_network() {
+        let mut notification = notification(
+            NotificationKind::Claimed,
+            "claimed",
+            "claim broadcast",
+            None,
+        );
+        notification.network = Some("liquid".to_string());
+
+        assert_eq!(
+            message(&notification),
+            Some("[liquid] Claimed swap swap".to_string())
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+
+use crate::notifier::{Notification, Notifier};
+
+/// Names of sinks that are muted; cheap to clone and shared between the clones,
+/// so that sinks can be toggled without reconnecting to them
+#[derive(Clone, Default)]
+pub struct SinkToggles {
+    disabled: Arc<RwLock<HashSet<String>>>,
+}
+
+impl SinkToggles {
+    /// Mutes the sinks with the given names, case-insensitively, and unmutes all others
+    pub fn set_disabled(&self, names: Vec<String>) {
+        *self.disabled.write().unwrap() = names
+            .into_iter()
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        !self.disabled.read().unwrap().contains(&name.to_lowercase())
+    }
+}
+
+/// Sends notifications to all sinks concurrently; a failing sink does not affect the others
+pub struct CompositeNotifier {
+    notifiers: Vec<Box<dyn Notifier + Send + Sync>>,
+    toggles: SinkToggles,
+    // Added to every notification, so that consumers can tell instances apart
+    network: Option<String>,
+}
+
+impl CompositeNotifier {
+    pub fn new(notifiers: Vec<Box<dyn Notifier + Send + Sync>>) -> CompositeNotifier {
+        CompositeNotifier {
+            notifiers,
+            toggles: SinkToggles::default(),
+            network: None,
+        }
+    }
+
+    pub fn with_toggles(mut self, toggles: SinkToggles) -> Self {
+        self.toggles = toggles;
+        self
+    }
+
+    pub fn with_network(mut self, network: String) -> Self {
+        self.network = Some(network);
+        self
+    }
+}
+
+#[async_trait]
+impl Notifier for CompositeNotifier {
+    fn name(&self) -> &'static str {
+        "sinks"
+    }
+
+    async fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut notification = notification.clone();
+        if let Some(network) = &self.network {
+            notification.network = Some(network.clone());
+        }
+
+        let notifiers: Vec<&(dyn Notifier + Send + Sync)> = self
+            .notifiers
+            .iter()
+            .map(|notifier| notifier.as_ref())
+            .filter(|notifier| self.toggles.is_enabled(notifier.name()))
+            .collect();
+
+        let results = join_all(
+            notifiers
+                .iter()
+                .map(|notifier| notifier.notify(&notification)),
+        )
+        .await;
+
+        let errors: Vec<String> = notifiers
+            .iter()
+            .zip(results)
+            .filter_map(|(notifier, res)| {
+                res.err().map(|err| format!("{}: {}", notifier.name(), err))
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", ").into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod composite_test {
+    use std::error::Error;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+
+    use crate::notifier::composite::{CompositeNotifier, SinkToggles};
+    use crate::notifier::{Notification, NotificationKind, Notifier};
+
+    struct MockNotifier {
+        fail: bool,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Notifier for MockNotifier {
+        fn name(&self) -> &'static str {
+            if self.fail {
+                "failing"
+            } else {
+                "mock"
+            }
+        }
+
+        async fn notify(&self, _: &Notification) -> Result<(), Box<dyn Error + Send + Sync>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+
+            if self.fail {
+                Err("unreachable".into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            kind: NotificationKind::CovenantRegistered,
+            swap_id: None,
+            output_script: "00".to_string(),
+            status: "pending",
+            reason: "covenant registered".to_string(),
+            tx_id: None,
+            network: None,
+            time: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notify_all() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let composite = CompositeNotifier::new(vec![
+            Box::new(MockNotifier {
+                fail: false,
+                calls: calls.clone(),
+            }),
+            Box::new(MockNotifier {
+                fail: false,
+                calls: calls.clone(),
+            }),
+        ]);
+
+        composite.notify(&notification()).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_notify_failing_sink() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let composite = CompositeNotifier::new(vec![
+            Box::new(MockNotifier {
+                fail: true,
+                calls: calls.clone(),
+            }),
+            Box::new(MockNotifier {
+                fail: false,
+                calls: calls.clone(),
+            }),
+        ]);
+
+        assert_eq!(
+            composite
+                .notify(&notification())
+                .await
+                .err()
+                .unwrap()
+                .to_string(),
+            "failing: unreachable"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_notify_disabled_sink() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let toggles = SinkToggles::default();
+        let composite = CompositeNotifier::new(vec![
+            Box::new(MockNotifier {
+                fail: true,
+                calls: calls.clone(),
+            }),
+            Box::new(MockNotifier {
+                fail: false,
+                calls: calls.clone(),
+            }),
+        ])
+        .with_toggles(toggles.clone());
+
+        toggles.set_disabled(vec!["FAILING".to_string()]);
+        composite.notify(&notification()).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        toggles.set_disabled(vec![]);
+        assert!(composite.notify(&notification()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use diesel::internal::derives::multiconnection::chrono::Utc;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::db::models::{ClaimTiming, PendingCovenant, PendingCovenantStatus};
+
+pub mod amqp;
+pub mod chat;
+pub mod composite;
+pub mod nats;
+pub mod webhook;
+
+/// What happened to a covenant, so that sinks do not have to parse the reason
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationKind {
+    CovenantRegistered,
+    LockupDetected,
+    Claimed,
+    ClaimConfirmed,
+    ClaimFailed,
+    Unclaimable,
+    SwapExpired,
+    SwapMismatch,
+}
+
+/// Lifecycle event of a covenant that is sent to the configured sinks
+#[derive(Serialize, Clone, Debug)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    #[serde(rename = "swapId")]
+    pub swap_id: Option<String>,
+    #[serde(rename = "outputScript")]
+    pub output_script: String,
+    pub status: &'static str,
+    pub reason: String,
+    #[serde(rename = "txId")]
+    pub tx_id: Option<String>,
+    // Set by the CompositeNotifier for all sinks
+    pub network: Option<String>,
+    // UNIX timestamp in seconds
+    pub time: i64,
+}
+
+impl Notification {
+    pub fn new(
+        covenant: &PendingCovenant,
+        kind: NotificationKind,
+        status: PendingCovenantStatus,
+        reason: String,
+        tx_id: Option<String>,
+    ) -> Notification {
+        Notification {
+            kind,
+            reason,
+            tx_id,
+            network: None,
+            swap_id: covenant.swap_id.clone(),
+            output_script: hex::encode(&covenant.output_script),
+            status: PendingCovenantStatus::name(status.to_int()),
+            time: Utc::now().timestamp(),
+        }
+    }
+
+    /// Notification about a claim that was included in a block
+    pub fn claim_confirmed(timing: &ClaimTiming) -> Notification {
+        Notification {
+            kind: NotificationKind::ClaimConfirmed,
+            swap_id: timing.swap_id.clone(),
+            output_script: hex::encode(&timing.output_script),
+            status: PendingCovenantStatus::name(PendingCovenantStatus::Claimed.to_int()),
+            reason: "claim confirmed".to_string(),
+            tx_id: timing.claim_tx_id.as_ref().map(|tx_id| tx_id.to_string()),
+            network: None,
+            time: Utc::now().timestamp(),
+        }
+    }
+}
+
+/// Sink for lifecycle events of covenants; all configured sinks are combined
+/// with a `CompositeNotifier`, so the claimer does not know about any of them
+#[async_trait]
+pub trait Notifier {
+    fn name(&self) -> &'static str;
+
+    async fn notify(&self, notification: &Notification)
+        -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+/// Sends the notification in the background, so that claims are not delayed by slow sinks
+pub fn send(notifier: Option<Arc<Box<dyn Notifier + Send + Sync>>>, notification: Notification) {
+    let notifier = match notifier {
+        Some(res) => res,
+        None => return,
+    };
+
+    tokio::spawn(async move {
+        if let Err(err) = notifier.notify(&notification).await {
+            warn!(
+                "Could not send notification to {}: {}",
+                notifier.name(),
+                err
+            );
+        }
+    });
+}
@@ -0,0 +1,58 @@
+use std::error::Error;
+
+use async_nats::jetstream;
+use async_trait::async_trait;
+use tracing::{info, trace};
+
+use crate::notifier::{Notification, Notifier};
+
+/// Publishes notifications to a NATS JetStream subject and waits for the acknowledgement
+/// of the stream, so that notifications are persisted when publishing succeeds
+#[derive(Clone)]
+pub struct NatsNotifier {
+    subject: String,
+    jetstream: jetstream::Context,
+}
+
+impl NatsNotifier {
+    pub async fn connect(
+        url: String,
+        subject: String,
+    ) -> Result<NatsNotifier, Box<dyn Error + Send + Sync>> {
+        let client = async_nats::connect(url.as_str()).await?;
+        info!("Connected to NATS: {}", url);
+
+        Ok(NatsNotifier {
+            subject,
+            jetstream: jetstream::new(client),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for NatsNotifier {
+    fn name(&self) -> &'static str {
+        "NATS"
+    }
+
+    async fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let ack = self
+            .jetstream
+            .publish(
+                self.subject.clone(),
+                serde_json::to_vec(notification)?.into(),
+            )
+            .await?
+            .await?;
+
+        trace!(
+            "Published notification to NATS stream {} with sequence {}",
+            ack.stream,
+            ack.sequence
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use std::error::Error;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use elements::hashes::{hmac, sha256, Hash, HashEngine};
+use tokio::time;
+use tracing::{debug, info, warn};
+
+use crate::db;
+use crate::db::helpers::{
+    delete_undelivered_notification, get_undelivered_notifications, insert_undelivered_notification,
+};
+use crate::notifier::{Notification, Notifier};
+use crate::utils::retry::RetryPolicy;
+
+const SIGNATURE_HEADER: &str = "X-Signature";
+
+// Persisted notifications that are delivered again per interval
+const REDELIVERY_BATCH_SIZE: i64 = 100;
+
+/// POSTs notifications as JSON signed with HMAC-SHA256 to a URL; notifications that
+/// could not be delivered after all retries are persisted and delivered again later
+#[derive(Clone)]
+pub struct WebhookNotifier {
+    db: db::Pool,
+    url: String,
+    secret: Vec<u8>,
+    http_client: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl WebhookNotifier {
+    pub fn new(
+        db: db::Pool,
+        url: String,
+        secret: String,
+        http_client: reqwest::Client,
+        retry_policy: RetryPolicy,
+    ) -> WebhookNotifier {
+        WebhookNotifier {
+            db,
+            url,
+            http_client,
+            retry_policy,
+            secret: secret.into_bytes(),
+        }
+    }
+
+    pub fn start_redelivery(&self, interval: u64) {
+        let clone = self.clone();
+
+        tokio::spawn(async move {
+            info!(
+                "Delivering undelivered webhook notifications every {} seconds",
+                interval
+            );
+            let mut interval = time::interval(Duration::from_secs(interval));
+
+            loop {
+                interval.tick().await;
+                clone.redeliver().await;
+            }
+        });
+    }
+
+    async fn redeliver(&self) {
+        let notifications =
+            match get_undelivered_notifications(self.db.clone(), REDELIVERY_BATCH_SIZE).await {
+                Ok(res) => res,
+                Err(err) => {
+                    warn!("Could not get undelivered notifications: {}", err);
+                    return;
+                }
+            };
+
+        for notification in notifications {
+            // No point in trying the others when the endpoint is still down
+            if let Err(err) = self.post(notification.payload.as_str()).await {
+                debug!("Could not deliver webhook notification again: {}", err);
+                return;
+            }
+
+            if let Err(err) =
+                delete_undelivered_notification(self.db.clone(), notification.id).await
+            {
+                warn!("Could not delete delivered notification: {}", err);
+            }
+        }
+    }
+
+    async fn post_with_retries(&self, payload: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut attempt = 0;
+
+        loop {
+            match self.post(payload).await {
+                Ok(_) => return Ok(()),
+                Err(err) => {
+                    if !self.retry_policy.should_retry(attempt) {
+                        return Err(err);
+                    }
+
+                    let delay = self.retry_policy.delay(attempt);
+                    debug!(
+                        "Webhook request failed; retrying in {}ms: {}",
+                        delay.as_millis(),
+                        err
+                    );
+
+                    attempt += 1;
+                    time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    async fn post(&self, payload: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.http_client
+            .post(self.url.as_str())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, sign(&self.secret, payload.as_bytes()))
+            .body(payload.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let payload = serde_json::to_string(notification)?;
+
+        match self.post_with_retries(payload.as_str()).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                insert_undelivered_notification(self.db.clone(), payload).await?;
+                Err(format!("{}; persisted it to deliver it again later", err).into())
+            }
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the payload, with which receivers can verify notifications
+pub fn sign(secret: &[u8], payload: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret);
+    engine.input(payload);
+
+    hex::encode(hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array())
+}
+
+#[cfg(test)]
+mod webhook_test {
+    use crate::notifier::webhook::sign;
+
+    #[test]
+    fn test_sign() {
+        // Test case 2 of RFC 4231
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use metrics::{counter, gauge};
+use tokio::sync::{mpsc, Mutex};
+
+// Blocks are never dropped, so the channel only has to buffer bursts
+pub const BLOCK_CHANNEL_SIZE: usize = 64;
+pub const TRANSACTION_CHANNEL_SIZE: usize = 4096;
+
+/// What to do with messages that are sent while the channel is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    // Wait until the receiver catches up, which slows down the sender
+    Wait,
+    // Drop the message; only for messages that will be delivered again in another way,
+    // like mempool transactions that are included in a block eventually
+    Drop,
+}
+
+#[derive(Clone)]
+pub struct Sender<T> {
+    name: &'static str,
+    overflow: Overflow,
+    sender: mpsc::Sender<T>,
+}
+
+/// Receivers can be cloned; every message is received by only one of the clones
+#[derive(Clone)]
+pub struct Receiver<T> {
+    name: &'static str,
+    receiver: Arc<Mutex<mpsc::Receiver<T>>>,
+}
+
+/// Bounded channel whose queue depth is exported as metric with the name as label
+pub fn bounded<T>(name: &'static str, size: usize, overflow: Overflow) -> (Sender<T>, Receiver<T>) {
+    let (sender, receiver) = mpsc::channel(size);
+
+    (
+        Sender {
+            name,
+            overflow,
+            sender,
+        },
+        Receiver {
+            name,
+            receiver: Arc::new(Mutex::new(receiver)),
+        },
+    )
+}
+
+impl<T> Sender<T> {
+    pub async fn send(&self, msg: T) -> Result<(), String> {
+        let res = match self.overflow {
+            Overflow::Wait => self
+                .sender
+                .send(msg)
+                .await
+                .map_err(|_| format!("{} channel is closed", self.name)),
+            Overflow::Drop => self.sender.try_send(msg).map_err(|err| match err {
+                mpsc::error::TrySendError::Full(_) => {
+                    counter!("covclaim_channel_dropped_total", "channel" => self.name).increment(1);
+                    format!("{} channel is full", self.name)
+                }
+                mpsc::error::TrySendError::Closed(_) => {
+                    format!("{} channel is closed", self.name)
+                }
+            }),
+        };
+
+        self.update_depth();
+        res
+    }
+
+    fn update_depth(&self) {
+        gauge!("covclaim_channel_depth", "channel" => self.name)
+            .set((self.sender.max_capacity() - self.sender.capacity()) as f64);
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Returns None when all senders were dropped
+    pub async fn recv(&self) -> Option<T> {
+        let mut receiver = self.receiver.lock().await;
+        let msg = receiver.recv().await;
+
+        gauge!("covclaim_channel_depth", "channel" => self.name).set(receiver.len() as f64);
+        msg
+    }
+}
+
+#[cfg(test)]
+mod channel_test {
+    use crate::utils::channel::{bounded, Overflow};
+
+    #[tokio::test]
+    async fn test_overflow_drop() {
+        let (sender, receiver) = bounded::<u8>("test", 1, Overflow::Drop);
+
+        sender.send(1).await.unwrap();
+        assert_eq!(sender.send(2).await.err().unwrap(), "test channel is full");
+
+        assert_eq!(receiver.recv().await, Some(1));
+        sender.send(3).await.unwrap();
+        assert_eq!(receiver.recv().await, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_closed() {
+        let (sender, receiver) = bounded::<u8>("test", 1, Overflow::Wait);
+        drop(receiver);
+
+        assert_eq!(
+            sender.send(1).await.err().unwrap(),
+            "test channel is closed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_senders_dropped() {
+        let (sender, receiver) = bounded::<u8>("test", 1, Overflow::Wait);
+        drop(sender);
+
+        assert_eq!(receiver.recv().await, None);
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+// Idle connections are kept around that long to be reused by later requests
+const POOL_IDLE_TIMEOUT: u64 = 90;
+const TCP_KEEPALIVE: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    // Timeout of requests in seconds; 0 to disable
+    pub timeout: u64,
+    // Timeout of establishing connections in seconds; 0 to disable
+    pub connect_timeout: u64,
+    // Proxy for all requests; use socks5h:// to resolve hostnames like onion addresses via the proxy
+    pub proxy: Option<String>,
+}
+
+impl HttpConfig {
+    pub fn build_client(&self) -> reqwest::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT))
+            .tcp_keepalive(Duration::from_secs(TCP_KEEPALIVE));
+
+        if self.timeout > 0 {
+            builder = builder.timeout(Duration::from_secs(self.timeout));
+        }
+
+        if self.connect_timeout > 0 {
+            builder = builder.connect_timeout(Duration::from_secs(self.connect_timeout));
+        }
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod http_test {
+    use crate::utils::http::HttpConfig;
+
+    #[test]
+    fn test_build_client_proxy() {
+        let config = HttpConfig {
+            timeout: 30,
+            connect_timeout: 10,
+            proxy: Some("socks5h://127.0.0.1:9050".to_string()),
+        };
+
+        assert!(config.build_client().is_ok());
+    }
+
+    #[test]
+    fn test_build_client_invalid_proxy() {
+        let config = HttpConfig {
+            timeout: 0,
+            connect_timeout: 0,
+            proxy: Some("not a proxy".to_string()),
+        };
+
+        assert!(config.build_client().is_err());
+    }
+}
@@ -1 +1,8 @@
+pub mod channel;
+pub mod http;
+pub mod rate_limit;
+pub mod request_id;
+pub mod request_metrics;
+pub mod retry;
 pub mod string;
+pub mod systemd;
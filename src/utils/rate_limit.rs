@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use metrics::histogram;
+use ratelimit::Ratelimiter;
+use tokio::time;
+use tracing::info;
+
+/// Limits the number of requests per second that are sent to a service;
+/// clones share their budget of requests
+#[derive(Clone)]
+pub struct RateLimit {
+    service: String,
+    limiter: Option<Arc<Ratelimiter>>,
+}
+
+impl RateLimit {
+    // 0 requests per second to disable the rate limit
+    pub fn new(service: &str, max_reqs_per_second: u64) -> Result<RateLimit, Box<dyn Error>> {
+        if max_reqs_per_second == 0 {
+            info!("Not rate limiting {}", service);
+            return Ok(RateLimit {
+                service: service.to_string(),
+                limiter: None,
+            });
+        }
+
+        info!(
+            "Rate limiting {} to {} requests/second",
+            service, max_reqs_per_second
+        );
+        Ok(RateLimit {
+            service: service.to_string(),
+            limiter: Some(Arc::new(
+                Ratelimiter::builder(max_reqs_per_second, Duration::from_secs(1))
+                    .max_tokens(max_reqs_per_second)
+                    .build()?,
+            )),
+        })
+    }
+
+    // Sleeps asynchronously, so waiting for the rate limit does not block the worker threads
+    pub async fn wait(&self) {
+        let limiter = match &self.limiter {
+            Some(res) => res,
+            None => return,
+        };
+
+        let start = Instant::now();
+        while let Err(delay) = limiter.try_wait() {
+            time::sleep(delay).await;
+        }
+
+        histogram!("covclaim_rate_limit_wait_seconds", "service" => self.service.clone())
+            .record(start.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_test {
+    use std::time::Instant;
+
+    use crate::utils::rate_limit::RateLimit;
+
+    #[tokio::test]
+    async fn test_wait_disabled() {
+        let rate_limit = RateLimit::new("test", 0).unwrap();
+
+        let start = Instant::now();
+        for _ in 0..100 {
+            rate_limit.wait().await;
+        }
+        assert!(start.elapsed().as_millis() < 100);
+    }
+
+    #[tokio::test]
+    async fn test_wait() {
+        let rate_limit = RateLimit::new("test", 2).unwrap();
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            rate_limit.wait().await;
+        }
+        assert!(start.elapsed().as_millis() >= 500);
+    }
+}
@@ -0,0 +1,77 @@
+use std::future::Future;
+
+use elements::secp256k1_zkp::rand::rngs::OsRng;
+use elements::secp256k1_zkp::rand::RngCore;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// Ids sent by clients that are longer are replaced, so that they cannot bloat logs and the database
+const MAX_LENGTH: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+/// Id of the API call that caused the current task, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok().flatten()
+}
+
+/// Runs the future with the request id; tasks that are spawned have to be scoped again
+pub async fn scope<F: Future>(request_id: Option<String>, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Runs the closure with the request id, for threads of the blocking pool
+pub fn sync_scope<F: FnOnce() -> R, R>(request_id: Option<String>, f: F) -> R {
+    REQUEST_ID.sync_scope(request_id, f)
+}
+
+/// Uses the id sent by the client when it is valid and generates one otherwise
+pub fn parse_or_generate(header: Option<&str>) -> String {
+    match header {
+        Some(id) if is_valid(id) => id.to_string(),
+        _ => {
+            let mut bytes = [0u8; 16];
+            OsRng.fill_bytes(&mut bytes);
+            hex::encode(bytes)
+        }
+    }
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod request_id_test {
+    use crate::utils::request_id::{current, parse_or_generate, scope, sync_scope};
+
+    #[test]
+    fn test_parse_or_generate() {
+        assert_eq!(parse_or_generate(Some("abc-123_DEF")), "abc-123_DEF");
+
+        for header in [None, Some(""), Some("with space"), Some(&"a".repeat(65))] {
+            let generated = parse_or_generate(header);
+            assert_eq!(generated.len(), 32);
+            assert_ne!(generated, parse_or_generate(header));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scope() {
+        assert_eq!(current(), None);
+        assert_eq!(
+            scope(Some("id".to_string()), async { current() }).await,
+            Some("id".to_string())
+        );
+        assert_eq!(
+            sync_scope(Some("id".to_string()), current),
+            Some("id".to_string())
+        );
+    }
+}
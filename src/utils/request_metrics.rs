@@ -0,0 +1,78 @@
+use std::future::Future;
+use std::time::Instant;
+
+use metrics::{counter, histogram};
+
+/// Sends the request and records its latency and whether it succeeded; the endpoint is a label,
+/// so it must not contain identifiers like hashes
+pub async fn observe<T, E>(
+    service: &'static str,
+    endpoint: &str,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let res = request.await;
+
+    histogram!(
+        "covclaim_request_duration_seconds",
+        "service" => service,
+        "endpoint" => endpoint.to_string()
+    )
+    .record(start.elapsed().as_secs_f64());
+    counter!(
+        "covclaim_requests_total",
+        "service" => service,
+        "endpoint" => endpoint.to_string(),
+        "result" => if res.is_ok() { "success" } else { "failure" }
+    )
+    .increment(1);
+
+    res
+}
+
+pub fn record_retry(service: &'static str, endpoint: &str) {
+    counter!(
+        "covclaim_request_retries_total",
+        "service" => service,
+        "endpoint" => endpoint.to_string()
+    )
+    .increment(1);
+}
+
+/// Replaces the heights, hashes and transaction ids in the path of a request,
+/// like `block/<hash>/raw`, with placeholders
+pub fn endpoint_name(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+                ":height"
+            } else if segment.len() == 64 && segment.chars().all(|c| c.is_ascii_hexdigit()) {
+                ":hash"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod request_metrics_test {
+    use crate::utils::request_metrics::endpoint_name;
+
+    #[test]
+    fn test_endpoint_name() {
+        assert_eq!(endpoint_name("blocks/tip/height"), "blocks/tip/height");
+        assert_eq!(
+            endpoint_name("block-height/2920407"),
+            "block-height/:height"
+        );
+        assert_eq!(
+            endpoint_name(
+                "block/4a1f9addaea74ef4132005fa1411abebb3f968b8cb766af93547c77b6207d619/raw"
+            ),
+            "block/:hash/raw"
+        );
+        assert_eq!(endpoint_name("tx"), "tx");
+    }
+}
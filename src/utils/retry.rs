@@ -0,0 +1,74 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+// Upper bound for the exponential backoff in milliseconds
+const MAX_DELAY: u64 = 30_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    // Delay before the first retry in milliseconds; doubled with every attempt
+    pub base_delay: u64,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32, base_delay: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay,
+        }
+    }
+
+    pub fn should_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_retries
+    }
+
+    /// Exponential backoff with jitter, so that clients that failed at the same time
+    /// do not retry at the same time again
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2_u64.saturating_pow(attempt))
+            .min(MAX_DELAY);
+
+        let jitter = match backoff / 2 {
+            0 => 0,
+            range => RandomState::new().build_hasher().finish() % range,
+        };
+
+        Duration::from_millis(backoff / 2 + jitter)
+    }
+}
+
+pub fn is_transient_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request()
+}
+
+#[cfg(test)]
+mod retry_test {
+    use std::time::Duration;
+
+    use crate::utils::retry::RetryPolicy;
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new(2, 100);
+        assert!(policy.should_retry(0));
+        assert!(policy.should_retry(1));
+        assert!(!policy.should_retry(2));
+
+        assert!(!RetryPolicy::default().should_retry(0));
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(10, 100);
+
+        for (attempt, backoff) in [(0, 100), (1, 200), (2, 400), (20, 30_000)] {
+            let delay = policy.delay(attempt);
+            assert!(delay >= Duration::from_millis(backoff / 2));
+            assert!(delay < Duration::from_millis(backoff));
+        }
+    }
+}
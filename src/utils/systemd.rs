@@ -0,0 +1,154 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sd_notify::NotifyState;
+use tokio::time;
+use tracing::{debug, error, info};
+
+/// Tells systemd that the daemon is up; does nothing when not started by systemd
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+pub fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+fn notify(state: NotifyState) {
+    if let Err(err) = sd_notify::notify(false, &[state]) {
+        debug!("Could not notify systemd: {}", err);
+    }
+}
+
+/// Sends keepalives to the systemd watchdog as long as no loop of the claimer is stuck.
+/// Loops are only considered stuck while they are busy, so waiting for blocks or the
+/// next sweep round for longer than the watchdog timeout is fine
+#[derive(Clone, Default)]
+pub struct Watchdog {
+    heartbeats: Arc<Mutex<Vec<(&'static str, Heartbeat)>>>,
+}
+
+/// Cheap to clone; a heartbeat that was not registered with a watchdog is never checked
+#[derive(Clone, Default)]
+pub struct Heartbeat {
+    // Milliseconds since the UNIX epoch at which the loop started working; 0 when idle
+    busy_since: Arc<AtomicU64>,
+}
+
+/// Marks the loop as idle again when dropped
+pub struct Busy {
+    heartbeat: Heartbeat,
+}
+
+impl Watchdog {
+    pub fn heartbeat(&self, name: &'static str) -> Heartbeat {
+        let heartbeat = Heartbeat::default();
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .push((name, heartbeat.clone()));
+        heartbeat
+    }
+
+    /// Starts sending keepalives when the watchdog of systemd is enabled for the service
+    pub fn start(&self) {
+        let mut timeout_usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut timeout_usec) {
+            debug!("systemd watchdog is not enabled");
+            return;
+        }
+
+        let timeout = Duration::from_micros(timeout_usec);
+        info!(
+            "Sending keepalives to systemd watchdog with timeout of {} seconds",
+            timeout.as_secs()
+        );
+
+        let watchdog = self.clone();
+        tokio::spawn(async move {
+            let mut interval = time::interval(timeout / 2);
+            loop {
+                interval.tick().await;
+
+                match watchdog.stuck(timeout) {
+                    Some(name) => error!(
+                        "{} loop is busy for longer than {} seconds; not sending keepalive",
+                        name,
+                        timeout.as_secs()
+                    ),
+                    None => notify(NotifyState::Watchdog),
+                };
+            }
+        });
+    }
+
+    fn stuck(&self, timeout: Duration) -> Option<&'static str> {
+        let now = now_millis();
+        self.heartbeats
+            .lock()
+            .unwrap()
+            .iter()
+            .find(
+                |(_, heartbeat)| match heartbeat.busy_since.load(Ordering::Relaxed) {
+                    0 => false,
+                    busy_since => now.saturating_sub(busy_since) > timeout.as_millis() as u64,
+                },
+            )
+            .map(|(name, _)| *name)
+    }
+}
+
+impl Heartbeat {
+    pub fn busy(&self) -> Busy {
+        self.busy_since.store(now_millis(), Ordering::Relaxed);
+        Busy {
+            heartbeat: self.clone(),
+        }
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.heartbeat.busy_since.store(0, Ordering::Relaxed);
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod systemd_test {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use crate::utils::systemd::{now_millis, Watchdog};
+
+    #[test]
+    fn test_stuck() {
+        let watchdog = Watchdog::default();
+        let _sweep = watchdog.heartbeat("sweep");
+        let blocks = watchdog.heartbeat("blocks");
+        assert_eq!(watchdog.stuck(Duration::from_secs(60)), None);
+
+        let busy = blocks.busy();
+        assert_eq!(watchdog.stuck(Duration::from_secs(60)), None);
+
+        blocks
+            .busy_since
+            .store(now_millis() - 61_000, Ordering::Relaxed);
+        assert_eq!(watchdog.stuck(Duration::from_secs(60)), Some("blocks"));
+
+        drop(busy);
+        assert_eq!(watchdog.stuck(Duration::from_secs(60)), None);
+
+        // Heartbeats of other watchdogs are not checked
+        let other = Watchdog::default().heartbeat("other");
+        other.busy_since.store(1, Ordering::Relaxed);
+        assert_eq!(watchdog.stuck(Duration::from_secs(60)), None);
+    }
+}
@@ -0,0 +1,441 @@
+//! End-to-end tests of claiming covenants on an Elements regtest node.
+//!
+//! Start the node with `docker compose -f docker/regtest/docker-compose.yml up -d`
+//! and run the tests with `cargo test --features regtest --test regtest`.
+
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use covclaim::claimer::tree::{SwapTree, TreeScript};
+use elements::hashes::{sha256, Hash};
+use elements::opcodes::all::{
+    OP_CHECKSIG, OP_DROP, OP_EQUAL, OP_EQUALVERIFY, OP_INSPECTOUTPUTASSET,
+    OP_INSPECTOUTPUTSCRIPTPUBKEY, OP_INSPECTOUTPUTVALUE, OP_PUSHNUM_1, OP_SHA256, OP_SIZE,
+};
+use elements::script::Builder;
+use elements::secp256k1_zkp::rand::rngs::OsRng;
+use elements::secp256k1_zkp::rand::RngCore;
+use elements::secp256k1_zkp::{MusigKeyAggCache, PublicKey, Secp256k1, SecretKey};
+use elements::{Address, AddressParams, AssetId, Script};
+use serde_json::{json, Value};
+
+// Have to match docker/regtest/docker-compose.yml
+const ELEMENTS_PORT: u16 = 18884;
+const ELEMENTS_USER: &str = "regtest";
+const ELEMENTS_PASSWORD: &str = "regtest";
+const WALLET: &str = "covclaim";
+
+const LOCKUP_AMOUNT: u64 = 100_000;
+// Blinded claims are large, so the fee has to be generous
+const CLAIM_AMOUNT: u64 = 95_000;
+
+// Seconds to wait for the node and covclaim
+const TIMEOUT: u64 = 60;
+
+#[tokio::test]
+async fn test_claim_unblinded() {
+    claim_covenant("unblinded", 18_101, false).await;
+}
+
+#[tokio::test]
+async fn test_claim_blinded() {
+    claim_covenant("blinded", 18_102, true).await;
+}
+
+async fn claim_covenant(name: &str, api_port: u16, blinded: bool) {
+    let node = Node::new();
+    node.setup_wallet().await;
+
+    let covclaim = &Covclaim::start(name, api_port);
+    wait_for("covclaim API", || async move {
+        covclaim.get("/version").await.map(|_| ())
+    })
+    .await;
+
+    let claim_address = node.unconfidential_address().await;
+    let covenant = TestCovenant::new(
+        &claim_address,
+        node.policy_asset().await,
+        format!("regtest-{}-{}", name, std::process::id()),
+    );
+
+    let (lockup_address, blinding_key) = match blinded {
+        true => {
+            let blinding_key = SecretKey::new(&mut OsRng);
+            (
+                covenant
+                    .lockup_address
+                    .to_confidential(PublicKey::from_secret_key(&Secp256k1::new(), &blinding_key)),
+                Some(hex::encode(blinding_key.secret_bytes())),
+            )
+        }
+        false => (covenant.lockup_address.clone(), None),
+    };
+
+    let res = covclaim
+        .http
+        .post(covclaim.url("/covenant"))
+        .json(&json!({
+            "claimPublicKey": hex::encode(covenant.claim_public_key.serialize()),
+            "refundPublicKey": hex::encode(covenant.refund_public_key.serialize()),
+            "preimage": hex::encode(covenant.preimage),
+            "blindingKey": blinding_key,
+            "address": claim_address.to_string(),
+            "tree": covenant.tree,
+            "swapId": covenant.swap_id,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), reqwest::StatusCode::CREATED);
+
+    let lockup_txid = node
+        .wallet_rpc(
+            "sendtoaddress",
+            json!([lockup_address.to_string(), sats_to_btc(LOCKUP_AMOUNT)]),
+        )
+        .await
+        .unwrap();
+    if blinded {
+        let lockup = node
+            .rpc("getrawtransaction", json!([lockup_txid, true]))
+            .await
+            .unwrap();
+        assert!(lockup["vout"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|out| out["scriptPubKey"]["hex"]
+                == hex::encode(covenant.lockup_address.script_pubkey().as_bytes())
+                && out.get("valuecommitment").is_some()));
+    }
+
+    // Covenants are claimed as soon as their lockup is in the mempool
+    let swap_id = &covenant.swap_id;
+    let claim_txid = wait_for("claim broadcast", || async move {
+        let events = covclaim
+            .get(&format!("/covenant/{}/events", swap_id))
+            .await?;
+        events
+            .as_array()?
+            .iter()
+            .find(|event| event["status"] == "claimed")
+            .and_then(|event| event["txId"].as_str().map(|txid| txid.to_string()))
+    })
+    .await;
+
+    let claim_tx = node
+        .rpc("getrawtransaction", json!([claim_txid, true]))
+        .await
+        .unwrap();
+    let claim_output = &claim_tx["vout"][0];
+    assert_eq!(
+        claim_output["scriptPubKey"]["hex"],
+        hex::encode(claim_address.script_pubkey().as_bytes())
+    );
+    assert_eq!(
+        (claim_output["value"].as_f64().unwrap() * 100_000_000.0).round() as u64,
+        CLAIM_AMOUNT
+    );
+
+    node.mine_block().await;
+
+    wait_for("claim confirmation", || async move {
+        covclaim
+            .get(&format!("/covenant/{}/timing", swap_id))
+            .await?["stages"]
+            .get("confirmation")
+            .map(|_| ())
+    })
+    .await;
+
+    let claim_tx = node
+        .rpc("getrawtransaction", json!([claim_txid, true]))
+        .await
+        .unwrap();
+    assert!(claim_tx["confirmations"].as_u64().unwrap() >= 1);
+}
+
+struct TestCovenant {
+    swap_id: String,
+    preimage: [u8; 32],
+    claim_public_key: PublicKey,
+    refund_public_key: PublicKey,
+    tree: SwapTree,
+    lockup_address: Address,
+}
+
+impl TestCovenant {
+    fn new(claim_address: &Address, asset: AssetId, swap_id: String) -> TestCovenant {
+        let secp = Secp256k1::new();
+        let claim_public_key = PublicKey::from_secret_key(&secp, &SecretKey::new(&mut OsRng));
+        let refund_public_key = PublicKey::from_secret_key(&secp, &SecretKey::new(&mut OsRng));
+
+        let mut preimage = [0u8; 32];
+        OsRng.fill_bytes(&mut preimage);
+
+        let tree = SwapTree {
+            claim_leaf: TreeScript {
+                output: checksig_leaf(&claim_public_key),
+            },
+            refund_leaf: TreeScript {
+                output: checksig_leaf(&refund_public_key),
+            },
+            covenant_claim_leaf: TreeScript {
+                output: covenant_leaf(&preimage, &claim_address.script_pubkey(), asset),
+            },
+            leaves: None,
+        };
+
+        // Like the API, with the key of Boltz first
+        let internal_key = MusigKeyAggCache::new(&secp, &[refund_public_key, claim_public_key])
+            .agg_pk()
+            .serialize()
+            .to_vec();
+        let lockup_address = tree
+            .clone()
+            .address(internal_key, &AddressParams::ELEMENTS)
+            .unwrap();
+
+        TestCovenant {
+            swap_id,
+            preimage,
+            claim_public_key,
+            refund_public_key,
+            tree,
+            lockup_address,
+        }
+    }
+}
+
+// Only the covenant claim leaf is spent in the tests
+fn checksig_leaf(public_key: &PublicKey) -> Vec<u8> {
+    Builder::new()
+        .push_slice(&public_key.x_only_public_key().0.serialize())
+        .push_opcode(OP_CHECKSIG)
+        .into_script()
+        .into_bytes()
+}
+
+fn covenant_leaf(preimage: &[u8; 32], output: &Script, asset: AssetId) -> Vec<u8> {
+    let preimage_hash: sha256::Hash = Hash::hash(preimage);
+
+    Builder::new()
+        .push_opcode(OP_SIZE)
+        .push_int(32)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_opcode(OP_SHA256)
+        .push_slice(preimage_hash.as_byte_array())
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTSCRIPTPUBKEY)
+        // Witness version 0
+        .push_int(0)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_slice(&output.as_bytes()[2..])
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTASSET)
+        .push_opcode(OP_PUSHNUM_1)
+        .push_opcode(OP_EQUALVERIFY)
+        .push_slice(&elements::encode::serialize(&asset))
+        .push_opcode(OP_EQUALVERIFY)
+        .push_int(0)
+        .push_opcode(OP_INSPECTOUTPUTVALUE)
+        .push_opcode(OP_DROP)
+        .push_slice(&CLAIM_AMOUNT.to_le_bytes())
+        .push_opcode(OP_EQUAL)
+        .into_script()
+        .into_bytes()
+}
+
+struct Node {
+    http: reqwest::Client,
+}
+
+impl Node {
+    fn new() -> Node {
+        Node {
+            http: reqwest::Client::new(),
+        }
+    }
+
+    // Tests run in parallel, so creating and funding the wallet is allowed to fail
+    // when another test did it already
+    async fn setup_wallet(&self) {
+        wait_for("Elements RPC", || async move {
+            self.rpc("getblockchaininfo", json!([])).await.ok()
+        })
+        .await;
+
+        let _ = self.rpc("createwallet", json!([WALLET])).await;
+        let _ = self.rpc("loadwallet", json!([WALLET])).await;
+        // Claims the initial free coins
+        let _ = self.wallet_rpc("rescanblockchain", json!([])).await;
+
+        wait_for("wallet balance", || async move {
+            let balance = self.wallet_rpc("getbalance", json!([])).await.ok()?;
+            match balance["bitcoin"].as_f64().or(balance.as_f64()) {
+                Some(balance) if balance > 0.0 => Some(()),
+                _ => None,
+            }
+        })
+        .await;
+    }
+
+    async fn policy_asset(&self) -> AssetId {
+        let labels = self.rpc("dumpassetlabels", json!([])).await.unwrap();
+        AssetId::from_str(labels["bitcoin"].as_str().unwrap()).unwrap()
+    }
+
+    async fn unconfidential_address(&self) -> Address {
+        let address = self
+            .wallet_rpc("getnewaddress", json!(["", "bech32"]))
+            .await
+            .unwrap();
+        let info = self
+            .wallet_rpc("getaddressinfo", json!([address]))
+            .await
+            .unwrap();
+
+        Address::from_str(info["unconfidential"].as_str().unwrap()).unwrap()
+    }
+
+    async fn mine_block(&self) {
+        let address = self.unconfidential_address().await;
+        self.rpc("generatetoaddress", json!([1, address.to_string()]))
+            .await
+            .unwrap();
+    }
+
+    async fn rpc(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.request("", method, params).await
+    }
+
+    async fn wallet_rpc(&self, method: &str, params: Value) -> Result<Value, String> {
+        self.request(&format!("wallet/{}", WALLET), method, params)
+            .await
+    }
+
+    async fn request(&self, path: &str, method: &str, params: Value) -> Result<Value, String> {
+        let res: Value = self
+            .http
+            .post(format!("http://127.0.0.1:{}/{}", ELEMENTS_PORT, path))
+            .basic_auth(ELEMENTS_USER, Some(ELEMENTS_PASSWORD))
+            .json(&json!({
+                "jsonrpc": "1.0",
+                "id": "regtest",
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(|err| err.to_string())?
+            .json()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        match res["error"].is_null() {
+            true => Ok(res["result"].clone()),
+            false => Err(format!("{} failed: {}", method, res["error"])),
+        }
+    }
+}
+
+/// covclaim daemon with its own database that is stopped when dropped
+struct Covclaim {
+    process: Child,
+    db_path: PathBuf,
+    api_port: u16,
+    http: reqwest::Client,
+}
+
+impl Covclaim {
+    fn start(name: &str, api_port: u16) -> Covclaim {
+        let db_path = std::env::temp_dir().join(format!(
+            "covclaim-regtest-{}-{}.sqlite",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&db_path);
+
+        // Variables that are not set here are read from the .env file
+        let process = Command::new(env!("CARGO_BIN_EXE_covclaim"))
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .envs([
+                ("RUST_LOG", "info"),
+                ("LOG_FORMAT", "text"),
+                ("NETWORK", "regtest"),
+                ("DATABASE_URL", &format!("sqlite://{}", db_path.display())),
+                ("API_HOST", "127.0.0.1"),
+                ("API_PORT", &api_port.to_string()),
+                ("CHAIN_BACKEND", "elements"),
+                ("ELEMENTS_HOST", "127.0.0.1"),
+                ("ELEMENTS_PORT", &ELEMENTS_PORT.to_string()),
+                ("ELEMENTS_COOKIE", ""),
+                ("ELEMENTS_USER", ELEMENTS_USER),
+                ("ELEMENTS_PASSWORD", ELEMENTS_PASSWORD),
+                ("RESCAN", "off"),
+                ("SWEEP_TIME", "0"),
+                ("SWEEP_INTERVAL", "0"),
+                ("BOLTZ_ENDPOINT", ""),
+                ("BOLTZ_WEBSOCKET_POLL_INTERVAL", "0"),
+                ("BOLTZ_VERIFY_INTERVAL", "0"),
+            ])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("could not start covclaim");
+
+        Covclaim {
+            process,
+            db_path,
+            api_port,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.api_port, path)
+    }
+
+    async fn get(&self, path: &str) -> Option<Value> {
+        let res = self.http.get(self.url(path)).send().await.ok()?;
+        if !res.status().is_success() {
+            return None;
+        }
+
+        res.json().await.ok()
+    }
+}
+
+impl Drop for Covclaim {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_file(&self.db_path);
+    }
+}
+
+async fn wait_for<T, F, Fut>(what: &str, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Option<T>>,
+{
+    for _ in 0..TIMEOUT {
+        if let Some(res) = check().await {
+            return res;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    panic!("timed out waiting for {}", what);
+}
+
+fn sats_to_btc(sats: u64) -> f64 {
+    sats as f64 / 100_000_000.0
+}